@@ -0,0 +1,124 @@
+-- Columns and tables added after the initial schema. Existing tables are altered rather than
+-- recreated so databases from the first release keep their rows.
+ALTER TABLE locations ADD COLUMN appointment_duration_minutes INTEGER NOT NULL DEFAULT 30;
+ALTER TABLE locations ADD COLUMN appointment_buffer_minutes INTEGER NOT NULL DEFAULT 10;
+ALTER TABLE locations ADD COLUMN reminder_enabled INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE locations ADD COLUMN tcpa_mode INTEGER NOT NULL DEFAULT 1;
+
+-- SQLite cannot add a REFERENCES column with a non-NULL default while foreign keys are on.
+ALTER TABLE leads ADD COLUMN location_id INTEGER NOT NULL DEFAULT 1;
+
+ALTER TABLE messages ADD COLUMN rate_limit_exempt INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE messages ADD COLUMN is_manual INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE messages ADD COLUMN failed_at TEXT;
+ALTER TABLE messages ADD COLUMN failure_reason TEXT;
+
+CREATE TABLE IF NOT EXISTS appointment_types (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL,
+  duration_minutes INTEGER NOT NULL,
+  confirmation_template TEXT NOT NULL,
+  reminder_template TEXT NOT NULL
+);
+
+ALTER TABLE appointments ADD COLUMN cancelled_at TEXT;
+ALTER TABLE appointments ADD COLUMN cancel_reason TEXT;
+ALTER TABLE appointments ADD COLUMN appointment_type_id INTEGER REFERENCES appointment_types(id);
+
+ALTER TABLE audit_log ADD COLUMN severity TEXT NOT NULL DEFAULT 'info';
+
+ALTER TABLE scheduled_jobs ADD COLUMN retry_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE scheduled_jobs ADD COLUMN max_retries INTEGER NOT NULL DEFAULT 3;
+ALTER TABLE scheduled_jobs ADD COLUMN completed_at TEXT;
+
+CREATE TABLE IF NOT EXISTS lead_tags (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  lead_id INTEGER NOT NULL,
+  tag TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  UNIQUE(lead_id, tag),
+  FOREIGN KEY (lead_id) REFERENCES leads(id)
+);
+
+CREATE TABLE IF NOT EXISTS lead_status_history (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  lead_id INTEGER NOT NULL,
+  from_status TEXT,
+  to_status TEXT NOT NULL,
+  changed_at TEXT NOT NULL,
+  trigger TEXT NOT NULL,
+  FOREIGN KEY (lead_id) REFERENCES leads(id)
+);
+
+CREATE TABLE IF NOT EXISTS lead_notes (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  lead_id INTEGER NOT NULL,
+  body TEXT NOT NULL,
+  author TEXT,
+  created_at TEXT NOT NULL,
+  FOREIGN KEY (lead_id) REFERENCES leads(id)
+);
+
+CREATE TABLE IF NOT EXISTS waitlist (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  lead_id INTEGER NOT NULL,
+  requested_at TEXT NOT NULL,
+  status TEXT NOT NULL DEFAULT 'waiting',
+  FOREIGN KEY (lead_id) REFERENCES leads(id)
+);
+
+CREATE TABLE IF NOT EXISTS reminder_configs (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  location_id INTEGER NOT NULL,
+  hours_before INTEGER NOT NULL,
+  template TEXT,
+  FOREIGN KEY (location_id) REFERENCES locations(id)
+);
+
+CREATE TABLE IF NOT EXISTS follow_up_sequences (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  location_id INTEGER NOT NULL,
+  step_number INTEGER NOT NULL,
+  delay_hours INTEGER NOT NULL,
+  template_key TEXT NOT NULL,
+  UNIQUE(location_id, step_number),
+  FOREIGN KEY (location_id) REFERENCES locations(id)
+);
+
+CREATE TABLE IF NOT EXISTS holidays (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  location_id INTEGER NOT NULL,
+  date TEXT NOT NULL,
+  name TEXT NOT NULL,
+  UNIQUE(location_id, date),
+  FOREIGN KEY (location_id) REFERENCES locations(id)
+);
+
+CREATE TABLE IF NOT EXISTS blackout_periods (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  location_id INTEGER NOT NULL,
+  start_date TEXT NOT NULL,
+  end_date TEXT NOT NULL,
+  reason TEXT NOT NULL,
+  FOREIGN KEY (location_id) REFERENCES locations(id)
+);
+
+CREATE TABLE IF NOT EXISTS custom_keywords (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  keyword TEXT NOT NULL UNIQUE,
+  response_body TEXT NOT NULL,
+  active INTEGER NOT NULL DEFAULT 1,
+  created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS message_templates (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  key TEXT NOT NULL UNIQUE,
+  body TEXT NOT NULL,
+  created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_lead_tags_tag ON lead_tags(tag);
+CREATE INDEX IF NOT EXISTS idx_lead_status_history_lead ON lead_status_history(lead_id, changed_at);
+CREATE INDEX IF NOT EXISTS idx_lead_notes_lead ON lead_notes(lead_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_waitlist_status ON waitlist(status, requested_at);
@@ -0,0 +1,13 @@
+-- Re-run by reset_demo_data, so existing keys are left alone.
+INSERT OR IGNORE INTO message_templates (key, body, created_at) VALUES
+  ('initial_follow_up', 'Hi {name}, this is {gym}. Reply YES to see two available intro session times.', strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+  ('reply_yes_prompt', 'Reply YES to get the next two available intro session times.', strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+  ('slot_offer', 'Choose a time:' || char(10) || '1) {slot1}' || char(10) || '2) {slot2}' || char(10) || char(10) || 'Reply 1 or 2.', strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+  ('repair_prompt', 'Please reply with 1 or 2 so I can book your session.', strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+  ('repair_staff_flagged', 'I also flagged this conversation for staff follow-up.', strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+  ('repair_no_slots', 'I couldn''t match that response to a slot. A staff member has been flagged to help.', strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+  ('already_booked', 'You''re already booked. Reply if you need staff help rescheduling.', strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+  ('waitlist_joined', 'All slots are full. You''re on the waitlist.', strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+  ('opt_out_confirmation', 'You are unsubscribed and will receive no more automated messages.', strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+  ('no_show_follow_up', 'We missed you today. Want to rebook?', strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+  ('re_engagement', 'Hi {name}, it''s {gym}. Still interested in an intro session? Reply YES to see two open times.', strftime('%Y-%m-%dT%H:%M:%SZ', 'now'));
@@ -0,0 +1,2 @@
+CREATE INDEX IF NOT EXISTS idx_leads_status ON leads(status);
+CREATE INDEX IF NOT EXISTS idx_audit_action_created ON audit_log(action_type, created_at);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS phone_blocklist (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  phone_e164 TEXT NOT NULL UNIQUE,
+  reason TEXT,
+  added_at TEXT NOT NULL
+);
@@ -0,0 +1,4 @@
+ALTER TABLE leads ADD COLUMN full_name TEXT
+  GENERATED ALWAYS AS (COALESCE(first_name, '') || ' ' || COALESCE(last_name, '')) VIRTUAL;
+
+CREATE INDEX IF NOT EXISTS idx_leads_full_name ON leads(full_name);
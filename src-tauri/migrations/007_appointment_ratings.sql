@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS appointment_ratings (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  appointment_id INTEGER NOT NULL UNIQUE,
+  lead_id INTEGER NOT NULL,
+  rating INTEGER NOT NULL CHECK (rating BETWEEN 1 AND 5),
+  created_at TEXT NOT NULL,
+  FOREIGN KEY (appointment_id) REFERENCES appointments(id),
+  FOREIGN KEY (lead_id) REFERENCES leads(id)
+);
+
+INSERT OR IGNORE INTO message_templates (key, body, created_at) VALUES
+  ('post_appointment_follow_up', 'Thanks for coming in, {name}! How was your session? Reply with a number from 1 (poor) to 5 (great).', strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+  ('rating_repair', 'Please reply with a number from 1 to 5 to rate your session.', strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+  ('rating_thanks', 'Thanks for your feedback!', strftime('%Y-%m-%dT%H:%M:%SZ', 'now'));
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS idempotency_records (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  key TEXT NOT NULL UNIQUE,
+  result_json TEXT NOT NULL,
+  created_at TEXT NOT NULL
+);
@@ -0,0 +1,7 @@
+INSERT INTO appointment_types (name, duration_minutes, confirmation_template, reminder_template)
+SELECT
+  'Intro Session',
+  30,
+  'Booked. Your intro session is confirmed for {time}. We will send a reminder 2 hours before.',
+  'Reminder {name}: your gym appointment is at {time}. Reply STOP to opt out.'
+WHERE NOT EXISTS (SELECT 1 FROM appointment_types);
@@ -0,0 +1,14 @@
+-- Every location gets the 2-hour reminder that used to be hard-coded.
+INSERT INTO reminder_configs (location_id, hours_before)
+SELECT id, 2 FROM locations
+WHERE NOT EXISTS (SELECT 1 FROM reminder_configs WHERE reminder_configs.location_id = locations.id);
+
+CREATE TRIGGER IF NOT EXISTS locations_default_reminder_config
+AFTER INSERT ON locations
+BEGIN
+  INSERT INTO reminder_configs (location_id, hours_before) VALUES (NEW.id, 2);
+END;
+
+UPDATE appointment_types
+SET confirmation_template = replace(confirmation_template, 'We will send a reminder 2 hours before.', '{reminder_notice}')
+WHERE confirmation_template LIKE '%We will send a reminder 2 hours before.%';
@@ -0,0 +1,11 @@
+-- Every location starts with a single immediate follow-up; gyms add later touches themselves.
+INSERT INTO follow_up_sequences (location_id, step_number, delay_hours, template_key)
+SELECT id, 1, 0, 'initial_follow_up' FROM locations
+WHERE NOT EXISTS (SELECT 1 FROM follow_up_sequences WHERE follow_up_sequences.location_id = locations.id);
+
+CREATE TRIGGER IF NOT EXISTS locations_default_follow_up_step
+AFTER INSERT ON locations
+BEGIN
+  INSERT INTO follow_up_sequences (location_id, step_number, delay_hours, template_key)
+  VALUES (NEW.id, 1, 0, 'initial_follow_up');
+END;
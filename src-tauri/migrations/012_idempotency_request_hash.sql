@@ -0,0 +1,3 @@
+-- Ties each idempotency key to the action it was first used with; a reused key with a
+-- different action is rejected instead of replaying the wrong result.
+ALTER TABLE idempotency_records ADD COLUMN request_hash TEXT;
@@ -1,16 +1,20 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use chrono::{
     DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Timelike, Utc, Weekday,
 };
 use chrono_tz::Tz;
-use rusqlite::{params, Connection, OptionalExtension};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, params_from_iter, Connection, OpenFlags, OptionalExtension};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::collections::HashMap;
-use std::fs::{self, OpenOptions};
-use std::io::Write;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration as StdDuration;
 use tauri::{AppHandle, Manager, State};
@@ -19,25 +23,93 @@ use thiserror::Error;
 #[cfg(test)]
 mod test_helpers;
 
+type DbPool = r2d2::Pool<SqliteConnectionManager>;
+type PooledConn = r2d2::PooledConnection<SqliteConnectionManager>;
+
 #[derive(Clone)]
 struct AppState {
-    db_path: PathBuf,
+    pool: DbPool,
+    clock: Arc<dyn Clock + Send + Sync>,
+}
+
+/// Source of "now" for code whose behaviour depends on the time of day, so tests can pin it.
+trait Clock {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+struct RealClock;
+
+impl Clock for RealClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+#[cfg(test)]
+pub(crate) struct FakeClock(pub(crate) DateTime<Utc>);
+
+#[cfg(test)]
+impl Clock for FakeClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+/// Applies the per-connection pragmas every time the pool hands out a new SQLite handle.
+#[derive(Debug)]
+struct ConnectionCustomizer;
+
+impl r2d2::CustomizeConnection<Connection, rusqlite::Error> for ConnectionCustomizer {
+    fn on_acquire(&self, conn: &mut Connection) -> Result<(), rusqlite::Error> {
+        configure_connection(conn)
+    }
 }
 
 #[derive(Error, Debug)]
 enum AppError {
     #[error("database error: {0}")]
     Db(#[from] rusqlite::Error),
+    #[error("connection pool error: {0}")]
+    Pool(#[from] r2d2::Error),
     #[error("json error: {0}")]
     Json(#[from] serde_json::Error),
     #[error("time parse error: {0}")]
     Chrono(#[from] chrono::ParseError),
     #[error("{0}")]
     Validation(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{1}")]
+    Coded(ErrorCode, String),
 }
 
 type AppResult<T> = Result<T, AppError>;
 
+/// Machine-readable error category sent to the frontend alongside the human message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ErrorCode {
+    DbBusy,
+    NotFound,
+    ConsentRequired,
+    OptedOut,
+    RateLimit,
+    BusinessHours,
+    ValidationFailed,
+    KillSwitch,
+    Internal,
+}
+
+/// JSON shape of every command error string. Not-found errors also carry `status: 404`.
+#[derive(Debug, Serialize)]
+struct AppErrorResponse {
+    code: ErrorCode,
+    message: String,
+    detail: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+}
+
 impl AppError {
     fn is_busy_or_locked(&self) -> bool {
         match self {
@@ -48,6 +120,25 @@ impl AppError {
             _ => false,
         }
     }
+
+    /// Covers the explicit variant as well as a single-row query that matched nothing.
+    fn is_not_found(&self) -> bool {
+        self.code() == ErrorCode::NotFound
+    }
+
+    fn code(&self) -> ErrorCode {
+        match self {
+            AppError::Coded(code, _) => *code,
+            AppError::NotFound(_) => ErrorCode::NotFound,
+            AppError::Validation(_) | AppError::Json(_) | AppError::Chrono(_) => {
+                ErrorCode::ValidationFailed
+            }
+            AppError::Db(rusqlite::Error::QueryReturnedNoRows) => ErrorCode::NotFound,
+            AppError::Pool(_) => ErrorCode::DbBusy,
+            err if err.is_busy_or_locked() => ErrorCode::DbBusy,
+            AppError::Db(_) => ErrorCode::Internal,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -63,9 +154,25 @@ struct LeadCreateInput {
 #[derive(Debug, Serialize)]
 struct LeadCreateResult {
     created: bool,
+    /// 0 when the phone is blocklisted and nothing was created.
     lead_id: i64,
     duplicate_of: Option<i64>,
     note: Option<String>,
+    blocked: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct BulkCreateError {
+    index: usize,
+    message: String,
+}
+
+#[derive(Debug, Serialize)]
+struct BulkCreateResult {
+    created: Vec<LeadCreateResult>,
+    duplicates: Vec<LeadCreateResult>,
+    errors: Vec<BulkCreateError>,
+    jobs_scheduled: i64,
 }
 
 #[derive(Debug, Serialize)]
@@ -81,6 +188,46 @@ struct LeadSummary {
     created_at: String,
 }
 
+/// Same status filter as `list_leads_by_status`; an empty list exports every lead.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct LeadExportFilter {
+    statuses: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct StaffAttentionResolution {
+    lead_id: i64,
+    resolution: String,
+    jobs_cancelled: i64,
+}
+
+/// Fields left `None` are not touched by `update_lead`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct LeadPatch {
+    first_name: Option<String>,
+    last_name: Option<String>,
+    phone_e164: Option<String>,
+    consent: Option<bool>,
+    consent_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct LeadConsentResult {
+    lead_id: i64,
+    consent: bool,
+    follow_up_scheduled: bool,
+    follow_ups_cancelled: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct LeadPage {
+    leads: Vec<LeadSummary>,
+    total: i64,
+    has_more: bool,
+}
+
 #[derive(Debug, Serialize)]
 struct LeadDetailLead {
     id: i64,
@@ -115,6 +262,24 @@ struct MessageView {
     body: String,
     status: String,
     created_at: String,
+    is_manual: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct AttentionLeadView {
+    #[serde(flatten)]
+    lead: LeadSummary,
+    attention_reason: Option<String>,
+    flagged_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct MessageFailureView {
+    #[serde(flatten)]
+    message: MessageView,
+    lead_id: i64,
+    failed_at: String,
+    failure_reason: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -125,12 +290,47 @@ struct AppointmentView {
     status: String,
 }
 
+#[derive(Debug, Serialize)]
+struct AppointmentDetail {
+    id: i64,
+    lead_id: i64,
+    first_name: Option<String>,
+    last_name: Option<String>,
+    phone_e164: String,
+    start_at: String,
+    end_at: String,
+    status: String,
+    appointment_type_id: Option<i64>,
+    created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+struct NoteView {
+    id: i64,
+    lead_id: i64,
+    body: String,
+    author: Option<String>,
+    created_at: String,
+}
+
 #[derive(Debug, Serialize)]
 struct LeadDetail {
     lead: LeadDetailLead,
     conversation: ConversationView,
     messages: Vec<MessageView>,
     appointments: Vec<AppointmentView>,
+    tags: Vec<String>,
+    notes: Vec<NoteView>,
+}
+
+#[derive(Debug, Serialize)]
+struct StatusHistoryEntry {
+    id: i64,
+    lead_id: i64,
+    from_status: Option<String>,
+    to_status: String,
+    changed_at: String,
+    trigger: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -142,11 +342,337 @@ struct TodayReport {
     needs_attention: i64,
 }
 
+#[derive(Debug, Serialize)]
+struct FunnelReport {
+    leads_created: i64,
+    leads_contacted: i64,
+    leads_responded_yes: i64,
+    leads_booked: i64,
+    leads_opted_out: i64,
+    conversion_rate_pct: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct SourceReport {
+    source: Option<String>,
+    leads: i64,
+    booked: i64,
+    opted_out: i64,
+    booking_rate_pct: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct DayReport {
+    date: String,
+    #[serde(flatten)]
+    report: TodayReport,
+}
+
+#[derive(Debug, Serialize)]
+struct CommSummary {
+    lead_id: i64,
+    total_outbound: i64,
+    total_inbound: i64,
+    first_contact_at: Option<String>,
+    last_contact_at: Option<String>,
+    days_since_last_contact: Option<i64>,
+    inbound_response_rate_pct: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+struct DailyStats {
+    date: String,
+    leads_created: i64,
+    contacted: i64,
+    booked: i64,
+    opt_outs: i64,
+    needs_attention_flagged: i64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct JobFilter {
+    status: Option<String>,
+    job_type: Option<String>,
+    since: Option<String>,
+    until: Option<String>,
+    limit: u32,
+}
+
+impl Default for JobFilter {
+    fn default() -> Self {
+        Self {
+            status: None,
+            job_type: None,
+            since: None,
+            until: None,
+            limit: 100,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ScheduledJobView {
+    id: i64,
+    job_type: String,
+    target_id: Option<i64>,
+    execute_at: String,
+    status: String,
+    payload_json: String,
+    created_at: String,
+    retry_count: i64,
+    max_retries: i64,
+    completed_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct AuditEntry {
+    id: i64,
+    action_type: String,
+    target_type: String,
+    target_id: Option<String>,
+    request_json: String,
+    response_json: Option<String>,
+    success: bool,
+    error_message: Option<String>,
+    created_at: String,
+    severity: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AuditLogFilter {
+    action_type: Option<String>,
+    target_type: Option<String>,
+    target_id: Option<String>,
+    success: Option<bool>,
+    since: Option<String>,
+    until: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct AuditLogPage {
+    entries: Vec<AuditEntry>,
+    total: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct MessageHistoryItem {
+    #[serde(flatten)]
+    message: MessageView,
+    lead_id: i64,
+    lead_phone: String,
+    conversation_id: i64,
+}
+
+/// One entry in a lead's merged timeline of messages and status changes.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum ConversationHistoryItem {
+    Message(MessageView),
+    StateTransition {
+        from: String,
+        to: String,
+        triggered_by: String,
+        at: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct MessageSearchResult {
+    message_id: i64,
+    conversation_id: i64,
+    lead_id: i64,
+    direction: String,
+    body: String,
+    created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+struct MessageHistoryPage {
+    messages: Vec<MessageHistoryItem>,
+    next_cursor: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct AuditPage {
+    entries: Vec<AuditEntry>,
+    next_cursor: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct PurgeResult {
+    rows_affected: i64,
+    oldest_kept: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct JobTypeStats {
+    job_type: String,
+    pending: i64,
+    completed: i64,
+    failed: i64,
+    cancelled: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct JobStats {
+    total_pending: i64,
+    total_completed: i64,
+    total_failed: i64,
+    total_cancelled: i64,
+    avg_delay_seconds: Option<f64>,
+    by_type: Vec<JobTypeStats>,
+}
+
+#[derive(Debug, Serialize)]
+struct CancelJobResult {
+    job_id: i64,
+    previous_status: String,
+}
+
 #[derive(Debug, Serialize)]
 struct RunJobsResult {
     processed: i64,
     skipped: i64,
     errors: i64,
+    auto_kill_switch_triggered: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct SettingView {
+    key: String,
+    value: String,
+    updated_at: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SettingType {
+    Bool,
+    Int,
+    Text,
+    Json,
+}
+
+/// Keys `set_setting` may write. Location-scoped and derived values (current location, kill switch
+/// reason) are managed by their own commands and are deliberately absent.
+const SETTABLE_SETTINGS: &[(&str, SettingType)] = &[
+    ("kill_switch", SettingType::Bool),
+    ("stop_keywords", SettingType::Json),
+    ("help_response_text", SettingType::Text),
+    ("conversation_expiry_hours", SettingType::Int),
+    ("max_outbound_per_lead_per_day", SettingType::Int),
+    ("max_outbound_per_location_per_hour", SettingType::Int),
+    ("auto_kill_switch_threshold", SettingType::Int),
+    ("audit_log_retention_days", SettingType::Int),
+];
+
+#[derive(Debug, Serialize)]
+struct IntegrityCheckResult {
+    ok: bool,
+    messages: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct BackupResult {
+    destination_path: String,
+    pages_copied: i64,
+    size_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct MigrationRecord {
+    version: i64,
+    applied_at: String,
+    description: String,
+    filename: Option<String>,
+    checksum: Option<String>,
+    /// Set when the recorded checksum no longer matches the file this build ships.
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct AppInfo {
+    app_version: String,
+    db_path: String,
+    db_size_bytes: u64,
+    schema_version: i64,
+    kill_switch_enabled: bool,
+    location_name: String,
+    location_timezone: String,
+}
+
+#[derive(Debug, Serialize)]
+struct VacuumResult {
+    size_before_bytes: u64,
+    size_after_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct CheckpointResult {
+    busy_frames: i64,
+    log_frames: i64,
+    checkpointed_frames: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct KillSwitchState {
+    enabled: bool,
+    reason: Option<String>,
+    enabled_at: Option<String>,
+    pending_jobs_count: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct RateLimitSettings {
+    max_outbound_per_lead_per_day: i64,
+    max_outbound_per_location_per_hour: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct RateLimitStatus {
+    location_hour_used: i64,
+    location_hour_limit: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lead_day_used: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lead_day_limit: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cooldown_seconds: Option<i64>,
+}
+
+#[cfg(debug_assertions)]
+#[derive(Debug, Deserialize)]
+struct BulkInboundItem {
+    lead_id: i64,
+    body: String,
+}
+
+#[derive(Debug, Serialize)]
+struct BulkInboundResult {
+    processed: u32,
+    errors: Vec<(i64, String)>,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchScheduleResult {
+    scheduled: u32,
+    skipped: u32,
+    errors: Vec<(i64, String)>,
+}
+
+#[derive(Debug, Serialize)]
+struct AppointmentCompletion {
+    appointment_id: i64,
+    follow_up_job_id: Option<i64>,
+    /// Why the rating follow-up could not be queued (e.g. the kill switch is on); the appointment
+    /// is still marked completed.
+    follow_up_error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct DemoResetResult {
+    rows_deleted: i64,
+    location_id: i64,
 }
 
 #[derive(Debug, Serialize)]
@@ -156,14 +682,23 @@ struct WipeAllDataResult {
     scheduled_jobs: i64,
     audit_log: i64,
     conversations: i64,
+    lead_tags: i64,
+    lead_status_history: i64,
+    lead_notes: i64,
+    waitlist: i64,
     leads: i64,
 }
 
 #[derive(Debug)]
 struct Location {
+    id: i64,
     gym_name: String,
     timezone: String,
     business_hours_json: String,
+    appointment_duration_minutes: i64,
+    appointment_buffer_minutes: i64,
+    reminder_enabled: bool,
+    tcpa_mode: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -172,6 +707,9 @@ struct LocationSettings {
     gym_name: String,
     timezone: String,
     business_hours_json: String,
+    appointment_duration_minutes: i64,
+    appointment_buffer_minutes: i64,
+    reminder_enabled: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -181,6 +719,40 @@ struct LocationSettingsInput {
     business_hours_json: String,
 }
 
+/// Fields left `None` are not touched by `update_location`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+struct LocationPatch {
+    gym_name: Option<String>,
+    timezone: Option<String>,
+    business_hours_json: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct LocationUpdateResult {
+    location: LocationSettings,
+    /// Pending jobs whose local wall-clock time, read in the new timezone, has already passed.
+    /// They are left queued for staff to review.
+    past_due_jobs: Vec<ScheduledJobView>,
+}
+
+/// Contents of the optional `config.toml` in the app data directory. It is only read when the
+/// database is seeded for the first time.
+#[derive(Debug, Default, Deserialize)]
+struct Config {
+    #[serde(default)]
+    location: LocationConfig,
+    #[serde(default)]
+    settings: HashMap<String, toml::Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct LocationConfig {
+    gym_name: Option<String>,
+    timezone: Option<String>,
+    business_hours_json: Option<String>,
+}
+
 #[derive(Debug)]
 struct LeadRow {
     id: i64,
@@ -208,6 +780,8 @@ struct SlotChoice {
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 struct ConversationState {
     offered_slots: Vec<SlotChoice>,
+    #[serde(default)]
+    appointment_type_id: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -220,6 +794,10 @@ struct OutboundRequest {
     allow_opted_out_once: bool,
     allow_after_reply: bool,
     ignore_business_hours: bool,
+    /// Compliance replies (HELP): skips the rate limits, is not counted by them and leaves
+    /// `last_outbound_at` untouched.
+    #[serde(default)]
+    rate_limit_exempt: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -236,21 +814,79 @@ struct AppointmentRequest {
     start_at: String,
     end_at: String,
     status: String,
+    #[serde(default)]
+    appointment_type_id: Option<i64>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct OptOutRequest {
-    lead_id: i64,
-    reason: String,
+#[derive(Debug, Serialize)]
+struct AppointmentType {
+    id: i64,
+    name: String,
+    duration_minutes: i64,
+    confirmation_template: String,
+    reminder_template: String,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
-enum AgentActionType {
-    SendOutbound,
-    BookAppointment,
-    SetOptOut,
-    ScheduleJob,
+#[derive(Debug, Deserialize)]
+struct AppointmentTypeInput {
+    name: String,
+    duration_minutes: i64,
+    confirmation_template: String,
+    reminder_template: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CancelAppointmentRequest {
+    appointment_id: i64,
+    reason: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CancelAppointmentResult {
+    appointment_id: i64,
+    reminder_jobs_cancelled: i64,
+    waitlist_lead_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RescheduleAppointmentRequest {
+    appointment_id: i64,
+    new_start_at: String,
+    new_end_at: String,
+    reason: String,
+}
+
+#[derive(Debug, Serialize)]
+struct RescheduleResult {
+    appointment_id: i64,
+    reminder_job_ids: Vec<i64>,
+    old_start_at: String,
+    old_end_at: String,
+    new_start_at: String,
+    new_end_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OverrideConversationStateRequest {
+    conversation_id: i64,
+    new_state: String,
+    state_json: String,
+    reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OptOutRequest {
+    lead_id: i64,
+    reason: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum AgentActionType {
+    SendOutbound,
+    BookAppointment,
+    SetOptOut,
+    ScheduleJob,
 }
 
 impl AgentActionType {
@@ -282,6 +918,8 @@ enum AgentAction {
         start_at: String,
         end_at: String,
         status: String,
+        #[serde(default)]
+        appointment_type_id: Option<i64>,
     },
     SetOptOut {
         lead_id: i64,
@@ -322,18 +960,47 @@ struct AgentDryRunResult {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct AgentExecuteRequest {
     action: AgentAction,
+    /// A retried request with the same key within 24 hours gets the first successful result back
+    /// instead of running again.
+    #[serde(default)]
+    idempotency_key: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 struct AgentExecuteResult {
     success: bool,
     result_json: Option<Value>,
     error: Option<String>,
 }
 
+/// One entry per attempted action. When `batch_aborted_at` is set, that action failed, later ones
+/// were not attempted and nothing from the batch was committed.
+#[derive(Debug, Serialize)]
+struct AgentBatchResult {
+    results: Vec<AgentExecuteResult>,
+    batch_aborted_at: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct AgentActionRecord {
+    id: i64,
+    action_type: String,
+    target_type: String,
+    success: bool,
+    request_json: Value,
+    response_json: Option<Value>,
+    created_at: String,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct InitialFollowUpPayload {
     lead_id: i64,
+    #[serde(default = "first_follow_up_step")]
+    step_number: i64,
+}
+
+fn first_follow_up_step() -> i64 {
+    1
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -341,29 +1008,157 @@ struct ReminderPayload {
     lead_id: i64,
     appointment_id: i64,
     start_at: String,
+    #[serde(default)]
+    reminder_config_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct MessageTemplate {
+    id: i64,
+    key: String,
+    body: String,
+    created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CustomKeyword {
+    id: i64,
+    keyword: String,
+    response_body: String,
+    active: bool,
+    created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+struct BlocklistEntry {
+    id: i64,
+    phone_e164: String,
+    reason: Option<String>,
+    added_at: String,
+}
+
+#[derive(Debug, Serialize)]
+struct HolidayView {
+    id: i64,
+    location_id: i64,
+    date: String,
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+struct BlackoutPeriodView {
+    id: i64,
+    location_id: i64,
+    start_date: String,
+    end_date: String,
+    reason: String,
+}
+
+/// Holidays and blackout periods for one location, as local calendar dates.
+struct ClosedDays {
+    holidays: HashSet<NaiveDate>,
+    blackouts: Vec<(NaiveDate, NaiveDate)>,
+}
+
+impl ClosedDays {
+    fn contains(&self, day: &NaiveDate) -> bool {
+        self.holidays.contains(day)
+            || self
+                .blackouts
+                .iter()
+                .any(|(start, end)| start <= day && day <= end)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct FollowUpStep {
+    id: i64,
+    location_id: i64,
+    step_number: i64,
+    delay_hours: i64,
+    template_key: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ReminderConfig {
+    id: i64,
+    location_id: i64,
+    hours_before: i64,
+    template: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct NoShowFollowUpPayload {
+    lead_id: i64,
+    appointment_id: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct PostAppointmentFollowUpPayload {
+    lead_id: i64,
+    appointment_id: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ReEngagementPayload {
+    lead_id: i64,
 }
 
+const JOB_STATUSES: [&str; 4] = ["pending", "completed", "failed", "cancelled"];
+
+const APPOINTMENT_STATUSES: [&str; 4] = ["booked", "cancelled", "no_show", "completed"];
+
+const CONVERSATION_STATES: [&str; 4] = [
+    "awaiting_yes",
+    "awaiting_time_choice",
+    "booked",
+    "awaiting_rating",
+];
+
+const LEAD_STATUSES: [&str; 6] = [
+    "awaiting_yes",
+    "awaiting_time_choice",
+    "booked",
+    "awaiting_rating",
+    "no_show",
+    "opted_out",
+];
+
 struct ActionGateway<'a> {
     conn: &'a Connection,
-    location: &'a Location,
+    clock: &'a dyn Clock,
 }
 
 impl<'a> ActionGateway<'a> {
-    fn new(conn: &'a Connection, location: &'a Location) -> Self {
-        Self { conn, location }
+    fn new(conn: &'a Connection) -> Self {
+        Self::with_clock(conn, &RealClock)
+    }
+
+    fn with_clock(conn: &'a Connection, clock: &'a dyn Clock) -> Self {
+        Self { conn, clock }
+    }
+
+    fn now_iso(&self) -> String {
+        self.clock.now().to_rfc3339()
     }
 
     fn validate_outbound(&self, req: &OutboundRequest) -> AppResult<()> {
+        self.validate_outbound_at(req, self.clock.now())
+    }
+
+    fn validate_outbound_at(&self, req: &OutboundRequest, now: DateTime<Utc>) -> AppResult<()> {
         if req.automated && is_kill_switch_enabled(self.conn)? {
             log_kill_switch_block(
                 self.conn,
+                self.clock,
                 "create_outbound_message",
                 "conversation",
                 Some(req.conversation_id.to_string()),
                 serde_json::to_value(req)?,
                 "automated outbound blocked because automation is paused (safe mode)",
             );
-            return Err(AppError::Validation(
+            return Err(AppError::Coded(
+                ErrorCode::KillSwitch,
                 "kill switch is enabled; automated outbound blocked".to_string(),
             ));
         }
@@ -377,30 +1172,50 @@ impl<'a> ActionGateway<'a> {
         }
 
         if !lead.consent && !req.allow_without_consent {
-            return Err(AppError::Validation(
+            return Err(AppError::Coded(
+                ErrorCode::ConsentRequired,
                 "consent required before outbound".to_string(),
             ));
         }
 
+        if is_lead_blocklisted(self.conn, req.lead_id)? {
+            return Err(AppError::Validation("phone is on blocklist".to_string()));
+        }
+
         if lead.opted_out && !req.allow_opted_out_once {
-            return Err(AppError::Validation(
+            return Err(AppError::Coded(
+                ErrorCode::OptedOut,
                 "lead is opted out; outbound blocked".to_string(),
             ));
         }
 
-        if !req.ignore_business_hours && !is_business_open(self.location, Utc::now())? {
-            return Err(AppError::Validation(
+        let location = get_lead_location(self.conn, req.lead_id)?;
+        if !req.ignore_business_hours && !is_business_open(&location, now)? {
+            return Err(AppError::Coded(
+                ErrorCode::BusinessHours,
                 "outside business hours; outbound blocked".to_string(),
             ));
         }
 
-        self.check_rate_limits(req.lead_id, &convo, req.allow_after_reply)?;
+        // TCPA quiet hours apply to automated sends even when business hours are bypassed.
+        if req.automated && location.tcpa_mode && !is_within_tcpa_window(&location, now)? {
+            return Err(AppError::Coded(
+                ErrorCode::BusinessHours,
+                "outside TCPA quiet hours (08:00-21:00 local); automated outbound blocked"
+                    .to_string(),
+            ));
+        }
+
+        if !req.rate_limit_exempt {
+            self.check_rate_limits(&location, req.lead_id, &convo, req.allow_after_reply)?;
+        }
         Ok(())
     }
 
     fn validate_agent_outbound(&self, req: &OutboundRequest) -> AppResult<()> {
         if req.allow_without_consent {
-            return Err(AppError::Validation(
+            return Err(AppError::Coded(
+                ErrorCode::ConsentRequired,
                 "agent outbound cannot bypass consent".to_string(),
             ));
         }
@@ -410,10 +1225,17 @@ impl<'a> ActionGateway<'a> {
             ));
         }
         if req.ignore_business_hours {
-            return Err(AppError::Validation(
+            return Err(AppError::Coded(
+                ErrorCode::BusinessHours,
                 "agent outbound cannot ignore business hours".to_string(),
             ));
         }
+        if req.rate_limit_exempt {
+            return Err(AppError::Coded(
+                ErrorCode::RateLimit,
+                "agent outbound cannot bypass rate limits".to_string(),
+            ));
+        }
 
         self.validate_outbound(req)?;
 
@@ -425,8 +1247,12 @@ impl<'a> ActionGateway<'a> {
              WHERE conversation_id = ?
                AND direction = 'OUTBOUND'
                AND body = ?
-               AND datetime(replace(replace(created_at, 'T', ' '), 'Z', '')) >= datetime('now', '-10 minutes')",
-            params![req.conversation_id, req.body],
+               AND datetime(replace(replace(created_at, 'T', ' '), 'Z', '')) >= datetime(?)",
+            params![
+                req.conversation_id,
+                req.body,
+                (self.clock.now() - Duration::minutes(10)).to_rfc3339()
+            ],
             |row| row.get(0),
         )?;
         if duplicate_count > 0 {
@@ -440,13 +1266,28 @@ impl<'a> ActionGateway<'a> {
     }
 
     fn validate_appointment(&self, req: &AppointmentRequest) -> AppResult<()> {
+        self.validate_appointment_excluding(req, None)
+    }
+
+    /// Same checks as `validate_appointment`, ignoring `exclude_id` in the overlap
+    /// query so an appointment being moved does not conflict with itself.
+    fn validate_appointment_excluding(
+        &self,
+        req: &AppointmentRequest,
+        exclude_id: Option<i64>,
+    ) -> AppResult<()> {
         let lead = get_lead(self.conn, req.lead_id)?;
         if lead.opted_out {
-            return Err(AppError::Validation(
+            return Err(AppError::Coded(
+                ErrorCode::OptedOut,
                 "cannot book appointment for opted-out lead".to_string(),
             ));
         }
 
+        if let Some(appointment_type_id) = req.appointment_type_id {
+            get_appointment_type(self.conn, Some(appointment_type_id))?;
+        }
+
         let start = parse_ts(&req.start_at)?;
         let end = parse_ts(&req.end_at)?;
         if end <= start {
@@ -455,13 +1296,18 @@ impl<'a> ActionGateway<'a> {
             ));
         }
 
+        let location = get_lead_location(self.conn, req.lead_id)?;
+        let buffer = format!("+{} minutes", location.appointment_buffer_minutes);
         let overlap_count: i64 = self.conn.query_row(
             "SELECT COUNT(*)
-             FROM appointments
-             WHERE status = 'booked'
-               AND datetime(start_at) < datetime(?, '+10 minutes')
-               AND datetime(end_at, '+10 minutes') > datetime(?)",
-            params![req.end_at, req.start_at],
+             FROM appointments a
+             JOIN leads l ON l.id = a.lead_id
+             WHERE a.status = 'booked'
+               AND l.location_id = ?4
+               AND a.id IS NOT ?5
+               AND datetime(a.start_at) < datetime(?1, ?3)
+               AND datetime(a.end_at, ?3) > datetime(?2)",
+            params![req.end_at, req.start_at, buffer, location.id, exclude_id],
             |row| row.get(0),
         )?;
 
@@ -478,13 +1324,15 @@ impl<'a> ActionGateway<'a> {
         if is_kill_switch_enabled(self.conn)? {
             log_kill_switch_block(
                 self.conn,
+                self.clock,
                 "schedule_job",
                 "scheduled_job",
                 None,
                 serde_json::to_value(req)?,
                 "job scheduling blocked because automation is paused (safe mode)",
             );
-            return Err(AppError::Validation(
+            return Err(AppError::Coded(
+                ErrorCode::KillSwitch,
                 "kill switch is enabled; job scheduling blocked".to_string(),
             ));
         }
@@ -505,17 +1353,20 @@ impl<'a> ActionGateway<'a> {
         let result = (|| -> AppResult<i64> {
             self.validate_outbound(&req)?;
 
-            let now = now_iso();
+            let now = self.now_iso();
             self.conn.execute(
-                "INSERT INTO messages (conversation_id, direction, body, status, created_at) VALUES (?, 'OUTBOUND', ?, 'sent', ?)",
-                params![req.conversation_id, req.body, now],
+                "INSERT INTO messages (conversation_id, direction, body, status, created_at, rate_limit_exempt)
+                 VALUES (?, 'OUTBOUND', ?, 'sent', ?, ?)",
+                params![req.conversation_id, req.body, now, bool_to_i64(req.rate_limit_exempt)],
             )?;
             let message_id = self.conn.last_insert_rowid();
 
-            self.conn.execute(
-                "UPDATE conversations SET last_outbound_at = ? WHERE id = ?",
-                params![now, req.conversation_id],
-            )?;
+            if !req.rate_limit_exempt {
+                self.conn.execute(
+                    "UPDATE conversations SET last_outbound_at = ? WHERE id = ?",
+                    params![now, req.conversation_id],
+                )?;
+            }
 
             self.conn.execute(
                 "UPDATE leads SET last_contact_at = ?, status = COALESCE(status, 'awaiting_yes') WHERE id = ?",
@@ -529,6 +1380,7 @@ impl<'a> ActionGateway<'a> {
             Ok(message_id) => {
                 let _ = insert_audit(
                     self.conn,
+                    self.clock,
                     action,
                     target_type,
                     target_id,
@@ -542,6 +1394,7 @@ impl<'a> ActionGateway<'a> {
             Err(err) => {
                 let _ = insert_audit(
                     self.conn,
+                    self.clock,
                     action,
                     target_type,
                     target_id,
@@ -555,6 +1408,28 @@ impl<'a> ActionGateway<'a> {
         }
     }
 
+    /// Replies to a HELP keyword. The message is rate-limit exempt and leaves
+    /// `last_outbound_at` untouched so it never delays the regular flow.
+    fn send_help_response(&self, lead_id: i64, conversation_id: i64) -> AppResult<i64> {
+        let location = get_lead_location(self.conn, lead_id)?;
+        let body = fill_template(
+            &get_help_response_with_conn(self.conn)?,
+            &HashMap::from([("gym", location.gym_name.as_str())]),
+        );
+        // Carriers expect HELP to be answered even before consent and outside opening hours.
+        self.create_outbound_message(OutboundRequest {
+            lead_id,
+            conversation_id,
+            body,
+            automated: false,
+            allow_without_consent: true,
+            allow_opted_out_once: false,
+            allow_after_reply: true,
+            ignore_business_hours: true,
+            rate_limit_exempt: true,
+        })
+    }
+
     fn create_outbound_message_for_agent(&self, req: OutboundRequest) -> AppResult<i64> {
         self.validate_agent_outbound(&req)?;
         self.create_outbound_message(req)
@@ -570,13 +1445,20 @@ impl<'a> ActionGateway<'a> {
             self.validate_appointment(&req)?;
 
             self.conn.execute(
-                "INSERT INTO appointments (lead_id, start_at, end_at, status, created_at) VALUES (?, ?, ?, ?, ?)",
-                params![req.lead_id, req.start_at, req.end_at, req.status, now_iso()],
+                "INSERT INTO appointments (lead_id, start_at, end_at, status, created_at, appointment_type_id) VALUES (?, ?, ?, ?, ?, ?)",
+                params![req.lead_id, req.start_at, req.end_at, req.status, self.now_iso(), req.appointment_type_id],
             )?;
 
             let appointment_id = self.conn.last_insert_rowid();
+            set_lead_status(
+                self.conn,
+                self.clock,
+                req.lead_id,
+                "booked",
+                "create_appointment",
+            )?;
             self.conn.execute(
-                "UPDATE leads SET status='booked', next_action_at=NULL WHERE id=?",
+                "UPDATE leads SET next_action_at=NULL WHERE id=?",
                 params![req.lead_id],
             )?;
 
@@ -587,6 +1469,7 @@ impl<'a> ActionGateway<'a> {
             Ok(appointment_id) => {
                 let _ = insert_audit(
                     self.conn,
+                    self.clock,
                     action,
                     target_type,
                     target_id,
@@ -600,6 +1483,7 @@ impl<'a> ActionGateway<'a> {
             Err(err) => {
                 let _ = insert_audit(
                     self.conn,
+                    self.clock,
                     action,
                     target_type,
                     target_id,
@@ -613,39 +1497,111 @@ impl<'a> ActionGateway<'a> {
         }
     }
 
-    fn set_opt_out(&self, req: OptOutRequest) -> AppResult<()> {
+    fn cancel_appointment(
+        &self,
+        req: CancelAppointmentRequest,
+    ) -> AppResult<CancelAppointmentResult> {
         let request_json = serde_json::to_value(&req)?;
-        let action = "set_opt_out";
+        let action = "cancel_appointment";
+        let target_id = Some(req.appointment_id.to_string());
+
+        let result = (|| -> AppResult<CancelAppointmentResult> {
+            let reason = req.reason.trim();
+            if reason.is_empty() {
+                return Err(AppError::Validation(
+                    "cancellation reason must be non-empty".to_string(),
+                ));
+            }
 
-        let result = (|| -> AppResult<()> {
-            self.validate_opt_out(&req)?;
-            self.conn.execute(
-                "UPDATE leads SET opted_out=1, status='opted_out', next_action_at=NULL WHERE id=?",
-                params![req.lead_id],
+            // Cancellation, reminder clean-up and waitlist promotion commit together.
+            let tx = self.conn.unchecked_transaction()?;
+            let gateway = ActionGateway::with_clock(&tx, self.clock);
+            let (lead_id, status): (i64, String) = tx
+                .query_row(
+                    "SELECT lead_id, status FROM appointments WHERE id=?",
+                    params![req.appointment_id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?
+                .ok_or_else(|| AppError::NotFound("appointment not found".to_string()))?;
+            if status != "booked" {
+                return Err(AppError::Validation(format!(
+                    "only booked appointments can be cancelled (status is {status})"
+                )));
+            }
+
+            tx.execute(
+                "UPDATE appointments SET status='cancelled', cancelled_at=?, cancel_reason=? WHERE id=?",
+                params![self.now_iso(), reason, req.appointment_id],
             )?;
-            Ok(())
+
+            let remaining_booked: i64 = tx.query_row(
+                "SELECT COUNT(*) FROM appointments WHERE lead_id=? AND status='booked'",
+                params![lead_id],
+                |row| row.get(0),
+            )?;
+            if remaining_booked == 0 {
+                set_lead_status(
+                    &tx,
+                    self.clock,
+                    lead_id,
+                    "awaiting_yes",
+                    "cancel_appointment",
+                )?;
+                tx.execute(
+                    "UPDATE leads SET next_action_at=NULL WHERE id=?",
+                    params![lead_id],
+                )?;
+                let conversation_id: Option<i64> = tx
+                    .query_row(
+                        "SELECT id FROM conversations WHERE lead_id=?",
+                        params![lead_id],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                if let Some(conversation_id) = conversation_id {
+                    reset_conversation_state(&tx, conversation_id)?;
+                }
+            }
+
+            let reminder_jobs_cancelled = tx.execute(
+                "UPDATE scheduled_jobs SET status='cancelled'
+                 WHERE status='pending' AND job_type='appointment_reminder' AND target_id=?",
+                params![req.appointment_id],
+            )? as i64;
+
+            let waitlist_lead_id = gateway.promote_waitlist()?;
+            tx.commit()?;
+
+            Ok(CancelAppointmentResult {
+                appointment_id: req.appointment_id,
+                reminder_jobs_cancelled,
+                waitlist_lead_id,
+            })
         })();
 
         match result {
-            Ok(()) => {
+            Ok(cancelled) => {
                 let _ = insert_audit(
                     self.conn,
+                    self.clock,
                     action,
-                    "lead",
-                    Some(req.lead_id.to_string()),
+                    "appointment",
+                    target_id,
                     request_json,
-                    Some(json!({ "result": "opted_out" })),
+                    Some(serde_json::to_value(&cancelled)?),
                     true,
                     None,
                 );
-                Ok(())
+                Ok(cancelled)
             }
             Err(err) => {
                 let _ = insert_audit(
                     self.conn,
+                    self.clock,
                     action,
-                    "lead",
-                    Some(req.lead_id.to_string()),
+                    "appointment",
+                    target_id,
                     request_json,
                     None,
                     false,
@@ -656,40 +1612,154 @@ impl<'a> ActionGateway<'a> {
         }
     }
 
-    fn schedule_job(&self, req: ScheduleJobRequest) -> AppResult<i64> {
+    /// Offers a freed slot to the oldest waiting lead by queueing their initial follow-up.
+    /// The entry stays `waiting` if the job cannot be scheduled (e.g. kill switch).
+    fn promote_waitlist(&self) -> AppResult<Option<i64>> {
+        let waiter: Option<(i64, i64)> = self
+            .conn
+            .query_row(
+                "SELECT w.id, w.lead_id
+                 FROM waitlist w
+                 JOIN leads l ON l.id = w.lead_id
+                 WHERE w.status='waiting' AND l.opted_out=0
+                 ORDER BY w.requested_at ASC, w.id ASC
+                 LIMIT 1",
+                params![],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let Some((waitlist_id, lead_id)) = waiter else {
+            return Ok(None);
+        };
+
+        let scheduled = self.schedule_job(ScheduleJobRequest {
+            job_type: "initial_follow_up".to_string(),
+            target_id: Some(lead_id),
+            execute_at: self.now_iso(),
+            payload_json: serde_json::to_string(&InitialFollowUpPayload {
+                lead_id,
+                step_number: 1,
+            })?,
+        });
+        if scheduled.is_err() {
+            return Ok(None);
+        }
+
+        self.conn.execute(
+            "UPDATE waitlist SET status='notified' WHERE id=?",
+            params![waitlist_id],
+        )?;
+        Ok(Some(lead_id))
+    }
+
+    fn reschedule_appointment(
+        &self,
+        req: RescheduleAppointmentRequest,
+    ) -> AppResult<RescheduleResult> {
         let request_json = serde_json::to_value(&req)?;
-        let action = "schedule_job";
+        let action = "reschedule_appointment";
+        let target_id = Some(req.appointment_id.to_string());
+
+        let result = (|| -> AppResult<RescheduleResult> {
+            if req.reason.trim().is_empty() {
+                return Err(AppError::Validation(
+                    "reschedule reason must be non-empty".to_string(),
+                ));
+            }
 
-        let result = (|| -> AppResult<i64> {
-            self.validate_schedule_job(&req)?;
-            self.conn.execute(
-                "INSERT INTO scheduled_jobs (job_type, target_id, execute_at, status, payload_json, created_at)
-                 VALUES (?, ?, ?, 'pending', ?, ?)",
-                params![req.job_type, req.target_id, req.execute_at, req.payload_json, now_iso()],
+            // The row keeps its id so reminder and rating jobs stay attached; a rejected
+            // slot rolls back and leaves the original time untouched.
+            let tx = self.conn.unchecked_transaction()?;
+            let gateway = ActionGateway::with_clock(&tx, self.clock);
+
+            let (lead_id, status, appointment_type_id, old_start_at, old_end_at): (
+                i64,
+                String,
+                Option<i64>,
+                String,
+                String,
+            ) = tx
+                .query_row(
+                    "SELECT lead_id, status, appointment_type_id, start_at, end_at
+                     FROM appointments WHERE id=?",
+                    params![req.appointment_id],
+                    |row| {
+                        Ok((
+                            row.get(0)?,
+                            row.get(1)?,
+                            row.get(2)?,
+                            row.get(3)?,
+                            row.get(4)?,
+                        ))
+                    },
+                )
+                .optional()?
+                .ok_or_else(|| AppError::NotFound("appointment not found".to_string()))?;
+            if status != "booked" {
+                return Err(AppError::Validation(format!(
+                    "only booked appointments can be rescheduled (status is {status})"
+                )));
+            }
+
+            gateway.validate_appointment_excluding(
+                &AppointmentRequest {
+                    lead_id,
+                    start_at: req.new_start_at.clone(),
+                    end_at: req.new_end_at.clone(),
+                    status: "booked".to_string(),
+                    appointment_type_id,
+                },
+                Some(req.appointment_id),
             )?;
-            Ok(self.conn.last_insert_rowid())
+            tx.execute(
+                "UPDATE appointments SET start_at=?, end_at=? WHERE id=?",
+                params![req.new_start_at, req.new_end_at, req.appointment_id],
+            )?;
+            tx.execute(
+                "UPDATE scheduled_jobs SET status='cancelled'
+                 WHERE status='pending' AND job_type='appointment_reminder' AND target_id=?",
+                params![req.appointment_id],
+            )?;
+            let reminder_job_ids = schedule_appointment_reminders(
+                &gateway,
+                lead_id,
+                req.appointment_id,
+                &req.new_start_at,
+            )?;
+
+            tx.commit()?;
+            Ok(RescheduleResult {
+                appointment_id: req.appointment_id,
+                reminder_job_ids,
+                old_start_at,
+                old_end_at,
+                new_start_at: req.new_start_at.clone(),
+                new_end_at: req.new_end_at.clone(),
+            })
         })();
 
         match result {
-            Ok(job_id) => {
+            Ok(rescheduled) => {
                 let _ = insert_audit(
                     self.conn,
+                    self.clock,
                     action,
-                    "scheduled_job",
-                    Some(job_id.to_string()),
+                    "appointment",
+                    target_id,
                     request_json,
-                    Some(json!({ "job_id": job_id })),
+                    Some(serde_json::to_value(&rescheduled)?),
                     true,
                     None,
                 );
-                Ok(job_id)
+                Ok(rescheduled)
             }
             Err(err) => {
                 let _ = insert_audit(
                     self.conn,
+                    self.clock,
                     action,
-                    "scheduled_job",
-                    None,
+                    "appointment",
+                    target_id,
                     request_json,
                     None,
                     false,
@@ -700,39 +1770,63 @@ impl<'a> ActionGateway<'a> {
         }
     }
 
-    fn cancel_jobs_on_kill_switch(&self) -> AppResult<usize> {
-        let action = "cancel_jobs_on_kill_switch";
-        let request = json!({ "scope": "all_pending" });
+    /// Staff-authored outbound. Consent, opt-out, business hours and rate limits still apply, but
+    /// the message does not touch conversation state and is not blocked by the kill switch.
+    fn send_manual_message(&self, lead_id: i64, body: &str) -> AppResult<i64> {
+        let request_json = json!({ "lead_id": lead_id, "body": body });
+        let action = "manual_message";
+        let target_type = "lead";
+        let target_id = Some(lead_id.to_string());
 
-        let result = (|| -> AppResult<usize> {
-            let changed = self.conn.execute(
-                "UPDATE scheduled_jobs SET status='cancelled' WHERE status='pending'",
-                params![],
+        let result = (|| -> AppResult<i64> {
+            let body = body.trim();
+            if body.is_empty() {
+                return Err(AppError::Validation(
+                    "message body must be non-empty".to_string(),
+                ));
+            }
+            let conversation = get_conversation_by_lead_id(self.conn, lead_id)?;
+            let message_id = self.create_outbound_message(OutboundRequest {
+                lead_id,
+                conversation_id: conversation.id,
+                body: body.to_string(),
+                automated: false,
+                allow_without_consent: false,
+                allow_opted_out_once: false,
+                allow_after_reply: true,
+                ignore_business_hours: false,
+                rate_limit_exempt: false,
+            })?;
+            self.conn.execute(
+                "UPDATE messages SET is_manual=1 WHERE id=?",
+                params![message_id],
             )?;
-            Ok(changed)
+            Ok(message_id)
         })();
 
         match result {
-            Ok(changed) => {
+            Ok(message_id) => {
                 let _ = insert_audit(
                     self.conn,
+                    self.clock,
                     action,
-                    "scheduled_job",
-                    None,
-                    request,
-                    Some(json!({ "cancelled": changed })),
+                    target_type,
+                    target_id,
+                    request_json,
+                    Some(json!({ "message_id": message_id })),
                     true,
                     None,
                 );
-                Ok(changed)
+                Ok(message_id)
             }
             Err(err) => {
                 let _ = insert_audit(
                     self.conn,
+                    self.clock,
                     action,
-                    "scheduled_job",
-                    None,
-                    request,
+                    target_type,
+                    target_id,
+                    request_json,
                     None,
                     false,
                     Some(err.to_string()),
@@ -742,2142 +1836,16290 @@ impl<'a> ActionGateway<'a> {
         }
     }
 
-    fn check_rate_limits(
-        &self,
-        lead_id: i64,
-        convo: &ConversationRow,
-        allow_after_reply: bool,
-    ) -> AppResult<()> {
-        let per_lead_today: i64 = self.conn.query_row(
-            "SELECT COUNT(*)
-             FROM messages m
-             JOIN conversations c ON c.id = m.conversation_id
-             WHERE c.lead_id = ?
-               AND m.direction = 'OUTBOUND'
-               AND date(m.created_at, 'localtime') = date('now', 'localtime')",
-            params![lead_id],
-            |row| row.get(0),
-        )?;
-        if per_lead_today >= 4 {
-            return Err(AppError::Validation(
-                "rate limit: max 4 outbound per lead/day".to_string(),
-            ));
-        }
+    /// Puts the conversation back to `awaiting_yes` without messaging the lead.
+    fn reset_conversation(&self, lead_id: i64, reason: &str) -> AppResult<()> {
+        let request_json = json!({ "lead_id": lead_id, "reason": reason });
+        let action = "reset_conversation";
+        let target_type = "lead";
+        let target_id = Some(lead_id.to_string());
 
-        let per_location_hour: i64 = self.conn.query_row(
-            "SELECT COUNT(*) FROM messages
-             WHERE direction = 'OUTBOUND'
-               AND datetime(created_at) >= datetime('now', '-1 hour')",
-            params![],
-            |row| row.get(0),
-        )?;
-        if per_location_hour >= 100 {
-            return Err(AppError::Validation(
-                "rate limit: max 100 outbound per location/hour".to_string(),
-            ));
+        let result = (|| -> AppResult<i64> {
+            let reason = reason.trim();
+            if reason.is_empty() {
+                return Err(AppError::Validation(
+                    "reset reason must be non-empty".to_string(),
+                ));
+            }
+            let lead = get_lead(self.conn, lead_id)?;
+            if lead.opted_out {
+                return Err(AppError::Coded(
+                    ErrorCode::OptedOut,
+                    "cannot reset opted-out lead".to_string(),
+                ));
+            }
+            if let Some(appointment_id) =
+                upcoming_appointment_id(self.conn, lead_id, self.clock.now())?
+            {
+                return Err(AppError::Validation(format!(
+                    "lead has an upcoming appointment ({appointment_id}); cancel it before resetting"
+                )));
+            }
+
+            let conversation = get_conversation_by_lead_id(self.conn, lead_id)?;
+            reset_conversation_state(self.conn, conversation.id)?;
+            set_lead_status(
+                self.conn,
+                self.clock,
+                lead_id,
+                "awaiting_yes",
+                "reset_conversation",
+            )?;
+
+            let follow_ups_cancelled = self.conn.execute(
+                "UPDATE scheduled_jobs SET status='cancelled'
+                 WHERE status='pending' AND job_type='initial_follow_up' AND target_id=?",
+                params![lead_id],
+            )? as i64;
+            Ok(follow_ups_cancelled)
+        })();
+
+        match result {
+            Ok(follow_ups_cancelled) => {
+                let _ = insert_audit(
+                    self.conn,
+                    self.clock,
+                    action,
+                    target_type,
+                    target_id,
+                    request_json,
+                    Some(json!({ "follow_up_jobs_cancelled": follow_ups_cancelled })),
+                    true,
+                    None,
+                );
+                Ok(())
+            }
+            Err(err) => {
+                let _ = insert_audit(
+                    self.conn,
+                    self.clock,
+                    action,
+                    target_type,
+                    target_id,
+                    request_json,
+                    None,
+                    false,
+                    Some(err.to_string()),
+                );
+                Err(err)
+            }
         }
+    }
 
-        if let Some(last_outbound_at) = &convo.last_outbound_at {
-            let last_outbound = parse_ts(last_outbound_at)?;
-            let since_last_outbound = Utc::now().signed_duration_since(last_outbound);
-            if since_last_outbound < Duration::hours(2) {
-                let replied_since_last = match (&convo.last_inbound_at, allow_after_reply) {
-                    (Some(last_inbound_at), true) => parse_ts(last_inbound_at)
-                        .map(|inbound| inbound > last_outbound)
-                        .unwrap_or(false),
-                    _ => false,
-                };
+    fn override_conversation_state(&self, req: OverrideConversationStateRequest) -> AppResult<()> {
+        let request_json = serde_json::to_value(&req)?;
+        let action = "override_conversation_state";
+        let target_type = "conversation";
+        let target_id = Some(req.conversation_id.to_string());
 
-                if !replied_since_last {
+        let result = (|| -> AppResult<Option<String>> {
+            if req.reason.trim().is_empty() {
+                return Err(AppError::Validation(
+                    "override reason must be non-empty".to_string(),
+                ));
+            }
+            if !CONVERSATION_STATES.contains(&req.new_state.as_str()) {
+                return Err(AppError::Validation(format!(
+                    "unknown conversation state: {}",
+                    req.new_state
+                )));
+            }
+            serde_json::from_str::<Value>(&req.state_json).map_err(|err| {
+                AppError::Validation(format!("state_json is not valid JSON: {err}"))
+            })?;
+            if req.new_state == "awaiting_time_choice" {
+                let state: ConversationState =
+                    serde_json::from_str(&req.state_json).map_err(|err| {
+                        AppError::Validation(format!(
+                            "state_json is not a conversation state: {err}"
+                        ))
+                    })?;
+                if state.offered_slots.len() != 2 {
                     return Err(AppError::Validation(
-                        "rate limit: minimum 2 hours between outbound unless lead just replied"
-                            .to_string(),
+                        "awaiting_time_choice requires exactly 2 offered_slots".to_string(),
                     ));
                 }
+                for slot in &state.offered_slots {
+                    parse_ts(&slot.start_at)?;
+                    parse_ts(&slot.end_at)?;
+                }
             }
-        }
 
-        Ok(())
-    }
-}
+            let (lead_id, previous_state): (i64, String) = self
+                .conn
+                .query_row(
+                    "SELECT lead_id, state FROM conversations WHERE id=?",
+                    params![req.conversation_id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?
+                .ok_or_else(|| AppError::NotFound("conversation not found".to_string()))?;
+            if get_lead(self.conn, lead_id)?.opted_out {
+                return Err(AppError::Coded(
+                    ErrorCode::OptedOut,
+                    "cannot override conversation of opted-out lead".to_string(),
+                ));
+            }
 
-#[tauri::command]
-fn create_lead(
-    state: State<AppState>,
-    app: AppHandle,
-    input: LeadCreateInput,
-) -> Result<LeadCreateResult, String> {
-    let result = retry_db(|| {
-        let conn = open_conn(&state)?;
-        let location = get_location(&conn)?;
-        let now = now_iso();
+            self.conn.execute(
+                "UPDATE conversations SET state=?, state_json=?, repair_attempts=0 WHERE id=?",
+                params![req.new_state, req.state_json, req.conversation_id],
+            )?;
+            set_lead_status(
+                self.conn,
+                self.clock,
+                lead_id,
+                &req.new_state,
+                "override_conversation_state",
+            )?;
+            Ok(Some(previous_state))
+        })();
 
-        let phone = input.phone_e164.trim().to_string();
-        if phone.is_empty() || !phone.starts_with('+') {
-            return Err(AppError::Validation(
-                "phone_e164 must be non-empty and start with '+'".to_string(),
-            ));
+        match result {
+            Ok(previous_state) => {
+                let _ = insert_audit(
+                    self.conn,
+                    self.clock,
+                    action,
+                    target_type,
+                    target_id,
+                    request_json,
+                    Some(json!({ "previous_state": previous_state })),
+                    true,
+                    None,
+                );
+                Ok(())
+            }
+            Err(err) => {
+                let _ = insert_audit(
+                    self.conn,
+                    self.clock,
+                    action,
+                    target_type,
+                    target_id,
+                    request_json,
+                    None,
+                    false,
+                    Some(err.to_string()),
+                );
+                Err(err)
+            }
         }
+    }
 
-        let duplicate_id: Option<i64> = conn
-            .query_row(
-                "SELECT id FROM leads
-                 WHERE phone_e164 = ?
-                   AND datetime(created_at) >= datetime('now', '-30 days')
-                 ORDER BY created_at DESC
-                 LIMIT 1",
-                params![phone],
-                |row| row.get(0),
-            )
-            .optional()?;
+    fn mark_appointment_no_show(&self, appointment_id: i64) -> AppResult<()> {
+        let request_json = json!({ "appointment_id": appointment_id });
+        let action = "mark_appointment_no_show";
+        let target_id = Some(appointment_id.to_string());
 
-        if let Some(existing) = duplicate_id {
-            let note = "Duplicate lead in last 30 days; automation not restarted. Note added to audit log.";
-            let _ = insert_audit(
-                &conn,
-                "duplicate_lead_detected",
-                "lead",
-                Some(existing.to_string()),
-                json!({
-                    "phone_e164": phone,
-                    "source": input.source,
-                    "attempted_at": now
-                }),
-                Some(json!({ "note": note })),
-                true,
-                None,
-            );
+        // The status changes and the follow-up commit together; if the follow-up is refused
+        // (e.g. kill switch) the appointment stays booked.
+        let result = (|| -> AppResult<i64> {
+            let tx = self.conn.unchecked_transaction()?;
+            let gateway = ActionGateway::with_clock(&tx, self.clock);
+            let (lead_id, status, end_at): (i64, String, String) = tx
+                .query_row(
+                    "SELECT lead_id, status, end_at FROM appointments WHERE id=?",
+                    params![appointment_id],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .optional()?
+                .ok_or_else(|| AppError::NotFound("appointment not found".to_string()))?;
+            if status != "booked" {
+                return Err(AppError::Validation(format!(
+                    "only booked appointments can be marked as no-show (status is {status})"
+                )));
+            }
 
-            return Ok(LeadCreateResult {
-                created: false,
-                lead_id: existing,
-                duplicate_of: Some(existing),
-                note: Some(note.to_string()),
-            });
-        }
+            tx.execute(
+                "UPDATE appointments SET status='no_show' WHERE id=?",
+                params![appointment_id],
+            )?;
+            set_lead_status(
+                &tx,
+                self.clock,
+                lead_id,
+                "no_show",
+                "mark_appointment_no_show",
+            )?;
 
-        conn.execute(
-            "INSERT INTO leads (
-                phone_e164, first_name, last_name, consent, consent_at, consent_source,
-                status, opted_out, needs_staff_attention, created_at
-             ) VALUES (?, ?, ?, ?, ?, ?, 'awaiting_yes', 0, 0, ?)",
-            params![
-                phone,
-                null_if_empty(&input.first_name),
-                null_if_empty(&input.last_name),
-                bool_to_i64(input.consent),
-                input.consent_at,
-                null_if_empty(&input.source),
-                now
-            ],
-        )?;
+            let follow_up_at = parse_ts(&end_at)? + Duration::minutes(30);
+            let follow_up_job_id = gateway.schedule_job(ScheduleJobRequest {
+                job_type: "no_show_follow_up".to_string(),
+                target_id: Some(lead_id),
+                execute_at: follow_up_at.to_rfc3339(),
+                payload_json: serde_json::to_string(&NoShowFollowUpPayload {
+                    lead_id,
+                    appointment_id,
+                })?,
+            })?;
+            tx.commit()?;
+            Ok(follow_up_job_id)
+        })();
 
-        let lead_id = conn.last_insert_rowid();
+        match result {
+            Ok(follow_up_job_id) => {
+                let _ = insert_audit(
+                    self.conn,
+                    self.clock,
+                    action,
+                    "appointment",
+                    target_id,
+                    request_json,
+                    Some(json!({ "follow_up_job_id": follow_up_job_id })),
+                    true,
+                    None,
+                );
+                Ok(())
+            }
+            Err(err) => {
+                let _ = insert_audit(
+                    self.conn,
+                    self.clock,
+                    action,
+                    "appointment",
+                    target_id,
+                    request_json,
+                    None,
+                    false,
+                    Some(err.to_string()),
+                );
+                Err(err)
+            }
+        }
+    }
 
-        conn.execute(
-            "INSERT INTO conversations (lead_id, state, state_json, repair_attempts) VALUES (?, 'awaiting_yes', ?, 0)",
-            params![lead_id, serde_json::to_string(&ConversationState::default())?],
-        )?;
+    fn mark_appointment_completed(&self, appointment_id: i64) -> AppResult<AppointmentCompletion> {
+        let request_json = json!({ "appointment_id": appointment_id });
+        let action = "mark_appointment_completed";
+        let target_id = Some(appointment_id.to_string());
+
+        let result = (|| -> AppResult<AppointmentCompletion> {
+            let (lead_id, status, end_at): (i64, String, String) = self
+                .conn
+                .query_row(
+                    "SELECT lead_id, status, end_at FROM appointments WHERE id=?",
+                    params![appointment_id],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .optional()?
+                .ok_or_else(|| AppError::NotFound("appointment not found".to_string()))?;
+            if status != "booked" {
+                return Err(AppError::Validation(format!(
+                    "only booked appointments can be marked as completed (status is {status})"
+                )));
+            }
 
-        let mut note: Option<String> = None;
-        if input.consent {
-            let gateway = ActionGateway::new(&conn, &location);
-            let execute_at_utc = if is_business_open(&location, Utc::now())? {
-                Utc::now() + Duration::seconds(30)
-            } else {
-                next_open_time(&location, Utc::now())?
-            };
+            self.conn.execute(
+                "UPDATE appointments SET status='completed' WHERE id=?",
+                params![appointment_id],
+            )?;
 
-            let schedule = gateway.schedule_job(ScheduleJobRequest {
-                job_type: "initial_follow_up".to_string(),
+            let follow_up_at = parse_ts(&end_at)? + Duration::minutes(30);
+            let follow_up = self.schedule_job(ScheduleJobRequest {
+                job_type: "post_appointment_follow_up".to_string(),
                 target_id: Some(lead_id),
-                execute_at: execute_at_utc.to_rfc3339(),
-                payload_json: serde_json::to_string(&InitialFollowUpPayload { lead_id })?,
+                execute_at: follow_up_at.to_rfc3339(),
+                payload_json: serde_json::to_string(&PostAppointmentFollowUpPayload {
+                    lead_id,
+                    appointment_id,
+                })?,
             });
+            Ok(AppointmentCompletion {
+                appointment_id,
+                follow_up_job_id: follow_up.as_ref().ok().copied(),
+                follow_up_error: follow_up.err().map(|err| err.to_string()),
+            })
+        })();
 
-            match schedule {
-                Ok(_) => {
-                    conn.execute(
-                        "UPDATE leads SET next_action_at=? WHERE id=?",
-                        params![execute_at_utc.to_rfc3339(), lead_id],
-                    )?;
-                }
-                Err(err) => {
-                    note = Some(format!(
-                        "Lead created, but auto-follow-up not scheduled: {err}"
-                    ));
-                }
+        match result {
+            Ok(completion) => {
+                let _ = insert_audit(
+                    self.conn,
+                    self.clock,
+                    action,
+                    "appointment",
+                    target_id,
+                    request_json,
+                    Some(serde_json::to_value(&completion)?),
+                    true,
+                    None,
+                );
+                Ok(completion)
+            }
+            Err(err) => {
+                let _ = insert_audit(
+                    self.conn,
+                    self.clock,
+                    action,
+                    "appointment",
+                    target_id,
+                    request_json,
+                    None,
+                    false,
+                    Some(err.to_string()),
+                );
+                Err(err)
             }
         }
+    }
 
-        Ok(LeadCreateResult {
-            created: true,
-            lead_id,
-            duplicate_of: None,
-            note,
-        })
-    });
-
-    map_cmd_result(result, "create_lead", &app)
-}
+    fn set_opt_out(&self, req: OptOutRequest) -> AppResult<()> {
+        let request_json = serde_json::to_value(&req)?;
+        let action = "set_opt_out";
 
-#[tauri::command]
-fn list_leads(state: State<AppState>, app: AppHandle) -> Result<Vec<LeadSummary>, String> {
-    let result = retry_db(|| {
-        let conn = open_conn(&state)?;
-        let mut stmt = conn.prepare(
-            "SELECT id, phone_e164, first_name, last_name, status, consent, opted_out, needs_staff_attention, created_at
-             FROM leads
-             ORDER BY datetime(created_at) DESC",
-        )?;
+        let result = (|| -> AppResult<()> {
+            self.validate_opt_out(&req)?;
+            set_lead_status(
+                self.conn,
+                self.clock,
+                req.lead_id,
+                "opted_out",
+                "set_opt_out",
+            )?;
+            self.conn.execute(
+                "UPDATE leads SET opted_out=1, next_action_at=NULL WHERE id=?",
+                params![req.lead_id],
+            )?;
+            Ok(())
+        })();
 
-        let rows = stmt.query_map(params![], |row| {
-            Ok(LeadSummary {
-                id: row.get(0)?,
-                phone_e164: row.get(1)?,
-                first_name: row.get(2)?,
-                last_name: row.get(3)?,
-                status: row.get(4)?,
-                consent: i64_to_bool(row.get(5)?),
-                opted_out: i64_to_bool(row.get(6)?),
-                needs_staff_attention: i64_to_bool(row.get(7)?),
-                created_at: row.get(8)?,
+        match result {
+            Ok(()) => {
+                let _ = insert_audit(
+                    self.conn,
+                    self.clock,
+                    action,
+                    "lead",
+                    Some(req.lead_id.to_string()),
+                    request_json,
+                    Some(json!({ "result": "opted_out" })),
+                    true,
+                    None,
+                );
+                Ok(())
+            }
+            Err(err) => {
+                let _ = insert_audit(
+                    self.conn,
+                    self.clock,
+                    action,
+                    "lead",
+                    Some(req.lead_id.to_string()),
+                    request_json,
+                    None,
+                    false,
+                    Some(err.to_string()),
+                );
+                Err(err)
+            }
+        }
+    }
+
+    fn schedule_job(&self, req: ScheduleJobRequest) -> AppResult<i64> {
+        let request_json = serde_json::to_value(&req)?;
+        let action = "schedule_job";
+
+        let result = (|| -> AppResult<i64> {
+            self.validate_schedule_job(&req)?;
+            self.conn.execute(
+                "INSERT INTO scheduled_jobs (job_type, target_id, execute_at, status, payload_json, created_at)
+                 VALUES (?, ?, ?, 'pending', ?, ?)",
+                params![req.job_type, req.target_id, req.execute_at, req.payload_json, self.now_iso()],
+            )?;
+            Ok(self.conn.last_insert_rowid())
+        })();
+
+        match result {
+            Ok(job_id) => {
+                let _ = insert_audit(
+                    self.conn,
+                    self.clock,
+                    action,
+                    "scheduled_job",
+                    Some(job_id.to_string()),
+                    request_json,
+                    Some(json!({ "job_id": job_id })),
+                    true,
+                    None,
+                );
+                Ok(job_id)
+            }
+            Err(err) => {
+                let _ = insert_audit(
+                    self.conn,
+                    self.clock,
+                    action,
+                    "scheduled_job",
+                    None,
+                    request_json,
+                    None,
+                    false,
+                    Some(err.to_string()),
+                );
+                Err(err)
+            }
+        }
+    }
+
+    fn cancel_scheduled_job(&self, job_id: i64, reason: &str) -> AppResult<CancelJobResult> {
+        let request_json = json!({ "job_id": job_id, "reason": reason });
+        let action = "cancel_scheduled_job";
+        let target_id = Some(job_id.to_string());
+
+        let result = (|| -> AppResult<CancelJobResult> {
+            if reason.trim().is_empty() {
+                return Err(AppError::Validation(
+                    "cancellation reason must be non-empty".to_string(),
+                ));
+            }
+            let previous_status: String = self
+                .conn
+                .query_row(
+                    "SELECT status FROM scheduled_jobs WHERE id=?",
+                    params![job_id],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .ok_or_else(|| AppError::NotFound("job not found".to_string()))?;
+            let changed = self.conn.execute(
+                "UPDATE scheduled_jobs SET status='cancelled' WHERE id=? AND status='pending'",
+                params![job_id],
+            )?;
+            if changed == 0 {
+                return Err(AppError::Validation("job is not pending".to_string()));
+            }
+            Ok(CancelJobResult {
+                job_id,
+                previous_status,
             })
-        })?;
+        })();
 
-        rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
-    });
+        match result {
+            Ok(cancelled) => {
+                let _ = insert_audit(
+                    self.conn,
+                    self.clock,
+                    action,
+                    "scheduled_job",
+                    target_id,
+                    request_json,
+                    Some(serde_json::to_value(&cancelled)?),
+                    true,
+                    None,
+                );
+                Ok(cancelled)
+            }
+            Err(err) => {
+                let _ = insert_audit(
+                    self.conn,
+                    self.clock,
+                    action,
+                    "scheduled_job",
+                    target_id,
+                    request_json,
+                    None,
+                    false,
+                    Some(err.to_string()),
+                );
+                Err(err)
+            }
+        }
+    }
 
-    map_cmd_result(result, "list_leads", &app)
+    /// Retries a failed job 30 seconds from now; see `retry_failed_job`.
+    fn requeue_failed_job(&self, job_id: i64) -> AppResult<ScheduledJobView> {
+        let execute_at = (self.clock.now() + Duration::seconds(30)).to_rfc3339();
+        let new_job_id = self.retry_failed_job(job_id, Some(&execute_at))?;
+        get_scheduled_job(self.conn, new_job_id)
+    }
+
+    /// Copies a failed job into a fresh pending row (default: a minute from now) and cancels the
+    /// original, so its failure history stays intact.
+    fn retry_failed_job(&self, job_id: i64, execute_at: Option<&str>) -> AppResult<i64> {
+        let request_json = json!({ "job_id": job_id, "execute_at": execute_at });
+        let action = "retry_failed_job";
+        let target_id = Some(job_id.to_string());
+
+        let result = (|| -> AppResult<i64> {
+            let tx = self.conn.unchecked_transaction()?;
+            let gateway = ActionGateway::with_clock(&tx, self.clock);
+            let job = get_scheduled_job(&tx, job_id)?;
+            if job.status != "failed" {
+                return Err(AppError::Validation("job is not failed".to_string()));
+            }
+            let execute_at = match execute_at {
+                Some(value) => parse_ts(value.trim())?,
+                None => self.clock.now() + Duration::seconds(60),
+            };
+            let new_job_id = gateway.schedule_job(ScheduleJobRequest {
+                job_type: job.job_type,
+                target_id: job.target_id,
+                execute_at: execute_at.to_rfc3339(),
+                payload_json: job.payload_json,
+            })?;
+            tx.execute(
+                "UPDATE scheduled_jobs SET status='cancelled' WHERE id=?",
+                params![job_id],
+            )?;
+            tx.commit()?;
+            Ok(new_job_id)
+        })();
+
+        match result {
+            Ok(new_job_id) => {
+                let _ = insert_audit(
+                    self.conn,
+                    self.clock,
+                    action,
+                    "scheduled_job",
+                    target_id,
+                    request_json,
+                    Some(json!({ "new_job_id": new_job_id })),
+                    true,
+                    None,
+                );
+                Ok(new_job_id)
+            }
+            Err(err) => {
+                let _ = insert_audit(
+                    self.conn,
+                    self.clock,
+                    action,
+                    "scheduled_job",
+                    target_id,
+                    request_json,
+                    None,
+                    false,
+                    Some(err.to_string()),
+                );
+                Err(err)
+            }
+        }
+    }
+
+    fn cancel_jobs_on_kill_switch(&self) -> AppResult<usize> {
+        let action = "cancel_jobs_on_kill_switch";
+        let request = json!({ "scope": "all_pending" });
+
+        let result = (|| -> AppResult<usize> {
+            let changed = self.conn.execute(
+                "UPDATE scheduled_jobs SET status='cancelled' WHERE status='pending'",
+                params![],
+            )?;
+            Ok(changed)
+        })();
+
+        match result {
+            Ok(changed) => {
+                let _ = insert_audit(
+                    self.conn,
+                    self.clock,
+                    action,
+                    "scheduled_job",
+                    None,
+                    request,
+                    Some(json!({ "cancelled": changed })),
+                    true,
+                    None,
+                );
+                Ok(changed)
+            }
+            Err(err) => {
+                let _ = insert_audit(
+                    self.conn,
+                    self.clock,
+                    action,
+                    "scheduled_job",
+                    None,
+                    request,
+                    None,
+                    false,
+                    Some(err.to_string()),
+                );
+                Err(err)
+            }
+        }
+    }
+
+    fn check_rate_limits(
+        &self,
+        location: &Location,
+        lead_id: i64,
+        convo: &ConversationRow,
+        allow_after_reply: bool,
+    ) -> AppResult<()> {
+        let limits = load_rate_limit_settings(self.conn)?;
+        let per_lead_today =
+            count_lead_outbound_today(self.conn, location, lead_id, self.clock.now())?;
+        if per_lead_today >= limits.max_outbound_per_lead_per_day {
+            return Err(AppError::Coded(
+                ErrorCode::RateLimit,
+                format!(
+                    "rate limit: max {} outbound per lead/day",
+                    limits.max_outbound_per_lead_per_day
+                ),
+            ));
+        }
+
+        let per_location_hour =
+            count_location_outbound_last_hour(self.conn, location.id, self.clock.now())?;
+        if per_location_hour >= limits.max_outbound_per_location_per_hour {
+            return Err(AppError::Coded(
+                ErrorCode::RateLimit,
+                format!(
+                    "rate limit: max {} outbound per location/hour",
+                    limits.max_outbound_per_location_per_hour
+                ),
+            ));
+        }
+
+        if let Some(last_outbound_at) = &convo.last_outbound_at {
+            let last_outbound = parse_ts(last_outbound_at)?;
+            let since_last_outbound = self.clock.now().signed_duration_since(last_outbound);
+            if since_last_outbound < Duration::hours(2) {
+                let replied_since_last = match (&convo.last_inbound_at, allow_after_reply) {
+                    (Some(last_inbound_at), true) => parse_ts(last_inbound_at)
+                        .map(|inbound| inbound > last_outbound)
+                        .unwrap_or(false),
+                    _ => false,
+                };
+
+                if !replied_since_last {
+                    return Err(AppError::Coded(
+                        ErrorCode::RateLimit,
+                        "rate limit: minimum 2 hours between outbound unless lead just replied"
+                            .to_string(),
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[tauri::command]
-fn search_leads(
+#[tracing::instrument(skip_all)]
+fn normalize_phone(
+    app: AppHandle,
+    raw: String,
+    default_country_code: Option<String>,
+) -> Result<String, String> {
+    let result = normalize_phone_e164(&raw, default_country_code.as_deref().unwrap_or("+1"));
+    map_cmd_result(result, "normalize_phone", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn create_lead(
     state: State<AppState>,
     app: AppHandle,
-    query: String,
-) -> Result<Vec<LeadSummary>, String> {
-    let query_trimmed = query.trim();
-    let wildcard = format!("%{}%", query_trimmed.to_lowercase());
+    input: LeadCreateInput,
+) -> Result<LeadCreateResult, String> {
     let result = retry_db(|| {
         let conn = open_conn(&state)?;
-        let mut stmt = conn.prepare(
-            "SELECT id, phone_e164, first_name, last_name, status, consent, opted_out, needs_staff_attention, created_at
-             FROM leads
-             WHERE LOWER(phone_e164) LIKE ?1
-                OR LOWER(COALESCE(first_name, '')) LIKE ?1
-                OR LOWER(COALESCE(last_name, '')) LIKE ?1
-             ORDER BY datetime(created_at) DESC",
-        )?;
-        let rows = stmt.query_map(params![wildcard.clone()], |row| {
-            Ok(LeadSummary {
-                id: row.get(0)?,
-                phone_e164: row.get(1)?,
-                first_name: row.get(2)?,
-                last_name: row.get(3)?,
-                status: row.get(4)?,
-                consent: i64_to_bool(row.get(5)?),
-                opted_out: i64_to_bool(row.get(6)?),
-                needs_staff_attention: i64_to_bool(row.get(7)?),
-                created_at: row.get(8)?,
-            })
-        })?;
-        rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+        let location = get_location(&conn)?;
+        create_lead_with_conn(&conn, state.clock.as_ref(), &location, &input)
     });
-    map_cmd_result(result, "search_leads", &app)
+
+    map_cmd_result(result, "create_lead", &app)
 }
 
 #[tauri::command]
-fn list_agent_queue(state: State<AppState>, app: AppHandle) -> Result<Vec<LeadSummary>, String> {
+#[tracing::instrument(skip_all, fields(count = inputs.len()))]
+fn bulk_create_leads(
+    state: State<AppState>,
+    app: AppHandle,
+    inputs: Vec<LeadCreateInput>,
+) -> Result<BulkCreateResult, String> {
+    let result = open_conn(&state)
+        .and_then(|conn| bulk_create_leads_with_conn(&conn, state.clock.as_ref(), &inputs));
+
+    map_cmd_result(result, "bulk_create_leads", &app)
+}
+
+/// Runs every row through `create_lead_with_conn` in its own transaction, so a bad row is
+/// reported by index without rolling back the rows around it.
+fn bulk_create_leads_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    inputs: &[LeadCreateInput],
+) -> AppResult<BulkCreateResult> {
+    if inputs.len() > 500 {
+        return Err(AppError::Validation(
+            "bulk imports are limited to 500 leads".to_string(),
+        ));
+    }
+    if is_kill_switch_enabled(conn)? {
+        return Err(AppError::Coded(
+            ErrorCode::KillSwitch,
+            "kill switch is enabled; bulk import blocked".to_string(),
+        ));
+    }
+
+    let location = get_location(conn)?;
+    let mut result = BulkCreateResult {
+        created: Vec::new(),
+        duplicates: Vec::new(),
+        errors: Vec::new(),
+        jobs_scheduled: 0,
+    };
+    for (index, input) in inputs.iter().enumerate() {
+        let row = retry_db(|| {
+            let tx = conn.unchecked_transaction()?;
+            let created = create_lead_with_conn(&tx, clock, &location, input)?;
+            tx.commit()?;
+            Ok(created)
+        });
+        match row {
+            Ok(created) if created.created => {
+                let scheduled: i64 = conn.query_row(
+                    "SELECT COUNT(*) FROM scheduled_jobs
+                     WHERE job_type='initial_follow_up' AND status='pending' AND target_id=?",
+                    params![created.lead_id],
+                    |row| row.get(0),
+                )?;
+                result.jobs_scheduled += scheduled;
+                result.created.push(created);
+            }
+            Ok(blocked) if blocked.blocked => result.errors.push(BulkCreateError {
+                index,
+                message: "phone is on blocklist".to_string(),
+            }),
+            Ok(duplicate) => result.duplicates.push(duplicate),
+            Err(err) => result.errors.push(BulkCreateError {
+                index,
+                message: err.to_string(),
+            }),
+        }
+    }
+    Ok(result)
+}
+
+fn create_lead_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    location: &Location,
+    input: &LeadCreateInput,
+) -> AppResult<LeadCreateResult> {
+    let now = clock.now().to_rfc3339();
+
+    let phone = normalize_phone_e164(&input.phone_e164, "+1")?;
+
+    if is_phone_blocklisted(conn, &phone)? {
+        let note = "Phone is on the blocklist; lead not created.";
+        let _ = insert_audit(
+            conn,
+            clock,
+            "blocked_lead_rejected",
+            "lead",
+            None,
+            json!({ "phone_e164": phone, "source": input.source }),
+            Some(json!({ "note": note })),
+            true,
+            None,
+        );
+        return Ok(LeadCreateResult {
+            created: false,
+            lead_id: 0,
+            duplicate_of: None,
+            note: Some(note.to_string()),
+            blocked: true,
+        });
+    }
+
+    let duplicate_id: Option<i64> = conn
+        .query_row(
+            "SELECT id FROM leads
+             WHERE phone_e164 = ?
+               AND datetime(created_at) >= datetime(?)
+             ORDER BY created_at DESC
+             LIMIT 1",
+            params![phone, (clock.now() - Duration::days(30)).to_rfc3339()],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    if let Some(existing) = duplicate_id {
+        let note =
+            "Duplicate lead in last 30 days; automation not restarted. Note added to audit log.";
+        let _ = insert_audit(
+            conn,
+            clock,
+            "duplicate_lead_detected",
+            "lead",
+            Some(existing.to_string()),
+            json!({
+                "phone_e164": phone,
+                "source": input.source,
+                "attempted_at": now
+            }),
+            Some(json!({ "note": note })),
+            true,
+            None,
+        );
+
+        return Ok(LeadCreateResult {
+            created: false,
+            lead_id: existing,
+            duplicate_of: Some(existing),
+            note: Some(note.to_string()),
+            blocked: false,
+        });
+    }
+
+    conn.execute(
+        "INSERT INTO leads (
+            phone_e164, first_name, last_name, consent, consent_at, consent_source,
+            status, opted_out, needs_staff_attention, created_at, location_id
+         ) VALUES (?, ?, ?, ?, ?, ?, 'awaiting_yes', 0, 0, ?, ?)",
+        params![
+            phone,
+            null_if_empty(&input.first_name),
+            null_if_empty(&input.last_name),
+            bool_to_i64(input.consent),
+            input.consent_at,
+            null_if_empty(&input.source),
+            now,
+            location.id
+        ],
+    )?;
+
+    let lead_id = conn.last_insert_rowid();
+
+    conn.execute(
+        "INSERT INTO conversations (lead_id, state, state_json, repair_attempts) VALUES (?, 'awaiting_yes', ?, 0)",
+        params![lead_id, serde_json::to_string(&ConversationState::default())?],
+    )?;
+
+    let mut note: Option<String> = None;
+    if input.consent {
+        if let Err(err) =
+            schedule_initial_follow_up(&ActionGateway::with_clock(conn, clock), lead_id)
+        {
+            note = Some(format!(
+                "Lead created, but auto-follow-up not scheduled: {err}"
+            ));
+        }
+    }
+
+    Ok(LeadCreateResult {
+        created: true,
+        lead_id,
+        duplicate_of: None,
+        note,
+        blocked: false,
+    })
+}
+
+/// Queues the first outbound for `lead_id` (soon if open, otherwise at the next open time).
+fn schedule_initial_follow_up(gateway: &ActionGateway, lead_id: i64) -> AppResult<()> {
+    let conn = gateway.conn;
+    let location = get_lead_location(conn, lead_id)?;
+    let now = gateway.clock.now();
+    let execute_at_utc = if is_business_open(&location, now)? {
+        now + Duration::seconds(30)
+    } else {
+        next_open_time(conn, &location, now)?
+    };
+
+    gateway.schedule_job(ScheduleJobRequest {
+        job_type: "initial_follow_up".to_string(),
+        target_id: Some(lead_id),
+        execute_at: execute_at_utc.to_rfc3339(),
+        payload_json: serde_json::to_string(&InitialFollowUpPayload {
+            lead_id,
+            step_number: 1,
+        })?,
+    })?;
+    conn.execute(
+        "UPDATE leads SET next_action_at=? WHERE id=?",
+        params![execute_at_utc.to_rfc3339(), lead_id],
+    )?;
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id, consent = consent))]
+fn update_lead_consent(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+    consent: bool,
+    consent_at: String,
+    consent_source: String,
+) -> Result<LeadConsentResult, String> {
     let result = retry_db(|| {
         let conn = open_conn(&state)?;
-        let mut stmt = conn.prepare(
-            "SELECT l.id, l.phone_e164, l.first_name, l.last_name, l.status, l.consent, l.opted_out, l.needs_staff_attention, l.created_at
-             FROM leads l
-             JOIN conversations c ON c.lead_id = l.id
-             WHERE l.opted_out = 0
-               AND l.needs_staff_attention = 0
-               AND l.consent = 1
-               AND (
-                    (l.next_action_at IS NOT NULL AND datetime(l.next_action_at) <= datetime('now'))
-                    OR (
-                        c.last_inbound_at IS NOT NULL
-                        AND datetime(c.last_inbound_at) >= datetime('now', '-3 days')
-                        AND (
-                            c.last_outbound_at IS NULL
-                            OR datetime(c.last_inbound_at) > datetime(c.last_outbound_at)
-                        )
-                    )
-               )
-             ORDER BY datetime(COALESCE(l.next_action_at, c.last_inbound_at, l.created_at)) ASC",
+        update_lead_consent_with_conn(
+            &conn,
+            state.clock.as_ref(),
+            lead_id,
+            consent,
+            &consent_at,
+            &consent_source,
+        )
+    });
+
+    map_cmd_result(result, "update_lead_consent", &app)
+}
+
+/// Records consent after intake. Granting it queues an `initial_follow_up` if none is pending;
+/// withdrawing it cancels pending ones.
+fn update_lead_consent_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    lead_id: i64,
+    consent: bool,
+    consent_at: &str,
+    consent_source: &str,
+) -> AppResult<LeadConsentResult> {
+    let request_json = json!({
+        "consent": consent,
+        "consent_at": consent_at,
+        "consent_source": consent_source
+    });
+
+    // The consent change and its follow-up scheduling commit together, so a refused
+    // follow-up (e.g. kill switch) leaves the lead's consent as it was.
+    let result = (|| -> AppResult<(bool, LeadConsentResult)> {
+        let consent_at = parse_ts(consent_at.trim())?.to_rfc3339();
+        let lead = get_lead_summary(conn, lead_id)?;
+
+        let tx = conn.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE leads SET consent=?, consent_at=?, consent_source=? WHERE id=?",
+            params![
+                bool_to_i64(consent),
+                consent_at,
+                null_if_empty(consent_source),
+                lead_id
+            ],
         )?;
+        let result = apply_consent_follow_ups(&tx, clock, &lead, consent)?;
+        tx.commit()?;
+        Ok((lead.consent, result))
+    })();
 
-        let rows = stmt.query_map(params![], |row| {
-            Ok(LeadSummary {
-                id: row.get(0)?,
-                phone_e164: row.get(1)?,
-                first_name: row.get(2)?,
-                last_name: row.get(3)?,
-                status: row.get(4)?,
-                consent: i64_to_bool(row.get(5)?),
-                opted_out: i64_to_bool(row.get(6)?),
-                needs_staff_attention: i64_to_bool(row.get(7)?),
-                created_at: row.get(8)?,
-            })
-        })?;
+    match result {
+        Ok((previous_consent, result)) => {
+            let mut request_json = request_json;
+            request_json["previous_consent"] = json!(previous_consent);
+            let _ = insert_audit(
+                conn,
+                clock,
+                "update_lead_consent",
+                "lead",
+                Some(lead_id.to_string()),
+                request_json,
+                Some(serde_json::to_value(&result)?),
+                true,
+                None,
+            );
+            Ok(result)
+        }
+        Err(err) => {
+            let _ = insert_audit(
+                conn,
+                clock,
+                "update_lead_consent",
+                "lead",
+                Some(lead_id.to_string()),
+                request_json,
+                None,
+                false,
+                Some(err.to_string()),
+            );
+            Err(err)
+        }
+    }
+}
 
-        rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id, consent = consent))]
+fn set_lead_consent(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+    consent: bool,
+    consent_at: String,
+    consent_source: String,
+    collected_by: String,
+) -> Result<LeadConsentResult, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        set_lead_consent_with_conn(
+            &conn,
+            state.clock.as_ref(),
+            lead_id,
+            consent,
+            &consent_at,
+            &consent_source,
+            &collected_by,
+        )
+    });
+
+    map_cmd_result(result, "set_lead_consent", &app)
+}
+
+/// Records a consent decision together with who collected it and how.
+fn set_lead_consent_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    lead_id: i64,
+    consent: bool,
+    consent_at: &str,
+    consent_source: &str,
+    collected_by: &str,
+) -> AppResult<LeadConsentResult> {
+    let consent_source = consent_source.trim();
+    let collected_by = collected_by.trim();
+    let mut request_json = json!({
+        "consent": consent,
+        "consent_at": consent_at.trim(),
+        "consent_source": consent_source,
+        "collected_by": collected_by
+    });
+
+    let result = (|| -> AppResult<(Value, String, LeadConsentResult)> {
+        if consent_source.is_empty() {
+            return Err(AppError::Validation(
+                "consent_source is required".to_string(),
+            ));
+        }
+        if collected_by.is_empty() {
+            return Err(AppError::Validation("collected_by is required".to_string()));
+        }
+        let consent_at = parse_ts(consent_at.trim())?.to_rfc3339();
+        let lead = get_lead_summary(conn, lead_id)?;
+        let (previous_consent_at, previous_consent_source): (Option<String>, Option<String>) = conn
+            .query_row(
+                "SELECT consent_at, consent_source FROM leads WHERE id=?",
+                params![lead_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+
+        let tx = conn.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE leads SET consent=?, consent_at=?, consent_source=? WHERE id=?",
+            params![bool_to_i64(consent), consent_at, consent_source, lead_id],
+        )?;
+        let result = apply_consent_follow_ups(&tx, clock, &lead, consent)?;
+        tx.commit()?;
+        let previous = json!({
+            "consent": lead.consent,
+            "consent_at": previous_consent_at,
+            "consent_source": previous_consent_source
+        });
+        Ok((previous, consent_at, result))
+    })();
+
+    match result {
+        Ok((previous, consent_at, result)) => {
+            request_json["previous"] = previous;
+            request_json["consent_at"] = json!(consent_at);
+            let _ = insert_audit(
+                conn,
+                clock,
+                "set_lead_consent",
+                "lead",
+                Some(lead_id.to_string()),
+                request_json,
+                Some(serde_json::to_value(&result)?),
+                true,
+                None,
+            );
+            Ok(result)
+        }
+        Err(err) => {
+            let _ = insert_audit(
+                conn,
+                clock,
+                "set_lead_consent",
+                "lead",
+                Some(lead_id.to_string()),
+                request_json,
+                None,
+                false,
+                Some(err.to_string()),
+            );
+            Err(err)
+        }
+    }
+}
+
+/// Keeps the job queue in step with a consent change made to `before`. A follow-up is only
+/// added for consenting `awaiting_yes` leads that have none pending; a revocation goes
+/// through `cancel_jobs_for_revoked_consent`.
+fn apply_consent_follow_ups(
+    conn: &Connection,
+    clock: &dyn Clock,
+    before: &LeadSummary,
+    consent: bool,
+) -> AppResult<LeadConsentResult> {
+    let pending: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM scheduled_jobs
+         WHERE status='pending' AND job_type='initial_follow_up' AND target_id=?",
+        params![before.id],
+        |row| row.get(0),
+    )?;
+    let mut follow_up_scheduled = false;
+    let mut follow_ups_cancelled = 0;
+    if consent
+        && !before.consent
+        && !before.opted_out
+        && before.status == "awaiting_yes"
+        && pending == 0
+    {
+        schedule_initial_follow_up(&ActionGateway::with_clock(conn, clock), before.id)?;
+        follow_up_scheduled = true;
+    } else if !consent {
+        follow_ups_cancelled = cancel_jobs_for_revoked_consent(conn, before.id)?;
+    }
+
+    Ok(LeadConsentResult {
+        lead_id: before.id,
+        consent,
+        follow_up_scheduled,
+        follow_ups_cancelled,
+    })
+}
+
+/// Cancels every pending job that would message the lead: its own jobs and the reminders
+/// for its appointments. Shared by all consent-revocation paths.
+fn cancel_jobs_for_revoked_consent(conn: &Connection, lead_id: i64) -> AppResult<i64> {
+    let cancelled = conn.execute(
+        "UPDATE scheduled_jobs SET status='cancelled'
+         WHERE status='pending'
+           AND ((job_type<>'appointment_reminder' AND target_id=?1)
+             OR (job_type='appointment_reminder'
+                 AND target_id IN (SELECT id FROM appointments WHERE lead_id=?1)))",
+        params![lead_id],
+    )? as i64;
+    if cancelled > 0 {
+        conn.execute(
+            "UPDATE leads SET next_action_at=NULL WHERE id=?",
+            params![lead_id],
+        )?;
+    }
+    Ok(cancelled)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id))]
+fn update_lead(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+    patch: LeadPatch,
+) -> Result<LeadSummary, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        update_lead_with_conn(&conn, state.clock.as_ref(), lead_id, &patch)
     });
 
-    map_cmd_result(result, "list_agent_queue", &app)
-}
+    map_cmd_result(result, "update_lead", &app)
+}
+
+fn get_lead_summary(conn: &Connection, lead_id: i64) -> AppResult<LeadSummary> {
+    conn.query_row(
+        "SELECT id, phone_e164, first_name, last_name, status, consent, opted_out, needs_staff_attention, created_at
+         FROM leads WHERE id=?",
+        params![lead_id],
+        map_lead_summary,
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound("lead not found".to_string()))
+}
+
+/// `None` leaves a field untouched; blank names are cleared. A new phone is normalized and must not
+/// belong to another lead or be blocklisted. Changing consent adjusts the initial follow-up the
+/// same way `update_lead_consent` does.
+fn update_lead_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    lead_id: i64,
+    patch: &LeadPatch,
+) -> AppResult<LeadSummary> {
+    // Field edits and the consent follow-up commit together, like `update_lead_consent`.
+    let result = (|| -> AppResult<(LeadSummary, LeadSummary, Option<LeadConsentResult>, Option<String>)> {
+        let before = get_lead_summary(conn, lead_id)?;
+
+        let phone = match patch.phone_e164.as_deref() {
+            Some(raw) => {
+                let phone = normalize_phone_e164(raw, "+1")?;
+                let existing: Option<i64> = conn
+                    .query_row(
+                        "SELECT id FROM leads WHERE phone_e164=? AND id<>? LIMIT 1",
+                        params![phone, lead_id],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                if let Some(existing) = existing {
+                    return Err(AppError::Validation(format!(
+                        "phone {phone} already belongs to lead {existing}"
+                    )));
+                }
+                if is_phone_blocklisted(conn, &phone)? {
+                    return Err(AppError::Validation("phone is on blocklist".to_string()));
+                }
+                phone
+            }
+            None => before.phone_e164.clone(),
+        };
+        let first_name = patch
+            .first_name
+            .as_deref()
+            .map_or_else(|| before.first_name.clone(), null_if_empty);
+        let last_name = patch
+            .last_name
+            .as_deref()
+            .map_or_else(|| before.last_name.clone(), null_if_empty);
+        let consent_at = patch
+            .consent_at
+            .as_deref()
+            .map(|value| parse_ts(value.trim()).map(|value| value.to_rfc3339()))
+            .transpose()?;
+
+        let tx = conn.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE leads SET phone_e164=?, first_name=?, last_name=? WHERE id=?",
+            params![phone, first_name, last_name, lead_id],
+        )?;
+        let consent_change = match patch.consent {
+            Some(consent) if consent != before.consent => {
+                tx.execute(
+                    "UPDATE leads SET consent=?, consent_at=? WHERE id=?",
+                    params![
+                        bool_to_i64(consent),
+                        consent_at
+                            .clone()
+                            .or_else(|| consent.then(|| clock.now().to_rfc3339())),
+                        lead_id
+                    ],
+                )?;
+                Some(apply_consent_follow_ups(&tx, clock, &before, consent)?)
+            }
+            _ => None,
+        };
+        let after = get_lead_summary(&tx, lead_id)?;
+        tx.commit()?;
+        Ok((before, after, consent_change, consent_at))
+    })();
+
+    match result {
+        Ok((before, after, consent_change, consent_at)) => {
+            let _ = insert_audit(
+                conn,
+                clock,
+                "update_lead",
+                "lead",
+                Some(lead_id.to_string()),
+                json!({
+                    "before": {
+                        "first_name": before.first_name,
+                        "last_name": before.last_name,
+                        "phone_e164": before.phone_e164,
+                        "consent": before.consent
+                    },
+                    "after": {
+                        "first_name": after.first_name,
+                        "last_name": after.last_name,
+                        "phone_e164": after.phone_e164,
+                        "consent": after.consent,
+                        "consent_at": consent_at
+                    }
+                }),
+                consent_change
+                    .as_ref()
+                    .map(serde_json::to_value)
+                    .transpose()?,
+                true,
+                None,
+            );
+            Ok(after)
+        }
+        Err(err) => {
+            let _ = insert_audit(
+                conn,
+                clock,
+                "update_lead",
+                "lead",
+                Some(lead_id.to_string()),
+                json!({
+                    "patch": {
+                        "first_name": patch.first_name,
+                        "last_name": patch.last_name,
+                        "phone_e164": patch.phone_e164,
+                        "consent": patch.consent,
+                        "consent_at": patch.consent_at
+                    }
+                }),
+                None,
+                false,
+                Some(err.to_string()),
+            );
+            Err(err)
+        }
+    }
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn list_leads(state: State<AppState>, app: AppHandle) -> Result<Vec<LeadSummary>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_leads_paginated_with_conn(&conn, 500, 0).map(|page| page.leads)
+    });
+
+    map_cmd_result(result, "list_leads", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(limit = limit, offset = offset))]
+fn list_leads_paginated(
+    state: State<AppState>,
+    app: AppHandle,
+    limit: u32,
+    offset: u32,
+) -> Result<LeadPage, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_leads_paginated_with_conn(&conn, limit, offset)
+    });
+    map_cmd_result(result, "list_leads_paginated", &app)
+}
+
+fn list_leads_paginated_with_conn(
+    conn: &Connection,
+    limit: u32,
+    offset: u32,
+) -> AppResult<LeadPage> {
+    if !(1..=500).contains(&limit) {
+        return Err(AppError::Validation(
+            "limit must be between 1 and 500".to_string(),
+        ));
+    }
+    let total: i64 = conn.query_row("SELECT COUNT(*) FROM leads", [], |row| row.get(0))?;
+    let mut stmt = conn.prepare(
+        "SELECT id, phone_e164, first_name, last_name, status, consent, opted_out, needs_staff_attention, created_at
+         FROM leads
+         ORDER BY datetime(created_at) DESC, id DESC
+         LIMIT ? OFFSET ?",
+    )?;
+    let leads = stmt
+        .query_map(params![limit, offset], map_lead_summary)?
+        .collect::<Result<Vec<_>, _>>()?;
+    let has_more = i64::from(offset) + (leads.len() as i64) < total;
+    Ok(LeadPage {
+        leads,
+        total,
+        has_more,
+    })
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn list_leads_by_status(
+    state: State<AppState>,
+    app: AppHandle,
+    statuses: Vec<String>,
+) -> Result<Vec<LeadSummary>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_leads_by_status_with_conn(&conn, &statuses)
+    });
+    map_cmd_result(result, "list_leads_by_status", &app)
+}
+
+fn list_leads_by_status_with_conn(
+    conn: &Connection,
+    statuses: &[String],
+) -> AppResult<Vec<LeadSummary>> {
+    if statuses.is_empty() {
+        return list_leads_paginated_with_conn(conn, 500, 0).map(|page| page.leads);
+    }
+    if let Some(unknown) = statuses
+        .iter()
+        .find(|status| !LEAD_STATUSES.contains(&status.as_str()))
+    {
+        return Err(AppError::Validation(format!(
+            "unknown lead status: {unknown}"
+        )));
+    }
+
+    let placeholders = vec!["?"; statuses.len()].join(", ");
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, phone_e164, first_name, last_name, status, consent, opted_out, needs_staff_attention, created_at
+         FROM leads
+         WHERE status IN ({placeholders})
+         ORDER BY datetime(created_at) DESC, id DESC"
+    ))?;
+    let rows = stmt.query_map(params_from_iter(statuses.iter()), map_lead_summary)?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn search_leads(
+    state: State<AppState>,
+    app: AppHandle,
+    query: String,
+    tags: Option<Vec<String>>,
+) -> Result<Vec<LeadSummary>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        search_leads_with_conn(&conn, &query, tags.as_deref())
+    });
+    map_cmd_result(result, "search_leads", &app)
+}
+
+fn search_leads_with_conn(
+    conn: &Connection,
+    query: &str,
+    tags: Option<&[String]>,
+) -> AppResult<Vec<LeadSummary>> {
+    let query = query.trim();
+    let wildcard = format!("%{}%", query.to_lowercase());
+    let mut sql = String::from(
+        "SELECT id, phone_e164, first_name, last_name, status, consent, opted_out, needs_staff_attention, created_at
+         FROM leads
+         WHERE (LOWER(phone_e164) LIKE ?1
+                OR LOWER(COALESCE(first_name, '')) LIKE ?1
+                OR LOWER(COALESCE(last_name, '')) LIKE ?1",
+    );
+    let mut values: Vec<String> = vec![wildcard];
+
+    // Every word has to appear in the full name, so "Smith John" finds "John Smith".
+    let words: Vec<String> = query
+        .split_whitespace()
+        .map(|word| format!("%{}%", word.to_lowercase()))
+        .collect();
+    if !words.is_empty() {
+        sql.push_str(&format!(
+            " OR ({})",
+            vec!["LOWER(full_name) LIKE ?"; words.len()].join(" AND ")
+        ));
+        values.extend(words);
+    }
+    if let Ok(phone) = normalize_phone_e164(query, "+1") {
+        sql.push_str(" OR phone_e164 = ?");
+        values.push(phone);
+    }
+    sql.push(')');
+
+    let normalized_tags = tags
+        .unwrap_or_default()
+        .iter()
+        .map(|tag| normalize_tag(tag))
+        .collect::<AppResult<Vec<_>>>()?;
+    if !normalized_tags.is_empty() {
+        let placeholders = vec!["?"; normalized_tags.len()].join(", ");
+        sql.push_str(&format!(
+            " AND id IN (SELECT lead_id FROM lead_tags WHERE tag IN ({placeholders}))"
+        ));
+        values.extend(normalized_tags);
+    }
+    sql.push_str(" ORDER BY datetime(created_at) DESC");
+
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params_from_iter(values.iter()), map_lead_summary)?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(inactive_days = inactive_days))]
+fn get_stale_leads(
+    state: State<AppState>,
+    app: AppHandle,
+    inactive_days: u32,
+) -> Result<Vec<LeadSummary>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_stale_leads_with_conn(&conn, state.clock.as_ref(), inactive_days)
+    });
+    map_cmd_result(result, "get_stale_leads", &app)
+}
+
+fn get_stale_leads_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    inactive_days: u32,
+) -> AppResult<Vec<LeadSummary>> {
+    if !(1..=365).contains(&inactive_days) {
+        return Err(AppError::Validation(
+            "inactive_days must be between 1 and 365".to_string(),
+        ));
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT id, phone_e164, first_name, last_name, status, consent, opted_out, needs_staff_attention, created_at
+         FROM leads
+         WHERE opted_out = 0
+           AND status NOT IN ('booked', 'awaiting_rating', 'opted_out')
+           AND datetime(last_contact_at) < datetime(?)
+         ORDER BY datetime(last_contact_at) ASC",
+    )?;
+    let cutoff = clock.now() - Duration::days(i64::from(inactive_days));
+    let rows = stmt.query_map(params![cutoff.to_rfc3339()], map_lead_summary)?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id))]
+fn add_lead_tag(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+    tag: String,
+) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        add_lead_tag_with_conn(&conn, state.clock.as_ref(), lead_id, &tag)
+    });
+    map_cmd_result(result, "add_lead_tag", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id))]
+fn remove_lead_tag(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+    tag: String,
+) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        remove_lead_tag_with_conn(&conn, state.clock.as_ref(), lead_id, &tag)
+    });
+    map_cmd_result(result, "remove_lead_tag", &app)
+}
+
+fn add_lead_tag_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    lead_id: i64,
+    tag: &str,
+) -> AppResult<()> {
+    let _ = get_lead(conn, lead_id)?;
+    let tag = normalize_tag(tag)?;
+    let inserted = conn.execute(
+        "INSERT INTO lead_tags (lead_id, tag, created_at) VALUES (?, ?, ?)
+         ON CONFLICT(lead_id, tag) DO NOTHING",
+        params![lead_id, tag, clock.now().to_rfc3339()],
+    )?;
+    if inserted == 0 {
+        return Err(AppError::Validation(format!(
+            "lead already has tag '{tag}'"
+        )));
+    }
+
+    let _ = insert_audit(
+        conn,
+        clock,
+        "add_lead_tag",
+        "lead",
+        Some(lead_id.to_string()),
+        json!({ "tag": tag }),
+        Some(json!({ "tags": list_lead_tags(conn, lead_id)? })),
+        true,
+        None,
+    );
+    Ok(())
+}
+
+fn remove_lead_tag_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    lead_id: i64,
+    tag: &str,
+) -> AppResult<()> {
+    let tag = normalize_tag(tag)?;
+    let removed = conn.execute(
+        "DELETE FROM lead_tags WHERE lead_id=? AND tag=?",
+        params![lead_id, tag],
+    )?;
+    if removed == 0 {
+        return Err(AppError::Validation(format!(
+            "lead does not have tag '{tag}'"
+        )));
+    }
+
+    let _ = insert_audit(
+        conn,
+        clock,
+        "remove_lead_tag",
+        "lead",
+        Some(lead_id.to_string()),
+        json!({ "tag": tag }),
+        Some(json!({ "tags": list_lead_tags(conn, lead_id)? })),
+        true,
+        None,
+    );
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn list_agent_queue(state: State<AppState>, app: AppHandle) -> Result<Vec<LeadSummary>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        let mut stmt = conn.prepare(
+            "SELECT l.id, l.phone_e164, l.first_name, l.last_name, l.status, l.consent, l.opted_out, l.needs_staff_attention, l.created_at
+             FROM leads l
+             JOIN conversations c ON c.lead_id = l.id
+             WHERE l.opted_out = 0
+               AND l.needs_staff_attention = 0
+               AND l.consent = 1
+               AND (
+                    (l.next_action_at IS NOT NULL AND datetime(l.next_action_at) <= datetime(?1))
+                    OR (
+                        c.last_inbound_at IS NOT NULL
+                        AND datetime(c.last_inbound_at) >= datetime(?1, '-3 days')
+                        AND (
+                            c.last_outbound_at IS NULL
+                            OR datetime(c.last_inbound_at) > datetime(c.last_outbound_at)
+                        )
+                    )
+               )
+             ORDER BY datetime(COALESCE(l.next_action_at, c.last_inbound_at, l.created_at)) ASC",
+        )?;
+
+        let rows = stmt.query_map(params![state.clock.now().to_rfc3339()], |row| {
+            Ok(LeadSummary {
+                id: row.get(0)?,
+                phone_e164: row.get(1)?,
+                first_name: row.get(2)?,
+                last_name: row.get(3)?,
+                status: row.get(4)?,
+                consent: i64_to_bool(row.get(5)?),
+                opted_out: i64_to_bool(row.get(6)?),
+                needs_staff_attention: i64_to_bool(row.get(7)?),
+                created_at: row.get(8)?,
+            })
+        })?;
+
+        rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+    });
+
+    map_cmd_result(result, "list_agent_queue", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn list_leads_needing_attention(
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<Vec<AttentionLeadView>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_leads_needing_attention_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "list_leads_needing_attention", &app)
+}
+
+/// Flagged leads, most recently flagged first, with the reason from the latest
+/// `flag_needs_staff_attention` audit entry.
+fn list_leads_needing_attention_with_conn(conn: &Connection) -> AppResult<Vec<AttentionLeadView>> {
+    let mut stmt = conn.prepare(
+        "SELECT l.id, l.phone_e164, l.first_name, l.last_name, l.status, l.consent, l.opted_out,
+                l.needs_staff_attention, l.created_at,
+                json_extract(a.request_json, '$.reason'), a.created_at
+         FROM leads l
+         LEFT JOIN audit_log a ON a.id = (
+             SELECT MAX(id) FROM audit_log
+             WHERE action_type = 'flag_needs_staff_attention'
+               AND target_type = 'lead'
+               AND target_id = CAST(l.id AS TEXT)
+         )
+         WHERE l.needs_staff_attention = 1
+         ORDER BY a.id IS NULL, a.id DESC, l.id DESC",
+    )?;
+    let rows = stmt.query_map(params![], |row| {
+        Ok(AttentionLeadView {
+            lead: map_lead_summary(row)?,
+            attention_reason: row.get(9)?,
+            flagged_at: row.get(10)?,
+        })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id))]
+fn get_lead_communication_summary(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+) -> Result<CommSummary, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_lead_communication_summary_with_conn(&conn, lead_id, state.clock.now())
+    });
+
+    map_cmd_result(result, "get_lead_communication_summary", &app)
+}
+
+/// Failed outbound rows are not counted. The response rate is inbound per outbound as a
+/// percentage and is `None` until something has been sent.
+fn get_lead_communication_summary_with_conn(
+    conn: &Connection,
+    lead_id: i64,
+    now: DateTime<Utc>,
+) -> AppResult<CommSummary> {
+    get_lead(conn, lead_id)?;
+    let (total_outbound, total_inbound, first_contact_at, last_contact_at): (
+        i64,
+        i64,
+        Option<String>,
+        Option<String>,
+    ) = conn.query_row(
+        "SELECT COALESCE(SUM(m.direction = 'OUTBOUND'), 0),
+                COALESCE(SUM(m.direction = 'INBOUND'), 0),
+                MIN(m.created_at),
+                MAX(m.created_at)
+         FROM messages m
+         JOIN conversations c ON c.id = m.conversation_id
+         WHERE c.lead_id = ?
+           AND NOT (m.direction = 'OUTBOUND' AND m.status = 'failed')",
+        params![lead_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )?;
+
+    let days_since_last_contact = last_contact_at
+        .as_deref()
+        .map(parse_ts)
+        .transpose()?
+        .map(|last| (now - last).num_days());
+    let inbound_response_rate_pct =
+        (total_outbound > 0).then(|| total_inbound as f64 / total_outbound as f64 * 100.0);
+
+    Ok(CommSummary {
+        lead_id,
+        total_outbound,
+        total_inbound,
+        first_contact_at,
+        last_contact_at,
+        days_since_last_contact,
+        inbound_response_rate_pct,
+    })
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id))]
+fn get_lead_detail(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+) -> Result<LeadDetail, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_lead_detail_with_conn(&conn, lead_id)
+    });
+
+    map_cmd_result(result, "get_lead_detail", &app)
+}
+
+fn get_lead_detail_with_conn(conn: &Connection, lead_id: i64) -> AppResult<LeadDetail> {
+    let lead = conn
+        .query_row(
+            "SELECT id, phone_e164, first_name, last_name, status, consent, consent_at, consent_source,
+                    opted_out, needs_staff_attention, last_contact_at, next_action_at, created_at
+             FROM leads WHERE id=?",
+            params![lead_id],
+            |row| {
+                Ok(LeadDetailLead {
+                    id: row.get(0)?,
+                    phone_e164: row.get(1)?,
+                    first_name: row.get(2)?,
+                    last_name: row.get(3)?,
+                    status: row.get(4)?,
+                    consent: i64_to_bool(row.get(5)?),
+                    consent_at: row.get(6)?,
+                    consent_source: row.get(7)?,
+                    opted_out: i64_to_bool(row.get(8)?),
+                    needs_staff_attention: i64_to_bool(row.get(9)?),
+                    last_contact_at: row.get(10)?,
+                    next_action_at: row.get(11)?,
+                    created_at: row.get(12)?,
+                })
+            },
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("lead not found".to_string()))?;
+
+    let conversation = conn.query_row(
+        "SELECT id, state, state_json, last_inbound_at, last_outbound_at, repair_attempts
+         FROM conversations WHERE lead_id=?",
+        params![lead_id],
+        |row| {
+            Ok(ConversationView {
+                id: row.get(0)?,
+                state: row.get(1)?,
+                state_json: row.get(2)?,
+                last_inbound_at: row.get(3)?,
+                last_outbound_at: row.get(4)?,
+                repair_attempts: row.get(5)?,
+            })
+        },
+    )?;
+
+    let mut msg_stmt = conn.prepare(
+        "SELECT id, direction, body, status, created_at, is_manual
+         FROM messages
+         WHERE conversation_id=? AND failed_at IS NULL
+         ORDER BY datetime(created_at) ASC",
+    )?;
+    let msg_rows = msg_stmt.query_map(params![conversation.id], |row| {
+        Ok(MessageView {
+            id: row.get(0)?,
+            direction: row.get(1)?,
+            body: row.get(2)?,
+            status: row.get(3)?,
+            created_at: row.get(4)?,
+            is_manual: i64_to_bool(row.get(5)?),
+        })
+    })?;
+    let messages = msg_rows.collect::<Result<Vec<_>, _>>()?;
+
+    let mut apt_stmt = conn.prepare(
+        "SELECT id, start_at, end_at, status
+         FROM appointments
+         WHERE lead_id=?
+         ORDER BY datetime(start_at) ASC",
+    )?;
+    let apt_rows = apt_stmt.query_map(params![lead_id], |row| {
+        Ok(AppointmentView {
+            id: row.get(0)?,
+            start_at: row.get(1)?,
+            end_at: row.get(2)?,
+            status: row.get(3)?,
+        })
+    })?;
+    let appointments = apt_rows.collect::<Result<Vec<_>, _>>()?;
+    let tags = list_lead_tags(conn, lead_id)?;
+    let notes = list_lead_notes_with_conn(conn, lead_id)?;
+
+    Ok(LeadDetail {
+        lead,
+        conversation,
+        messages,
+        appointments,
+        tags,
+        notes,
+    })
+}
+
+fn mark_message_failed(
+    conn: &Connection,
+    clock: &dyn Clock,
+    message_id: i64,
+    reason: &str,
+) -> AppResult<()> {
+    let reason = reason.trim();
+    if reason.is_empty() {
+        return Err(AppError::Validation(
+            "failure reason must be non-empty".to_string(),
+        ));
+    }
+    let updated = conn.execute(
+        "UPDATE messages SET status='failed', failed_at=?, failure_reason=?
+         WHERE id=? AND direction='OUTBOUND'",
+        params![clock.now().to_rfc3339(), reason, message_id],
+    )?;
+    if updated == 0 {
+        return Err(AppError::NotFound("outbound message not found".to_string()));
+    }
+    let _ = insert_audit(
+        conn,
+        clock,
+        "mark_message_failed",
+        "message",
+        Some(message_id.to_string()),
+        json!({ "reason": reason }),
+        None,
+        true,
+        None,
+    );
+    Ok(())
+}
+
+fn list_outbound_failures_with_conn(
+    conn: &Connection,
+    limit: Option<u32>,
+) -> AppResult<Vec<MessageFailureView>> {
+    let limit = limit.unwrap_or(50);
+    if !(1..=500).contains(&limit) {
+        return Err(AppError::Validation(
+            "limit must be between 1 and 500".to_string(),
+        ));
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT m.id, m.direction, m.body, m.status, m.created_at, m.is_manual,
+                c.lead_id, m.failed_at, m.failure_reason
+         FROM messages m
+         JOIN conversations c ON c.id = m.conversation_id
+         WHERE m.direction = 'OUTBOUND' AND m.failed_at IS NOT NULL
+         ORDER BY datetime(m.failed_at) DESC, m.id DESC
+         LIMIT ?",
+    )?;
+    let rows = stmt.query_map(params![limit], |row| {
+        Ok(MessageFailureView {
+            message: MessageView {
+                id: row.get(0)?,
+                direction: row.get(1)?,
+                body: row.get(2)?,
+                status: row.get(3)?,
+                created_at: row.get(4)?,
+                is_manual: i64_to_bool(row.get(5)?),
+            },
+            lead_id: row.get(6)?,
+            failed_at: row.get(7)?,
+            failure_reason: row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+        })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(limit = limit, cursor = cursor))]
+fn get_conversation_history(
+    state: State<AppState>,
+    app: AppHandle,
+    direction: Option<String>,
+    since: Option<String>,
+    limit: Option<u32>,
+    cursor: Option<i64>,
+) -> Result<MessageHistoryPage, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_conversation_history_with_conn(
+            &conn,
+            direction.as_deref(),
+            since.as_deref(),
+            limit,
+            cursor,
+        )
+    });
+
+    map_cmd_result(result, "get_conversation_history", &app)
+}
+
+/// Messages across all leads, newest first, paged by id like `list_audit_log`.
+fn get_conversation_history_with_conn(
+    conn: &Connection,
+    direction: Option<&str>,
+    since: Option<&str>,
+    limit: Option<u32>,
+    cursor: Option<i64>,
+) -> AppResult<MessageHistoryPage> {
+    let limit = limit.unwrap_or(100);
+    if !(1..=500).contains(&limit) {
+        return Err(AppError::Validation(
+            "limit must be between 1 and 500".to_string(),
+        ));
+    }
+    let direction = direction
+        .map(|value| value.trim().to_uppercase())
+        .filter(|value| !value.is_empty());
+    if let Some(direction) = direction.as_deref() {
+        if direction != "INBOUND" && direction != "OUTBOUND" {
+            return Err(AppError::Validation(
+                "direction must be INBOUND or OUTBOUND".to_string(),
+            ));
+        }
+    }
+    let since = since
+        .map(|value| parse_ts(value.trim()))
+        .transpose()?
+        .map(|value| value.to_rfc3339());
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT m.id, m.direction, m.body, m.status, m.created_at, m.is_manual,
+                l.id, l.phone_e164, c.id
+         FROM messages m
+         JOIN conversations c ON c.id = m.conversation_id
+         JOIN leads l ON l.id = c.lead_id
+         WHERE (?1 IS NULL OR m.direction = ?1)
+           AND (?2 IS NULL OR datetime(m.created_at) >= datetime(?2))
+           AND (?3 IS NULL OR m.id < ?3)
+         ORDER BY m.id DESC
+         LIMIT {}",
+        // One extra row tells us whether another page exists.
+        limit + 1
+    ))?;
+    let rows = stmt.query_map(params![direction, since, cursor], |row| {
+        Ok(MessageHistoryItem {
+            message: MessageView {
+                id: row.get(0)?,
+                direction: row.get(1)?,
+                body: row.get(2)?,
+                status: row.get(3)?,
+                created_at: row.get(4)?,
+                is_manual: i64_to_bool(row.get(5)?),
+            },
+            lead_id: row.get(6)?,
+            lead_phone: row.get(7)?,
+            conversation_id: row.get(8)?,
+        })
+    })?;
+    let mut messages = rows.collect::<Result<Vec<_>, _>>()?;
+
+    let next_cursor = if messages.len() > limit as usize {
+        messages.truncate(limit as usize);
+        messages.last().map(|item| item.message.id)
+    } else {
+        None
+    };
+
+    Ok(MessageHistoryPage {
+        messages,
+        next_cursor,
+    })
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(limit = limit))]
+fn search_messages(
+    state: State<AppState>,
+    app: AppHandle,
+    query: String,
+    direction: Option<String>,
+    limit: Option<u32>,
+) -> Result<Vec<MessageSearchResult>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        search_messages_with_conn(&conn, &query, direction.as_deref(), limit)
+    });
+
+    map_cmd_result(result, "search_messages", &app)
+}
+
+/// Case-insensitive substring match on message bodies, newest first.
+fn search_messages_with_conn(
+    conn: &Connection,
+    query: &str,
+    direction: Option<&str>,
+    limit: Option<u32>,
+) -> AppResult<Vec<MessageSearchResult>> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Err(AppError::Validation(
+            "search query cannot be empty".to_string(),
+        ));
+    }
+    let limit = limit.unwrap_or(100);
+    if !(1..=500).contains(&limit) {
+        return Err(AppError::Validation(
+            "limit must be between 1 and 500".to_string(),
+        ));
+    }
+    let direction = direction
+        .map(|value| value.trim().to_uppercase())
+        .filter(|value| !value.is_empty());
+    if let Some(direction) = direction.as_deref() {
+        if direction != "INBOUND" && direction != "OUTBOUND" {
+            return Err(AppError::Validation(
+                "direction must be INBOUND or OUTBOUND".to_string(),
+            ));
+        }
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT m.id, m.conversation_id, c.lead_id, m.direction, m.body, m.created_at
+         FROM messages m
+         JOIN conversations c ON c.id = m.conversation_id
+         WHERE LOWER(m.body) LIKE '%' || ?1 || '%'
+           AND (?2 IS NULL OR m.direction = ?2)
+         ORDER BY m.id DESC
+         LIMIT ?3",
+    )?;
+    let rows = stmt.query_map(params![query, direction, limit], |row| {
+        Ok(MessageSearchResult {
+            message_id: row.get(0)?,
+            conversation_id: row.get(1)?,
+            lead_id: row.get(2)?,
+            direction: row.get(3)?,
+            body: row.get(4)?,
+            created_at: row.get(5)?,
+        })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id))]
+fn add_lead_note(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+    body: String,
+    author: Option<String>,
+) -> Result<i64, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        add_lead_note_with_conn(
+            &conn,
+            state.clock.as_ref(),
+            lead_id,
+            &body,
+            author.as_deref(),
+        )
+    });
+
+    map_cmd_result(result, "add_lead_note", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id))]
+fn list_lead_notes(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+) -> Result<Vec<NoteView>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_lead_notes_with_conn(&conn, lead_id)
+    });
+
+    map_cmd_result(result, "list_lead_notes", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(note_id = note_id))]
+fn delete_lead_note(state: State<AppState>, app: AppHandle, note_id: i64) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        delete_lead_note_with_conn(&conn, state.clock.as_ref(), note_id)
+    });
+
+    map_cmd_result(result, "delete_lead_note", &app)
+}
+
+fn add_lead_note_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    lead_id: i64,
+    body: &str,
+    author: Option<&str>,
+) -> AppResult<i64> {
+    let _ = get_lead(conn, lead_id)?;
+    let body = body.trim();
+    if body.is_empty() {
+        return Err(AppError::Validation(
+            "note body cannot be empty".to_string(),
+        ));
+    }
+    if body.chars().count() > 2000 {
+        return Err(AppError::Validation(
+            "note body must be at most 2000 characters".to_string(),
+        ));
+    }
+    let author = author.and_then(null_if_empty);
+
+    conn.execute(
+        "INSERT INTO lead_notes (lead_id, body, author, created_at) VALUES (?, ?, ?, ?)",
+        params![lead_id, body, author, clock.now().to_rfc3339()],
+    )?;
+    let note_id = conn.last_insert_rowid();
+
+    let _ = insert_audit(
+        conn,
+        clock,
+        "add_lead_note",
+        "lead",
+        Some(lead_id.to_string()),
+        json!({ "author": author }),
+        Some(json!({ "note_id": note_id })),
+        true,
+        None,
+    );
+    Ok(note_id)
+}
+
+fn list_lead_notes_with_conn(conn: &Connection, lead_id: i64) -> AppResult<Vec<NoteView>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, lead_id, body, author, created_at
+         FROM lead_notes
+         WHERE lead_id=?
+         ORDER BY datetime(created_at) ASC, id ASC",
+    )?;
+    let rows = stmt.query_map(params![lead_id], |row| {
+        Ok(NoteView {
+            id: row.get(0)?,
+            lead_id: row.get(1)?,
+            body: row.get(2)?,
+            author: row.get(3)?,
+            created_at: row.get(4)?,
+        })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+fn delete_lead_note_with_conn(conn: &Connection, clock: &dyn Clock, note_id: i64) -> AppResult<()> {
+    let lead_id: i64 = conn
+        .query_row(
+            "SELECT lead_id FROM lead_notes WHERE id=?",
+            params![note_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("note not found".to_string()))?;
+
+    conn.execute("DELETE FROM lead_notes WHERE id=?", params![note_id])?;
+
+    let _ = insert_audit(
+        conn,
+        clock,
+        "delete_lead_note",
+        "lead",
+        Some(lead_id.to_string()),
+        json!({ "note_id": note_id }),
+        None,
+        true,
+        None,
+    );
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id))]
+fn get_lead_timeline(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+) -> Result<Vec<StatusHistoryEntry>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_lead_timeline_with_conn(&conn, lead_id)
+    });
+
+    map_cmd_result(result, "get_lead_timeline", &app)
+}
+
+fn get_lead_timeline_with_conn(
+    conn: &Connection,
+    lead_id: i64,
+) -> AppResult<Vec<StatusHistoryEntry>> {
+    let _ = get_lead(conn, lead_id)?;
+    let mut stmt = conn.prepare(
+        "SELECT id, lead_id, from_status, to_status, changed_at, trigger
+         FROM lead_status_history
+         WHERE lead_id=?
+         ORDER BY datetime(changed_at) ASC, id ASC",
+    )?;
+    let rows = stmt.query_map(params![lead_id], |row| {
+        Ok(StatusHistoryEntry {
+            id: row.get(0)?,
+            lead_id: row.get(1)?,
+            from_status: row.get(2)?,
+            to_status: row.get(3)?,
+            changed_at: row.get(4)?,
+            trigger: row.get(5)?,
+        })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id))]
+fn get_lead_conversation_history(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+) -> Result<Vec<ConversationHistoryItem>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_lead_conversation_history_with_conn(&conn, lead_id)
+    });
+
+    map_cmd_result(result, "get_lead_conversation_history", &app)
+}
+
+/// Messages and `lead_status_history` transitions merged oldest first. A message and a transition
+/// with the same timestamp keep message-first order.
+fn get_lead_conversation_history_with_conn(
+    conn: &Connection,
+    lead_id: i64,
+) -> AppResult<Vec<ConversationHistoryItem>> {
+    let transitions = get_lead_timeline_with_conn(conn, lead_id)?;
+    let mut stmt = conn.prepare(
+        "SELECT m.id, m.direction, m.body, m.status, m.created_at, m.is_manual
+         FROM messages m
+         JOIN conversations c ON c.id = m.conversation_id
+         WHERE c.lead_id=?
+         ORDER BY m.id ASC",
+    )?;
+    let rows = stmt.query_map(params![lead_id], |row| {
+        Ok(MessageView {
+            id: row.get(0)?,
+            direction: row.get(1)?,
+            body: row.get(2)?,
+            status: row.get(3)?,
+            created_at: row.get(4)?,
+            is_manual: i64_to_bool(row.get(5)?),
+        })
+    })?;
+
+    let mut timed = Vec::new();
+    for message in rows {
+        let message = message?;
+        timed.push((
+            parse_ts(&message.created_at)?,
+            ConversationHistoryItem::Message(message),
+        ));
+    }
+    for entry in transitions {
+        timed.push((
+            parse_ts(&entry.changed_at)?,
+            ConversationHistoryItem::StateTransition {
+                from: entry.from_status.unwrap_or_default(),
+                to: entry.to_status,
+                triggered_by: entry.trigger,
+                at: entry.changed_at,
+            },
+        ));
+    }
+    // Stable sort, so ties keep the push order above.
+    timed.sort_by_key(|(at, _)| *at);
+
+    Ok(timed.into_iter().map(|(_, item)| item).collect())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id))]
+fn send_manual_message(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+    body: String,
+) -> Result<i64, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        let gateway = ActionGateway::with_clock(&conn, state.clock.as_ref());
+        gateway.send_manual_message(lead_id, &body)
+    });
+
+    map_cmd_result(result, "send_manual_message", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(limit = limit))]
+fn list_outbound_failures(
+    state: State<AppState>,
+    app: AppHandle,
+    limit: Option<u32>,
+) -> Result<Vec<MessageFailureView>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_outbound_failures_with_conn(&conn, limit)
+    });
+
+    map_cmd_result(result, "list_outbound_failures", &app)
+}
+
+// Called when the SMS provider reports that an outbound message was not delivered.
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(message_id = message_id))]
+fn record_outbound_failure(
+    state: State<AppState>,
+    app: AppHandle,
+    message_id: i64,
+    reason: String,
+) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        mark_message_failed(&conn, state.clock.as_ref(), message_id, &reason)
+    });
+
+    map_cmd_result(result, "record_outbound_failure", &app)
+}
+
+// Nothing is delivered for real in this build, so failures are simulated the same way inbound
+// replies are.
+#[cfg(debug_assertions)]
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(message_id = message_id))]
+fn simulate_outbound_failure(
+    state: State<AppState>,
+    app: AppHandle,
+    message_id: i64,
+    reason: String,
+) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        mark_message_failed(&conn, state.clock.as_ref(), message_id, &reason)
+    });
+
+    map_cmd_result(result, "simulate_outbound_failure", &app)
+}
+
+// Test helper; release builds keep the command name registered but refuse to run it.
+#[cfg(not(debug_assertions))]
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn simulate_outbound_failure(app: AppHandle) -> Result<(), String> {
+    map_cmd_result(
+        Err(AppError::Validation(
+            "simulate_outbound_failure is only available in debug builds".to_string(),
+        )),
+        "simulate_outbound_failure",
+        &app,
+    )
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id))]
+fn simulate_inbound_sms(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+    body: String,
+) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        simulate_inbound_sms_with_conn(&conn, state.clock.as_ref(), lead_id, &body)
+    });
+
+    map_cmd_result(result, "simulate_inbound_sms", &app)
+}
+
+#[cfg(debug_assertions)]
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id))]
+fn simulate_outbound_sms(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+    body: String,
+) -> Result<i64, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        simulate_outbound_sms_with_conn(&conn, state.clock.as_ref(), lead_id, &body)
+    });
+
+    map_cmd_result(result, "simulate_outbound_sms", &app)
+}
+
+// Test helper; release builds keep the command name registered but refuse to run it.
+#[cfg(not(debug_assertions))]
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn simulate_outbound_sms(app: AppHandle) -> Result<i64, String> {
+    map_cmd_result(
+        Err(AppError::Validation(
+            "simulate_outbound_sms is only available in debug builds".to_string(),
+        )),
+        "simulate_outbound_sms",
+        &app,
+    )
+}
+
+/// Writes an outbound row with status `simulated` and none of the gateway's consent, opt-out,
+/// rate-limit or business-hours checks. It is exempt from rate-limit counts.
+#[cfg(debug_assertions)]
+fn simulate_outbound_sms_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    lead_id: i64,
+    body: &str,
+) -> AppResult<i64> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err(AppError::Validation(
+            "outbound body cannot be empty".to_string(),
+        ));
+    }
+    let conversation = get_conversation_by_lead_id(conn, lead_id)?;
+
+    let now = clock.now().to_rfc3339();
+    conn.execute(
+        "INSERT INTO messages (conversation_id, direction, body, status, created_at, rate_limit_exempt)
+         VALUES (?, 'OUTBOUND', ?, 'simulated', ?, 1)",
+        params![conversation.id, body, now],
+    )?;
+    let message_id = conn.last_insert_rowid();
+    conn.execute(
+        "UPDATE conversations SET last_outbound_at=? WHERE id=?",
+        params![now, conversation.id],
+    )?;
+
+    let _ = insert_audit(
+        conn,
+        clock,
+        "simulate_outbound",
+        "lead",
+        Some(lead_id.to_string()),
+        json!({ "body": body }),
+        Some(json!({ "message_id": message_id, "conversation_id": conversation.id })),
+        true,
+        None,
+    );
+    Ok(message_id)
+}
+
+fn simulate_inbound_sms_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    lead_id: i64,
+    body: &str,
+) -> AppResult<()> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err(AppError::Validation(
+            "inbound body cannot be empty".to_string(),
+        ));
+    }
+
+    let lead = get_lead(conn, lead_id)?;
+    let conversation = get_conversation_by_lead_id(conn, lead_id)?;
+
+    let now = clock.now().to_rfc3339();
+    conn.execute(
+        "INSERT INTO messages (conversation_id, direction, body, status, created_at)
+         VALUES (?, 'INBOUND', ?, 'received', ?)",
+        params![conversation.id, body, now],
+    )?;
+
+    conn.execute(
+        "UPDATE conversations SET last_inbound_at=? WHERE id=?",
+        params![now, conversation.id],
+    )?;
+    conn.execute(
+        "UPDATE leads SET last_contact_at=? WHERE id=?",
+        params![now, lead_id],
+    )?;
+
+    process_inbound_state_machine(conn, clock, &lead, &conversation, body)
+}
+
+#[cfg(debug_assertions)]
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(count = messages.len()))]
+fn simulate_bulk_inbound(
+    state: State<AppState>,
+    app: AppHandle,
+    messages: Vec<BulkInboundItem>,
+) -> Result<BulkInboundResult, String> {
+    let result = open_conn(&state)
+        .map(|conn| simulate_bulk_inbound_with_conn(&conn, state.clock.as_ref(), &messages));
+
+    map_cmd_result(result, "simulate_bulk_inbound", &app)
+}
+
+// Load-testing helper; release builds keep the command name registered but refuse to run it.
+#[cfg(not(debug_assertions))]
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn simulate_bulk_inbound(app: AppHandle) -> Result<BulkInboundResult, String> {
+    map_cmd_result(
+        Err(AppError::Validation(
+            "simulate_bulk_inbound is only available in debug builds".to_string(),
+        )),
+        "simulate_bulk_inbound",
+        &app,
+    )
+}
+
+/// Each item goes through the same path as `simulate_inbound_sms`, retried on its own so one
+/// failing lead does not abort the rest of the batch.
+#[cfg(debug_assertions)]
+fn simulate_bulk_inbound_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    messages: &[BulkInboundItem],
+) -> BulkInboundResult {
+    let mut result = BulkInboundResult {
+        processed: 0,
+        errors: Vec::new(),
+    };
+    for item in messages {
+        match retry_db(|| simulate_inbound_sms_with_conn(conn, clock, item.lead_id, &item.body)) {
+            Ok(()) => result.processed += 1,
+            Err(err) => result.errors.push((item.lead_id, err.to_string())),
+        }
+    }
+    result
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(appointment_id = appointment_id))]
+fn cancel_appointment(
+    state: State<AppState>,
+    app: AppHandle,
+    appointment_id: i64,
+    reason: String,
+) -> Result<CancelAppointmentResult, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        let gateway = ActionGateway::with_clock(&conn, state.clock.as_ref());
+        gateway.cancel_appointment(CancelAppointmentRequest {
+            appointment_id,
+            reason: reason.clone(),
+        })
+    });
+
+    map_cmd_result(result, "cancel_appointment", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(appointment_id = appointment_id))]
+fn reschedule_appointment(
+    state: State<AppState>,
+    app: AppHandle,
+    appointment_id: i64,
+    new_start_at: String,
+    new_end_at: String,
+    reason: String,
+) -> Result<RescheduleResult, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        let gateway = ActionGateway::with_clock(&conn, state.clock.as_ref());
+        gateway.reschedule_appointment(RescheduleAppointmentRequest {
+            appointment_id,
+            new_start_at: new_start_at.clone(),
+            new_end_at: new_end_at.clone(),
+            reason: reason.clone(),
+        })
+    });
+
+    map_cmd_result(result, "reschedule_appointment", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id))]
+fn resolve_staff_attention(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+    resolution: String,
+    notes: Option<String>,
+) -> Result<StaffAttentionResolution, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        resolve_staff_attention_with_conn(
+            &conn,
+            state.clock.as_ref(),
+            lead_id,
+            &resolution,
+            notes.as_deref(),
+        )
+    });
+
+    map_cmd_result(result, "resolve_staff_attention", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id))]
+fn mark_needs_attention_resolved(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+    resolved_by: String,
+    note: String,
+) -> Result<LeadSummary, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        mark_needs_attention_resolved_with_conn(
+            &conn,
+            state.clock.as_ref(),
+            lead_id,
+            &resolved_by,
+            &note,
+        )
+    });
+
+    map_cmd_result(result, "mark_needs_attention_resolved", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id))]
+fn reset_conversation(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+    reason: String,
+) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        let gateway = ActionGateway::with_clock(&conn, state.clock.as_ref());
+        gateway.reset_conversation(lead_id, &reason)
+    });
+
+    map_cmd_result(result, "reset_conversation", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(conversation_id = conversation_id))]
+fn override_conversation_state(
+    state: State<AppState>,
+    app: AppHandle,
+    conversation_id: i64,
+    new_state: String,
+    state_json: String,
+    reason: String,
+) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        let gateway = ActionGateway::with_clock(&conn, state.clock.as_ref());
+        gateway.override_conversation_state(OverrideConversationStateRequest {
+            conversation_id,
+            new_state: new_state.clone(),
+            state_json: state_json.clone(),
+            reason: reason.clone(),
+        })
+    });
+
+    map_cmd_result(result, "override_conversation_state", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(appointment_id = appointment_id))]
+fn mark_appointment_no_show(
+    state: State<AppState>,
+    app: AppHandle,
+    appointment_id: i64,
+) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        let gateway = ActionGateway::with_clock(&conn, state.clock.as_ref());
+        gateway.mark_appointment_no_show(appointment_id)
+    });
+
+    map_cmd_result(result, "mark_appointment_no_show", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(appointment_id = appointment_id))]
+fn mark_appointment_completed(
+    state: State<AppState>,
+    app: AppHandle,
+    appointment_id: i64,
+) -> Result<AppointmentCompletion, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        let gateway = ActionGateway::with_clock(&conn, state.clock.as_ref());
+        gateway.mark_appointment_completed(appointment_id)
+    });
+
+    map_cmd_result(result, "mark_appointment_completed", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(limit = limit))]
+fn list_appointments(
+    state: State<AppState>,
+    app: AppHandle,
+    status: Option<String>,
+    start_after: Option<String>,
+    start_before: Option<String>,
+    limit: Option<u32>,
+) -> Result<Vec<AppointmentDetail>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_appointments_with_conn(
+            &conn,
+            status.as_deref(),
+            start_after.as_deref(),
+            start_before.as_deref(),
+            limit,
+        )
+    });
+
+    map_cmd_result(result, "list_appointments", &app)
+}
+
+/// Soonest first. Both bounds are exclusive on `start_at`.
+fn list_appointments_with_conn(
+    conn: &Connection,
+    status: Option<&str>,
+    start_after: Option<&str>,
+    start_before: Option<&str>,
+    limit: Option<u32>,
+) -> AppResult<Vec<AppointmentDetail>> {
+    let limit = limit.unwrap_or(100);
+    if !(1..=500).contains(&limit) {
+        return Err(AppError::Validation(
+            "limit must be between 1 and 500".to_string(),
+        ));
+    }
+    if let Some(status) = status {
+        if !APPOINTMENT_STATUSES.contains(&status) {
+            return Err(AppError::Validation(format!(
+                "unknown appointment status: {status}"
+            )));
+        }
+    }
+    let start_after = start_after
+        .map(|value| parse_ts(value.trim()))
+        .transpose()?
+        .map(|value| value.to_rfc3339());
+    let start_before = start_before
+        .map(|value| parse_ts(value.trim()))
+        .transpose()?
+        .map(|value| value.to_rfc3339());
+
+    let mut stmt = conn.prepare(
+        "SELECT a.id, a.lead_id, l.first_name, l.last_name, l.phone_e164, a.start_at, a.end_at,
+                a.status, a.appointment_type_id, a.created_at
+         FROM appointments a
+         JOIN leads l ON l.id = a.lead_id
+         WHERE (?1 IS NULL OR a.status = ?1)
+           AND (?2 IS NULL OR datetime(a.start_at) > datetime(?2))
+           AND (?3 IS NULL OR datetime(a.start_at) < datetime(?3))
+         ORDER BY datetime(a.start_at) ASC, a.id ASC
+         LIMIT ?4",
+    )?;
+    let rows = stmt.query_map(params![status, start_after, start_before, limit], |row| {
+        Ok(AppointmentDetail {
+            id: row.get(0)?,
+            lead_id: row.get(1)?,
+            first_name: row.get(2)?,
+            last_name: row.get(3)?,
+            phone_e164: row.get(4)?,
+            start_at: row.get(5)?,
+            end_at: row.get(6)?,
+            status: row.get(7)?,
+            appointment_type_id: row.get(8)?,
+            created_at: row.get(9)?,
+        })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(appointment_id = appointment_id))]
+fn export_appointment_ics(
+    state: State<AppState>,
+    app: AppHandle,
+    appointment_id: i64,
+) -> Result<String, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        export_appointment_ics_with_conn(&conn, appointment_id)
+    });
+
+    map_cmd_result(result, "export_appointment_ics", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id))]
+fn join_appointment_waitlist(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+) -> Result<i64, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        join_appointment_waitlist_with_conn(&conn, state.clock.as_ref(), lead_id)
+    });
+
+    map_cmd_result(result, "join_appointment_waitlist", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn list_appointment_types(
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<Vec<AppointmentType>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_appointment_types_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "list_appointment_types", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn create_appointment_type(
+    state: State<AppState>,
+    app: AppHandle,
+    input: AppointmentTypeInput,
+) -> Result<AppointmentType, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        create_appointment_type_with_conn(&conn, &input)
+    });
+
+    map_cmd_result(result, "create_appointment_type", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(appointment_type_id = appointment_type_id))]
+fn update_appointment_type(
+    state: State<AppState>,
+    app: AppHandle,
+    appointment_type_id: i64,
+    input: AppointmentTypeInput,
+) -> Result<AppointmentType, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        update_appointment_type_with_conn(&conn, appointment_type_id, &input)
+    });
+
+    map_cmd_result(result, "update_appointment_type", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id, appointment_type_id = appointment_type_id))]
+fn set_conversation_appointment_type(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: i64,
+    appointment_type_id: Option<i64>,
+) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        set_conversation_appointment_type_with_conn(
+            &conn,
+            state.clock.as_ref(),
+            lead_id,
+            appointment_type_id,
+        )
+    });
+
+    map_cmd_result(result, "set_conversation_appointment_type", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_today_report(
+    state: State<AppState>,
+    app: AppHandle,
+    date: Option<String>,
+) -> Result<TodayReport, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_today_report_with_conn(&conn, date.as_deref(), state.clock.now())
+    });
+
+    map_cmd_result(result, "get_today_report", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_range_report(
+    state: State<AppState>,
+    app: AppHandle,
+    start_date: String,
+    end_date: String,
+) -> Result<Vec<DayReport>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_range_report_with_conn(&conn, &start_date, &end_date)
+    });
+
+    map_cmd_result(result, "get_range_report", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_weekly_report(state: State<AppState>, app: AppHandle) -> Result<Vec<DailyStats>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_weekly_report_with_conn(&conn, state.clock.now())
+    });
+
+    map_cmd_result(result, "get_weekly_report", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_funnel_report(
+    state: State<AppState>,
+    app: AppHandle,
+    since: Option<String>,
+) -> Result<FunnelReport, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_funnel_report_with_conn(&conn, since.as_deref())
+    });
+
+    map_cmd_result(result, "get_funnel_report", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_source_report(
+    state: State<AppState>,
+    app: AppHandle,
+    since: Option<String>,
+) -> Result<Vec<SourceReport>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_source_report_with_conn(&conn, since.as_deref())
+    });
+
+    map_cmd_result(result, "get_source_report", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_kill_switch(state: State<AppState>, app: AppHandle) -> Result<bool, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        is_kill_switch_enabled(&conn)
+    });
+
+    map_cmd_result(result, "get_kill_switch", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_kill_switch_state(
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<KillSwitchState, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_kill_switch_state_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "get_kill_switch_state", &app)
+}
+
+#[derive(Debug, Serialize)]
+struct ValidationReport {
+    valid: bool,
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+#[tauri::command]
+#[tracing::instrument]
+fn validate_business_hours(json: String) -> Result<ValidationReport, String> {
+    Ok(validate_business_hours_json(&json))
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_location_settings(state: State<AppState>, app: AppHandle) -> Result<LocationSettings, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        let location_id = current_location_id(&conn)?;
+        get_location_settings_with_conn(&conn, location_id)
+    });
+
+    map_cmd_result(result, "get_location_settings", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(location_id = location_id, duration_minutes = duration_minutes, buffer_minutes = buffer_minutes, reminder_enabled = reminder_enabled))]
+fn update_location_schedule_config(
+    state: State<AppState>,
+    app: AppHandle,
+    location_id: i64,
+    duration_minutes: i64,
+    buffer_minutes: i64,
+    reminder_enabled: bool,
+) -> Result<LocationSettings, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        update_location_schedule_config_with_conn(
+            &conn,
+            location_id,
+            duration_minutes,
+            buffer_minutes,
+            reminder_enabled,
+        )
+    });
+
+    map_cmd_result(result, "update_location_schedule_config", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(location_id = location_id, hours_before = hours_before))]
+fn add_reminder_config(
+    state: State<AppState>,
+    app: AppHandle,
+    location_id: i64,
+    hours_before: i64,
+    template: Option<String>,
+) -> Result<ReminderConfig, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        add_reminder_config_with_conn(
+            &conn,
+            state.clock.as_ref(),
+            location_id,
+            hours_before,
+            template.clone(),
+        )
+    });
+
+    map_cmd_result(result, "add_reminder_config", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(location_id = location_id))]
+fn list_follow_up_sequence(
+    state: State<AppState>,
+    app: AppHandle,
+    location_id: i64,
+) -> Result<Vec<FollowUpStep>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_follow_up_sequence_with_conn(&conn, location_id)
+    });
+
+    map_cmd_result(result, "list_follow_up_sequence", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(location_id = location_id, step_number = step_number, delay_hours = delay_hours))]
+fn upsert_follow_up_step(
+    state: State<AppState>,
+    app: AppHandle,
+    location_id: i64,
+    step_number: i64,
+    delay_hours: i64,
+    template_key: String,
+) -> Result<FollowUpStep, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        upsert_follow_up_step_with_conn(&conn, location_id, step_number, delay_hours, &template_key)
+    });
+
+    map_cmd_result(result, "upsert_follow_up_step", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn add_phone_to_blocklist(
+    state: State<AppState>,
+    app: AppHandle,
+    phone_e164: String,
+    reason: Option<String>,
+) -> Result<BlocklistEntry, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        add_phone_to_blocklist_with_conn(
+            &conn,
+            state.clock.as_ref(),
+            &phone_e164,
+            reason.as_deref(),
+        )
+    });
+
+    map_cmd_result(result, "add_phone_to_blocklist", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn remove_phone_from_blocklist(
+    state: State<AppState>,
+    app: AppHandle,
+    phone_e164: String,
+) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        remove_phone_from_blocklist_with_conn(&conn, state.clock.as_ref(), &phone_e164)
+    });
+
+    map_cmd_result(result, "remove_phone_from_blocklist", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn list_blocklist(state: State<AppState>, app: AppHandle) -> Result<Vec<BlocklistEntry>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_blocklist_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "list_blocklist", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn add_holiday(
+    state: State<AppState>,
+    app: AppHandle,
+    date: String,
+    name: String,
+) -> Result<i64, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        add_holiday_with_conn(&conn, &date, &name)
+    });
+
+    map_cmd_result(result, "add_holiday", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(id = id))]
+fn remove_holiday(state: State<AppState>, app: AppHandle, id: i64) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        remove_holiday_with_conn(&conn, id)
+    });
+
+    map_cmd_result(result, "remove_holiday", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn list_holidays(state: State<AppState>, app: AppHandle) -> Result<Vec<HolidayView>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_holidays_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "list_holidays", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_next_available_slot(
+    state: State<AppState>,
+    app: AppHandle,
+    from_utc: Option<String>,
+) -> Result<Option<SlotChoice>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_next_available_slot_with_conn(&conn, from_utc.as_deref(), state.clock.now())
+    });
+
+    map_cmd_result(result, "get_next_available_slot", &app)
+}
+
+/// Looks 14 business days ahead, the widest window `get_available_slots` allows.
+fn get_next_available_slot_with_conn(
+    conn: &Connection,
+    from_utc: Option<&str>,
+    now: DateTime<Utc>,
+) -> AppResult<Option<SlotChoice>> {
+    let from_utc = match from_utc {
+        Some(value) => parse_ts(value.trim())?,
+        None => now,
+    };
+    let location = get_location(conn)?;
+    Ok(find_open_slots(
+        conn,
+        &location,
+        from_utc,
+        location.appointment_duration_minutes,
+        14,
+        Some(1),
+    )?
+    .into_iter()
+    .next())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(days_ahead = days_ahead))]
+fn get_available_slots(
+    state: State<AppState>,
+    app: AppHandle,
+    from_utc: Option<String>,
+    days_ahead: Option<u32>,
+) -> Result<Vec<SlotChoice>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_available_slots_with_conn(&conn, from_utc.as_deref(), days_ahead, state.clock.now())
+    });
+
+    map_cmd_result(result, "get_available_slots", &app)
+}
+
+/// Every open slot in the next `days_ahead` business days (default 3, at most 14), not just the
+/// two a lead would be offered.
+fn get_available_slots_with_conn(
+    conn: &Connection,
+    from_utc: Option<&str>,
+    days_ahead: Option<u32>,
+    now: DateTime<Utc>,
+) -> AppResult<Vec<SlotChoice>> {
+    let days_ahead = days_ahead.unwrap_or(3);
+    if !(1..=14).contains(&days_ahead) {
+        return Err(AppError::Validation(
+            "days_ahead must be between 1 and 14".to_string(),
+        ));
+    }
+    let from_utc = match from_utc {
+        Some(value) => parse_ts(value.trim())?,
+        None => now,
+    };
+    let location = get_location(conn)?;
+    find_open_slots(
+        conn,
+        &location,
+        from_utc,
+        location.appointment_duration_minutes,
+        i64::from(days_ahead),
+        None,
+    )
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn check_slot_available(
+    state: State<AppState>,
+    app: AppHandle,
+    start_at: String,
+    end_at: String,
+) -> Result<bool, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        check_slot_available_with_conn(&conn, &start_at, &end_at)
+    });
+
+    map_cmd_result(result, "check_slot_available", &app)
+}
+
+/// Only the overlap check (including the location's buffer) against booked appointments; business
+/// hours, holidays and blackouts are not considered.
+fn check_slot_available_with_conn(
+    conn: &Connection,
+    start_at: &str,
+    end_at: &str,
+) -> AppResult<bool> {
+    let start = parse_ts(start_at.trim())?;
+    let end = parse_ts(end_at.trim())?;
+    if end <= start {
+        return Err(AppError::Validation(
+            "end_at must be after start_at".to_string(),
+        ));
+    }
+    let location = get_location(conn)?;
+    let existing = load_booked_intervals(conn, location.id, start)?;
+    Ok(!has_appointment_conflict(
+        start,
+        end,
+        location.appointment_buffer_minutes,
+        &existing,
+    ))
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn add_blackout_period(
+    state: State<AppState>,
+    app: AppHandle,
+    start_date: String,
+    end_date: String,
+    reason: String,
+) -> Result<i64, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        add_blackout_period_with_conn(&conn, &start_date, &end_date, &reason)
+    });
+
+    map_cmd_result(result, "add_blackout_period", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(id = id))]
+fn remove_blackout_period(state: State<AppState>, app: AppHandle, id: i64) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        remove_blackout_period_with_conn(&conn, id)
+    });
+
+    map_cmd_result(result, "remove_blackout_period", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn list_blackout_periods(
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<Vec<BlackoutPeriodView>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_blackout_periods_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "list_blackout_periods", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(reminder_config_id = reminder_config_id))]
+fn remove_reminder_config(
+    state: State<AppState>,
+    app: AppHandle,
+    reminder_config_id: i64,
+) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        remove_reminder_config_with_conn(&conn, state.clock.as_ref(), reminder_config_id)
+    });
+
+    map_cmd_result(result, "remove_reminder_config", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn update_location_settings(
+    state: State<AppState>,
+    app: AppHandle,
+    input: LocationSettingsInput,
+) -> Result<LocationSettings, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        let location_id = current_location_id(&conn)?;
+        let patch = LocationPatch {
+            gym_name: Some(input.gym_name.clone()),
+            timezone: Some(input.timezone.clone()),
+            business_hours_json: Some(input.business_hours_json.clone()),
+        };
+        update_location_with_conn(
+            &conn,
+            state.clock.as_ref(),
+            location_id,
+            &patch,
+            state.clock.now(),
+        )
+        .map(|result| result.location)
+    });
+
+    map_cmd_result(result, "update_location_settings", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn list_locations(state: State<AppState>, app: AppHandle) -> Result<Vec<LocationSettings>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_locations_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "list_locations", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn add_location(
+    state: State<AppState>,
+    app: AppHandle,
+    input: LocationSettingsInput,
+) -> Result<LocationSettings, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        add_location_with_conn(&conn, &input)
+    });
+
+    map_cmd_result(result, "add_location", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(location_id = location_id))]
+fn update_location(
+    state: State<AppState>,
+    app: AppHandle,
+    location_id: i64,
+    patch: LocationPatch,
+) -> Result<LocationUpdateResult, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        update_location_with_conn(
+            &conn,
+            state.clock.as_ref(),
+            location_id,
+            &patch,
+            state.clock.now(),
+        )
+    });
+
+    map_cmd_result(result, "update_location", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(location_id = location_id))]
+fn set_current_location(
+    state: State<AppState>,
+    app: AppHandle,
+    location_id: i64,
+) -> Result<LocationSettings, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        set_current_location_with_conn(&conn, state.clock.as_ref(), location_id)
+    });
+
+    map_cmd_result(result, "set_current_location", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(enabled = enabled))]
+fn set_kill_switch(
+    state: State<AppState>,
+    app: AppHandle,
+    enabled: bool,
+    reason: Option<String>,
+) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        set_kill_switch_with_conn(&conn, state.clock.as_ref(), enabled, reason.as_deref())
+    });
+
+    map_cmd_result(result, "set_kill_switch", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_auto_kill_switch_threshold(state: State<AppState>, app: AppHandle) -> Result<i64, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_auto_kill_switch_threshold_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "get_auto_kill_switch_threshold", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(threshold = threshold))]
+fn set_auto_kill_switch_threshold(
+    state: State<AppState>,
+    app: AppHandle,
+    threshold: i64,
+) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        set_auto_kill_switch_threshold_with_conn(&conn, state.clock.as_ref(), threshold)
+    });
+
+    map_cmd_result(result, "set_auto_kill_switch_threshold", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn check_db_integrity(
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<IntegrityCheckResult, String> {
+    let result = retry_db(|| {
+        let conn = open_conn_read_only(&db_file_path(&state)?)?;
+        check_db_integrity_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "check_db_integrity", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn backup_db(
+    state: State<AppState>,
+    app: AppHandle,
+    destination_path: String,
+) -> Result<BackupResult, String> {
+    let result = ensure_app_data_dir(&app)
+        .map_err(AppError::Validation)
+        .and_then(|app_dir| {
+            retry_db(|| {
+                let conn = open_conn(&state)?;
+                backup_db_with_conn(&conn, state.clock.as_ref(), &app_dir, &destination_path)
+            })
+        });
+
+    map_cmd_result(result, "backup_db", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn vacuum_db(state: State<AppState>, app: AppHandle) -> Result<VacuumResult, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        vacuum_db_with_conn(&conn, state.clock.as_ref())
+    });
+
+    map_cmd_result(result, "vacuum_db", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_app_info(state: State<AppState>, app: AppHandle) -> Result<AppInfo, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_app_info_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "get_app_info", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn schema_version(state: State<AppState>, app: AppHandle) -> Result<i64, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        schema_version_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "schema_version", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn list_applied_migrations(
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<Vec<MigrationRecord>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_applied_migrations_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "list_applied_migrations", &app)
+}
+
+/// Same history as `list_applied_migrations`, kept under the name the diagnostics panel calls.
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_schema_version(
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<Vec<MigrationRecord>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_applied_migrations_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "get_schema_version", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(mode = %mode))]
+fn checkpoint_db(
+    state: State<AppState>,
+    app: AppHandle,
+    mode: String,
+) -> Result<CheckpointResult, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        checkpoint_db_with_conn(&conn, state.clock.as_ref(), &mode)
+    });
+
+    map_cmd_result(result, "checkpoint_db", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_settings(state: State<AppState>, app: AppHandle) -> Result<Vec<SettingView>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_settings_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "get_settings", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(key = %key))]
+fn get_setting(
+    state: State<AppState>,
+    app: AppHandle,
+    key: String,
+) -> Result<Option<String>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        read_setting(&conn, key.trim())
+    });
+
+    map_cmd_result(result, "get_setting", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(key = %key))]
+fn set_setting(
+    state: State<AppState>,
+    app: AppHandle,
+    key: String,
+    value: String,
+) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        set_setting_with_conn(&conn, state.clock.as_ref(), &key, &value)
+    });
+
+    map_cmd_result(result, "set_setting", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_stop_keywords(state: State<AppState>, app: AppHandle) -> Result<Vec<String>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_stop_keywords_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "get_stop_keywords", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn set_stop_keywords(
+    state: State<AppState>,
+    app: AppHandle,
+    keywords: Vec<String>,
+) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        set_stop_keywords_with_conn(&conn, state.clock.as_ref(), keywords.clone())
+    });
+
+    map_cmd_result(result, "set_stop_keywords", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_rate_limit_settings(
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<RateLimitSettings, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        load_rate_limit_settings(&conn)
+    });
+
+    map_cmd_result(result, "get_rate_limit_settings", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id))]
+fn get_rate_limit_status(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: Option<i64>,
+) -> Result<RateLimitStatus, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_rate_limit_status_with_conn(&conn, lead_id, state.clock.now())
+    });
+
+    map_cmd_result(result, "get_rate_limit_status", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(per_lead_day = per_lead_day, per_location_hour = per_location_hour))]
+fn set_rate_limit_settings(
+    state: State<AppState>,
+    app: AppHandle,
+    per_lead_day: i64,
+    per_location_hour: i64,
+) -> Result<RateLimitSettings, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        set_rate_limit_settings_with_conn(
+            &conn,
+            state.clock.as_ref(),
+            per_lead_day,
+            per_location_hour,
+        )
+    });
+
+    map_cmd_result(result, "set_rate_limit_settings", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_help_response(state: State<AppState>, app: AppHandle) -> Result<String, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_help_response_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "get_help_response", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn set_help_response(state: State<AppState>, app: AppHandle, text: String) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        set_help_response_with_conn(&conn, state.clock.as_ref(), &text)
+    });
+
+    map_cmd_result(result, "set_help_response", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_conversation_expiry_hours(state: State<AppState>, app: AppHandle) -> Result<i64, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_conversation_expiry_hours_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "get_conversation_expiry_hours", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(hours = hours))]
+fn set_conversation_expiry_hours(
+    state: State<AppState>,
+    app: AppHandle,
+    hours: i64,
+) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        set_conversation_expiry_hours_with_conn(&conn, state.clock.as_ref(), hours)
+    });
+
+    map_cmd_result(result, "set_conversation_expiry_hours", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_tcpa_mode(state: State<AppState>, app: AppHandle) -> Result<bool, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_tcpa_mode_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "get_tcpa_mode", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(enabled = enabled))]
+fn set_tcpa_mode(state: State<AppState>, app: AppHandle, enabled: bool) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        set_tcpa_mode_with_conn(&conn, state.clock.as_ref(), enabled)
+    });
+
+    map_cmd_result(result, "set_tcpa_mode", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn add_custom_keyword(
+    state: State<AppState>,
+    app: AppHandle,
+    keyword: String,
+    response_body: String,
+) -> Result<CustomKeyword, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        add_custom_keyword_with_conn(&conn, state.clock.as_ref(), &keyword, &response_body)
+    });
+
+    map_cmd_result(result, "add_custom_keyword", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(id = id))]
+fn update_custom_keyword(
+    state: State<AppState>,
+    app: AppHandle,
+    id: i64,
+    keyword: String,
+    response_body: String,
+) -> Result<CustomKeyword, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        update_custom_keyword_with_conn(&conn, id, &keyword, &response_body)
+    });
+
+    map_cmd_result(result, "update_custom_keyword", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(id = id, active = active))]
+fn set_custom_keyword_active(
+    state: State<AppState>,
+    app: AppHandle,
+    id: i64,
+    active: bool,
+) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        set_custom_keyword_active_with_conn(&conn, id, active)
+    });
+
+    map_cmd_result(result, "set_custom_keyword_active", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn list_custom_keywords(
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<Vec<CustomKeyword>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_custom_keywords_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "list_custom_keywords", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(key = %key))]
+fn get_template(
+    state: State<AppState>,
+    app: AppHandle,
+    key: String,
+) -> Result<MessageTemplate, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_template_with_conn(&conn, &key)
+    });
+
+    map_cmd_result(result, "get_template", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(key = %key))]
+fn set_template(
+    state: State<AppState>,
+    app: AppHandle,
+    key: String,
+    body: String,
+) -> Result<MessageTemplate, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        set_template_with_conn(&conn, &key, &body)
+    });
+
+    map_cmd_result(result, "set_template", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn list_templates(state: State<AppState>, app: AppHandle) -> Result<Vec<MessageTemplate>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_templates_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "list_templates", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn export_db_path(state: State<AppState>, app: AppHandle) -> Result<String, String> {
+    let result = db_file_path(&state).map(|path| path.to_string_lossy().to_string());
+
+    map_cmd_result(result, "export_db_path", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_config_path(app: AppHandle) -> Result<String, String> {
+    let app_dir = ensure_app_data_dir(&app)?;
+    Ok(app_dir.join("config.toml").to_string_lossy().to_string())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn wipe_all_data_confirmed(
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<WipeAllDataResult, String> {
+    let result = retry_db(|| {
+        let mut conn = open_conn(&state)?;
+        let tx = conn.transaction()?;
+
+        let messages = tx.execute("DELETE FROM messages", params![])? as i64;
+        let appointments = tx.execute("DELETE FROM appointments", params![])? as i64;
+        let scheduled_jobs = tx.execute("DELETE FROM scheduled_jobs", params![])? as i64;
+        let audit_log = tx.execute("DELETE FROM audit_log", params![])? as i64;
+        let conversations = tx.execute("DELETE FROM conversations", params![])? as i64;
+        let lead_tags = tx.execute("DELETE FROM lead_tags", params![])? as i64;
+        let lead_status_history = tx.execute("DELETE FROM lead_status_history", params![])? as i64;
+        let lead_notes = tx.execute("DELETE FROM lead_notes", params![])? as i64;
+        let waitlist = tx.execute("DELETE FROM waitlist", params![])? as i64;
+        let leads = tx.execute("DELETE FROM leads", params![])? as i64;
+
+        let counts = WipeAllDataResult {
+            messages,
+            appointments,
+            scheduled_jobs,
+            audit_log,
+            conversations,
+            lead_tags,
+            lead_status_history,
+            lead_notes,
+            waitlist,
+            leads,
+        };
+
+        tx.execute(
+            "INSERT INTO audit_log (action_type, target_type, target_id, request_json, response_json, success, error_message, created_at, severity)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                "wipe_all_data_confirmed",
+                "system",
+                Option::<String>::None,
+                "{}",
+                Some(serde_json::to_string(&counts)?),
+                1,
+                Option::<String>::None,
+                state.clock.now().to_rfc3339(),
+                audit_severity("wipe_all_data_confirmed")
+            ],
+        )?;
+
+        tx.commit()?;
+        Ok(counts)
+    });
+
+    map_cmd_result(result, "wipe_all_data_confirmed", &app)
+}
+
+#[cfg(debug_assertions)]
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn reset_demo_data(state: State<AppState>, app: AppHandle) -> Result<DemoResetResult, String> {
+    let result = retry_db(|| {
+        let mut conn = open_conn(&state)?;
+        reset_demo_data_with_conn(&mut conn, state.clock.as_ref())
+    });
+
+    map_cmd_result(result, "reset_demo_data", &app)
+}
+
+// Demo helper; release builds keep the command name registered but refuse to run it.
+#[cfg(not(debug_assertions))]
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn reset_demo_data(app: AppHandle) -> Result<DemoResetResult, String> {
+    map_cmd_result(
+        Err(AppError::Validation(
+            "reset_demo_data is only available in debug builds".to_string(),
+        )),
+        "reset_demo_data",
+        &app,
+    )
+}
+
+/// Empties every table except `settings` and `schema_migrations`, restarts the AUTOINCREMENT
+/// counters and replaces all locations with a single default one, so a demo starts from the same
+/// state as a fresh install.
+#[cfg(debug_assertions)]
+fn reset_demo_data_with_conn(
+    conn: &mut Connection,
+    clock: &dyn Clock,
+) -> AppResult<DemoResetResult> {
+    let tx = conn.transaction()?;
+    tx.pragma_update(None, "defer_foreign_keys", "ON")?;
+
+    let tables = {
+        let mut stmt = tx.prepare(
+            "SELECT name FROM sqlite_master
+             WHERE type='table' AND name NOT IN ('settings', 'locations', 'schema_migrations') AND name NOT LIKE 'sqlite_%'
+             ORDER BY name",
+        )?;
+        let names = stmt
+            .query_map(params![], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        names
+    };
+    let mut rows_deleted = 0;
+    for table in &tables {
+        rows_deleted += tx.execute(&format!("DELETE FROM \"{table}\""), params![])? as i64;
+    }
+    rows_deleted += tx.execute("DELETE FROM locations", params![])? as i64;
+    tx.execute("DELETE FROM sqlite_sequence", params![])?;
+
+    for migration in MIGRATIONS.iter().filter(|m| m.seeds) {
+        tx.execute_batch(migration.sql)?;
+    }
+    tx.execute(
+        "INSERT INTO locations (gym_name, timezone, business_hours_json) VALUES (?, ?, ?)",
+        params![
+            default_gym_name(),
+            default_timezone(),
+            default_business_hours_json()
+        ],
+    )?;
+    let location_id = tx.last_insert_rowid();
+    put_setting(&tx, clock, "current_location_id", &location_id.to_string())?;
+    ensure_housekeeping_jobs(&tx, clock)?;
+
+    let reset = DemoResetResult {
+        rows_deleted,
+        location_id,
+    };
+    insert_audit(
+        &tx,
+        clock,
+        "reset_demo_data",
+        "system",
+        None,
+        json!({}),
+        Some(serde_json::to_value(&reset)?),
+        true,
+        None,
+    )?;
+    tx.commit()?;
+    Ok(reset)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn list_scheduled_jobs(
+    state: State<AppState>,
+    app: AppHandle,
+    filter: JobFilter,
+) -> Result<Vec<ScheduledJobView>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_scheduled_jobs_with_conn(&conn, &filter)
+    });
+
+    map_cmd_result(result, "list_scheduled_jobs", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(success = success, limit = limit, cursor = cursor))]
+fn list_audit_log(
+    state: State<AppState>,
+    app: AppHandle,
+    action_type: Option<String>,
+    target_type: Option<String>,
+    success: Option<bool>,
+    since: Option<String>,
+    limit: Option<u32>,
+    cursor: Option<i64>,
+) -> Result<AuditPage, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_audit_log_with_conn(
+            &conn,
+            action_type.as_deref(),
+            target_type.as_deref(),
+            success,
+            since.as_deref(),
+            limit,
+            cursor,
+        )
+    });
+
+    map_cmd_result(result, "list_audit_log", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(limit = limit, offset = offset))]
+fn get_audit_log(
+    state: State<AppState>,
+    app: AppHandle,
+    filter: AuditLogFilter,
+    limit: u32,
+    offset: u32,
+) -> Result<AuditLogPage, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_audit_log_with_conn(&conn, &filter, limit, offset)
+    });
+
+    map_cmd_result(result, "get_audit_log", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn export_audit_log_csv(
+    state: State<AppState>,
+    app: AppHandle,
+    since: Option<String>,
+    until: Option<String>,
+) -> Result<String, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        export_audit_log_csv_with_conn(&conn, since.as_deref(), until.as_deref())
+    });
+
+    map_cmd_result(result, "export_audit_log_csv", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn export_leads_csv(
+    state: State<AppState>,
+    app: AppHandle,
+    filter: LeadExportFilter,
+) -> Result<String, String> {
+    let result = ensure_app_data_dir(&app)
+        .map_err(AppError::Validation)
+        .and_then(|app_dir| {
+            retry_db(|| {
+                let conn = open_conn(&state)?;
+                export_leads_csv_with_conn(&conn, state.clock.as_ref(), &app_dir, &filter)
+            })
+        })
+        .map(|path| path.to_string_lossy().into_owned());
+
+    map_cmd_result(result, "export_leads_csv", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(dry_run = dry_run))]
+fn purge_old_audit_logs(
+    state: State<AppState>,
+    app: AppHandle,
+    dry_run: bool,
+) -> Result<PurgeResult, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        purge_old_audit_logs_with_conn(&conn, state.clock.as_ref(), dry_run, state.clock.now())
+    });
+
+    map_cmd_result(result, "purge_old_audit_logs", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_audit_log_retention_days(state: State<AppState>, app: AppHandle) -> Result<i64, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_audit_log_retention_days_with_conn(&conn)
+    });
+
+    map_cmd_result(result, "get_audit_log_retention_days", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(days = days))]
+fn set_audit_log_retention_days(
+    state: State<AppState>,
+    app: AppHandle,
+    days: i64,
+) -> Result<(), String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        set_audit_log_retention_days_with_conn(&conn, state.clock.as_ref(), days)
+    });
+
+    map_cmd_result(result, "set_audit_log_retention_days", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(job_id = job_id))]
+fn cancel_scheduled_job(
+    state: State<AppState>,
+    app: AppHandle,
+    job_id: i64,
+    reason: String,
+) -> Result<CancelJobResult, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        let gateway = ActionGateway::with_clock(&conn, state.clock.as_ref());
+        gateway.cancel_scheduled_job(job_id, &reason)
+    });
+
+    map_cmd_result(result, "cancel_scheduled_job", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn get_job_stats(
+    state: State<AppState>,
+    app: AppHandle,
+    since: Option<String>,
+) -> Result<JobStats, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_job_stats_with_conn(&conn, since.as_deref())
+    });
+
+    map_cmd_result(result, "get_job_stats", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(job_id = job_id))]
+fn requeue_failed_job(
+    state: State<AppState>,
+    app: AppHandle,
+    job_id: i64,
+) -> Result<ScheduledJobView, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        let gateway = ActionGateway::with_clock(&conn, state.clock.as_ref());
+        gateway.requeue_failed_job(job_id)
+    });
+
+    map_cmd_result(result, "requeue_failed_job", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(job_id = job_id))]
+fn retry_failed_job(
+    state: State<AppState>,
+    app: AppHandle,
+    job_id: i64,
+    execute_at: Option<String>,
+) -> Result<i64, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        let gateway = ActionGateway::with_clock(&conn, state.clock.as_ref());
+        gateway.retry_failed_job(job_id, execute_at.as_deref())
+    });
+
+    map_cmd_result(result, "retry_failed_job", &app)
+}
+
+/// `since`/`until` bound `execute_at`. Pending (or unfiltered) jobs come soonest first; completed,
+/// failed and cancelled ones most recent first.
+fn list_scheduled_jobs_with_conn(
+    conn: &Connection,
+    filter: &JobFilter,
+) -> AppResult<Vec<ScheduledJobView>> {
+    let limit = filter.limit;
+    if !(1..=500).contains(&limit) {
+        return Err(AppError::Validation(
+            "limit must be between 1 and 500".to_string(),
+        ));
+    }
+
+    let mut sql = String::from(
+        "SELECT id, job_type, target_id, execute_at, status, payload_json, created_at,
+                retry_count, max_retries, completed_at
+         FROM scheduled_jobs
+         WHERE 1=1",
+    );
+    let mut values: Vec<String> = Vec::new();
+    let status = filter.status.as_deref();
+    if let Some(status) = status {
+        if !JOB_STATUSES.contains(&status) {
+            return Err(AppError::Validation(format!(
+                "unknown job status: {status}"
+            )));
+        }
+        sql.push_str(" AND status = ?");
+        values.push(status.to_string());
+    }
+    if let Some(job_type) = filter
+        .job_type
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+    {
+        sql.push_str(" AND job_type = ?");
+        values.push(job_type.to_string());
+    }
+    let since = filter
+        .since
+        .as_deref()
+        .map(|value| parse_ts(value.trim()))
+        .transpose()?;
+    let until = filter
+        .until
+        .as_deref()
+        .map(|value| parse_ts(value.trim()))
+        .transpose()?;
+    if let (Some(since), Some(until)) = (since, until) {
+        if since > until {
+            return Err(AppError::Validation(
+                "since must not be after until".to_string(),
+            ));
+        }
+    }
+    if let Some(since) = since {
+        sql.push_str(" AND datetime(execute_at) >= datetime(?)");
+        values.push(since.to_rfc3339());
+    }
+    if let Some(until) = until {
+        sql.push_str(" AND datetime(execute_at) <= datetime(?)");
+        values.push(until.to_rfc3339());
+    }
+    let order = match status {
+        None | Some("pending") => "ASC",
+        Some(_) => "DESC",
+    };
+    sql.push_str(&format!(
+        " ORDER BY datetime(execute_at) {order}, id {order} LIMIT {limit}"
+    ));
+
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params_from_iter(values.iter()), map_scheduled_job)?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+/// Newest first. `cursor` is the `next_cursor` of the previous page: only ids below it are returned.
+fn list_audit_log_with_conn(
+    conn: &Connection,
+    action_type: Option<&str>,
+    target_type: Option<&str>,
+    success: Option<bool>,
+    since: Option<&str>,
+    limit: Option<u32>,
+    cursor: Option<i64>,
+) -> AppResult<AuditPage> {
+    let limit = limit.unwrap_or(100);
+    if !(1..=500).contains(&limit) {
+        return Err(AppError::Validation(
+            "limit must be between 1 and 500".to_string(),
+        ));
+    }
+
+    let (where_clause, mut values) = audit_filter_clause(&AuditLogFilter {
+        action_type: action_type.map(str::to_string),
+        target_type: target_type.map(str::to_string),
+        success,
+        since: since.map(str::to_string),
+        ..AuditLogFilter::default()
+    })?;
+    let mut sql = format!(
+        "SELECT id, action_type, target_type, target_id, request_json, response_json, success,
+                error_message, created_at, severity
+         FROM audit_log
+         WHERE {where_clause}"
+    );
+    if let Some(cursor) = cursor {
+        sql.push_str(" AND id < ?");
+        values.push(cursor.to_string());
+    }
+    // One extra row tells us whether another page exists.
+    sql.push_str(&format!(" ORDER BY id DESC LIMIT {}", limit + 1));
+
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params_from_iter(values.iter()), map_audit_entry)?;
+    let mut entries = rows.collect::<Result<Vec<_>, _>>()?;
+
+    let next_cursor = if entries.len() > limit as usize {
+        entries.truncate(limit as usize);
+        entries.last().map(|entry| entry.id)
+    } else {
+        None
+    };
+
+    Ok(AuditPage {
+        entries,
+        next_cursor,
+    })
+}
+
+/// Builds the `WHERE` conditions shared by the audit log queries; values are bound, never inlined.
+fn audit_filter_clause(filter: &AuditLogFilter) -> AppResult<(String, Vec<String>)> {
+    let non_empty = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+    let mut clause = String::from("1=1");
+    let mut values: Vec<String> = Vec::new();
+    if let Some(action_type) = non_empty(&filter.action_type) {
+        clause.push_str(" AND action_type = ?");
+        values.push(action_type);
+    }
+    if let Some(target_type) = non_empty(&filter.target_type) {
+        clause.push_str(" AND target_type = ?");
+        values.push(target_type);
+    }
+    if let Some(target_id) = non_empty(&filter.target_id) {
+        clause.push_str(" AND target_id = ?");
+        values.push(target_id);
+    }
+    if let Some(success) = filter.success {
+        clause.push_str(" AND success = ?");
+        values.push(bool_to_i64(success).to_string());
+    }
+    if let Some(since) = &filter.since {
+        clause.push_str(" AND datetime(created_at) >= datetime(?)");
+        values.push(parse_ts(since.trim())?.to_rfc3339());
+    }
+    if let Some(until) = &filter.until {
+        clause.push_str(" AND datetime(created_at) <= datetime(?)");
+        values.push(parse_ts(until.trim())?.to_rfc3339());
+    }
+    Ok((clause, values))
+}
+
+fn get_audit_log_with_conn(
+    conn: &Connection,
+    filter: &AuditLogFilter,
+    limit: u32,
+    offset: u32,
+) -> AppResult<AuditLogPage> {
+    if !(1..=500).contains(&limit) {
+        return Err(AppError::Validation(
+            "limit must be between 1 and 500".to_string(),
+        ));
+    }
+    let (where_clause, values) = audit_filter_clause(filter)?;
+    let total: i64 = conn.query_row(
+        &format!("SELECT COUNT(*) FROM audit_log WHERE {where_clause}"),
+        params_from_iter(values.iter()),
+        |row| row.get(0),
+    )?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, action_type, target_type, target_id, request_json, response_json, success,
+                error_message, created_at, severity
+         FROM audit_log
+         WHERE {where_clause}
+         ORDER BY id DESC
+         LIMIT {limit} OFFSET {offset}"
+    ))?;
+    let entries = stmt
+        .query_map(params_from_iter(values.iter()), map_audit_entry)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(AuditLogPage { entries, total })
+}
+
+/// `request_json` and `response_json` are base64-encoded so the JSON never needs CSV quoting.
+fn export_audit_log_csv_with_conn(
+    conn: &Connection,
+    since: Option<&str>,
+    until: Option<&str>,
+) -> AppResult<String> {
+    let since = since.map(|value| parse_ts(value.trim())).transpose()?;
+    let until = until.map(|value| parse_ts(value.trim())).transpose()?;
+    if let (Some(since), Some(until)) = (since, until) {
+        if since > until {
+            return Err(AppError::Validation(
+                "since must not be after until".to_string(),
+            ));
+        }
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT id, action_type, target_type, target_id, request_json, response_json, success,
+                error_message, created_at, severity
+         FROM audit_log
+         WHERE (?1 IS NULL OR datetime(created_at) >= datetime(?1))
+           AND (?2 IS NULL OR datetime(created_at) <= datetime(?2))
+         ORDER BY id ASC",
+    )?;
+    let rows = stmt.query_map(
+        params![
+            since.map(|value| value.to_rfc3339()),
+            until.map(|value| value.to_rfc3339())
+        ],
+        map_audit_entry,
+    )?;
+
+    let mut csv = String::from(
+        "id,action_type,target_type,target_id,success,error_message,created_at,request_json,response_json\n",
+    );
+    for row in rows {
+        let entry = row?;
+        let fields = [
+            entry.id.to_string(),
+            csv_field(&entry.action_type),
+            csv_field(&entry.target_type),
+            csv_field(entry.target_id.as_deref().unwrap_or_default()),
+            entry.success.to_string(),
+            csv_field(entry.error_message.as_deref().unwrap_or_default()),
+            csv_field(&entry.created_at),
+            BASE64.encode(&entry.request_json),
+            entry
+                .response_json
+                .map(|value| BASE64.encode(value))
+                .unwrap_or_default(),
+        ];
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+
+    Ok(csv)
+}
+
+/// Streams matching leads row by row into `leads_export_<timestamp>.csv` under `app_dir`.
+fn export_leads_csv_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    app_dir: &Path,
+    filter: &LeadExportFilter,
+) -> AppResult<PathBuf> {
+    if let Some(unknown) = filter
+        .statuses
+        .iter()
+        .find(|status| !LEAD_STATUSES.contains(&status.as_str()))
+    {
+        return Err(AppError::Validation(format!(
+            "unknown lead status: {unknown}"
+        )));
+    }
+
+    let status_clause = if filter.statuses.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "WHERE l.status IN ({})",
+            vec!["?"; filter.statuses.len()].join(", ")
+        )
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT l.id, l.phone_e164, l.first_name, l.last_name, l.status, l.consent, l.opted_out,
+                l.needs_staff_attention, l.created_at, l.last_contact_at,
+                (SELECT COUNT(*) FROM appointments a WHERE a.lead_id = l.id)
+         FROM leads l
+         {status_clause}
+         ORDER BY l.id ASC"
+    ))?;
+    let mut rows = stmt.query(params_from_iter(filter.statuses.iter()))?;
+
+    let io_err =
+        |err: std::io::Error| AppError::Validation(format!("failed to write export: {err}"));
+    let (path, file) = create_export_file(app_dir, clock, "leads_export", "csv").map_err(io_err)?;
+    let mut out = BufWriter::new(file);
+    writeln!(
+        out,
+        "id,phone_e164,first_name,last_name,status,consent,opted_out,needs_staff_attention,created_at,last_contact_at,appointment_count"
+    )
+    .map_err(io_err)?;
+    let mut exported = 0_i64;
+    while let Some(row) = rows.next()? {
+        let fields = [
+            row.get::<_, i64>(0)?.to_string(),
+            csv_field(&row.get::<_, String>(1)?),
+            csv_field(&row.get::<_, Option<String>>(2)?.unwrap_or_default()),
+            csv_field(&row.get::<_, Option<String>>(3)?.unwrap_or_default()),
+            csv_field(&row.get::<_, String>(4)?),
+            i64_to_bool(row.get(5)?).to_string(),
+            i64_to_bool(row.get(6)?).to_string(),
+            i64_to_bool(row.get(7)?).to_string(),
+            csv_field(&row.get::<_, String>(8)?),
+            csv_field(&row.get::<_, Option<String>>(9)?.unwrap_or_default()),
+            row.get::<_, i64>(10)?.to_string(),
+        ];
+        writeln!(out, "{}", fields.join(",")).map_err(io_err)?;
+        exported += 1;
+    }
+    out.flush().map_err(io_err)?;
+
+    let _ = insert_audit(
+        conn,
+        clock,
+        "export_leads_csv",
+        "lead",
+        None,
+        json!({ "statuses": filter.statuses }),
+        Some(json!({ "path": path.to_string_lossy(), "rows": exported })),
+        true,
+        None,
+    );
+    Ok(path)
+}
+
+/// Opens `<prefix>_<timestamp>.<ext>` under `app_dir` with `create_new`, adding a counter
+/// when an export from the same millisecond already claimed the name.
+fn create_export_file(
+    app_dir: &Path,
+    clock: &dyn Clock,
+    prefix: &str,
+    ext: &str,
+) -> std::io::Result<(PathBuf, File)> {
+    let stamp = clock.now().format("%Y%m%dT%H%M%S%3fZ");
+    let mut attempt = 0;
+    loop {
+        let name = if attempt == 0 {
+            format!("{prefix}_{stamp}.{ext}")
+        } else {
+            format!("{prefix}_{stamp}_{attempt}.{ext}")
+        };
+        let path = app_dir.join(name);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Deletes audit rows older than `audit_log_retention_days`; a dry run only counts them.
+fn purge_old_audit_logs_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    dry_run: bool,
+    now: DateTime<Utc>,
+) -> AppResult<PurgeResult> {
+    let retention_days = get_audit_log_retention_days_with_conn(conn)?;
+    let cutoff = (now - Duration::days(retention_days)).to_rfc3339();
+
+    let rows_affected = if dry_run {
+        conn.query_row(
+            "SELECT COUNT(*) FROM audit_log WHERE datetime(created_at) < datetime(?)",
+            params![cutoff],
+            |row| row.get(0),
+        )?
+    } else {
+        conn.execute(
+            "DELETE FROM audit_log WHERE datetime(created_at) < datetime(?)",
+            params![cutoff],
+        )? as i64
+    };
+    let oldest_kept: Option<String> = conn.query_row(
+        "SELECT MIN(created_at) FROM audit_log WHERE datetime(created_at) >= datetime(?)",
+        params![cutoff],
+        |row| row.get(0),
+    )?;
+
+    if !dry_run {
+        let _ = insert_audit(
+            conn,
+            clock,
+            "purge_audit_log",
+            "audit_log",
+            None,
+            json!({ "retention_days": retention_days, "cutoff": cutoff }),
+            Some(json!({ "rows_affected": rows_affected })),
+            true,
+            None,
+        );
+    }
+
+    Ok(PurgeResult {
+        rows_affected,
+        oldest_kept,
+    })
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn map_audit_entry(row: &rusqlite::Row<'_>) -> rusqlite::Result<AuditEntry> {
+    Ok(AuditEntry {
+        id: row.get(0)?,
+        action_type: row.get(1)?,
+        target_type: row.get(2)?,
+        target_id: row.get(3)?,
+        request_json: row.get(4)?,
+        response_json: row.get(5)?,
+        success: i64_to_bool(row.get(6)?),
+        error_message: row.get(7)?,
+        created_at: row.get(8)?,
+        severity: row.get(9)?,
+    })
+}
+
+fn map_scheduled_job(row: &rusqlite::Row<'_>) -> rusqlite::Result<ScheduledJobView> {
+    Ok(ScheduledJobView {
+        id: row.get(0)?,
+        job_type: row.get(1)?,
+        target_id: row.get(2)?,
+        execute_at: row.get(3)?,
+        status: row.get(4)?,
+        payload_json: row.get(5)?,
+        created_at: row.get(6)?,
+        retry_count: row.get(7)?,
+        max_retries: row.get(8)?,
+        completed_at: row.get(9)?,
+    })
+}
+
+/// `avg_delay_seconds` is how late completed jobs ran relative to their `execute_at`.
+fn get_job_stats_with_conn(conn: &Connection, since: Option<&str>) -> AppResult<JobStats> {
+    let since = since
+        .map(|value| parse_ts(value.trim()))
+        .transpose()?
+        .map(|value| value.to_rfc3339());
+
+    let mut stmt = conn.prepare(
+        "SELECT job_type, status, COUNT(*)
+         FROM scheduled_jobs
+         WHERE ?1 IS NULL OR datetime(created_at) >= datetime(?1)
+         GROUP BY job_type, status
+         ORDER BY job_type",
+    )?;
+    let rows = stmt.query_map(params![since], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, i64>(2)?,
+        ))
+    })?;
+
+    let mut stats = JobStats {
+        total_pending: 0,
+        total_completed: 0,
+        total_failed: 0,
+        total_cancelled: 0,
+        avg_delay_seconds: None,
+        by_type: Vec::new(),
+    };
+    for row in rows {
+        let (job_type, status, count) = row?;
+        if stats.by_type.last().map(|entry| entry.job_type.as_str()) != Some(job_type.as_str()) {
+            stats.by_type.push(JobTypeStats {
+                job_type,
+                pending: 0,
+                completed: 0,
+                failed: 0,
+                cancelled: 0,
+            });
+        }
+        let entry = stats.by_type.last_mut().expect("entry pushed above");
+        match status.as_str() {
+            "pending" => {
+                entry.pending += count;
+                stats.total_pending += count;
+            }
+            "completed" => {
+                entry.completed += count;
+                stats.total_completed += count;
+            }
+            "failed" => {
+                entry.failed += count;
+                stats.total_failed += count;
+            }
+            "cancelled" => {
+                entry.cancelled += count;
+                stats.total_cancelled += count;
+            }
+            _ => {}
+        }
+    }
+
+    stats.avg_delay_seconds = conn.query_row(
+        "SELECT AVG(strftime('%s', completed_at) - strftime('%s', execute_at))
+         FROM scheduled_jobs
+         WHERE status = 'completed'
+           AND completed_at IS NOT NULL
+           AND (?1 IS NULL OR datetime(created_at) >= datetime(?1))",
+        params![since],
+        |row| row.get(0),
+    )?;
+
+    Ok(stats)
+}
+
+fn get_scheduled_job(conn: &Connection, job_id: i64) -> AppResult<ScheduledJobView> {
+    conn.query_row(
+        "SELECT id, job_type, target_id, execute_at, status, payload_json, created_at,
+                retry_count, max_retries, completed_at
+         FROM scheduled_jobs WHERE id=?",
+        params![job_id],
+        map_scheduled_job,
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound("job not found".to_string()))
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(interval_seconds = interval_seconds, count = lead_ids.len()))]
+fn schedule_re_engagement_batch(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_ids: Vec<i64>,
+    start_at: String,
+    interval_seconds: u32,
+) -> Result<BatchScheduleResult, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        schedule_re_engagement_batch_with_conn(
+            &conn,
+            state.clock.as_ref(),
+            &lead_ids,
+            &start_at,
+            interval_seconds,
+        )
+    });
+
+    map_cmd_result(result, "schedule_re_engagement_batch", &app)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn run_due_jobs(state: State<AppState>, app: AppHandle) -> Result<RunJobsResult, String> {
+    let result = retry_db_with_policy(&RetryPolicy::aggressive(), || {
+        let conn = open_conn(&state)?;
+        run_due_jobs_with_conn(&conn, state.clock.as_ref())
+    });
+
+    map_cmd_result(result, "run_due_jobs", &app)
+}
+
+/// Non-blocking hints for an outbound send: the lead is one message from its daily cap, the
+/// location closes within 30 minutes, or the lead hasn't answered the last outbound.
+fn agent_dry_run_warnings(
+    conn: &Connection,
+    action: &AgentAction,
+    now: DateTime<Utc>,
+) -> AppResult<Vec<String>> {
+    let AgentAction::SendOutbound { lead_id, .. } = action else {
+        return Ok(Vec::new());
+    };
+    let location = get_lead_location(conn, *lead_id)?;
+    let mut warnings = Vec::new();
+
+    let limit = load_rate_limit_settings(conn)?.max_outbound_per_lead_per_day;
+    let used = count_lead_outbound_today(conn, &location, *lead_id, now)?;
+    if used >= limit - 1 {
+        warnings.push(format!(
+            "Lead has {used} of {limit} daily outbound messages used"
+        ));
+    }
+
+    if minutes_until_close(&location, now)?.is_some_and(|minutes| minutes < 30) {
+        warnings.push("Business closes in less than 30 minutes".to_string());
+    }
+
+    let convo = match get_conversation_by_lead_id(conn, *lead_id) {
+        Ok(convo) => Some(convo),
+        Err(err) if err.is_not_found() => None,
+        Err(err) => return Err(err),
+    };
+    if let Some(last_outbound_at) = convo.as_ref().and_then(|c| c.last_outbound_at.as_deref()) {
+        let last_outbound = parse_ts(last_outbound_at)?;
+        let replied = match convo.as_ref().and_then(|c| c.last_inbound_at.as_deref()) {
+            Some(last_inbound_at) => parse_ts(last_inbound_at)? >= last_outbound,
+            None => false,
+        };
+        if !replied {
+            warnings.push("Lead has not replied to last outbound".to_string());
+        }
+    }
+
+    Ok(warnings)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(action = req.action.action_type().as_str()))]
+fn agent_dry_run(
+    state: State<AppState>,
+    app: AppHandle,
+    req: AgentDryRunRequest,
+) -> Result<AgentDryRunResult, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        let gateway = ActionGateway::with_clock(&conn, state.clock.as_ref());
+
+        let validation = match &req.action {
+            AgentAction::SendOutbound {
+                lead_id,
+                conversation_id,
+                body,
+                automated,
+                allow_without_consent,
+                allow_opted_out_once,
+                allow_after_reply,
+                ignore_business_hours,
+            } => gateway.validate_agent_outbound(&OutboundRequest {
+                lead_id: *lead_id,
+                conversation_id: *conversation_id,
+                body: body.clone(),
+                automated: *automated,
+                allow_without_consent: *allow_without_consent,
+                allow_opted_out_once: *allow_opted_out_once,
+                allow_after_reply: *allow_after_reply,
+                ignore_business_hours: *ignore_business_hours,
+                rate_limit_exempt: false,
+            }),
+            AgentAction::BookAppointment {
+                lead_id,
+                start_at,
+                end_at,
+                status,
+                appointment_type_id,
+            } => gateway.validate_appointment(&AppointmentRequest {
+                lead_id: *lead_id,
+                start_at: start_at.clone(),
+                end_at: end_at.clone(),
+                status: status.clone(),
+                appointment_type_id: *appointment_type_id,
+            }),
+            AgentAction::SetOptOut { lead_id, reason } => {
+                gateway.validate_opt_out(&OptOutRequest {
+                    lead_id: *lead_id,
+                    reason: reason.clone(),
+                })
+            }
+            AgentAction::ScheduleJob {
+                job_type,
+                target_id,
+                execute_at,
+                payload_json,
+            } => gateway.validate_schedule_job(&ScheduleJobRequest {
+                job_type: job_type.clone(),
+                target_id: *target_id,
+                execute_at: execute_at.clone(),
+                payload_json: payload_json.clone(),
+            }),
+        };
+
+        let normalized = Some(serde_json::to_value(&req.action)?);
+        let warnings = agent_dry_run_warnings(&conn, &req.action, gateway.clock.now())?;
+        let response = match validation {
+            Ok(()) => AgentDryRunResult {
+                allowed: true,
+                blocked_reason: None,
+                warnings,
+                normalized,
+            },
+            Err(err) => AgentDryRunResult {
+                allowed: false,
+                blocked_reason: Some(err.to_string()),
+                warnings,
+                normalized,
+            },
+        };
+
+        let _ = insert_audit(
+            &conn,
+            state.clock.as_ref(),
+            "agent_dry_run",
+            "agent_action",
+            Some(req.action.action_type().as_str().to_string()),
+            serde_json::to_value(&req)?,
+            Some(json!({
+                "allowed": response.allowed,
+                "blocked_reason": response.blocked_reason,
+                "warnings": response.warnings
+            })),
+            response.allowed,
+            response.blocked_reason.clone(),
+        );
+
+        Ok(response)
+    });
+
+    map_cmd_result(result, "agent_dry_run", &app)
+}
+
+fn execute_agent_action(gateway: &ActionGateway, action: &AgentAction) -> AppResult<Value> {
+    match action {
+        AgentAction::SendOutbound {
+            lead_id,
+            conversation_id,
+            body,
+            automated,
+            allow_without_consent,
+            allow_opted_out_once,
+            allow_after_reply,
+            ignore_business_hours,
+        } => gateway
+            .create_outbound_message_for_agent(OutboundRequest {
+                lead_id: *lead_id,
+                conversation_id: *conversation_id,
+                body: body.clone(),
+                automated: *automated,
+                allow_without_consent: *allow_without_consent,
+                allow_opted_out_once: *allow_opted_out_once,
+                allow_after_reply: *allow_after_reply,
+                ignore_business_hours: *ignore_business_hours,
+                rate_limit_exempt: false,
+            })
+            .map(|message_id| json!({ "message_id": message_id })),
+        AgentAction::BookAppointment {
+            lead_id,
+            start_at,
+            end_at,
+            status,
+            appointment_type_id,
+        } => gateway
+            .create_appointment(AppointmentRequest {
+                lead_id: *lead_id,
+                start_at: start_at.clone(),
+                end_at: end_at.clone(),
+                status: status.clone(),
+                appointment_type_id: *appointment_type_id,
+            })
+            .map(|appointment_id| json!({ "appointment_id": appointment_id })),
+        AgentAction::SetOptOut { lead_id, reason } => gateway
+            .set_opt_out(OptOutRequest {
+                lead_id: *lead_id,
+                reason: reason.clone(),
+            })
+            .map(|_| json!({ "result": "opted_out" })),
+        AgentAction::ScheduleJob {
+            job_type,
+            target_id,
+            execute_at,
+            payload_json,
+        } => gateway
+            .schedule_job(ScheduleJobRequest {
+                job_type: job_type.clone(),
+                target_id: *target_id,
+                execute_at: execute_at.clone(),
+                payload_json: payload_json.clone(),
+            })
+            .map(|job_id| json!({ "job_id": job_id })),
+    }
+}
+
+fn agent_execute_result(execution: &AppResult<Value>) -> AgentExecuteResult {
+    match execution {
+        Ok(result_json) => AgentExecuteResult {
+            success: true,
+            result_json: Some(result_json.clone()),
+            error: None,
+        },
+        Err(err) => AgentExecuteResult {
+            success: false,
+            result_json: None,
+            error: Some(err.to_string()),
+        },
+    }
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(count = actions.len()))]
+fn agent_execute_batch(
+    state: State<AppState>,
+    app: AppHandle,
+    actions: Vec<AgentAction>,
+) -> Result<AgentBatchResult, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        agent_execute_batch_with_conn(&conn, state.clock.as_ref(), &actions)
+    });
+
+    map_cmd_result(result, "agent_execute_batch", &app)
+}
+
+/// Runs the actions in order inside one transaction and stops at the first failure, rolling back
+/// everything before it. The per-action audit rows roll back with it, so the batch writes its own.
+fn agent_execute_batch_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    actions: &[AgentAction],
+) -> AppResult<AgentBatchResult> {
+    if actions.is_empty() {
+        return Err(AppError::Validation(
+            "actions must not be empty".to_string(),
+        ));
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    let gateway = ActionGateway::with_clock(&tx, clock);
+    let mut results = Vec::new();
+    let mut batch_aborted_at = None;
+    let mut error_message = None;
+    for (index, action) in actions.iter().enumerate() {
+        let execution = execute_agent_action(&gateway, action);
+        results.push(agent_execute_result(&execution));
+        if let Err(err) = execution {
+            batch_aborted_at = Some(index);
+            error_message = Some(err.to_string());
+            break;
+        }
+    }
+    if batch_aborted_at.is_none() {
+        tx.commit()?;
+    } else {
+        tx.rollback()?;
+    }
+
+    let action_types: Vec<&str> = actions
+        .iter()
+        .map(|action| action.action_type().as_str())
+        .collect();
+    let _ = insert_audit(
+        conn,
+        clock,
+        "agent_execute_batch",
+        "agent_action",
+        None,
+        json!({ "actions": actions, "action_types": action_types }),
+        Some(json!({ "results": results, "batch_aborted_at": batch_aborted_at })),
+        batch_aborted_at.is_none(),
+        error_message,
+    );
+
+    Ok(AgentBatchResult {
+        results,
+        batch_aborted_at,
+    })
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(action = req.action.action_type().as_str()))]
+fn agent_execute(
+    state: State<AppState>,
+    app: AppHandle,
+    req: AgentExecuteRequest,
+) -> Result<AgentExecuteResult, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        agent_execute_with_conn(&conn, state.clock.as_ref(), &req)
+    });
+
+    map_cmd_result(result, "agent_execute", &app)
+}
+
+/// Only successful results are remembered, so a request that failed (e.g. on a rate limit) can
+/// be retried with the same key.
+fn agent_execute_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    req: &AgentExecuteRequest,
+) -> AppResult<AgentExecuteResult> {
+    let now = clock.now();
+    conn.execute(
+        "DELETE FROM idempotency_records WHERE datetime(created_at) < datetime(?)",
+        params![(now - Duration::hours(24)).to_rfc3339()],
+    )?;
+    let key = req
+        .idempotency_key
+        .as_deref()
+        .map(str::trim)
+        .filter(|key| !key.is_empty());
+    let request_hash = format!(
+        "{:x}",
+        Sha256::digest(serde_json::to_string(&req.action)?.as_bytes())
+    );
+    if let Some(key) = key {
+        let cached: Option<(String, Option<String>)> = conn
+            .query_row(
+                "SELECT result_json, request_hash FROM idempotency_records WHERE key=?",
+                params![key],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        if let Some((cached, cached_hash)) = cached {
+            if cached_hash.as_deref() != Some(request_hash.as_str()) {
+                return Err(AppError::Validation(
+                    "idempotency key was already used for a different action".to_string(),
+                ));
+            }
+            return Ok(serde_json::from_str(&cached)?);
+        }
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    let gateway = ActionGateway::with_clock(&tx, clock);
+    let response = agent_execute_result(&execute_agent_action(&gateway, &req.action));
+    if let (Some(key), true) = (key, response.success) {
+        tx.execute(
+            "INSERT INTO idempotency_records (key, result_json, request_hash, created_at)
+             VALUES (?, ?, ?, ?)",
+            params![
+                key,
+                serde_json::to_string(&response)?,
+                request_hash,
+                now.to_rfc3339()
+            ],
+        )?;
+    }
+    tx.commit()?;
+    Ok(response)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(lead_id = lead_id, limit = limit))]
+fn list_agent_actions(
+    state: State<AppState>,
+    app: AppHandle,
+    lead_id: Option<i64>,
+    since: Option<String>,
+    limit: Option<u32>,
+) -> Result<Vec<AgentActionRecord>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        list_agent_actions_with_conn(&conn, lead_id, since.as_deref(), limit)
+    });
+
+    map_cmd_result(result, "list_agent_actions", &app)
+}
+
+/// Newest first. Outbound messages are tied to a lead through their conversation, dry runs
+/// through the action's `lead_id` and scheduled jobs through their `target_id` (reminders, which
+/// target an appointment, are not matched).
+fn list_agent_actions_with_conn(
+    conn: &Connection,
+    lead_id: Option<i64>,
+    since: Option<&str>,
+    limit: Option<u32>,
+) -> AppResult<Vec<AgentActionRecord>> {
+    let limit = limit.unwrap_or(100);
+    if !(1..=500).contains(&limit) {
+        return Err(AppError::Validation(
+            "limit must be between 1 and 500".to_string(),
+        ));
+    }
+    let since = since
+        .map(|value| parse_ts(value.trim()))
+        .transpose()?
+        .map(|value| value.to_rfc3339());
+
+    let mut stmt = conn.prepare(
+        "SELECT a.id, a.action_type, a.target_type, a.success, a.request_json, a.response_json,
+                a.created_at
+         FROM audit_log a
+         LEFT JOIN conversations c
+           ON a.target_type = 'conversation' AND c.id = CAST(a.target_id AS INTEGER)
+         WHERE a.action_type IN ('agent_dry_run', 'create_outbound_message', 'create_appointment',
+                                 'set_opt_out', 'schedule_job')
+           AND (?1 IS NULL
+                OR (a.target_type = 'lead' AND a.target_id = CAST(?1 AS TEXT))
+                OR c.lead_id = ?1
+                OR (a.action_type = 'agent_dry_run'
+                    AND json_extract(a.request_json, '$.action.lead_id') = ?1)
+                OR (a.action_type = 'schedule_job'
+                    AND json_extract(a.request_json, '$.job_type') <> 'appointment_reminder'
+                    AND json_extract(a.request_json, '$.target_id') = ?1))
+           AND (?2 IS NULL OR datetime(a.created_at) >= datetime(?2))
+         ORDER BY a.id DESC
+         LIMIT ?3",
+    )?;
+    let rows = stmt.query_map(params![lead_id, since, limit], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            i64_to_bool(row.get(3)?),
+            row.get::<_, String>(4)?,
+            row.get::<_, Option<String>>(5)?,
+            row.get::<_, String>(6)?,
+        ))
+    })?;
+
+    let mut records = Vec::new();
+    for row in rows {
+        let (id, action_type, target_type, success, request_json, response_json, created_at) = row?;
+        records.push(AgentActionRecord {
+            id,
+            action_type,
+            target_type,
+            success,
+            request_json: serde_json::from_str(&request_json)?,
+            response_json: response_json
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()?,
+            created_at,
+        });
+    }
+    Ok(records)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(source = %source))]
+fn log_client_error(
+    state: State<AppState>,
+    _app: AppHandle,
+    message: String,
+    stack: Option<String>,
+    source: String,
+) -> Result<(), String> {
+    let stack = stack.filter(|text| !text.trim().is_empty());
+    let stored = retry_db(|| {
+        let conn = open_conn(&state)?;
+        log_client_error_with_conn(
+            &conn,
+            state.clock.as_ref(),
+            &message,
+            stack.as_deref(),
+            &source,
+        )
+    });
+    // app.log still gets the error when the database can't take it.
+    if let Err(err) = stored {
+        tracing::error!(target: "client", source = %source, stack = ?stack, db_error = %err, "{message}");
+    }
+    Ok(())
+}
+
+fn log_client_error_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    message: &str,
+    stack: Option<&str>,
+    source: &str,
+) -> AppResult<()> {
+    insert_audit(
+        conn,
+        clock,
+        "client_error",
+        "frontend",
+        None,
+        json!({ "message": message, "stack": stack, "source": source }),
+        None,
+        false,
+        Some(message.to_string()),
+    )?;
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(limit = limit))]
+fn get_client_errors(
+    state: State<AppState>,
+    app: AppHandle,
+    limit: u32,
+) -> Result<Vec<AuditEntry>, String> {
+    let result = retry_db(|| {
+        let conn = open_conn(&state)?;
+        get_client_errors_with_conn(&conn, limit)
+    });
+
+    map_cmd_result(result, "get_client_errors", &app)
+}
+
+fn get_client_errors_with_conn(conn: &Connection, limit: u32) -> AppResult<Vec<AuditEntry>> {
+    if !(1..=500).contains(&limit) {
+        return Err(AppError::Validation(
+            "limit must be between 1 and 500".to_string(),
+        ));
+    }
+    let mut stmt = conn.prepare(
+        "SELECT id, action_type, target_type, target_id, request_json, response_json, success,
+                error_message, created_at, severity
+         FROM audit_log
+         WHERE action_type = 'client_error'
+         ORDER BY id DESC
+         LIMIT ?",
+    )?;
+    let rows = stmt.query_map(params![limit], map_audit_entry)?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+fn open_devtools(app: AppHandle) -> Result<(), String> {
+    let window = app
+        .get_window("main")
+        .ok_or_else(|| "main window not found".to_string())?;
+    window.open_devtools();
+    Ok(())
+}
+
+fn execute_initial_follow_up(
+    conn: &Connection,
+    clock: &dyn Clock,
+    lead_id: i64,
+    step_number: i64,
+) -> AppResult<()> {
+    let lead = get_lead(conn, lead_id)?;
+    // Later touches only go to leads that still have not replied YES.
+    if step_number > 1 && get_lead_status(conn, lead_id)? != "awaiting_yes" {
+        return Ok(());
+    }
+    let conversation = get_conversation_by_lead_id(conn, lead_id)?;
+    let location = get_lead_location(conn, lead_id)?;
+    let gateway = ActionGateway::with_clock(conn, clock);
+    let step = get_follow_up_step(conn, location.id, step_number)?;
+    let template_key = step
+        .as_ref()
+        .map(|step| step.template_key.as_str())
+        .unwrap_or("initial_follow_up");
+
+    let display_name = lead.first_name.as_deref().unwrap_or("there");
+
+    gateway.create_outbound_message(OutboundRequest {
+        lead_id,
+        conversation_id: conversation.id,
+        body: render_template(
+            conn,
+            template_key,
+            HashMap::from([("name", display_name), ("gym", location.gym_name.as_str())]),
+        )?,
+        automated: true,
+        allow_without_consent: false,
+        allow_opted_out_once: false,
+        allow_after_reply: false,
+        ignore_business_hours: false,
+        rate_limit_exempt: false,
+    })?;
+
+    conn.execute(
+        "UPDATE leads SET next_action_at=NULL WHERE id=?",
+        params![lead_id],
+    )?;
+
+    if get_lead_status(conn, lead_id)? == "awaiting_yes" {
+        if let (Some(step), Some(next)) = (
+            step,
+            get_follow_up_step(conn, location.id, step_number + 1)?,
+        ) {
+            // Step delays are measured from the start of the sequence.
+            let execute_at = clock.now() + Duration::hours(next.delay_hours - step.delay_hours);
+            let scheduled = gateway.schedule_job(ScheduleJobRequest {
+                job_type: "initial_follow_up".to_string(),
+                target_id: Some(lead_id),
+                execute_at: execute_at.to_rfc3339(),
+                payload_json: serde_json::to_string(&InitialFollowUpPayload {
+                    lead_id,
+                    step_number: next.step_number,
+                })?,
+            });
+            // This step's message is already out, so failing the job would only resend it on
+            // retry; the gateway audits the refusal and staff pick the sequence up from here.
+            if scheduled.is_err() {
+                flag_needs_staff_attention(conn, clock, lead_id, "follow_up_sequence_interrupted")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn get_lead_status(conn: &Connection, lead_id: i64) -> AppResult<String> {
+    conn.query_row(
+        "SELECT status FROM leads WHERE id=?",
+        params![lead_id],
+        |row| row.get(0),
+    )
+    .map_err(AppError::from)
+}
+
+fn execute_appointment_reminder(
+    conn: &Connection,
+    clock: &dyn Clock,
+    payload: ReminderPayload,
+) -> AppResult<()> {
+    let lead = get_lead(conn, payload.lead_id)?;
+    let conversation = get_conversation_by_lead_id(conn, payload.lead_id)?;
+    let gateway = ActionGateway::with_clock(conn, clock);
+
+    let appointment_type_id: Option<i64> = conn
+        .query_row(
+            "SELECT appointment_type_id FROM appointments WHERE id=?",
+            params![payload.appointment_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+    let appointment_type = get_appointment_type(conn, appointment_type_id)?;
+    let config_template: Option<String> = match payload.reminder_config_id {
+        Some(config_id) => conn
+            .query_row(
+                "SELECT template FROM reminder_configs WHERE id=?",
+                params![config_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten(),
+        None => None,
+    };
+    let template = config_template.unwrap_or(appointment_type.reminder_template);
+    let location = get_lead_location(conn, payload.lead_id)?;
+    let local_start = local_display(&location, &payload.start_at)?;
+
+    gateway.create_outbound_message(OutboundRequest {
+        lead_id: payload.lead_id,
+        conversation_id: conversation.id,
+        body: render_appointment_template(&template, &lead, &local_start, &location, ""),
+        automated: true,
+        allow_without_consent: false,
+        allow_opted_out_once: false,
+        allow_after_reply: false,
+        ignore_business_hours: false,
+        rate_limit_exempt: false,
+    })?;
+
+    Ok(())
+}
+
+fn execute_no_show_follow_up(
+    conn: &Connection,
+    clock: &dyn Clock,
+    payload: NoShowFollowUpPayload,
+) -> AppResult<()> {
+    let lead = get_lead(conn, payload.lead_id)?;
+    if lead.opted_out {
+        return Ok(());
+    }
+
+    let conversation = get_conversation_by_lead_id(conn, payload.lead_id)?;
+    let gateway = ActionGateway::with_clock(conn, clock);
+    gateway.create_outbound_message(OutboundRequest {
+        lead_id: payload.lead_id,
+        conversation_id: conversation.id,
+        body: render_template(conn, "no_show_follow_up", HashMap::new())?,
+        automated: true,
+        allow_without_consent: false,
+        allow_opted_out_once: false,
+        allow_after_reply: false,
+        ignore_business_hours: false,
+        rate_limit_exempt: false,
+    })?;
+
+    reset_conversation_state(conn, conversation.id)?;
+    set_lead_status(
+        conn,
+        clock,
+        payload.lead_id,
+        "awaiting_yes",
+        "no_show_follow_up",
+    )?;
+    Ok(())
+}
+
+/// Asks for a 1–5 rating of a completed session and waits for it in `awaiting_rating`.
+fn execute_post_appointment_follow_up(
+    conn: &Connection,
+    clock: &dyn Clock,
+    payload: PostAppointmentFollowUpPayload,
+) -> AppResult<()> {
+    let lead = get_lead(conn, payload.lead_id)?;
+    if lead.opted_out {
+        return Ok(());
+    }
+
+    let conversation = get_conversation_by_lead_id(conn, payload.lead_id)?;
+    let location = get_lead_location(conn, payload.lead_id)?;
+    let gateway = ActionGateway::with_clock(conn, clock);
+    let display_name = lead.first_name.as_deref().unwrap_or("there");
+    gateway.create_outbound_message(OutboundRequest {
+        lead_id: payload.lead_id,
+        conversation_id: conversation.id,
+        body: render_template(
+            conn,
+            "post_appointment_follow_up",
+            HashMap::from([("name", display_name), ("gym", location.gym_name.as_str())]),
+        )?,
+        automated: true,
+        allow_without_consent: false,
+        allow_opted_out_once: false,
+        allow_after_reply: false,
+        ignore_business_hours: false,
+        rate_limit_exempt: false,
+    })?;
+
+    conn.execute(
+        "UPDATE conversations SET state='awaiting_rating', state_json=?, repair_attempts=0 WHERE id=?",
+        params![
+            serde_json::to_string(&ConversationState::default())?,
+            conversation.id
+        ],
+    )?;
+    set_lead_status(
+        conn,
+        clock,
+        payload.lead_id,
+        "awaiting_rating",
+        "post_appointment_follow_up",
+    )?;
+    Ok(())
+}
+
+fn execute_re_engagement(
+    conn: &Connection,
+    clock: &dyn Clock,
+    payload: ReEngagementPayload,
+) -> AppResult<()> {
+    let lead = get_lead(conn, payload.lead_id)?;
+    let gateway = ActionGateway::with_clock(conn, clock);
+    // A lead who booked after the batch was queued keeps their booking and conversation.
+    if lead.opted_out || upcoming_appointment_id(conn, lead.id, gateway.clock.now())?.is_some() {
+        return Ok(());
+    }
+
+    let conversation = get_conversation_by_lead_id(conn, payload.lead_id)?;
+    let location = get_lead_location(conn, payload.lead_id)?;
+    let display_name = lead.first_name.as_deref().unwrap_or("there");
+    gateway.create_outbound_message(OutboundRequest {
+        lead_id: payload.lead_id,
+        conversation_id: conversation.id,
+        body: render_template(
+            conn,
+            "re_engagement",
+            HashMap::from([("name", display_name), ("gym", location.gym_name.as_str())]),
+        )?,
+        automated: true,
+        allow_without_consent: false,
+        allow_opted_out_once: false,
+        allow_after_reply: false,
+        ignore_business_hours: false,
+        rate_limit_exempt: false,
+    })?;
+
+    reset_conversation_state(conn, conversation.id)?;
+    set_lead_status(
+        conn,
+        clock,
+        payload.lead_id,
+        "awaiting_yes",
+        "re_engagement",
+    )?;
+    Ok(())
+}
+
+/// The lead's next booked appointment that has not started yet.
+fn upcoming_appointment_id(
+    conn: &Connection,
+    lead_id: i64,
+    now: DateTime<Utc>,
+) -> AppResult<Option<i64>> {
+    Ok(conn
+        .query_row(
+            "SELECT id FROM appointments
+             WHERE lead_id=? AND status='booked' AND datetime(start_at) > datetime(?)
+             ORDER BY datetime(start_at) LIMIT 1",
+            params![lead_id, now.to_rfc3339()],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+/// Queues one `re_engagement` job per contactable lead, spaced `interval_seconds` apart so a
+/// large batch does not trip the per-location hourly rate limit all at once.
+fn schedule_re_engagement_batch_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    lead_ids: &[i64],
+    start_at: &str,
+    interval_seconds: u32,
+) -> AppResult<BatchScheduleResult> {
+    if lead_ids.len() > 500 {
+        return Err(AppError::Validation(
+            "re-engagement batches are limited to 500 leads".to_string(),
+        ));
+    }
+    if interval_seconds < 5 {
+        return Err(AppError::Validation(
+            "interval_seconds must be at least 5".to_string(),
+        ));
+    }
+    let start = parse_ts(start_at)?;
+
+    let gateway = ActionGateway::with_clock(conn, clock);
+    let mut result = BatchScheduleResult {
+        scheduled: 0,
+        skipped: 0,
+        errors: Vec::new(),
+    };
+
+    for &lead_id in lead_ids {
+        let lead = match get_lead(conn, lead_id) {
+            Ok(lead) => lead,
+            Err(err) => {
+                result.errors.push((lead_id, err.to_string()));
+                continue;
+            }
+        };
+        if lead.opted_out
+            || !lead.consent
+            || upcoming_appointment_id(conn, lead_id, gateway.clock.now())?.is_some()
+        {
+            result.skipped += 1;
+            continue;
+        }
+
+        let execute_at =
+            start + Duration::seconds(i64::from(result.scheduled) * i64::from(interval_seconds));
+        let scheduled = gateway.schedule_job(ScheduleJobRequest {
+            job_type: "re_engagement".to_string(),
+            target_id: Some(lead_id),
+            execute_at: execute_at.to_rfc3339(),
+            payload_json: serde_json::to_string(&ReEngagementPayload { lead_id })?,
+        });
+        match scheduled {
+            Ok(_) => result.scheduled += 1,
+            Err(err) => result.errors.push((lead_id, err.to_string())),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Flags leads that went quiet while the bot was waiting on them. Leads already flagged are left
+/// alone so the daily sweep does not re-flag the same conversation every run.
+fn execute_expire_stale_conversations(conn: &Connection, clock: &dyn Clock) -> AppResult<()> {
+    let expiry_hours = get_conversation_expiry_hours_with_conn(conn)?;
+    let mut stmt = conn.prepare(
+        "SELECT c.id, c.lead_id
+         FROM conversations c
+         JOIN leads l ON l.id = c.lead_id
+         WHERE c.state IN ('awaiting_yes', 'awaiting_time_choice')
+           AND l.opted_out = 0
+           AND l.needs_staff_attention = 0
+           AND c.last_outbound_at IS NOT NULL
+           AND datetime(c.last_outbound_at) < datetime(?)
+           AND (c.last_inbound_at IS NULL
+                OR datetime(c.last_inbound_at) < datetime(c.last_outbound_at))",
+    )?;
+    let cutoff = (clock.now() - Duration::hours(expiry_hours)).to_rfc3339();
+    let rows = stmt.query_map(params![cutoff], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+    })?;
+    let mut stale = Vec::new();
+    for row in rows {
+        stale.push(row?);
+    }
+
+    for (conversation_id, lead_id) in stale {
+        reset_conversation_state(conn, conversation_id)?;
+        set_lead_status(conn, clock, lead_id, "awaiting_yes", "conversation_expired")?;
+        flag_needs_staff_attention(conn, clock, lead_id, "conversation_expired")?;
+    }
+
+    schedule_conversation_expiry(
+        conn,
+        clock,
+        &(clock.now() + Duration::hours(24)).to_rfc3339(),
+    )?;
+    Ok(())
+}
+
+// The sweep is internal housekeeping rather than outreach, so it is queued directly instead of
+// through the gateway, which refuses to schedule anything while the kill switch is on.
+fn schedule_conversation_expiry(
+    conn: &Connection,
+    clock: &dyn Clock,
+    execute_at: &str,
+) -> AppResult<i64> {
+    conn.execute(
+        "INSERT INTO scheduled_jobs (job_type, target_id, execute_at, status, payload_json, created_at)
+         VALUES ('expire_stale_conversations', NULL, ?, 'pending', '{}', ?)",
+        params![execute_at, clock.now().to_rfc3339()],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn execute_purge_audit_log(conn: &Connection, clock: &dyn Clock) -> AppResult<()> {
+    purge_old_audit_logs_with_conn(conn, clock, false, clock.now())?;
+    schedule_audit_log_purge(
+        conn,
+        clock,
+        &(clock.now() + Duration::hours(24)).to_rfc3339(),
+    )?;
+    Ok(())
+}
+
+fn schedule_audit_log_purge(
+    conn: &Connection,
+    clock: &dyn Clock,
+    execute_at: &str,
+) -> AppResult<i64> {
+    conn.execute(
+        "INSERT INTO scheduled_jobs (job_type, target_id, execute_at, status, payload_json, created_at)
+         VALUES ('purge_audit_log', NULL, ?, 'pending', '{}', ?)",
+        params![execute_at, clock.now().to_rfc3339()],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Queues the daily housekeeping sweeps if they are not already pending.
+fn ensure_housekeeping_jobs(conn: &Connection, clock: &dyn Clock) -> AppResult<()> {
+    ensure_conversation_expiry_job(conn, clock)?;
+
+    let pending: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM scheduled_jobs
+         WHERE job_type='purge_audit_log' AND status='pending'",
+        params![],
+        |row| row.get(0),
+    )?;
+    if pending == 0 {
+        schedule_audit_log_purge(conn, clock, &clock.now().to_rfc3339())?;
+    }
+    Ok(())
+}
+
+fn ensure_conversation_expiry_job(conn: &Connection, clock: &dyn Clock) -> AppResult<()> {
+    let pending: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM scheduled_jobs
+         WHERE job_type='expire_stale_conversations' AND status='pending'",
+        params![],
+        |row| row.get(0),
+    )?;
+    if pending == 0 {
+        schedule_conversation_expiry(conn, clock, &clock.now().to_rfc3339())?;
+    }
+    Ok(())
+}
+
+fn reset_conversation_state(conn: &Connection, conversation_id: i64) -> AppResult<()> {
+    conn.execute(
+        "UPDATE conversations SET state='awaiting_yes', state_json=?, repair_attempts=0 WHERE id=?",
+        params![
+            serde_json::to_string(&ConversationState::default())?,
+            conversation_id
+        ],
+    )?;
+    Ok(())
+}
+
+fn process_inbound_state_machine(
+    conn: &Connection,
+    clock: &dyn Clock,
+    lead: &LeadRow,
+    conversation: &ConversationRow,
+    inbound_body: &str,
+) -> AppResult<()> {
+    let location = &get_lead_location(conn, lead.id)?;
+    let gateway = ActionGateway::with_clock(conn, clock);
+    let normalized = inbound_body.trim().to_ascii_uppercase();
+    let now = clock.now();
+    let stop_keywords = get_stop_keywords_with_conn(conn)?;
+
+    if stop_keywords.contains(&normalized) {
+        gateway.set_opt_out(OptOutRequest {
+            lead_id: lead.id,
+            reason: "lead sent stop keyword".to_string(),
+        })?;
+
+        gateway.create_outbound_message(OutboundRequest {
+            lead_id: lead.id,
+            conversation_id: conversation.id,
+            body: render_template(conn, "opt_out_confirmation", HashMap::new())?,
+            automated: false,
+            allow_without_consent: true,
+            allow_opted_out_once: true,
+            allow_after_reply: true,
+            ignore_business_hours: true,
+            rate_limit_exempt: false,
+        })?;
+
+        return Ok(());
+    }
+
+    if lead.opted_out {
+        return Ok(());
+    }
+
+    if normalized == "HELP" {
+        gateway.send_help_response(lead.id, conversation.id)?;
+        return Ok(());
+    }
+
+    if let Some(response_body) = find_active_custom_keyword_response(conn, &normalized)? {
+        gateway.create_outbound_message(OutboundRequest {
+            lead_id: lead.id,
+            conversation_id: conversation.id,
+            body: response_body,
+            automated: false,
+            allow_without_consent: false,
+            allow_opted_out_once: false,
+            allow_after_reply: true,
+            ignore_business_hours: true,
+            rate_limit_exempt: false,
+        })?;
+        return Ok(());
+    }
+
+    if let Some(last_outbound_at) = &conversation.last_outbound_at {
+        let last_outbound = parse_ts(last_outbound_at)?;
+        if now.signed_duration_since(last_outbound) >= Duration::hours(24) {
+            gateway.create_outbound_message(OutboundRequest {
+                lead_id: lead.id,
+                conversation_id: conversation.id,
+                body: render_template(conn, "reply_yes_prompt", HashMap::new())?,
+                automated: false,
+                allow_without_consent: false,
+                allow_opted_out_once: false,
+                allow_after_reply: true,
+                ignore_business_hours: true,
+                rate_limit_exempt: false,
+            })?;
+
+            let previous_state: ConversationState =
+                serde_json::from_str(&conversation.state_json).unwrap_or_default();
+            let state = serde_json::to_string(&ConversationState {
+                offered_slots: Vec::new(),
+                appointment_type_id: previous_state.appointment_type_id,
+            })?;
+            conn.execute(
+                "UPDATE conversations SET state='awaiting_yes', state_json=?, repair_attempts=0 WHERE id=?",
+                params![state, conversation.id],
+            )?;
+            set_lead_status(conn, clock, lead.id, "awaiting_yes", "inbound_after_24h")?;
+            return Ok(());
+        }
+    }
+
+    match conversation.state.as_str() {
+        "awaiting_yes" => {
+            if normalized == "YES" || normalized == "Y" {
+                let previous_state: ConversationState =
+                    serde_json::from_str(&conversation.state_json).unwrap_or_default();
+                let offered = generate_slot_choices_for_type(
+                    conn,
+                    location,
+                    previous_state.appointment_type_id,
+                    now,
+                )?;
+                if offered.len() < 2 {
+                    join_appointment_waitlist_with_conn(conn, clock, lead.id)?;
+                    gateway.create_outbound_message(OutboundRequest {
+                        lead_id: lead.id,
+                        conversation_id: conversation.id,
+                        body: render_template(conn, "waitlist_joined", HashMap::new())?,
+                        automated: false,
+                        allow_without_consent: false,
+                        allow_opted_out_once: false,
+                        allow_after_reply: true,
+                        ignore_business_hours: true,
+                        rate_limit_exempt: false,
+                    })?;
+                    return Ok(());
+                }
+
+                let state = ConversationState {
+                    offered_slots: offered.clone(),
+                    appointment_type_id: previous_state.appointment_type_id,
+                };
+                conn.execute(
+                    "UPDATE conversations SET state='awaiting_time_choice', state_json=?, repair_attempts=0 WHERE id=?",
+                    params![serde_json::to_string(&state)?, conversation.id],
+                )?;
+                set_lead_status(conn, clock, lead.id, "awaiting_time_choice", "inbound_yes")?;
+
+                gateway.create_outbound_message(OutboundRequest {
+                    lead_id: lead.id,
+                    conversation_id: conversation.id,
+                    body: format_slot_offer(conn, location, &offered)?,
+                    automated: false,
+                    allow_without_consent: false,
+                    allow_opted_out_once: false,
+                    allow_after_reply: true,
+                    ignore_business_hours: true,
+                    rate_limit_exempt: false,
+                })?;
+            } else {
+                gateway.create_outbound_message(OutboundRequest {
+                    lead_id: lead.id,
+                    conversation_id: conversation.id,
+                    body: render_template(conn, "reply_yes_prompt", HashMap::new())?,
+                    automated: false,
+                    allow_without_consent: false,
+                    allow_opted_out_once: false,
+                    allow_after_reply: true,
+                    ignore_business_hours: true,
+                    rate_limit_exempt: false,
+                })?;
+            }
+        }
+        "awaiting_time_choice" => {
+            let state: ConversationState =
+                serde_json::from_str(&conversation.state_json).unwrap_or_default();
+            if normalized == "1" || normalized == "2" {
+                let index = if normalized == "1" { 0 } else { 1 };
+                if let Some(slot) = state.offered_slots.get(index).cloned() {
+                    let appointment_type = get_appointment_type(conn, state.appointment_type_id)?;
+                    let appointment_id = gateway.create_appointment(AppointmentRequest {
+                        lead_id: lead.id,
+                        start_at: slot.start_at.clone(),
+                        end_at: slot.end_at.clone(),
+                        status: "booked".to_string(),
+                        appointment_type_id: Some(appointment_type.id),
+                    })?;
+
+                    conn.execute(
+                        "UPDATE conversations SET state='booked', repair_attempts=0, state_json=? WHERE id=?",
+                        params![serde_json::to_string(&ConversationState::default())?, conversation.id],
+                    )?;
+
+                    let local_slot = local_display(location, &slot.start_at)?;
+                    gateway.create_outbound_message(OutboundRequest {
+                        lead_id: lead.id,
+                        conversation_id: conversation.id,
+                        body: render_appointment_template(
+                            &appointment_type.confirmation_template,
+                            lead,
+                            &local_slot,
+                            location,
+                            &reminder_notice(conn, location)?,
+                        ),
+                        automated: false,
+                        allow_without_consent: false,
+                        allow_opted_out_once: false,
+                        allow_after_reply: true,
+                        ignore_business_hours: true,
+                        rate_limit_exempt: false,
+                    })?;
+
+                    schedule_appointment_reminders(
+                        &gateway,
+                        lead.id,
+                        appointment_id,
+                        &slot.start_at,
+                    )?;
+                } else {
+                    handle_time_choice_repair(&gateway, location, lead, conversation)?;
+                }
+            } else {
+                handle_time_choice_repair(&gateway, location, lead, conversation)?;
+            }
+        }
+        "booked" => {
+            gateway.create_outbound_message(OutboundRequest {
+                lead_id: lead.id,
+                conversation_id: conversation.id,
+                body: render_template(conn, "already_booked", HashMap::new())?,
+                automated: false,
+                allow_without_consent: false,
+                allow_opted_out_once: false,
+                allow_after_reply: true,
+                ignore_business_hours: true,
+                rate_limit_exempt: false,
+            })?;
+        }
+        "awaiting_rating" => {
+            let rating = match normalized.as_str() {
+                "1" | "2" | "3" | "4" | "5" => normalized.parse::<i64>().ok(),
+                _ => None,
+            };
+            let Some(rating) = rating else {
+                gateway.create_outbound_message(OutboundRequest {
+                    lead_id: lead.id,
+                    conversation_id: conversation.id,
+                    body: render_template(conn, "rating_repair", HashMap::new())?,
+                    automated: false,
+                    allow_without_consent: false,
+                    allow_opted_out_once: false,
+                    allow_after_reply: true,
+                    ignore_business_hours: true,
+                    rate_limit_exempt: false,
+                })?;
+                return Ok(());
+            };
+
+            let appointment_id: Option<i64> = conn
+                .query_row(
+                    "SELECT id FROM appointments
+                     WHERE lead_id=? AND status='completed'
+                       AND id NOT IN (SELECT appointment_id FROM appointment_ratings)
+                     ORDER BY datetime(end_at) DESC, id DESC
+                     LIMIT 1",
+                    params![lead.id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(appointment_id) = appointment_id {
+                conn.execute(
+                    "INSERT INTO appointment_ratings (appointment_id, lead_id, rating, created_at)
+                     VALUES (?, ?, ?, ?)",
+                    params![appointment_id, lead.id, rating, now.to_rfc3339()],
+                )?;
+            }
+            reset_conversation_state(conn, conversation.id)?;
+            set_lead_status(conn, clock, lead.id, "awaiting_yes", "appointment_rating")?;
+
+            gateway.create_outbound_message(OutboundRequest {
+                lead_id: lead.id,
+                conversation_id: conversation.id,
+                body: render_template(conn, "rating_thanks", HashMap::new())?,
+                automated: false,
+                allow_without_consent: false,
+                allow_opted_out_once: false,
+                allow_after_reply: true,
+                ignore_business_hours: true,
+                rate_limit_exempt: false,
+            })?;
+        }
+        _ => {
+            conn.execute(
+                "UPDATE conversations SET state='awaiting_yes', state_json=?, repair_attempts=0 WHERE id=?",
+                params![serde_json::to_string(&ConversationState::default())?, conversation.id],
+            )?;
+            gateway.create_outbound_message(OutboundRequest {
+                lead_id: lead.id,
+                conversation_id: conversation.id,
+                body: render_template(conn, "reply_yes_prompt", HashMap::new())?,
+                automated: false,
+                allow_without_consent: false,
+                allow_opted_out_once: false,
+                allow_after_reply: true,
+                ignore_business_hours: true,
+                rate_limit_exempt: false,
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) fn test_run_migrations(conn: &Connection) -> Result<(), String> {
+    run_migrations(conn, MIGRATIONS)
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+pub(crate) fn test_execute_initial_follow_up(
+    conn: &Connection,
+    clock: &FakeClock,
+    lead_id: i64,
+) -> Result<(), String> {
+    execute_initial_follow_up(conn, clock, lead_id, 1).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+pub(crate) fn test_process_inbound_state_machine(
+    conn: &Connection,
+    clock: &FakeClock,
+    lead_id: i64,
+    inbound_body: &str,
+) -> Result<(), String> {
+    let body = inbound_body.trim();
+    if body.is_empty() {
+        return Err("inbound body cannot be empty".to_string());
+    }
+
+    let conversation = get_conversation_by_lead_id(conn, lead_id).map_err(|err| err.to_string())?;
+
+    let now = clock.now().to_rfc3339();
+    conn.execute(
+        "INSERT INTO messages (conversation_id, direction, body, status, created_at)
+         VALUES (?, 'INBOUND', ?, 'received', ?)",
+        params![conversation.id, body, now],
+    )
+    .map_err(|err| err.to_string())?;
+    conn.execute(
+        "UPDATE conversations SET last_inbound_at=? WHERE id=?",
+        params![now, conversation.id],
+    )
+    .map_err(|err| err.to_string())?;
+    conn.execute(
+        "UPDATE leads SET last_contact_at=? WHERE id=?",
+        params![now, lead_id],
+    )
+    .map_err(|err| err.to_string())?;
+
+    let lead = get_lead(conn, lead_id).map_err(|err| err.to_string())?;
+    let refreshed_conversation =
+        get_conversation_by_lead_id(conn, lead_id).map_err(|err| err.to_string())?;
+
+    process_inbound_state_machine(conn, clock, &lead, &refreshed_conversation, body)
+        .map_err(|err| err.to_string())
+}
+
+// Reminder scheduling is best-effort: a blocked job (e.g. kill switch) must not undo the booking.
+fn schedule_appointment_reminders(
+    gateway: &ActionGateway,
+    lead_id: i64,
+    appointment_id: i64,
+    start_at: &str,
+) -> AppResult<Vec<i64>> {
+    let location = get_lead_location(gateway.conn, lead_id)?;
+    if !location.reminder_enabled {
+        return Ok(Vec::new());
+    }
+
+    let start = parse_ts(start_at)?;
+    let mut job_ids = Vec::new();
+    for config in list_reminder_configs(gateway.conn, location.id)? {
+        let reminder_at = start - Duration::hours(config.hours_before);
+        if reminder_at <= gateway.clock.now() {
+            continue;
+        }
+
+        let scheduled = gateway.schedule_job(ScheduleJobRequest {
+            job_type: "appointment_reminder".to_string(),
+            target_id: Some(appointment_id),
+            execute_at: reminder_at.to_rfc3339(),
+            payload_json: serde_json::to_string(&ReminderPayload {
+                lead_id,
+                appointment_id,
+                start_at: start_at.to_string(),
+                reminder_config_id: Some(config.id),
+            })?,
+        });
+        if let Ok(job_id) = scheduled {
+            job_ids.push(job_id);
+        }
+    }
+    Ok(job_ids)
+}
+
+fn map_follow_up_step(row: &rusqlite::Row<'_>) -> rusqlite::Result<FollowUpStep> {
+    Ok(FollowUpStep {
+        id: row.get(0)?,
+        location_id: row.get(1)?,
+        step_number: row.get(2)?,
+        delay_hours: row.get(3)?,
+        template_key: row.get(4)?,
+    })
+}
+
+fn get_follow_up_step(
+    conn: &Connection,
+    location_id: i64,
+    step_number: i64,
+) -> AppResult<Option<FollowUpStep>> {
+    conn.query_row(
+        "SELECT id, location_id, step_number, delay_hours, template_key
+         FROM follow_up_sequences WHERE location_id=? AND step_number=?",
+        params![location_id, step_number],
+        map_follow_up_step,
+    )
+    .optional()
+    .map_err(AppError::from)
+}
+
+fn list_follow_up_sequence_with_conn(
+    conn: &Connection,
+    location_id: i64,
+) -> AppResult<Vec<FollowUpStep>> {
+    get_location_settings_with_conn(conn, location_id)?;
+    let mut stmt = conn.prepare(
+        "SELECT id, location_id, step_number, delay_hours, template_key
+         FROM follow_up_sequences WHERE location_id=? ORDER BY step_number",
+    )?;
+    let rows = stmt.query_map(params![location_id], map_follow_up_step)?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+/// Steps stay contiguous from 1 and each step must wait longer than the one before it.
+fn upsert_follow_up_step_with_conn(
+    conn: &Connection,
+    location_id: i64,
+    step_number: i64,
+    delay_hours: i64,
+    template_key: &str,
+) -> AppResult<FollowUpStep> {
+    let steps = list_follow_up_sequence_with_conn(conn, location_id)?;
+    if step_number < 1 || step_number > steps.len() as i64 + 1 {
+        return Err(AppError::Validation(format!(
+            "step_number must be between 1 and {}",
+            steps.len() + 1
+        )));
+    }
+    if delay_hours < 0 {
+        return Err(AppError::Validation(
+            "delay_hours must not be negative".to_string(),
+        ));
+    }
+    let template_key = template_key.trim();
+    get_template_with_conn(conn, template_key)?;
+
+    let previous = steps
+        .iter()
+        .find(|step| step.step_number == step_number - 1);
+    if let Some(previous) = previous {
+        if delay_hours <= previous.delay_hours {
+            return Err(AppError::Validation(format!(
+                "delay_hours must be greater than step {}'s {} hours",
+                previous.step_number, previous.delay_hours
+            )));
+        }
+    }
+    let next = steps
+        .iter()
+        .find(|step| step.step_number == step_number + 1);
+    if let Some(next) = next {
+        if delay_hours >= next.delay_hours {
+            return Err(AppError::Validation(format!(
+                "delay_hours must be less than step {}'s {} hours",
+                next.step_number, next.delay_hours
+            )));
+        }
+    }
+
+    conn.execute(
+        "INSERT INTO follow_up_sequences (location_id, step_number, delay_hours, template_key)
+         VALUES (?, ?, ?, ?)
+         ON CONFLICT(location_id, step_number)
+         DO UPDATE SET delay_hours=excluded.delay_hours, template_key=excluded.template_key",
+        params![location_id, step_number, delay_hours, template_key],
+    )?;
+    get_follow_up_step(conn, location_id, step_number)?
+        .ok_or_else(|| AppError::NotFound("follow-up step not found".to_string()))
+}
+
+/// The sentence the booking confirmation uses for `{reminder_notice}`, e.g. "We will send
+/// reminders 24 hours and 2 hours before."; empty when the location sends none.
+fn reminder_notice(conn: &Connection, location: &Location) -> AppResult<String> {
+    if !location.reminder_enabled {
+        return Ok(String::new());
+    }
+    let offsets: Vec<String> = list_reminder_configs(conn, location.id)?
+        .iter()
+        .map(|config| match config.hours_before {
+            1 => "1 hour".to_string(),
+            hours => format!("{hours} hours"),
+        })
+        .collect();
+    Ok(match offsets.as_slice() {
+        [] => String::new(),
+        [only] => format!("We will send a reminder {only} before."),
+        [rest @ .., last] => format!(
+            "We will send reminders {} and {last} before.",
+            rest.join(", ")
+        ),
+    })
+}
+
+fn list_reminder_configs(conn: &Connection, location_id: i64) -> AppResult<Vec<ReminderConfig>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, location_id, hours_before, template
+         FROM reminder_configs WHERE location_id=? ORDER BY hours_before DESC",
+    )?;
+    let rows = stmt.query_map(params![location_id], |row| {
+        Ok(ReminderConfig {
+            id: row.get(0)?,
+            location_id: row.get(1)?,
+            hours_before: row.get(2)?,
+            template: row.get(3)?,
+        })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+fn add_reminder_config_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    location_id: i64,
+    hours_before: i64,
+    template: Option<String>,
+) -> AppResult<ReminderConfig> {
+    if hours_before <= 0 {
+        return Err(AppError::Validation(
+            "hours_before must be a positive integer".to_string(),
+        ));
+    }
+    get_location_settings_with_conn(conn, location_id)?;
+
+    let duplicate: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM reminder_configs WHERE location_id=? AND hours_before=?",
+        params![location_id, hours_before],
+        |row| row.get(0),
+    )?;
+    if duplicate > 0 {
+        return Err(AppError::Validation(format!(
+            "a {hours_before}-hour reminder is already configured"
+        )));
+    }
+
+    let template = template
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
+    conn.execute(
+        "INSERT INTO reminder_configs (location_id, hours_before, template) VALUES (?, ?, ?)",
+        params![location_id, hours_before, template],
+    )?;
+
+    let config = ReminderConfig {
+        id: conn.last_insert_rowid(),
+        location_id,
+        hours_before,
+        template,
+    };
+    let _ = insert_audit(
+        conn,
+        clock,
+        "add_reminder_config",
+        "reminder_config",
+        Some(config.id.to_string()),
+        json!({
+            "location_id": location_id,
+            "hours_before": hours_before,
+            "template": config.template,
+        }),
+        None,
+        true,
+        None,
+    );
+    Ok(config)
+}
+
+fn remove_reminder_config_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    reminder_config_id: i64,
+) -> AppResult<()> {
+    let removed: (i64, i64, Option<String>) = conn
+        .query_row(
+            "SELECT location_id, hours_before, template FROM reminder_configs WHERE id=?",
+            params![reminder_config_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("reminder config not found".to_string()))?;
+    conn.execute(
+        "DELETE FROM reminder_configs WHERE id=?",
+        params![reminder_config_id],
+    )?;
+    let (location_id, hours_before, template) = removed;
+    let _ = insert_audit(
+        conn,
+        clock,
+        "remove_reminder_config",
+        "reminder_config",
+        Some(reminder_config_id.to_string()),
+        json!({ "reminder_config_id": reminder_config_id }),
+        Some(json!({
+            "location_id": location_id,
+            "hours_before": hours_before,
+            "template": template,
+        })),
+        true,
+        None,
+    );
+    Ok(())
+}
+
+fn handle_time_choice_repair(
+    gateway: &ActionGateway,
+    location: &Location,
+    lead: &LeadRow,
+    conversation: &ConversationRow,
+) -> AppResult<()> {
+    let conn = gateway.conn;
+    let attempts = conversation.repair_attempts + 1;
+    let previous_state: ConversationState =
+        serde_json::from_str(&conversation.state_json).unwrap_or_default();
+    let offered = generate_slot_choices_for_type(
+        conn,
+        location,
+        previous_state.appointment_type_id,
+        gateway.clock.now(),
+    )?;
+
+    if offered.len() < 2 {
+        flag_needs_staff_attention(conn, gateway.clock, lead.id, "repair_no_slots")?;
+        gateway.create_outbound_message(OutboundRequest {
+            lead_id: lead.id,
+            conversation_id: conversation.id,
+            body: render_template(conn, "repair_no_slots", HashMap::new())?,
+            automated: false,
+            allow_without_consent: false,
+            allow_opted_out_once: false,
+            allow_after_reply: true,
+            ignore_business_hours: true,
+            rate_limit_exempt: false,
+        })?;
+        return Ok(());
+    }
+
+    let mut body = format!(
+        "{}\n\n{}",
+        render_template(conn, "repair_prompt", HashMap::new())?,
+        format_slot_offer(conn, location, &offered)?
+    );
+
+    if attempts >= 2 {
+        flag_needs_staff_attention(conn, gateway.clock, lead.id, "repair_attempts_exceeded")?;
+        body = format!(
+            "{}\n\n{}",
+            body,
+            render_template(conn, "repair_staff_flagged", HashMap::new())?
+        );
+    }
+
+    conn.execute(
+        "UPDATE conversations SET state='awaiting_time_choice', state_json=?, repair_attempts=? WHERE id=?",
+        params![
+            serde_json::to_string(&ConversationState {
+                offered_slots: offered,
+                appointment_type_id: previous_state.appointment_type_id,
+            })?,
+            attempts,
+            conversation.id
+        ],
+    )?;
+
+    gateway.create_outbound_message(OutboundRequest {
+        lead_id: lead.id,
+        conversation_id: conversation.id,
+        body,
+        automated: false,
+        allow_without_consent: false,
+        allow_opted_out_once: false,
+        allow_after_reply: true,
+        ignore_business_hours: true,
+        rate_limit_exempt: false,
+    })?;
+
+    Ok(())
+}
+
+fn set_lead_status(
+    conn: &Connection,
+    clock: &dyn Clock,
+    lead_id: i64,
+    to_status: &str,
+    trigger: &str,
+) -> AppResult<()> {
+    let from_status: Option<String> = conn
+        .query_row(
+            "SELECT status FROM leads WHERE id=?",
+            params![lead_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if from_status.as_deref() == Some(to_status) {
+        return Ok(());
+    }
+
+    let now = clock.now().to_rfc3339();
+    conn.execute(
+        "UPDATE leads SET status=? WHERE id=?",
+        params![to_status, lead_id],
+    )?;
+    conn.execute(
+        "INSERT INTO lead_status_history (lead_id, from_status, to_status, changed_at, trigger)
+         VALUES (?, ?, ?, ?, ?)",
+        params![lead_id, from_status, to_status, now, trigger],
+    )?;
+    Ok(())
+}
+
+/// Adds the lead to the waitlist, reusing an existing `waiting` entry.
+fn join_appointment_waitlist_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    lead_id: i64,
+) -> AppResult<i64> {
+    let lead = get_lead(conn, lead_id)?;
+    if lead.opted_out {
+        return Err(AppError::Coded(
+            ErrorCode::OptedOut,
+            "cannot waitlist an opted-out lead".to_string(),
+        ));
+    }
+
+    let existing: Option<i64> = conn
+        .query_row(
+            "SELECT id FROM waitlist WHERE lead_id=? AND status='waiting'",
+            params![lead_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(id) = existing {
+        return Ok(id);
+    }
+
+    conn.execute(
+        "INSERT INTO waitlist (lead_id, requested_at, status) VALUES (?, ?, 'waiting')",
+        params![lead_id, clock.now().to_rfc3339()],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn flag_needs_staff_attention(
+    conn: &Connection,
+    clock: &dyn Clock,
+    lead_id: i64,
+    reason: &str,
+) -> AppResult<()> {
+    conn.execute(
+        "UPDATE leads SET needs_staff_attention=1 WHERE id=?",
+        params![lead_id],
+    )?;
+    let _ = insert_audit(
+        conn,
+        clock,
+        "flag_needs_staff_attention",
+        "lead",
+        Some(lead_id.to_string()),
+        json!({ "reason": reason }),
+        Some(json!({ "needs_staff_attention": true })),
+        true,
+        None,
+    );
+    Ok(())
+}
+
+const STAFF_ATTENTION_RESOLUTIONS: [&str; 4] = ["Resolved", "ReEngage", "Archived", "NoAction"];
+
+/// Clears `needs_staff_attention`. `ReEngage` also resets the conversation to `awaiting_yes`;
+/// `Archived` cancels the lead's pending follow-up and re-engagement jobs.
+fn resolve_staff_attention_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    lead_id: i64,
+    resolution: &str,
+    notes: Option<&str>,
+) -> AppResult<StaffAttentionResolution> {
+    let resolution = resolution.trim();
+    if !STAFF_ATTENTION_RESOLUTIONS.contains(&resolution) {
+        return Err(AppError::Validation(format!(
+            "resolution must be one of {}",
+            STAFF_ATTENTION_RESOLUTIONS.join(", ")
+        )));
+    }
+    let lead = get_lead_summary(conn, lead_id)?;
+    if !lead.needs_staff_attention {
+        return Err(AppError::Validation(
+            "lead is not flagged for staff attention".to_string(),
+        ));
+    }
+    let notes = notes.and_then(null_if_empty);
+
+    let mut jobs_cancelled = 0;
+    match resolution {
+        "ReEngage" => {
+            ActionGateway::with_clock(conn, clock)
+                .reset_conversation(lead_id, "staff attention resolved: re-engage")?;
+        }
+        "Archived" => {
+            jobs_cancelled = conn.execute(
+                "UPDATE scheduled_jobs SET status='cancelled'
+                 WHERE status='pending' AND target_id=?
+                   AND job_type IN ('initial_follow_up', 'no_show_follow_up', 're_engagement', 'post_appointment_follow_up')",
+                params![lead_id],
+            )? as i64;
+            conn.execute(
+                "UPDATE leads SET next_action_at=NULL WHERE id=?",
+                params![lead_id],
+            )?;
+        }
+        _ => {}
+    }
+    conn.execute(
+        "UPDATE leads SET needs_staff_attention=0 WHERE id=?",
+        params![lead_id],
+    )?;
+
+    let result = StaffAttentionResolution {
+        lead_id,
+        resolution: resolution.to_string(),
+        jobs_cancelled,
+    };
+    let _ = insert_audit(
+        conn,
+        clock,
+        "resolve_staff_attention",
+        "lead",
+        Some(lead_id.to_string()),
+        json!({ "resolution": resolution, "notes": notes }),
+        Some(serde_json::to_value(&result)?),
+        true,
+        None,
+    );
+    Ok(result)
+}
+
+/// Clears `needs_staff_attention` on behalf of `resolved_by`. Clearing an unflagged lead succeeds
+/// but the audit entry carries a warning.
+fn mark_needs_attention_resolved_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    lead_id: i64,
+    resolved_by: &str,
+    note: &str,
+) -> AppResult<LeadSummary> {
+    let resolved_by = resolved_by.trim();
+    if resolved_by.is_empty() {
+        return Err(AppError::Validation(
+            "resolved_by must be non-empty".to_string(),
+        ));
+    }
+    let before = get_lead_summary(conn, lead_id)?;
+    conn.execute(
+        "UPDATE leads SET needs_staff_attention=0 WHERE id=?",
+        params![lead_id],
+    )?;
+    let warning =
+        (!before.needs_staff_attention).then_some("lead was not flagged for staff attention");
+
+    let _ = insert_audit(
+        conn,
+        clock,
+        "mark_needs_attention_resolved",
+        "lead",
+        Some(lead_id.to_string()),
+        json!({ "resolved_by": resolved_by, "note": note.trim() }),
+        Some(json!({ "was_flagged": before.needs_staff_attention, "warning": warning })),
+        true,
+        None,
+    );
+    get_lead_summary(conn, lead_id)
+}
+
+/// The two slots offered to a lead: the first open ones in the next 3 business days.
+#[cfg(test)]
+fn generate_slot_choices(
+    conn: &Connection,
+    location: &Location,
+    from_utc: DateTime<Utc>,
+) -> AppResult<Vec<SlotChoice>> {
+    generate_slot_choices_for_type(conn, location, None, from_utc)
+}
+
+/// Slots as long as the conversation's appointment type; without one the location's configured
+/// duration applies.
+fn generate_slot_choices_for_type(
+    conn: &Connection,
+    location: &Location,
+    appointment_type_id: Option<i64>,
+    from_utc: DateTime<Utc>,
+) -> AppResult<Vec<SlotChoice>> {
+    let duration = match appointment_type_id {
+        Some(id) => get_appointment_type(conn, Some(id))?.duration_minutes,
+        None => location.appointment_duration_minutes,
+    };
+    find_open_slots(conn, location, from_utc, duration, 3, Some(2))
+}
+
+/// Booked appointments at the location starting from the day before `from`, as UTC intervals.
+fn load_booked_intervals(
+    conn: &Connection,
+    location_id: i64,
+    from: DateTime<Utc>,
+) -> AppResult<Vec<(DateTime<Utc>, DateTime<Utc>)>> {
+    let mut appointments_stmt = conn.prepare(
+        "SELECT a.start_at, a.end_at FROM appointments a
+         JOIN leads l ON l.id = a.lead_id
+         WHERE a.status='booked' AND datetime(a.start_at) >= datetime(?)
+           AND l.location_id = ?",
+    )?;
+    let since = (from - Duration::days(1)).to_rfc3339();
+    let appt_rows = appointments_stmt.query_map(params![since, location_id], |row| {
+        let start: String = row.get(0)?;
+        let end: String = row.get(1)?;
+        Ok((start, end))
+    })?;
+
+    let mut existing: Vec<(DateTime<Utc>, DateTime<Utc>)> = Vec::new();
+    for row in appt_rows {
+        let (start, end) = row?;
+        existing.push((parse_ts(&start)?, parse_ts(&end)?));
+    }
+    Ok(existing)
+}
+
+/// Open `duration`-minute slots after `from_utc` over the next `business_days` days with opening
+/// hours, stopping early once `max_slots` are found.
+fn find_open_slots(
+    conn: &Connection,
+    location: &Location,
+    from_utc: DateTime<Utc>,
+    duration: i64,
+    business_days: i64,
+    max_slots: Option<usize>,
+) -> AppResult<Vec<SlotChoice>> {
+    let tz = parse_tz(&location.timezone)?;
+    let business_hours = parse_business_hours(&location.business_hours_json)?;
+    let buffer = location.appointment_buffer_minutes;
+    let stride = duration + buffer;
+
+    let existing = load_booked_intervals(conn, location.id, from_utc)?;
+    let closed_dates = load_closed_days(conn, location.id)?;
+
+    let local_start = from_utc.with_timezone(&tz);
+    let max_day_offset = 14.max(business_days * 3);
+    let mut business_days_seen = 0;
+    let mut day_offset = 0;
+    let mut slots: Vec<SlotChoice> = Vec::new();
+
+    while business_days_seen < business_days && day_offset < max_day_offset {
+        let day: NaiveDate = local_start.date_naive() + Duration::days(day_offset);
+        if closed_dates.contains(&day) {
+            day_offset += 1;
+            continue;
+        }
+        let weekday = day.weekday();
+        let ranges = business_hours.get(&weekday).cloned().unwrap_or_default();
+
+        if !ranges.is_empty() {
+            business_days_seen += 1;
+            for (range_start, range_end) in ranges {
+                let mut current_minutes =
+                    range_start.hour() as i64 * 60 + range_start.minute() as i64;
+                let end_minutes = range_end.hour() as i64 * 60 + range_end.minute() as i64;
+
+                while current_minutes + duration <= end_minutes {
+                    let hour = (current_minutes / 60) as u32;
+                    let minute = (current_minutes % 60) as u32;
+                    let naive_time = NaiveTime::from_hms_opt(hour, minute, 0)
+                        .ok_or_else(|| AppError::Validation("invalid time computed".to_string()))?;
+                    let local_candidate = tz
+                        .from_local_datetime(&day.and_time(naive_time))
+                        .earliest()
+                        .ok_or_else(|| {
+                            AppError::Validation(
+                                "could not resolve local appointment slot timestamp".to_string(),
+                            )
+                        })?;
+                    let start_utc = local_candidate.with_timezone(&Utc);
+                    let end_utc = start_utc + Duration::minutes(duration);
+
+                    if start_utc <= from_utc {
+                        current_minutes += stride;
+                        continue;
+                    }
+
+                    if !has_appointment_conflict(start_utc, end_utc, buffer, &existing) {
+                        slots.push(SlotChoice {
+                            start_at: start_utc.to_rfc3339(),
+                            end_at: end_utc.to_rfc3339(),
+                        });
+                    }
+
+                    if max_slots == Some(slots.len()) {
+                        return Ok(slots);
+                    }
+
+                    current_minutes += stride;
+                }
+            }
+        }
+
+        day_offset += 1;
+    }
+
+    Ok(slots)
+}
+
+/// Local calendar days the location is closed regardless of its weekly hours.
+fn load_closed_days(conn: &Connection, location_id: i64) -> AppResult<ClosedDays> {
+    let mut stmt = conn.prepare("SELECT date FROM holidays WHERE location_id=?")?;
+    let rows = stmt.query_map(params![location_id], |row| row.get::<_, String>(0))?;
+    let mut holidays = HashSet::new();
+    for row in rows {
+        holidays.insert(parse_calendar_date(&row?)?);
+    }
+
+    let mut stmt =
+        conn.prepare("SELECT start_date, end_date FROM blackout_periods WHERE location_id=?")?;
+    let rows = stmt.query_map(params![location_id], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+    let mut blackouts = Vec::new();
+    for row in rows {
+        let (start, end) = row?;
+        blackouts.push((parse_calendar_date(&start)?, parse_calendar_date(&end)?));
+    }
+
+    Ok(ClosedDays {
+        holidays,
+        blackouts,
+    })
+}
+
+/// `date` is a calendar day in the location's timezone; `None` means the location's today.
+fn get_today_report_with_conn(
+    conn: &Connection,
+    date: Option<&str>,
+    now: DateTime<Utc>,
+) -> AppResult<TodayReport> {
+    let location = get_location(conn)?;
+    let day = match date {
+        Some(date) => parse_calendar_date(date)?,
+        None => now
+            .with_timezone(&parse_tz(&location.timezone)?)
+            .date_naive(),
+    };
+    let (start, end) = local_day_bounds_utc(&location, day)?;
+    report_for_window(conn, start, end)
+}
+
+/// One report per local calendar day, both ends inclusive, at most 90 days.
+fn get_range_report_with_conn(
+    conn: &Connection,
+    start_date: &str,
+    end_date: &str,
+) -> AppResult<Vec<DayReport>> {
+    let start = parse_calendar_date(start_date)?;
+    let end = parse_calendar_date(end_date)?;
+    if end < start {
+        return Err(AppError::Validation(
+            "end_date must not be before start_date".to_string(),
+        ));
+    }
+    if (end - start).num_days() >= 90 {
+        return Err(AppError::Validation(
+            "date range cannot exceed 90 days".to_string(),
+        ));
+    }
+
+    let location = get_location(conn)?;
+    let mut reports = Vec::new();
+    let mut day = start;
+    while day <= end {
+        let (window_start, window_end) = local_day_bounds_utc(&location, day)?;
+        reports.push(DayReport {
+            date: day.format("%Y-%m-%d").to_string(),
+            report: report_for_window(conn, window_start, window_end)?,
+        });
+        day += Duration::days(1);
+    }
+
+    Ok(reports)
+}
+
+/// The seven local calendar days ending with the day containing `now`, oldest first. Days are
+/// bounded in the location's timezone, so each one is counted with its own window.
+fn get_weekly_report_with_conn(
+    conn: &Connection,
+    now: DateTime<Utc>,
+) -> AppResult<Vec<DailyStats>> {
+    let location = get_location(conn)?;
+    let today = now
+        .with_timezone(&parse_tz(&location.timezone)?)
+        .date_naive();
+
+    let mut days = Vec::with_capacity(7);
+    for offset in (0..7).rev() {
+        let day = today - Duration::days(offset);
+        let (start, end) = local_day_bounds_utc(&location, day)?;
+        let report = report_for_window(conn, start, end)?;
+        let needs_attention_flagged: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM audit_log
+             WHERE action_type='flag_needs_staff_attention'
+               AND datetime(created_at) >= datetime(?1) AND datetime(created_at) < datetime(?2)",
+            params![start.to_rfc3339(), end.to_rfc3339()],
+            |row| row.get(0),
+        )?;
+        days.push(DailyStats {
+            date: day.format("%Y-%m-%d").to_string(),
+            leads_created: report.leads_created,
+            contacted: report.contacted,
+            booked: report.booked,
+            opt_outs: report.opt_outs,
+            needs_attention_flagged,
+        });
+    }
+
+    Ok(days)
+}
+
+/// Cohort of leads created since `since`. A lead "responded yes" once it has ever been in
+/// `awaiting_time_choice` or `booked`, and counts as booked once it has ever had an appointment.
+fn get_funnel_report_with_conn(conn: &Connection, since: Option<&str>) -> AppResult<FunnelReport> {
+    let since = since
+        .map(|value| parse_ts(value.trim()))
+        .transpose()?
+        .map(|value| value.to_rfc3339());
+
+    let (leads_created, leads_contacted, leads_responded_yes, leads_booked, leads_opted_out) = conn
+        .query_row(
+            "SELECT COUNT(*),
+                    COALESCE(SUM(EXISTS (
+                        SELECT 1 FROM messages m
+                        JOIN conversations c ON c.id = m.conversation_id
+                        WHERE c.lead_id = l.id AND m.direction = 'OUTBOUND' AND m.status != 'failed'
+                    )), 0),
+                    COALESCE(SUM(l.status IN ('awaiting_time_choice', 'booked') OR EXISTS (
+                        SELECT 1 FROM lead_status_history h
+                        WHERE h.lead_id = l.id AND h.to_status IN ('awaiting_time_choice', 'booked')
+                    )), 0),
+                    COALESCE(SUM(EXISTS (
+                        SELECT 1 FROM appointments a WHERE a.lead_id = l.id
+                    )), 0),
+                    COALESCE(SUM(l.opted_out), 0)
+             FROM leads l
+             WHERE ?1 IS NULL OR datetime(l.created_at) >= datetime(?1)",
+            params![since],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            },
+        )?;
+
+    let conversion_rate_pct = if leads_created > 0 {
+        leads_booked as f64 / leads_created as f64 * 100.0
+    } else {
+        0.0
+    };
+
+    Ok(FunnelReport {
+        leads_created,
+        leads_contacted,
+        leads_responded_yes,
+        leads_booked,
+        leads_opted_out,
+        conversion_rate_pct,
+    })
+}
+
+/// Groups leads by `consent_source` (leads without one share a `None` row, listed last). Booked
+/// uses the same "ever had an appointment" rule as the funnel report.
+fn get_source_report_with_conn(
+    conn: &Connection,
+    since: Option<&str>,
+) -> AppResult<Vec<SourceReport>> {
+    let since = since
+        .map(|value| parse_ts(value.trim()))
+        .transpose()?
+        .map(|value| value.to_rfc3339());
+
+    let mut stmt = conn.prepare(
+        "SELECT l.consent_source,
+                COUNT(*),
+                SUM(EXISTS (SELECT 1 FROM appointments a WHERE a.lead_id = l.id)),
+                SUM(l.opted_out)
+         FROM leads l
+         WHERE ?1 IS NULL OR datetime(l.created_at) >= datetime(?1)
+         GROUP BY l.consent_source
+         ORDER BY l.consent_source IS NULL, l.consent_source",
+    )?;
+    let rows = stmt.query_map(params![since], |row| {
+        let leads: i64 = row.get(1)?;
+        let booked: i64 = row.get(2)?;
+        Ok(SourceReport {
+            source: row.get(0)?,
+            leads,
+            booked,
+            opted_out: row.get(3)?,
+            booking_rate_pct: booked as f64 / leads as f64 * 100.0,
+        })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+/// UTC instants of local midnight at the start of `day` and of the following day.
+fn local_day_bounds_utc(
+    location: &Location,
+    day: NaiveDate,
+) -> AppResult<(DateTime<Utc>, DateTime<Utc>)> {
+    let tz = parse_tz(&location.timezone)?;
+    let midnight = |day: NaiveDate| {
+        tz.from_local_datetime(&day.and_time(NaiveTime::MIN))
+            .earliest()
+            .map(|value| value.with_timezone(&Utc))
+            .ok_or_else(|| {
+                AppError::Validation(format!("could not resolve local midnight for {day}"))
+            })
+    };
+    Ok((midnight(day)?, midnight(day + Duration::days(1))?))
+}
+
+/// Counts activity in `[start, end)`. `needs_attention` is the current backlog, not windowed.
+fn report_for_window(
+    conn: &Connection,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> AppResult<TodayReport> {
+    let window = params![start.to_rfc3339(), end.to_rfc3339()];
+
+    let leads_created: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM leads
+         WHERE datetime(created_at) >= datetime(?1) AND datetime(created_at) < datetime(?2)",
+        window,
+        |row| row.get(0),
+    )?;
+
+    let contacted: i64 = conn.query_row(
+        "SELECT COUNT(DISTINCT c.lead_id)
+         FROM messages m
+         JOIN conversations c ON c.id = m.conversation_id
+         WHERE m.direction='OUTBOUND'
+           AND datetime(m.created_at) >= datetime(?1) AND datetime(m.created_at) < datetime(?2)",
+        window,
+        |row| row.get(0),
+    )?;
+
+    let booked: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM appointments
+         WHERE status='booked'
+           AND datetime(created_at) >= datetime(?1) AND datetime(created_at) < datetime(?2)",
+        window,
+        |row| row.get(0),
+    )?;
+
+    let opt_outs: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM audit_log
+         WHERE action_type='set_opt_out'
+           AND success=1
+           AND datetime(created_at) >= datetime(?1) AND datetime(created_at) < datetime(?2)",
+        window,
+        |row| row.get(0),
+    )?;
+
+    let needs_attention: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM leads WHERE needs_staff_attention=1",
+        params![],
+        |row| row.get(0),
+    )?;
+
+    Ok(TodayReport {
+        leads_created,
+        contacted,
+        booked,
+        opt_outs,
+        needs_attention,
+    })
+}
+
+fn parse_calendar_date(value: &str) -> AppResult<NaiveDate> {
+    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+        .map_err(|_| AppError::Validation(format!("date must be YYYY-MM-DD: {value}")))
+}
+
+fn list_holidays_with_conn(conn: &Connection) -> AppResult<Vec<HolidayView>> {
+    let location_id = current_location_id(conn)?;
+    let mut stmt = conn.prepare(
+        "SELECT id, location_id, date, name FROM holidays WHERE location_id=? ORDER BY date",
+    )?;
+    let rows = stmt.query_map(params![location_id], |row| {
+        Ok(HolidayView {
+            id: row.get(0)?,
+            location_id: row.get(1)?,
+            date: row.get(2)?,
+            name: row.get(3)?,
+        })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+fn is_phone_blocklisted(conn: &Connection, phone_e164: &str) -> AppResult<bool> {
+    let blocked: i64 = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM phone_blocklist WHERE phone_e164=?)",
+        params![phone_e164],
+        |row| row.get(0),
+    )?;
+    Ok(blocked == 1)
+}
+
+fn is_lead_blocklisted(conn: &Connection, lead_id: i64) -> AppResult<bool> {
+    let blocked: i64 = conn.query_row(
+        "SELECT EXISTS(
+           SELECT 1 FROM phone_blocklist b JOIN leads l ON l.phone_e164 = b.phone_e164
+           WHERE l.id=?
+         )",
+        params![lead_id],
+        |row| row.get(0),
+    )?;
+    Ok(blocked == 1)
+}
+
+fn map_blocklist_entry(row: &rusqlite::Row<'_>) -> rusqlite::Result<BlocklistEntry> {
+    Ok(BlocklistEntry {
+        id: row.get(0)?,
+        phone_e164: row.get(1)?,
+        reason: row.get(2)?,
+        added_at: row.get(3)?,
+    })
+}
+
+fn add_phone_to_blocklist_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    phone_e164: &str,
+    reason: Option<&str>,
+) -> AppResult<BlocklistEntry> {
+    let phone = normalize_phone_e164(phone_e164, "+1")?;
+    let reason = reason.map(str::trim).filter(|reason| !reason.is_empty());
+    let inserted = conn.execute(
+        "INSERT INTO phone_blocklist (phone_e164, reason, added_at) VALUES (?, ?, ?)
+         ON CONFLICT(phone_e164) DO NOTHING",
+        params![phone, reason, clock.now().to_rfc3339()],
+    )?;
+    if inserted == 0 {
+        return Err(AppError::Validation(format!(
+            "{phone} is already on the blocklist"
+        )));
+    }
+    let entry = conn.query_row(
+        "SELECT id, phone_e164, reason, added_at FROM phone_blocklist WHERE id=?",
+        params![conn.last_insert_rowid()],
+        map_blocklist_entry,
+    )?;
+    let _ = insert_audit(
+        conn,
+        clock,
+        "add_phone_to_blocklist",
+        "phone_blocklist",
+        Some(entry.id.to_string()),
+        json!({ "phone_e164": phone, "reason": reason }),
+        None,
+        true,
+        None,
+    );
+    Ok(entry)
+}
+
+fn remove_phone_from_blocklist_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    phone_e164: &str,
+) -> AppResult<()> {
+    let phone = normalize_phone_e164(phone_e164, "+1")?;
+    let deleted = conn.execute(
+        "DELETE FROM phone_blocklist WHERE phone_e164=?",
+        params![phone],
+    )?;
+    if deleted == 0 {
+        return Err(AppError::NotFound("blocklist entry not found".to_string()));
+    }
+    let _ = insert_audit(
+        conn,
+        clock,
+        "remove_phone_from_blocklist",
+        "phone_blocklist",
+        None,
+        json!({ "phone_e164": phone }),
+        None,
+        true,
+        None,
+    );
+    Ok(())
+}
+
+fn list_blocklist_with_conn(conn: &Connection) -> AppResult<Vec<BlocklistEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, phone_e164, reason, added_at FROM phone_blocklist ORDER BY added_at DESC, id DESC",
+    )?;
+    let rows = stmt.query_map(params![], map_blocklist_entry)?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+fn add_holiday_with_conn(conn: &Connection, date: &str, name: &str) -> AppResult<i64> {
+    let date = parse_calendar_date(date)?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::Validation(
+            "holiday name must be non-empty".to_string(),
+        ));
+    }
+    let location_id = current_location_id(conn)?;
+    let inserted = conn.execute(
+        "INSERT INTO holidays (location_id, date, name) VALUES (?, ?, ?)
+         ON CONFLICT(location_id, date) DO NOTHING",
+        params![location_id, date.format("%Y-%m-%d").to_string(), name],
+    )?;
+    if inserted == 0 {
+        return Err(AppError::Validation(format!(
+            "a holiday is already set for {date}"
+        )));
+    }
+    Ok(conn.last_insert_rowid())
+}
+
+fn remove_holiday_with_conn(conn: &Connection, id: i64) -> AppResult<()> {
+    let deleted = conn.execute("DELETE FROM holidays WHERE id=?", params![id])?;
+    if deleted == 0 {
+        return Err(AppError::NotFound("holiday not found".to_string()));
+    }
+    Ok(())
+}
+
+fn list_blackout_periods_with_conn(conn: &Connection) -> AppResult<Vec<BlackoutPeriodView>> {
+    let location_id = current_location_id(conn)?;
+    let mut stmt = conn.prepare(
+        "SELECT id, location_id, start_date, end_date, reason
+         FROM blackout_periods WHERE location_id=? ORDER BY start_date",
+    )?;
+    let rows = stmt.query_map(params![location_id], |row| {
+        Ok(BlackoutPeriodView {
+            id: row.get(0)?,
+            location_id: row.get(1)?,
+            start_date: row.get(2)?,
+            end_date: row.get(3)?,
+            reason: row.get(4)?,
+        })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+fn add_blackout_period_with_conn(
+    conn: &Connection,
+    start_date: &str,
+    end_date: &str,
+    reason: &str,
+) -> AppResult<i64> {
+    let start = parse_calendar_date(start_date)?;
+    let end = parse_calendar_date(end_date)?;
+    if end < start {
+        return Err(AppError::Validation(
+            "end_date must be on or after start_date".to_string(),
+        ));
+    }
+    let reason = reason.trim();
+    if reason.is_empty() {
+        return Err(AppError::Validation(
+            "blackout reason must be non-empty".to_string(),
+        ));
+    }
+    let location_id = current_location_id(conn)?;
+    conn.execute(
+        "INSERT INTO blackout_periods (location_id, start_date, end_date, reason)
+         VALUES (?, ?, ?, ?)",
+        params![
+            location_id,
+            start.format("%Y-%m-%d").to_string(),
+            end.format("%Y-%m-%d").to_string(),
+            reason
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn remove_blackout_period_with_conn(conn: &Connection, id: i64) -> AppResult<()> {
+    let deleted = conn.execute("DELETE FROM blackout_periods WHERE id=?", params![id])?;
+    if deleted == 0 {
+        return Err(AppError::NotFound("blackout period not found".to_string()));
+    }
+    Ok(())
+}
+
+fn has_appointment_conflict(
+    candidate_start: DateTime<Utc>,
+    candidate_end: DateTime<Utc>,
+    buffer_minutes: i64,
+    existing: &[(DateTime<Utc>, DateTime<Utc>)],
+) -> bool {
+    let candidate_end_with_buffer = candidate_end + Duration::minutes(buffer_minutes);
+
+    existing.iter().any(|(start, end)| {
+        let existing_end_with_buffer = *end + Duration::minutes(buffer_minutes);
+        candidate_start < existing_end_with_buffer && *start < candidate_end_with_buffer
+    })
+}
+
+fn format_slot_offer(
+    conn: &Connection,
+    location: &Location,
+    slots: &[SlotChoice],
+) -> AppResult<String> {
+    if slots.len() < 2 {
+        return Err(AppError::Validation(
+            "expected at least 2 slots for offer".to_string(),
+        ));
+    }
+
+    let first = local_display(location, &slots[0].start_at)?;
+    let second = local_display(location, &slots[1].start_at)?;
+
+    render_template(
+        conn,
+        "slot_offer",
+        HashMap::from([("slot1", first.as_str()), ("slot2", second.as_str())]),
+    )
+}
+
+fn export_appointment_ics_with_conn(conn: &Connection, appointment_id: i64) -> AppResult<String> {
+    let (start_at, end_at, created_at, first_name, appointment_type_id, location_id): (
+        String,
+        String,
+        String,
+        Option<String>,
+        Option<i64>,
+        i64,
+    ) = conn
+        .query_row(
+            "SELECT a.start_at, a.end_at, a.created_at, l.first_name, a.appointment_type_id,
+                    l.location_id
+             FROM appointments a
+             JOIN leads l ON l.id = a.lead_id
+             WHERE a.id=?",
+            params![appointment_id],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            },
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("appointment not found".to_string()))?;
+    let location = get_location_by_id(conn, location_id)?;
+    let appointment_type = get_appointment_type(conn, appointment_type_id)?;
+    let name = first_name.unwrap_or_else(|| "member".to_string());
+
+    let lines = [
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//GoldBot//Appointments//EN".to_string(),
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{appointment_id}@goldbot"),
+        // DTSTAMP uses the booking time so repeated exports are byte-identical.
+        format!("DTSTAMP:{}", ics_utc(&created_at)?),
+        format!("DTSTART:{}", ics_utc(&start_at)?),
+        format!("DTEND:{}", ics_utc(&end_at)?),
+        format!(
+            "SUMMARY:{}",
+            ics_escape(&format!("{} {}", location.gym_name, appointment_type.name))
+        ),
+        format!(
+            "DESCRIPTION:{}",
+            ics_escape(&format!("{} for {name}", appointment_type.name))
+        ),
+        "END:VEVENT".to_string(),
+        "END:VCALENDAR".to_string(),
+    ];
+
+    Ok(lines
+        .iter()
+        .map(|line| fold_ics_line(line))
+        .collect::<Vec<_>>()
+        .join(""))
+}
+
+fn ics_utc(iso: &str) -> AppResult<String> {
+    Ok(parse_ts(iso)?.format("%Y%m%dT%H%M%SZ").to_string())
+}
+
+fn ics_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// RFC 5545 content lines are at most 75 octets; continuations start with a single space.
+fn fold_ics_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut width = 0;
+    for ch in line.chars() {
+        let len = ch.len_utf8();
+        if width + len > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(ch);
+        width += len;
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+fn local_display(location: &Location, iso: &str) -> AppResult<String> {
+    let tz = parse_tz(&location.timezone)?;
+    let dt = parse_ts(iso)?.with_timezone(&tz);
+    Ok(dt.format("%a %b %-d at %-I:%M %p").to_string())
+}
+
+fn parse_business_hours(input: &str) -> AppResult<HashMap<Weekday, Vec<(NaiveTime, NaiveTime)>>> {
+    let raw: Value = serde_json::from_str(input)?;
+    let obj = raw
+        .as_object()
+        .ok_or_else(|| AppError::Validation("invalid business_hours_json object".to_string()))?;
+
+    let mut map: HashMap<Weekday, Vec<(NaiveTime, NaiveTime)>> = HashMap::new();
+
+    for (key, value) in obj {
+        let weekday = match key.as_str() {
+            "mon" => Weekday::Mon,
+            "tue" => Weekday::Tue,
+            "wed" => Weekday::Wed,
+            "thu" => Weekday::Thu,
+            "fri" => Weekday::Fri,
+            "sat" => Weekday::Sat,
+            "sun" => Weekday::Sun,
+            _ => continue,
+        };
+
+        let mut ranges: Vec<(NaiveTime, NaiveTime)> = Vec::new();
+        if let Some(arr) = value.as_array() {
+            for range in arr {
+                let pair = range.as_array().ok_or_else(|| {
+                    AppError::Validation("business hours range must be [start, end]".to_string())
+                })?;
+                if pair.len() != 2 {
+                    continue;
+                }
+                let start = pair[0]
+                    .as_str()
+                    .ok_or_else(|| AppError::Validation("start time must be string".to_string()))?;
+                let end = pair[1]
+                    .as_str()
+                    .ok_or_else(|| AppError::Validation("end time must be string".to_string()))?;
+
+                let start_time = NaiveTime::parse_from_str(start, "%H:%M")?;
+                let end_time = NaiveTime::parse_from_str(end, "%H:%M")?;
+                ranges.push((start_time, end_time));
+            }
+        }
+        map.insert(weekday, ranges);
+    }
+
+    Ok(map)
+}
+
+/// Stricter than `parse_business_hours`: reports every problem instead of stopping at the first,
+/// and flags ranges that parse fine but would never produce a slot.
+fn validate_business_hours_json(input: &str) -> ValidationReport {
+    const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+
+    let raw: Value = match serde_json::from_str(input) {
+        Ok(raw) => raw,
+        Err(err) => {
+            return ValidationReport {
+                valid: false,
+                errors: vec![format!("invalid JSON: {err}")],
+                warnings,
+            }
+        }
+    };
+    let Some(obj) = raw.as_object() else {
+        return ValidationReport {
+            valid: false,
+            errors: vec!["business hours must be a JSON object keyed by day".to_string()],
+            warnings,
+        };
+    };
+
+    for key in obj.keys() {
+        if !DAYS.contains(&key.as_str()) {
+            warnings.push(format!("{key}: unknown day key is ignored"));
+        }
+    }
+
+    for day in DAYS {
+        let Some(value) = obj.get(day) else {
+            warnings.push(format!("{day}: no hours configured"));
+            continue;
+        };
+        let Some(ranges) = value.as_array() else {
+            errors.push(format!("{day}: expected a list of [start, end] ranges"));
+            continue;
+        };
+        if ranges.is_empty() {
+            warnings.push(format!("{day}: no hours configured"));
+            continue;
+        }
+
+        let mut parsed: Vec<(NaiveTime, NaiveTime)> = Vec::new();
+        for (index, range) in ranges.iter().enumerate() {
+            let label = format!("{day} range {}", index + 1);
+            let pair = match range.as_array() {
+                Some(pair) if pair.len() == 2 => pair,
+                _ => {
+                    errors.push(format!("{label}: expected [start, end]"));
+                    continue;
+                }
+            };
+            let times: Vec<Option<NaiveTime>> = pair
+                .iter()
+                .map(|time| {
+                    time.as_str()
+                        .and_then(|time| NaiveTime::parse_from_str(time, "%H:%M").ok())
+                })
+                .collect();
+            let (Some(start), Some(end)) = (times[0], times[1]) else {
+                errors.push(format!("{label}: times must be HH:MM strings"));
+                continue;
+            };
+            if end <= start {
+                errors.push(format!(
+                    "{label}: end {} is not after start {}",
+                    end.format("%H:%M"),
+                    start.format("%H:%M")
+                ));
+                continue;
+            }
+            parsed.push((start, end));
+        }
+
+        parsed.sort();
+        for pair in parsed.windows(2) {
+            if pair[1].0 < pair[0].1 {
+                errors.push(format!(
+                    "{day}: {}-{} overlaps {}-{}",
+                    pair[0].0.format("%H:%M"),
+                    pair[0].1.format("%H:%M"),
+                    pair[1].0.format("%H:%M"),
+                    pair[1].1.format("%H:%M")
+                ));
+            }
+        }
+    }
+
+    ValidationReport {
+        valid: errors.is_empty(),
+        errors,
+        warnings,
+    }
+}
+
+fn is_business_open(location: &Location, when_utc: DateTime<Utc>) -> AppResult<bool> {
+    let tz = parse_tz(&location.timezone)?;
+    let local = when_utc.with_timezone(&tz);
+    let business_hours = parse_business_hours(&location.business_hours_json)?;
+    let ranges = business_hours
+        .get(&local.weekday())
+        .cloned()
+        .unwrap_or_default();
+
+    let current_time = local.time();
+    Ok(ranges
+        .iter()
+        .any(|(start, end)| current_time >= *start && current_time < *end))
+}
+
+/// Minutes left in the business-hours range that contains `when_utc`, or `None` while closed.
+fn minutes_until_close(location: &Location, when_utc: DateTime<Utc>) -> AppResult<Option<i64>> {
+    let tz = parse_tz(&location.timezone)?;
+    let local = when_utc.with_timezone(&tz);
+    let business_hours = parse_business_hours(&location.business_hours_json)?;
+    let current_time = local.time();
+    Ok(business_hours
+        .get(&local.weekday())
+        .into_iter()
+        .flatten()
+        .find(|(start, end)| current_time >= *start && current_time < *end)
+        .map(|(_, end)| (*end - current_time).num_minutes()))
+}
+
+fn is_within_tcpa_window(location: &Location, when_utc: DateTime<Utc>) -> AppResult<bool> {
+    let tz = parse_tz(&location.timezone)?;
+    let hour = when_utc.with_timezone(&tz).hour();
+    Ok((8..21).contains(&hour))
+}
+
+fn next_open_time(
+    conn: &Connection,
+    location: &Location,
+    from_utc: DateTime<Utc>,
+) -> AppResult<DateTime<Utc>> {
+    let tz = parse_tz(&location.timezone)?;
+    let local = from_utc.with_timezone(&tz);
+    let business_hours = parse_business_hours(&location.business_hours_json)?;
+    let closed_dates = load_closed_days(conn, location.id)?;
+
+    for day_offset in 0..21 {
+        let day = local.date_naive() + Duration::days(day_offset);
+        if closed_dates.contains(&day) {
+            continue;
+        }
+        let ranges = business_hours
+            .get(&day.weekday())
+            .cloned()
+            .unwrap_or_default();
+        for (start, _) in ranges {
+            if day_offset == 0 && start <= local.time() {
+                continue;
+            }
+            // On the fall-back night the opening time happens twice; use the first occurrence that
+            // is still ahead of `from_utc`.
+            let local_result = tz.from_local_datetime(&day.and_time(start));
+            let local_dt = local_result
+                .earliest()
+                .filter(|dt| dt.with_timezone(&Utc) > from_utc)
+                .or_else(|| local_result.latest())
+                .ok_or_else(|| {
+                    AppError::Validation(
+                        "unable to resolve local datetime for next business opening".to_string(),
+                    )
+                })?;
+            return Ok(local_dt.with_timezone(&Utc));
+        }
+    }
+
+    Ok(from_utc + Duration::hours(24))
+}
+
+/// The location selected in the UI. Anything acting on a lead uses `get_lead_location` instead.
+fn get_location(conn: &Connection) -> AppResult<Location> {
+    get_location_by_id(conn, current_location_id(conn)?)
+}
+
+/// The location a lead belongs to, whose hours, timezone, TCPA mode, slot grid and rate limit
+/// apply to everything sent to or booked for that lead.
+fn get_lead_location(conn: &Connection, lead_id: i64) -> AppResult<Location> {
+    let location_id: i64 = conn
+        .query_row(
+            "SELECT location_id FROM leads WHERE id=?",
+            params![lead_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("lead not found".to_string()))?;
+    get_location_by_id(conn, location_id)
+}
+
+fn get_location_by_id(conn: &Connection, location_id: i64) -> AppResult<Location> {
+    conn.query_row(
+        "SELECT id, gym_name, timezone, business_hours_json, appointment_duration_minutes,
+                appointment_buffer_minutes, reminder_enabled, tcpa_mode
+         FROM locations WHERE id=?",
+        params![location_id],
+        |row| {
+            Ok(Location {
+                id: row.get(0)?,
+                gym_name: row.get(1)?,
+                timezone: row.get(2)?,
+                business_hours_json: row.get(3)?,
+                appointment_duration_minutes: row.get(4)?,
+                appointment_buffer_minutes: row.get(5)?,
+                reminder_enabled: i64_to_bool(row.get(6)?),
+                tcpa_mode: i64_to_bool(row.get(7)?),
+            })
+        },
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound("location not found".to_string()))
+}
+
+fn get_location_settings_with_conn(
+    conn: &Connection,
+    location_id: i64,
+) -> AppResult<LocationSettings> {
+    conn.query_row(
+        "SELECT id, gym_name, timezone, business_hours_json, appointment_duration_minutes,
+                appointment_buffer_minutes, reminder_enabled
+         FROM locations WHERE id=?",
+        params![location_id],
+        |row| {
+            Ok(LocationSettings {
+                id: row.get(0)?,
+                gym_name: row.get(1)?,
+                timezone: row.get(2)?,
+                business_hours_json: row.get(3)?,
+                appointment_duration_minutes: row.get(4)?,
+                appointment_buffer_minutes: row.get(5)?,
+                reminder_enabled: i64_to_bool(row.get(6)?),
+            })
+        },
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound("location not found".to_string()))
+}
+
+fn validate_location_input(input: &LocationSettingsInput) -> AppResult<(String, String, String)> {
+    let gym_name = input.gym_name.trim().to_string();
+    if gym_name.is_empty() {
+        return Err(AppError::Validation(
+            "gym_name must be non-empty".to_string(),
+        ));
+    }
+
+    let timezone = input.timezone.trim().to_string();
+    parse_tz(&timezone)?;
+
+    let business_hours_json = input.business_hours_json.trim().to_string();
+    parse_business_hours(&business_hours_json)?;
+
+    Ok((gym_name, timezone, business_hours_json))
+}
+
+fn list_locations_with_conn(conn: &Connection) -> AppResult<Vec<LocationSettings>> {
+    let mut stmt = conn.prepare("SELECT id FROM locations ORDER BY id")?;
+    let ids = stmt
+        .query_map(params![], |row| row.get::<_, i64>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    ids.into_iter()
+        .map(|id| get_location_settings_with_conn(conn, id))
+        .collect()
+}
+
+fn add_location_with_conn(
+    conn: &Connection,
+    input: &LocationSettingsInput,
+) -> AppResult<LocationSettings> {
+    let (gym_name, timezone, business_hours_json) = validate_location_input(input)?;
+    conn.execute(
+        "INSERT INTO locations (gym_name, timezone, business_hours_json) VALUES (?, ?, ?)",
+        params![gym_name, timezone, business_hours_json],
+    )?;
+    get_location_settings_with_conn(conn, conn.last_insert_rowid())
+}
+
+/// Applies only the fields that are `Some`; the audit entry records the row before and after.
+/// A timezone change leaves `execute_at` alone but reports the pending jobs that would already be
+/// due if their local time were read in the new zone.
+fn update_location_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    location_id: i64,
+    patch: &LocationPatch,
+    now: DateTime<Utc>,
+) -> AppResult<LocationUpdateResult> {
+    let request_json = serde_json::to_value(patch)?;
+    let result = (|| -> AppResult<(LocationSettings, LocationSettings, Vec<ScheduledJobView>)> {
+        let before = get_location_settings_with_conn(conn, location_id)?;
+
+        let gym_name = match patch.gym_name.as_deref().map(str::trim) {
+            Some("") => {
+                return Err(AppError::Validation(
+                    "gym_name must be non-empty".to_string(),
+                ))
+            }
+            Some(name) => name.to_string(),
+            None => before.gym_name.clone(),
+        };
+        let timezone = match patch.timezone.as_deref().map(str::trim) {
+            Some(timezone) => {
+                parse_tz(timezone)?;
+                timezone.to_string()
+            }
+            None => before.timezone.clone(),
+        };
+        let business_hours_json = match patch.business_hours_json.as_deref().map(str::trim) {
+            Some(json) => {
+                let report = validate_business_hours_json(json);
+                if !report.valid {
+                    return Err(AppError::Validation(format!(
+                        "invalid business hours: {}",
+                        report.errors.join("; ")
+                    )));
+                }
+                json.to_string()
+            }
+            None => before.business_hours_json.clone(),
+        };
+        let past_due_jobs = if timezone == before.timezone {
+            Vec::new()
+        } else {
+            pending_jobs_past_due_in_timezone(
+                conn,
+                location_id,
+                parse_tz(&before.timezone)?,
+                parse_tz(&timezone)?,
+                now,
+            )?
+        };
+
+        conn.execute(
+            "UPDATE locations SET gym_name=?, timezone=?, business_hours_json=? WHERE id=?",
+            params![gym_name, timezone, business_hours_json, location_id],
+        )?;
+        let after = get_location_settings_with_conn(conn, location_id)?;
+        Ok((before, after, past_due_jobs))
+    })();
+
+    match result {
+        Ok((before, after, past_due_jobs)) => {
+            let past_due_job_ids: Vec<i64> = past_due_jobs.iter().map(|job| job.id).collect();
+            let _ = insert_audit(
+                conn,
+                clock,
+                "update_location",
+                "location",
+                Some(location_id.to_string()),
+                request_json,
+                Some(json!({
+                    "before": before,
+                    "after": after,
+                    "past_due_job_ids": past_due_job_ids
+                })),
+                true,
+                None,
+            );
+            Ok(LocationUpdateResult {
+                location: after,
+                past_due_jobs,
+            })
+        }
+        Err(err) => {
+            let _ = insert_audit(
+                conn,
+                clock,
+                "update_location",
+                "location",
+                Some(location_id.to_string()),
+                request_json,
+                None,
+                false,
+                Some(err.to_string()),
+            );
+            Err(err)
+        }
+    }
+}
+
+/// Pending jobs for the location's leads (reminders via their appointment) whose `execute_at`,
+/// taken as a wall-clock time in `old_tz` and re-read in `new_tz`, is no longer after `now`.
+fn pending_jobs_past_due_in_timezone(
+    conn: &Connection,
+    location_id: i64,
+    old_tz: Tz,
+    new_tz: Tz,
+    now: DateTime<Utc>,
+) -> AppResult<Vec<ScheduledJobView>> {
+    let mut stmt = conn.prepare(
+        "SELECT j.id, j.job_type, j.target_id, j.execute_at, j.status, j.payload_json,
+                j.created_at, j.retry_count, j.max_retries, j.completed_at
+         FROM scheduled_jobs j
+         LEFT JOIN appointments a
+           ON j.job_type = 'appointment_reminder' AND a.id = j.target_id
+         JOIN leads l
+           ON l.id = CASE WHEN j.job_type = 'appointment_reminder' THEN a.lead_id ELSE j.target_id END
+         WHERE j.status = 'pending' AND l.location_id = ?
+         ORDER BY datetime(j.execute_at) ASC, j.id ASC",
+    )?;
+    let jobs = stmt
+        .query_map(params![location_id], map_scheduled_job)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut past_due = Vec::new();
+    for job in jobs {
+        let local = parse_ts(&job.execute_at)?
+            .with_timezone(&old_tz)
+            .naive_local();
+        let shifted = new_tz
+            .from_local_datetime(&local)
+            .earliest()
+            .map(|value| value.with_timezone(&Utc));
+        match shifted {
+            Some(value) if value > now => {}
+            _ => past_due.push(job),
+        }
+    }
+    Ok(past_due)
+}
+
+fn set_current_location_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    location_id: i64,
+) -> AppResult<LocationSettings> {
+    let settings = get_location_settings_with_conn(conn, location_id)?;
+    put_setting(conn, clock, "current_location_id", &location_id.to_string())?;
+    Ok(settings)
+}
+
+fn update_location_schedule_config_with_conn(
+    conn: &Connection,
+    location_id: i64,
+    duration_minutes: i64,
+    buffer_minutes: i64,
+    reminder_enabled: bool,
+) -> AppResult<LocationSettings> {
+    if duration_minutes <= 0 {
+        return Err(AppError::Validation(
+            "duration_minutes must be a positive integer".to_string(),
+        ));
+    }
+    if buffer_minutes <= 0 {
+        return Err(AppError::Validation(
+            "buffer_minutes must be a positive integer".to_string(),
+        ));
+    }
+    if duration_minutes + buffer_minutes > 120 {
+        return Err(AppError::Validation(
+            "duration_minutes + buffer_minutes must be at most 120".to_string(),
+        ));
+    }
+
+    let updated = conn.execute(
+        "UPDATE locations
+         SET appointment_duration_minutes=?, appointment_buffer_minutes=?, reminder_enabled=?
+         WHERE id=?",
+        params![
+            duration_minutes,
+            buffer_minutes,
+            bool_to_i64(reminder_enabled),
+            location_id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(AppError::NotFound("location not found".to_string()));
+    }
+
+    get_location_settings_with_conn(conn, location_id)
+}
+
+fn get_tcpa_mode_with_conn(conn: &Connection) -> AppResult<bool> {
+    Ok(get_location(conn)?.tcpa_mode)
+}
+
+fn set_tcpa_mode_with_conn(conn: &Connection, clock: &dyn Clock, enabled: bool) -> AppResult<()> {
+    let location_id = current_location_id(conn)?;
+    conn.execute(
+        "UPDATE locations SET tcpa_mode=? WHERE id=?",
+        params![bool_to_i64(enabled), location_id],
+    )?;
+    let _ = insert_audit(
+        conn,
+        clock,
+        "set_tcpa_mode",
+        "location",
+        Some(location_id.to_string()),
+        json!({ "enabled": enabled }),
+        None,
+        true,
+        None,
+    );
+    Ok(())
+}
+
+fn get_lead(conn: &Connection, lead_id: i64) -> AppResult<LeadRow> {
+    conn.query_row(
+        "SELECT id, first_name, consent, opted_out
+         FROM leads WHERE id=?",
+        params![lead_id],
+        |row| {
+            Ok(LeadRow {
+                id: row.get(0)?,
+                first_name: row.get(1)?,
+                consent: i64_to_bool(row.get(2)?),
+                opted_out: i64_to_bool(row.get(3)?),
+            })
+        },
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound("lead not found".to_string()))
+}
+
+fn list_lead_tags(conn: &Connection, lead_id: i64) -> AppResult<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT tag FROM lead_tags WHERE lead_id=? ORDER BY tag ASC")?;
+    let rows = stmt.query_map(params![lead_id], |row| row.get(0))?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+fn map_lead_summary(row: &rusqlite::Row<'_>) -> rusqlite::Result<LeadSummary> {
+    Ok(LeadSummary {
+        id: row.get(0)?,
+        phone_e164: row.get(1)?,
+        first_name: row.get(2)?,
+        last_name: row.get(3)?,
+        status: row.get(4)?,
+        consent: i64_to_bool(row.get(5)?),
+        opted_out: i64_to_bool(row.get(6)?),
+        needs_staff_attention: i64_to_bool(row.get(7)?),
+        created_at: row.get(8)?,
+    })
+}
+
+fn get_conversation_by_lead_id(conn: &Connection, lead_id: i64) -> AppResult<ConversationRow> {
+    conn.query_row(
+        "SELECT id, state, state_json, last_inbound_at, last_outbound_at, repair_attempts
+         FROM conversations WHERE lead_id=?",
+        params![lead_id],
+        |row| {
+            Ok(ConversationRow {
+                id: row.get(0)?,
+                state: row.get(1)?,
+                state_json: row.get(2)?,
+                last_inbound_at: row.get(3)?,
+                last_outbound_at: row.get(4)?,
+                repair_attempts: row.get(5)?,
+            })
+        },
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound("conversation not found".to_string()))
+}
+
+fn is_kill_switch_enabled(conn: &Connection) -> AppResult<bool> {
+    let raw: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key='kill_switch' LIMIT 1",
+            params![],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(matches!(raw.as_deref(), Some("true") | Some("1")))
+}
+
+fn set_kill_switch_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    enabled: bool,
+    reason: Option<&str>,
+) -> AppResult<()> {
+    let now = clock.now().to_rfc3339();
+    let reason = reason.map(str::trim).filter(|reason| !reason.is_empty());
+
+    conn.execute(
+        "INSERT INTO settings (key, value, updated_at)
+         VALUES ('kill_switch', ?, ?)
+         ON CONFLICT(key) DO UPDATE SET value=excluded.value, updated_at=excluded.updated_at",
+        params![if enabled { "true" } else { "false" }, now],
+    )?;
+    // The reason and timestamp only describe the current pause, so they are dropped on resume.
+    conn.execute(
+        "DELETE FROM settings WHERE key IN ('kill_switch_reason', 'kill_switch_enabled_at')",
+        params![],
+    )?;
+    if enabled {
+        put_setting(conn, clock, "kill_switch_enabled_at", &now)?;
+        if let Some(reason) = reason {
+            put_setting(conn, clock, "kill_switch_reason", reason)?;
+        }
+    }
+
+    let _ = insert_audit(
+        conn,
+        clock,
+        "set_kill_switch",
+        "settings",
+        Some("kill_switch".to_string()),
+        json!({ "enabled": enabled, "reason": reason }),
+        Some(json!({ "updated_at": now })),
+        true,
+        None,
+    );
+
+    if enabled {
+        let gateway = ActionGateway::new(conn);
+        gateway.cancel_jobs_on_kill_switch()?;
+    } else {
+        ensure_housekeeping_jobs(conn, clock)?;
+    }
+
+    Ok(())
+}
+
+fn get_kill_switch_state_with_conn(conn: &Connection) -> AppResult<KillSwitchState> {
+    let pending_jobs_count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM scheduled_jobs WHERE status='pending'",
+        params![],
+        |row| row.get(0),
+    )?;
+
+    Ok(KillSwitchState {
+        enabled: is_kill_switch_enabled(conn)?,
+        reason: read_setting(conn, "kill_switch_reason")?,
+        enabled_at: read_setting(conn, "kill_switch_enabled_at")?,
+        pending_jobs_count,
+    })
+}
+
+/// 0 means the automatic kill switch is disabled.
+fn get_auto_kill_switch_threshold_with_conn(conn: &Connection) -> AppResult<i64> {
+    Ok(read_setting(conn, "auto_kill_switch_threshold")?
+        .and_then(|raw| raw.parse::<i64>().ok())
+        .filter(|threshold| *threshold >= 0)
+        .unwrap_or(0))
+}
+
+fn set_auto_kill_switch_threshold_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    threshold: i64,
+) -> AppResult<()> {
+    if threshold < 0 {
+        return Err(AppError::Validation(
+            "auto kill switch threshold must be 0 (disabled) or greater".to_string(),
+        ));
+    }
+    put_setting(
+        conn,
+        clock,
+        "auto_kill_switch_threshold",
+        &threshold.to_string(),
+    )
+}
+
+fn read_setting(conn: &Connection, key: &str) -> AppResult<Option<String>> {
+    conn.query_row(
+        "SELECT value FROM settings WHERE key=? LIMIT 1",
+        params![key],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(AppError::from)
+}
+
+/// Reports at most 100 problems; a healthy database yields the single row "ok".
+fn check_db_integrity_with_conn(conn: &Connection) -> AppResult<IntegrityCheckResult> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check(100)")?;
+    let messages = stmt
+        .query_map(params![], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(IntegrityCheckResult {
+        ok: messages.len() == 1 && messages[0] == "ok",
+        messages,
+    })
+}
+
+/// Copies the live database with SQLite's online backup API. Relative destinations resolve against
+/// `allowed_root`, and anything that lands outside it is rejected.
+fn backup_db_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    allowed_root: &Path,
+    destination_path: &str,
+) -> AppResult<BackupResult> {
+    let request_json = json!({ "destination_path": destination_path });
+    let result = (|| -> AppResult<BackupResult> {
+        let destination = PathBuf::from(destination_path.trim());
+        let destination = if destination.is_absolute() {
+            destination
+        } else {
+            allowed_root.join(destination)
+        };
+        let file_name = destination.file_name().ok_or_else(|| {
+            AppError::Validation("backup destination needs a file name".to_string())
+        })?;
+        let root = allowed_root.canonicalize().map_err(|err| {
+            AppError::Validation(format!("failed to resolve app data dir: {err}"))
+        })?;
+        let directory = destination
+            .parent()
+            .and_then(|parent| parent.canonicalize().ok())
+            .ok_or_else(|| {
+                AppError::Validation("backup destination directory does not exist".to_string())
+            })?;
+        if !directory.starts_with(&root) {
+            return Err(AppError::Validation(
+                "backup destination must be inside the app data directory".to_string(),
+            ));
+        }
+        let destination = directory.join(file_name);
+        let live_path = conn
+            .path()
+            .and_then(|path| Path::new(path).canonicalize().ok());
+        if live_path.as_deref() == Some(destination.as_path()) {
+            return Err(AppError::Validation(
+                "backup destination cannot be the live database".to_string(),
+            ));
+        }
+
+        let mut target = Connection::open(&destination)?;
+        let pages_copied = {
+            let backup = rusqlite::backup::Backup::new(conn, &mut target)?;
+            backup.run_to_completion(100, StdDuration::from_millis(10), None)?;
+            let progress = backup.progress();
+            i64::from(progress.pagecount - progress.remaining)
+        };
+        drop(target);
+
+        let size_bytes = fs::metadata(&destination)
+            .map_err(|err| AppError::Validation(format!("failed to read backup file: {err}")))?
+            .len();
+        Ok(BackupResult {
+            destination_path: destination.to_string_lossy().to_string(),
+            pages_copied,
+            size_bytes,
+        })
+    })();
+
+    match result {
+        Ok(backup) => {
+            let _ = insert_audit(
+                conn,
+                clock,
+                "backup_db",
+                "database",
+                None,
+                request_json,
+                Some(serde_json::to_value(&backup)?),
+                true,
+                None,
+            );
+            Ok(backup)
+        }
+        Err(err) => {
+            let _ = insert_audit(
+                conn,
+                clock,
+                "backup_db",
+                "database",
+                None,
+                request_json,
+                None,
+                false,
+                Some(err.to_string()),
+            );
+            Err(err)
+        }
+    }
+}
+
+fn db_size_bytes(conn: &Connection) -> AppResult<u64> {
+    let page_count: i64 = conn.pragma_query_value(None, "page_count", |row| row.get(0))?;
+    let page_size: i64 = conn.pragma_query_value(None, "page_size", |row| row.get(0))?;
+    Ok(u64::try_from(page_count * page_size).unwrap_or_default())
+}
+
+fn schema_version_with_conn(conn: &Connection) -> AppResult<i64> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+fn list_applied_migrations_with_conn(conn: &Connection) -> AppResult<Vec<MigrationRecord>> {
+    let mut stmt = conn.prepare(
+        "SELECT version, applied_at, description, filename, checksum
+         FROM schema_migrations ORDER BY version",
+    )?;
+    let rows = stmt.query_map(params![], |row| {
+        Ok(MigrationRecord {
+            version: row.get(0)?,
+            applied_at: row.get(1)?,
+            description: row.get(2)?,
+            filename: row.get(3)?,
+            checksum: row.get(4)?,
+            error: None,
+        })
+    })?;
+    let mut records = rows.collect::<Result<Vec<_>, _>>()?;
+    for record in &mut records {
+        record.error = migration_checksum_error(record.version, record.checksum.as_deref());
+    }
+    Ok(records)
+}
+
+fn get_app_info_with_conn(conn: &Connection) -> AppResult<AppInfo> {
+    let location = get_location(conn)?;
+    Ok(AppInfo {
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        db_path: conn.path().unwrap_or_default().to_string(),
+        db_size_bytes: db_size_bytes(conn)?,
+        schema_version: schema_version_with_conn(conn)?,
+        kill_switch_enabled: is_kill_switch_enabled(conn)?,
+        location_name: location.gym_name,
+        location_timezone: location.timezone,
+    })
+}
+
+fn vacuum_db_with_conn(conn: &Connection, clock: &dyn Clock) -> AppResult<VacuumResult> {
+    let result = (|| -> AppResult<VacuumResult> {
+        if is_kill_switch_enabled(conn)? {
+            return Err(AppError::Coded(
+                ErrorCode::KillSwitch,
+                "turn the kill switch off before running vacuum".to_string(),
+            ));
+        }
+
+        let size_before_bytes = db_size_bytes(conn)?;
+        conn.execute_batch("VACUUM")?;
+        Ok(VacuumResult {
+            size_before_bytes,
+            size_after_bytes: db_size_bytes(conn)?,
+        })
+    })();
+
+    match result {
+        Ok(vacuum) => {
+            let _ = insert_audit(
+                conn,
+                clock,
+                "vacuum_db",
+                "database",
+                None,
+                json!({}),
+                Some(serde_json::to_value(&vacuum)?),
+                true,
+                None,
+            );
+            Ok(vacuum)
+        }
+        Err(err) => {
+            let _ = insert_audit(
+                conn,
+                clock,
+                "vacuum_db",
+                "database",
+                None,
+                json!({}),
+                None,
+                false,
+                Some(err.to_string()),
+            );
+            Err(err)
+        }
+    }
+}
+
+/// Runs `PRAGMA wal_checkpoint` in the requested mode. SQLite reports -1 frame counts when the
+/// database is not in WAL mode.
+fn checkpoint_db_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    mode: &str,
+) -> AppResult<CheckpointResult> {
+    let request_json = json!({ "mode": mode });
+    let result = (|| -> AppResult<CheckpointResult> {
+        let mode = match mode.trim().to_ascii_lowercase().as_str() {
+            "passive" => "PASSIVE",
+            "full" => "FULL",
+            "restart" => "RESTART",
+            _ => {
+                return Err(AppError::Validation(
+                    "mode must be one of passive, full, restart".to_string(),
+                ))
+            }
+        };
+        let mut checkpoint = None;
+        conn.pragma(None, "wal_checkpoint", mode, |row| {
+            checkpoint = Some(CheckpointResult {
+                busy_frames: row.get(0)?,
+                log_frames: row.get(1)?,
+                checkpointed_frames: row.get(2)?,
+            });
+            Ok(())
+        })?;
+        checkpoint
+            .ok_or_else(|| AppError::Validation("wal_checkpoint returned no result".to_string()))
+    })();
+
+    match result {
+        Ok(checkpoint) => {
+            let _ = insert_audit(
+                conn,
+                clock,
+                "checkpoint_db",
+                "database",
+                None,
+                request_json,
+                Some(serde_json::to_value(&checkpoint)?),
+                true,
+                None,
+            );
+            Ok(checkpoint)
+        }
+        Err(err) => {
+            let _ = insert_audit(
+                conn,
+                clock,
+                "checkpoint_db",
+                "database",
+                None,
+                request_json,
+                None,
+                false,
+                Some(err.to_string()),
+            );
+            Err(err)
+        }
+    }
+}
+
+fn get_settings_with_conn(conn: &Connection) -> AppResult<Vec<SettingView>> {
+    let mut stmt = conn.prepare("SELECT key, value, updated_at FROM settings ORDER BY key")?;
+    let rows = stmt.query_map(params![], |row| {
+        Ok(SettingView {
+            key: row.get(0)?,
+            value: row.get(1)?,
+            updated_at: row.get(2)?,
+        })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+/// Generic setter for allowlisted keys. The value is checked against the key's type, then handed
+/// to the key's own setter so the same range and content rules apply as in the dedicated commands.
+fn set_setting_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    key: &str,
+    value: &str,
+) -> AppResult<()> {
+    let key = key.trim();
+    let request_json = json!({ "key": key, "value": value });
+    let result = (|| -> AppResult<Option<String>> {
+        let setting_type = SETTABLE_SETTINGS
+            .iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, setting_type)| *setting_type)
+            .ok_or_else(|| AppError::Validation(format!("unknown setting: {key}")))?;
+
+        let value = value.trim();
+        let normalized = match setting_type {
+            SettingType::Bool => match value.to_ascii_lowercase().as_str() {
+                "true" | "1" => "true".to_string(),
+                "false" | "0" => "false".to_string(),
+                _ => return Err(AppError::Validation(format!("{key} must be true or false"))),
+            },
+            SettingType::Int => value
+                .parse::<i64>()
+                .map_err(|_| AppError::Validation(format!("{key} must be an integer")))?
+                .to_string(),
+            SettingType::Text => {
+                if value.is_empty() {
+                    return Err(AppError::Validation(format!("{key} must be non-empty")));
+                }
+                value.to_string()
+            }
+            SettingType::Json => {
+                serde_json::from_str::<Value>(value)
+                    .map_err(|_| AppError::Validation(format!("{key} must be valid JSON")))?;
+                value.to_string()
+            }
+        };
+
+        let previous = read_setting(conn, key)?;
+        let int = || {
+            normalized
+                .parse::<i64>()
+                .map_err(|_| AppError::Validation(format!("{key} must be an integer")))
+        };
+        match key {
+            "kill_switch" => set_kill_switch_with_conn(conn, clock, normalized == "true", None)?,
+            "stop_keywords" => {
+                let keywords: Vec<String> = serde_json::from_str(&normalized).map_err(|_| {
+                    AppError::Validation(
+                        "stop_keywords must be a JSON array of strings".to_string(),
+                    )
+                })?;
+                set_stop_keywords_with_conn(conn, clock, keywords)?;
+            }
+            "help_response_text" => set_help_response_with_conn(conn, clock, &normalized)?,
+            "conversation_expiry_hours" => {
+                set_conversation_expiry_hours_with_conn(conn, clock, int()?)?
+            }
+            "max_outbound_per_lead_per_day" => {
+                let current = load_rate_limit_settings(conn)?;
+                set_rate_limit_settings_with_conn(
+                    conn,
+                    clock,
+                    int()?,
+                    current.max_outbound_per_location_per_hour,
+                )?;
+            }
+            "max_outbound_per_location_per_hour" => {
+                let current = load_rate_limit_settings(conn)?;
+                set_rate_limit_settings_with_conn(
+                    conn,
+                    clock,
+                    current.max_outbound_per_lead_per_day,
+                    int()?,
+                )?;
+            }
+            "auto_kill_switch_threshold" => {
+                set_auto_kill_switch_threshold_with_conn(conn, clock, int()?)?
+            }
+            "audit_log_retention_days" => {
+                set_audit_log_retention_days_with_conn(conn, clock, int()?)?
+            }
+            _ => return Err(AppError::Validation(format!("unknown setting: {key}"))),
+        }
+        Ok(previous)
+    })();
+
+    match result {
+        Ok(previous) => {
+            let _ = insert_audit(
+                conn,
+                clock,
+                "set_setting",
+                "settings",
+                Some(key.to_string()),
+                request_json,
+                Some(json!({ "previous": previous })),
+                true,
+                None,
+            );
+            Ok(())
+        }
+        Err(err) => {
+            let _ = insert_audit(
+                conn,
+                clock,
+                "set_setting",
+                "settings",
+                Some(key.to_string()),
+                request_json,
+                None,
+                false,
+                Some(err.to_string()),
+            );
+            Err(err)
+        }
+    }
+}
+
+fn put_setting(conn: &Connection, clock: &dyn Clock, key: &str, value: &str) -> AppResult<()> {
+    conn.execute(
+        "INSERT INTO settings (key, value, updated_at)
+         VALUES (?, ?, ?)
+         ON CONFLICT(key) DO UPDATE SET value=excluded.value, updated_at=excluded.updated_at",
+        params![key, value, clock.now().to_rfc3339()],
+    )?;
+    Ok(())
+}
+
+/// Carrier opt-out words; always part of the stop keywords whatever the setting holds.
+fn default_stop_keywords() -> Vec<String> {
+    vec!["STOP".to_string(), "UNSUBSCRIBE".to_string()]
+}
+
+/// The defaults followed by any extra keywords, without duplicates.
+fn with_default_stop_keywords(keywords: &[String]) -> Vec<String> {
+    let mut merged = default_stop_keywords();
+    for keyword in keywords {
+        if !merged.contains(keyword) {
+            merged.push(keyword.clone());
+        }
+    }
+    merged
+}
+
+// A corrupt or incomplete stored value still includes the defaults so STOP handling can never be
+// switched off.
+fn get_stop_keywords_with_conn(conn: &Connection) -> AppResult<Vec<String>> {
+    let stored = read_setting(conn, "stop_keywords")?
+        .and_then(|raw| serde_json::from_str::<Vec<String>>(&raw).ok())
+        .unwrap_or_default();
+    Ok(with_default_stop_keywords(&stored))
+}
+
+fn set_stop_keywords_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    keywords: Vec<String>,
+) -> AppResult<()> {
+    let request_json = json!({ "keywords": keywords });
+    let result = (|| -> AppResult<Vec<String>> {
+        if keywords.is_empty() {
+            return Err(AppError::Validation(
+                "stop keyword list must be non-empty".to_string(),
+            ));
+        }
+        if let Some(invalid) = keywords
+            .iter()
+            .find(|keyword| keyword.is_empty() || !keyword.chars().all(|c| c.is_ascii_uppercase()))
+        {
+            return Err(AppError::Validation(format!(
+                "stop keyword must be non-empty ASCII uppercase: {invalid:?}"
+            )));
+        }
+
+        let previous = get_stop_keywords_with_conn(conn)?;
+        put_setting(
+            conn,
+            clock,
+            "stop_keywords",
+            &serde_json::to_string(&with_default_stop_keywords(&keywords))?,
+        )?;
+        Ok(previous)
+    })();
+
+    match result {
+        Ok(previous) => {
+            let _ = insert_audit(
+                conn,
+                clock,
+                "set_stop_keywords",
+                "settings",
+                Some("stop_keywords".to_string()),
+                request_json,
+                Some(json!({ "previous": previous })),
+                true,
+                None,
+            );
+            Ok(())
+        }
+        Err(err) => {
+            let _ = insert_audit(
+                conn,
+                clock,
+                "set_stop_keywords",
+                "settings",
+                Some("stop_keywords".to_string()),
+                request_json,
+                None,
+                false,
+                Some(err.to_string()),
+            );
+            Err(err)
+        }
+    }
+}
+
+/// "Today" is the calendar day at `now` in the location's timezone.
+fn count_lead_outbound_today(
+    conn: &Connection,
+    location: &Location,
+    lead_id: i64,
+    now: DateTime<Utc>,
+) -> AppResult<i64> {
+    let day = now
+        .with_timezone(&parse_tz(&location.timezone)?)
+        .date_naive();
+    let (start, end) = local_day_bounds_utc(location, day)?;
+    conn.query_row(
+        "SELECT COUNT(*)
+         FROM messages m
+         JOIN conversations c ON c.id = m.conversation_id
+         WHERE c.lead_id = ?
+           AND m.direction = 'OUTBOUND'
+           AND m.rate_limit_exempt = 0
+           AND datetime(m.created_at) >= datetime(?)
+           AND datetime(m.created_at) < datetime(?)",
+        params![lead_id, start.to_rfc3339(), end.to_rfc3339()],
+        |row| row.get(0),
+    )
+    .map_err(AppError::from)
+}
+
+fn count_location_outbound_last_hour(
+    conn: &Connection,
+    location_id: i64,
+    now: DateTime<Utc>,
+) -> AppResult<i64> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM messages m
+         JOIN conversations c ON c.id = m.conversation_id
+         JOIN leads l ON l.id = c.lead_id
+         WHERE m.direction = 'OUTBOUND'
+           AND m.rate_limit_exempt = 0
+           AND datetime(m.created_at) >= datetime(?)
+           AND l.location_id = ?",
+        params![(now - Duration::hours(1)).to_rfc3339(), location_id],
+        |row| row.get(0),
+    )
+    .map_err(AppError::from)
+}
+
+/// Uses the same counts as `check_rate_limits`, against the lead's location when a lead is given
+/// and the selected one otherwise. The cooldown ignores the "lead just replied" exemption because
+/// manual sends do not get it.
+fn get_rate_limit_status_with_conn(
+    conn: &Connection,
+    lead_id: Option<i64>,
+    now: DateTime<Utc>,
+) -> AppResult<RateLimitStatus> {
+    let limits = load_rate_limit_settings(conn)?;
+    let location = match lead_id {
+        Some(lead_id) => get_lead_location(conn, lead_id)?,
+        None => get_location(conn)?,
+    };
+    let mut status = RateLimitStatus {
+        location_hour_used: count_location_outbound_last_hour(conn, location.id, now)?,
+        location_hour_limit: limits.max_outbound_per_location_per_hour,
+        lead_day_used: None,
+        lead_day_limit: None,
+        cooldown_seconds: None,
+    };
+
+    if let Some(lead_id) = lead_id {
+        status.lead_day_used = Some(count_lead_outbound_today(conn, &location, lead_id, now)?);
+        status.lead_day_limit = Some(limits.max_outbound_per_lead_per_day);
+        let last_outbound_at: Option<String> = conn
+            .query_row(
+                "SELECT last_outbound_at FROM conversations WHERE lead_id=?",
+                params![lead_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
+        if let Some(last_outbound_at) = last_outbound_at {
+            let remaining =
+                Duration::hours(2) - now.signed_duration_since(parse_ts(&last_outbound_at)?);
+            if remaining > Duration::zero() {
+                status.cooldown_seconds = Some(remaining.num_seconds());
+            }
+        }
+    }
+
+    Ok(status)
+}
+
+fn load_rate_limit_settings(conn: &Connection) -> AppResult<RateLimitSettings> {
+    let read = |key: &str, default: i64| -> AppResult<i64> {
+        Ok(read_setting(conn, key)?
+            .and_then(|raw| raw.parse::<i64>().ok())
+            .filter(|value| *value > 0)
+            .unwrap_or(default))
+    };
+    Ok(RateLimitSettings {
+        max_outbound_per_lead_per_day: read("max_outbound_per_lead_per_day", 4)?,
+        max_outbound_per_location_per_hour: read("max_outbound_per_location_per_hour", 100)?,
+    })
+}
+
+fn set_rate_limit_settings_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    per_lead_day: i64,
+    per_location_hour: i64,
+) -> AppResult<RateLimitSettings> {
+    let request_json = json!({
+        "per_lead_day": per_lead_day,
+        "per_location_hour": per_location_hour
+    });
+    let result = (|| -> AppResult<RateLimitSettings> {
+        if !(1..=20).contains(&per_lead_day) {
+            return Err(AppError::Validation(
+                "per-lead daily limit must be between 1 and 20".to_string(),
+            ));
+        }
+        if per_location_hour < 1 {
+            return Err(AppError::Validation(
+                "per-location hourly limit must be at least 1".to_string(),
+            ));
+        }
+
+        let previous = load_rate_limit_settings(conn)?;
+        put_setting(
+            conn,
+            clock,
+            "max_outbound_per_lead_per_day",
+            &per_lead_day.to_string(),
+        )?;
+        put_setting(
+            conn,
+            clock,
+            "max_outbound_per_location_per_hour",
+            &per_location_hour.to_string(),
+        )?;
+        Ok(previous)
+    })();
+
+    match result {
+        Ok(previous) => {
+            let _ = insert_audit(
+                conn,
+                clock,
+                "set_rate_limit_settings",
+                "settings",
+                None,
+                request_json,
+                Some(json!({ "previous": previous })),
+                true,
+                None,
+            );
+            load_rate_limit_settings(conn)
+        }
+        Err(err) => {
+            let _ = insert_audit(
+                conn,
+                clock,
+                "set_rate_limit_settings",
+                "settings",
+                None,
+                request_json,
+                None,
+                false,
+                Some(err.to_string()),
+            );
+            Err(err)
+        }
+    }
+}
+
+fn get_help_response_with_conn(conn: &Connection) -> AppResult<String> {
+    Ok(read_setting(conn, "help_response_text")?
+        .unwrap_or_else(|| "For help contact {gym} or reply STOP to unsubscribe.".to_string()))
+}
+
+fn get_conversation_expiry_hours_with_conn(conn: &Connection) -> AppResult<i64> {
+    Ok(read_setting(conn, "conversation_expiry_hours")?
+        .and_then(|raw| raw.parse::<i64>().ok())
+        .filter(|hours| *hours > 0)
+        .unwrap_or(48))
+}
+
+fn get_audit_log_retention_days_with_conn(conn: &Connection) -> AppResult<i64> {
+    Ok(read_setting(conn, "audit_log_retention_days")?
+        .and_then(|raw| raw.parse::<i64>().ok())
+        .filter(|days| *days > 0)
+        .unwrap_or(90))
+}
+
+fn set_audit_log_retention_days_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    days: i64,
+) -> AppResult<()> {
+    if days <= 0 {
+        return Err(AppError::Validation(
+            "audit log retention days must be greater than 0".to_string(),
+        ));
+    }
+    put_setting(conn, clock, "audit_log_retention_days", &days.to_string())
+}
+
+fn set_conversation_expiry_hours_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    hours: i64,
+) -> AppResult<()> {
+    if hours <= 0 {
+        return Err(AppError::Validation(
+            "conversation expiry hours must be greater than 0".to_string(),
+        ));
+    }
+    put_setting(conn, clock, "conversation_expiry_hours", &hours.to_string())
+}
+
+fn set_help_response_with_conn(conn: &Connection, clock: &dyn Clock, text: &str) -> AppResult<()> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(AppError::Validation(
+            "help response text must be non-empty".to_string(),
+        ));
+    }
+    put_setting(conn, clock, "help_response_text", text)
+}
+
+fn find_active_custom_keyword_response(
+    conn: &Connection,
+    normalized: &str,
+) -> AppResult<Option<String>> {
+    conn.query_row(
+        "SELECT response_body FROM custom_keywords WHERE keyword=? AND active=1",
+        params![normalized],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(AppError::from)
+}
+
+/// Keywords are stored uppercase; words the state machine already reacts to are rejected.
+fn normalize_custom_keyword(conn: &Connection, keyword: &str) -> AppResult<String> {
+    let normalized = keyword.trim().to_ascii_uppercase();
+    if normalized.is_empty() {
+        return Err(AppError::Validation(
+            "keyword must be non-empty".to_string(),
+        ));
+    }
+    let reserved = ["HELP", "YES", "Y", "1", "2"];
+    if reserved.contains(&normalized.as_str())
+        || get_stop_keywords_with_conn(conn)?.contains(&normalized)
+    {
+        return Err(AppError::Validation(format!(
+            "{normalized} is reserved and cannot be a custom keyword"
+        )));
+    }
+    Ok(normalized)
+}
+
+fn validate_custom_keyword_response(response_body: &str) -> AppResult<String> {
+    let response_body = response_body.trim();
+    if response_body.is_empty() {
+        return Err(AppError::Validation(
+            "response_body must be non-empty".to_string(),
+        ));
+    }
+    Ok(response_body.to_string())
+}
+
+fn get_custom_keyword(conn: &Connection, id: i64) -> AppResult<CustomKeyword> {
+    conn.query_row(
+        "SELECT id, keyword, response_body, active, created_at FROM custom_keywords WHERE id=?",
+        params![id],
+        map_custom_keyword,
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound("custom keyword not found".to_string()))
+}
+
+fn map_custom_keyword(row: &rusqlite::Row<'_>) -> rusqlite::Result<CustomKeyword> {
+    Ok(CustomKeyword {
+        id: row.get(0)?,
+        keyword: row.get(1)?,
+        response_body: row.get(2)?,
+        active: i64_to_bool(row.get(3)?),
+        created_at: row.get(4)?,
+    })
+}
+
+fn ensure_custom_keyword_unique(conn: &Connection, keyword: &str, except_id: i64) -> AppResult<()> {
+    let existing: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM custom_keywords WHERE keyword=? AND id<>?",
+        params![keyword, except_id],
+        |row| row.get(0),
+    )?;
+    if existing > 0 {
+        return Err(AppError::Validation(format!(
+            "keyword {keyword} already exists"
+        )));
+    }
+    Ok(())
+}
+
+fn add_custom_keyword_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    keyword: &str,
+    response_body: &str,
+) -> AppResult<CustomKeyword> {
+    let keyword = normalize_custom_keyword(conn, keyword)?;
+    let response_body = validate_custom_keyword_response(response_body)?;
+    ensure_custom_keyword_unique(conn, &keyword, 0)?;
+
+    conn.execute(
+        "INSERT INTO custom_keywords (keyword, response_body, active, created_at) VALUES (?, ?, 1, ?)",
+        params![keyword, response_body, clock.now().to_rfc3339()],
+    )?;
+    get_custom_keyword(conn, conn.last_insert_rowid())
+}
+
+fn update_custom_keyword_with_conn(
+    conn: &Connection,
+    id: i64,
+    keyword: &str,
+    response_body: &str,
+) -> AppResult<CustomKeyword> {
+    get_custom_keyword(conn, id)?;
+    let keyword = normalize_custom_keyword(conn, keyword)?;
+    let response_body = validate_custom_keyword_response(response_body)?;
+    ensure_custom_keyword_unique(conn, &keyword, id)?;
+
+    conn.execute(
+        "UPDATE custom_keywords SET keyword=?, response_body=? WHERE id=?",
+        params![keyword, response_body, id],
+    )?;
+    get_custom_keyword(conn, id)
+}
+
+fn set_custom_keyword_active_with_conn(conn: &Connection, id: i64, active: bool) -> AppResult<()> {
+    let updated = conn.execute(
+        "UPDATE custom_keywords SET active=? WHERE id=?",
+        params![bool_to_i64(active), id],
+    )?;
+    if updated == 0 {
+        return Err(AppError::NotFound("custom keyword not found".to_string()));
+    }
+    Ok(())
+}
+
+fn list_custom_keywords_with_conn(conn: &Connection) -> AppResult<Vec<CustomKeyword>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, keyword, response_body, active, created_at FROM custom_keywords ORDER BY keyword",
+    )?;
+    let rows = stmt.query_map(params![], map_custom_keyword)?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+fn insert_audit(
+    conn: &Connection,
+    clock: &dyn Clock,
+    action_type: &str,
+    target_type: &str,
+    target_id: Option<String>,
+    request_json: Value,
+    response_json: Option<Value>,
+    success: bool,
+    error_message: Option<String>,
+) -> AppResult<()> {
+    conn.execute(
+        "INSERT INTO audit_log (action_type, target_type, target_id, request_json, response_json, success, error_message, created_at, severity)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            action_type,
+            target_type,
+            target_id,
+            serde_json::to_string(&request_json)?,
+            response_json.map(|v| serde_json::to_string(&v)).transpose()?,
+            bool_to_i64(success),
+            error_message,
+            clock.now().to_rfc3339(),
+            audit_severity(action_type)
+        ],
+    )?;
+    Ok(())
+}
+
+/// Actions that bypass the normal safety rails are flagged for review.
+fn audit_severity(action_type: &str) -> &'static str {
+    match action_type {
+        "override_conversation_state" | "wipe_all_data_confirmed" | "reset_demo_data" => "high",
+        _ => "info",
+    }
+}
+
+fn log_kill_switch_block(
+    conn: &Connection,
+    clock: &dyn Clock,
+    action_type: &str,
+    target_type: &str,
+    target_id: Option<String>,
+    request_json: Value,
+    detail: &str,
+) {
+    let message = format!("kill switch block: {detail}");
+    let _ = insert_audit(
+        conn,
+        clock,
+        action_type,
+        target_type,
+        target_id,
+        request_json,
+        Some(json!({
+            "blocked_by": "kill_switch",
+            "detail": detail
+        })),
+        false,
+        Some(message),
+    );
+}
+
+fn open_conn(state: &State<AppState>) -> AppResult<PooledConn> {
+    Ok(state.pool.get()?)
+}
+
+fn build_pool(db_path: &Path) -> AppResult<DbPool> {
+    let manager = SqliteConnectionManager::file(db_path);
+    Ok(r2d2::Pool::builder()
+        .max_size(4)
+        .connection_customizer(Box::new(ConnectionCustomizer))
+        .build(manager)?)
+}
+
+fn configure_connection(conn: &Connection) -> rusqlite::Result<()> {
+    conn.busy_timeout(StdDuration::from_millis(500))?;
+    conn.pragma_update(None, "foreign_keys", "ON")?;
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    Ok(())
+}
+
+/// Absolute path of the database file behind the pool.
+fn db_file_path(state: &State<AppState>) -> AppResult<PathBuf> {
+    open_conn(state)?
+        .path()
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .ok_or_else(|| AppError::Validation("database has no file path".to_string()))
+}
+
+fn open_conn_read_only(db_path: &Path) -> AppResult<Connection> {
+    let conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    conn.busy_timeout(StdDuration::from_millis(500))?;
+    Ok(conn)
+}
+
+#[tracing::instrument(skip_all)]
+fn run_due_jobs_with_conn(conn: &Connection, clock: &dyn Clock) -> AppResult<RunJobsResult> {
+    if is_kill_switch_enabled(conn)? {
+        let skipped: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM scheduled_jobs
+             WHERE status='pending' AND datetime(execute_at) <= datetime(?)",
+            params![clock.now().to_rfc3339()],
+            |row| row.get(0),
+        )?;
+        if skipped > 0 {
+            log_kill_switch_block(
+                conn,
+                clock,
+                "run_due_jobs",
+                "scheduled_job",
+                None,
+                json!({ "scope": "due_jobs", "skipped": skipped }),
+                "due jobs skipped because automation is paused (safe mode)",
+            );
+        }
+        return Ok(RunJobsResult {
+            processed: 0,
+            skipped,
+            errors: 0,
+            auto_kill_switch_triggered: false,
+        });
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT id, job_type, target_id, payload_json
+         FROM scheduled_jobs
+         WHERE status='pending' AND datetime(execute_at) <= datetime(?)
+         ORDER BY datetime(execute_at) ASC",
+    )?;
+
+    let mut jobs: Vec<(i64, String, Option<i64>, String)> = Vec::new();
+    let mapped = stmt.query_map(params![clock.now().to_rfc3339()], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+    })?;
+    for item in mapped {
+        jobs.push(item?);
+    }
+
+    let mut processed = 0;
+    let mut skipped = 0;
+    let mut errors = 0;
+
+    for (job_id, job_type, target_id, payload_json) in jobs {
+        if is_kill_switch_enabled(conn)? {
+            skipped += 1;
+            log_kill_switch_block(
+                conn,
+                clock,
+                "run_scheduled_job",
+                "scheduled_job",
+                Some(job_id.to_string()),
+                json!({
+                    "job_type": job_type,
+                    "target_id": target_id,
+                    "payload_json": payload_json
+                }),
+                "scheduled job skipped because automation is paused (safe mode)",
+            );
+            continue;
+        }
+
+        let run_result = match job_type.as_str() {
+            "initial_follow_up" => {
+                let payload: InitialFollowUpPayload = serde_json::from_str(&payload_json)?;
+                execute_initial_follow_up(conn, clock, payload.lead_id, payload.step_number)
+            }
+            "appointment_reminder" => {
+                let payload: ReminderPayload = serde_json::from_str(&payload_json)?;
+                execute_appointment_reminder(conn, clock, payload)
+            }
+            "no_show_follow_up" => {
+                let payload: NoShowFollowUpPayload = serde_json::from_str(&payload_json)?;
+                execute_no_show_follow_up(conn, clock, payload)
+            }
+            "expire_stale_conversations" => execute_expire_stale_conversations(conn, clock),
+            "purge_audit_log" => execute_purge_audit_log(conn, clock),
+            "re_engagement" => {
+                let payload: ReEngagementPayload = serde_json::from_str(&payload_json)?;
+                execute_re_engagement(conn, clock, payload)
+            }
+            "post_appointment_follow_up" => {
+                let payload: PostAppointmentFollowUpPayload = serde_json::from_str(&payload_json)?;
+                execute_post_appointment_follow_up(conn, clock, payload)
+            }
+            _ => Err(AppError::Validation(format!("unknown job_type: {job_type}"))),
+        };
+
+        match run_result {
+            Ok(()) => {
+                processed += 1;
+                conn.execute(
+                    "UPDATE scheduled_jobs SET status='completed', completed_at=? WHERE id=?",
+                    params![clock.now().to_rfc3339(), job_id],
+                )?;
+            }
+            Err(err) => {
+                errors += 1;
+                tracing::warn!(job_id, job_type = %job_type, error = %err, "scheduled job failed");
+                let retry_at = schedule_job_retry(conn, clock, job_id)?;
+                let _ = insert_audit(
+                    conn,
+                    clock,
+                    "run_scheduled_job",
+                    "scheduled_job",
+                    Some(job_id.to_string()),
+                    json!({
+                        "job_type": job_type,
+                        "target_id": target_id,
+                        "payload_json": payload_json
+                    }),
+                    Some(json!({ "retry_at": retry_at })),
+                    false,
+                    Some(err.to_string()),
+                );
+            }
+        }
+    }
+
+    let threshold = get_auto_kill_switch_threshold_with_conn(conn)?;
+    let auto_kill_switch_triggered = threshold > 0 && errors >= threshold;
+    if auto_kill_switch_triggered {
+        set_kill_switch_with_conn(
+            conn,
+            clock,
+            true,
+            Some("auto_kill_switch: threshold exceeded"),
+        )?;
+        let _ = insert_audit(
+            conn,
+            clock,
+            "auto_kill_switch",
+            "settings",
+            Some("kill_switch".to_string()),
+            json!({
+                "reason": "auto_kill_switch: threshold exceeded",
+                "errors": errors,
+                "threshold": threshold
+            }),
+            None,
+            true,
+            None,
+        );
+    }
+
+    tracing::info!(
+        processed,
+        skipped,
+        errors,
+        auto_kill_switch_triggered,
+        "due jobs run"
+    );
+    Ok(RunJobsResult {
+        processed,
+        skipped,
+        errors,
+        auto_kill_switch_triggered,
+    })
+}
+
+/// Puts a failed job back in the queue with a linear backoff (60, 120, 180... minutes) until it
+/// runs out of retries, at which point it is marked `failed`. Returns the retry time, if any.
+fn schedule_job_retry(
+    conn: &Connection,
+    clock: &dyn Clock,
+    job_id: i64,
+) -> AppResult<Option<String>> {
+    let job = get_scheduled_job(conn, job_id)?;
+    if job.retry_count >= job.max_retries {
+        conn.execute(
+            "UPDATE scheduled_jobs SET status='failed' WHERE id=?",
+            params![job_id],
+        )?;
+        return Ok(None);
+    }
+
+    let retry_count = job.retry_count + 1;
+    let retry_at = (clock.now() + Duration::minutes(retry_count * 60)).to_rfc3339();
+    conn.execute(
+        "UPDATE scheduled_jobs SET status='pending', retry_count=?, execute_at=? WHERE id=?",
+        params![retry_count, retry_at, job_id],
+    )?;
+    Ok(Some(retry_at))
+}
+
+fn load_config(path: &Path) -> AppResult<Config> {
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+    let text = fs::read_to_string(path)
+        .map_err(|err| AppError::Validation(format!("failed to read {}: {err}", path.display())))?;
+    toml::from_str(&text)
+        .map_err(|err| AppError::Validation(format!("invalid {}: {err}", path.display())))
+}
+
+/// `config_path` is only read while the database has no location yet, so a broken file cannot keep
+/// an already set-up install from starting. Seeding from it is all-or-nothing and is retried on
+/// the next start if it fails.
+fn initialize_db(db_path: &Path, config_path: &Path, clock: &dyn Clock) -> AppResult<()> {
+    if let Some(parent) = db_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| AppError::Validation(e.to_string()))?;
+    }
+
+    let mut conn = Connection::open(db_path)?;
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    conn.pragma_update(None, "foreign_keys", "ON")?;
+    run_migrations(&conn, MIGRATIONS)?;
+
+    let location_count: i64 =
+        conn.query_row("SELECT COUNT(*) FROM locations", params![], |row| {
+            row.get(0)
+        })?;
+    if location_count == 0 {
+        let config = load_config(config_path)?;
+        let tx = conn.transaction()?;
+        let (gym_name, timezone, business_hours_json) =
+            validate_location_input(&LocationSettingsInput {
+                gym_name: config
+                    .location
+                    .gym_name
+                    .clone()
+                    .unwrap_or_else(|| default_gym_name().to_string()),
+                timezone: config
+                    .location
+                    .timezone
+                    .clone()
+                    .unwrap_or_else(|| default_timezone().to_string()),
+                business_hours_json: config
+                    .location
+                    .business_hours_json
+                    .clone()
+                    .unwrap_or_else(|| default_business_hours_json().to_string()),
+            })?;
+        tx.execute(
+            "INSERT INTO locations (gym_name, timezone, business_hours_json) VALUES (?, ?, ?)",
+            params![gym_name, timezone, business_hours_json],
+        )?;
+        for (key, value) in &config.settings {
+            let value = match value {
+                toml::Value::String(text) => text.clone(),
+                other => other.to_string(),
+            };
+            set_setting_with_conn(&tx, clock, key, &value)?;
+        }
+        tx.commit()?;
+    }
+
+    conn.execute(
+        "INSERT INTO settings (key, value, updated_at)
+         VALUES ('kill_switch', 'true', ?)
+         ON CONFLICT(key) DO NOTHING",
+        params![clock.now().to_rfc3339()],
+    )?;
+    ensure_housekeeping_jobs(&conn, clock)?;
+
+    Ok(())
+}
+
+struct Migration {
+    version: i64,
+    description: &'static str,
+    file: &'static str,
+    sql: &'static str,
+    /// Seeds default rows (templates etc.); `reset_demo_data` re-runs these after emptying the
+    /// tables, so their SQL must be safe to apply twice.
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    seeds: bool,
+}
+
+/// `file` names the entry in `migrations/` once so the recorded filename and the embedded SQL
+/// cannot point at different files.
+macro_rules! migration {
+    ($version:expr, $file:literal, $description:expr, seeds: $seeds:expr) => {
+        Migration {
+            version: $version,
+            description: $description,
+            file: $file,
+            sql: include_str!(concat!("../migrations/", $file)),
+            seeds: $seeds,
+        }
+    };
+}
+
+/// Applied in order; `PRAGMA user_version` holds the last one that ran. Never edit a shipped entry,
+/// add a new numbered file instead. The files are embedded rather than read from a directory at
+/// runtime because the app bundle does not ship `migrations/`.
+const MIGRATIONS: &[Migration] = &[
+    migration!(1, "001_init.sql", "initial schema", seeds: false),
+    migration!(2, "002_lead_workflow_schema.sql", "lead workflow and scheduling schema", seeds: false),
+    migration!(3, "003_default_message_templates.sql", "default message templates", seeds: true),
+    migration!(4, "004_lookup_indexes.sql", "lead status and audit action indexes", seeds: false),
+    migration!(5, "005_phone_blocklist.sql", "phone blocklist", seeds: false),
+    migration!(6, "006_lead_full_name.sql", "lead full_name column", seeds: false),
+    migration!(7, "007_appointment_ratings.sql", "appointment ratings", seeds: true),
+    migration!(8, "008_idempotency_records.sql", "agent idempotency records", seeds: false),
+    migration!(9, "009_default_appointment_type.sql", "default appointment type", seeds: true),
+    migration!(10, "010_default_reminder_configs.sql", "default reminder configs", seeds: true),
+    migration!(11, "011_default_follow_up_step.sql", "default follow-up step", seeds: false),
+    migration!(12, "012_idempotency_request_hash.sql", "idempotency request hash", seeds: false),
+];
+
+fn migration_checksum(sql: &str) -> String {
+    format!("{:x}", Sha256::digest(sql.as_bytes()))
+}
+
+fn migration_checksum_error(version: i64, recorded: Option<&str>) -> Option<String> {
+    let Some(migration) = MIGRATIONS.iter().find(|m| m.version == version) else {
+        return Some(format!(
+            "migration {version} is not shipped with this build"
+        ));
+    };
+    let recorded = recorded?;
+    (recorded != migration_checksum(migration.sql)).then(|| {
+        format!(
+            "checksum mismatch: {} was edited after it was applied",
+            migration.file
+        )
+    })
+}
+
+/// Returns the files applied by this call, in order.
+fn run_migrations(conn: &Connection, migrations: &[Migration]) -> AppResult<Vec<String>> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+           version INTEGER PRIMARY KEY,
+           applied_at TEXT NOT NULL,
+           description TEXT NOT NULL,
+           filename TEXT,
+           checksum TEXT
+         )",
+    )?;
+    ensure_column(conn, "schema_migrations", "filename", "TEXT")?;
+    ensure_column(conn, "schema_migrations", "checksum", "TEXT")?;
+    let current = schema_version_with_conn(conn)?;
+    let mut applied = Vec::new();
+    // Rows written before checksums were recorded are filled in from the embedded file; a
+    // mismatch on a recorded one means a shipped migration was edited.
+    for migration in migrations.iter().filter(|m| m.version <= current) {
+        let checksum = migration_checksum(migration.sql);
+        conn.execute(
+            "UPDATE schema_migrations SET filename=?, checksum=? WHERE version=? AND checksum IS NULL",
+            params![migration.file, checksum, migration.version],
+        )?;
+        let recorded: Option<String> = conn
+            .query_row(
+                "SELECT checksum FROM schema_migrations WHERE version=?",
+                params![migration.version],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
+        if recorded.is_some_and(|recorded| recorded != checksum) {
+            // Also surfaced through `list_applied_migrations`.
+            tracing::warn!(
+                version = migration.version,
+                file = migration.file,
+                "applied migration differs from the shipped file"
+            );
+        }
+    }
+    for migration in migrations.iter().filter(|m| m.version > current) {
+        let tx = conn.unchecked_transaction()?;
+        tx.execute_batch(migration.sql)?;
+        tx.execute(
+            "INSERT INTO schema_migrations (version, applied_at, description, filename, checksum)
+             VALUES (?, ?, ?, ?, ?)",
+            params![
+                migration.version,
+                now_iso(),
+                migration.description,
+                migration.file,
+                migration_checksum(migration.sql)
+            ],
+        )?;
+        tracing::info!(
+            version = migration.version,
+            file = migration.file,
+            "applied migration"
+        );
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+        applied.push(migration.file.to_string());
+    }
+    Ok(applied)
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> AppResult<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let columns = stmt
+        .query_map(params![], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(columns.iter().any(|existing| existing == column))
+}
+
+fn ensure_column(conn: &Connection, table: &str, column: &str, definition: &str) -> AppResult<()> {
+    if !has_column(conn, table, column)? {
+        conn.execute_batch(&format!(
+            "ALTER TABLE {table} ADD COLUMN {column} {definition}"
+        ))?;
+    }
+    Ok(())
+}
+
+/// Sends tracing output as JSON lines to `app.log` in the app data directory. Business events
+/// still go to `audit_log`; this is for operational diagnostics.
+fn init_tracing(app_dir: &Path) -> std::io::Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(app_dir.join("app.log"))?;
+    let _ = tracing_subscriber::fmt()
+        .json()
+        .with_writer(std::sync::Mutex::new(file))
+        .try_init();
+    Ok(())
+}
+
+fn ensure_app_data_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let app_dir = app
+        .path_resolver()
+        .app_local_data_dir()
+        .ok_or_else(|| "failed to resolve app local data dir".to_string())?;
+    fs::create_dir_all(&app_dir).map_err(|err| format!("failed to create app data dir: {err}"))?;
+    Ok(app_dir)
+}
+
+fn map_cmd_result<T: Serialize>(
+    result: AppResult<T>,
+    action_name: &str,
+    app: &AppHandle,
+) -> Result<T, String> {
+    match result {
+        Ok(value) => Ok(value),
+        Err(err) => {
+            tracing::error!(action = action_name, code = ?err.code(), "{err}");
+            log_command_failure(app, action_name, &format!("Alert: {err}"));
+            Err(error_response_json(&err, action_name))
+        }
+    }
+}
+
+fn error_response(err: &AppError, action_name: &str) -> AppErrorResponse {
+    AppErrorResponse {
+        code: err.code(),
+        message: err.to_string(),
+        detail: Some(json!({ "action": action_name })),
+        status: err.is_not_found().then_some(404),
+    }
+}
+
+fn error_response_json(err: &AppError, action_name: &str) -> String {
+    serde_json::to_string(&error_response(err, action_name))
+        .unwrap_or_else(|_| format!("Alert: {err}"))
+}
+
+fn log_command_failure(app: &AppHandle, action_name: &str, message: &str) {
+    if let Some(state) = app.try_state::<AppState>() {
+        if let Ok(conn) = state.pool.get() {
+            let _ = insert_audit(
+                &conn,
+                state.clock.as_ref(),
+                action_name,
+                "command",
+                None,
+                json!({ "action": action_name }),
+                None,
+                false,
+                Some(message.to_string()),
+            );
+        }
+    }
+}
+
+/// How `retry_db_with_policy` backs off while SQLite reports busy/locked.
+#[derive(Debug, Clone, Copy)]
+struct RetryPolicy {
+    max_attempts: u32,
+    base_delay_ms: u64,
+    max_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    /// Five attempts waiting roughly 40, 80, 160 and 160 ms, close to the old linear schedule.
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay_ms: 20,
+            max_delay_ms: 160,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// For callers that regularly contend with the job runner or a WAL checkpoint.
+    fn aggressive() -> Self {
+        Self {
+            max_attempts: 10,
+            base_delay_ms: 20,
+            max_delay_ms: 2000,
+        }
+    }
+
+    /// `base * 2^attempt + jitter`, capped at `max_delay_ms`. Jitter below `base_delay_ms` keeps
+    /// the sequence non-decreasing.
+    fn delay_ms(&self, attempt: u32, jitter_ms: u64) -> u64 {
+        let exponential = self
+            .base_delay_ms
+            .saturating_mul(1_u64.checked_shl(attempt).unwrap_or(u64::MAX));
+        exponential
+            .saturating_add(jitter_ms % self.base_delay_ms.max(1))
+            .min(self.max_delay_ms)
+    }
+}
+
+fn retry_db<T, F>(f: F) -> AppResult<T>
+where
+    F: FnMut() -> AppResult<T>,
+{
+    retry_db_with_policy(&RetryPolicy::default(), f)
+}
+
+fn retry_db_with_policy<T, F>(policy: &RetryPolicy, mut f: F) -> AppResult<T>
+where
+    F: FnMut() -> AppResult<T>,
+{
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(err) if err.is_busy_or_locked() && attempt < policy.max_attempts => {
+                // Sub-second clock noise is plenty to spread out competing retries.
+                let jitter = Utc::now().timestamp_subsec_nanos() as u64;
+                thread::sleep(StdDuration::from_millis(policy.delay_ms(attempt, jitter)));
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn now_iso() -> String {
+    Utc::now().to_rfc3339()
+}
+
+fn parse_ts(input: &str) -> AppResult<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(input)?.with_timezone(&Utc))
+}
+
+fn bool_to_i64(v: bool) -> i64 {
+    if v {
+        1
+    } else {
+        0
+    }
+}
+
+fn i64_to_bool(v: i64) -> bool {
+    v != 0
+}
+
+fn parse_tz(tz_name: &str) -> AppResult<Tz> {
+    tz_name
+        .parse::<Tz>()
+        .map_err(|_| AppError::Validation(format!("invalid timezone: {tz_name}")))
+}
+
+/// `None` resolves to the default type, the oldest one (the "Intro Session" seeded by migration 7).
+fn get_appointment_type(
+    conn: &Connection,
+    appointment_type_id: Option<i64>,
+) -> AppResult<AppointmentType> {
+    conn.query_row(
+        "SELECT id, name, duration_minutes, confirmation_template, reminder_template
+         FROM appointment_types WHERE id=COALESCE(?, (SELECT MIN(id) FROM appointment_types))",
+        params![appointment_type_id],
+        map_appointment_type,
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound("appointment type not found".to_string()))
+}
+
+fn map_appointment_type(row: &rusqlite::Row<'_>) -> rusqlite::Result<AppointmentType> {
+    Ok(AppointmentType {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        duration_minutes: row.get(2)?,
+        confirmation_template: row.get(3)?,
+        reminder_template: row.get(4)?,
+    })
+}
+
+fn list_appointment_types_with_conn(conn: &Connection) -> AppResult<Vec<AppointmentType>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, duration_minutes, confirmation_template, reminder_template
+         FROM appointment_types ORDER BY id",
+    )?;
+    let rows = stmt.query_map(params![], map_appointment_type)?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+/// The type the lead's next slot offer and booking use; `None` goes back to the default.
+fn set_conversation_appointment_type_with_conn(
+    conn: &Connection,
+    clock: &dyn Clock,
+    lead_id: i64,
+    appointment_type_id: Option<i64>,
+) -> AppResult<()> {
+    if let Some(id) = appointment_type_id {
+        get_appointment_type(conn, Some(id))?;
+    }
+    let conversation = get_conversation_by_lead_id(conn, lead_id)?;
+    let mut state: ConversationState =
+        serde_json::from_str(&conversation.state_json).unwrap_or_default();
+    let previous = state.appointment_type_id;
+    state.appointment_type_id = appointment_type_id;
+    conn.execute(
+        "UPDATE conversations SET state_json=? WHERE id=?",
+        params![serde_json::to_string(&state)?, conversation.id],
+    )?;
+    insert_audit(
+        conn,
+        clock,
+        "set_conversation_appointment_type",
+        "conversation",
+        Some(conversation.id.to_string()),
+        json!({
+            "lead_id": lead_id,
+            "appointment_type_id": appointment_type_id,
+            "previous_appointment_type_id": previous,
+        }),
+        None,
+        true,
+        None,
+    )?;
+    Ok(())
+}
+
+fn validate_appointment_type_input(input: &AppointmentTypeInput) -> AppResult<()> {
+    if input.name.trim().is_empty() {
+        return Err(AppError::Validation(
+            "appointment type name must be non-empty".to_string(),
+        ));
+    }
+    if input.duration_minutes <= 0 {
+        return Err(AppError::Validation(
+            "duration_minutes must be a positive integer".to_string(),
+        ));
+    }
+    if input.confirmation_template.trim().is_empty() || input.reminder_template.trim().is_empty() {
+        return Err(AppError::Validation(
+            "confirmation and reminder templates must be non-empty".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn create_appointment_type_with_conn(
+    conn: &Connection,
+    input: &AppointmentTypeInput,
+) -> AppResult<AppointmentType> {
+    validate_appointment_type_input(input)?;
+    conn.execute(
+        "INSERT INTO appointment_types (name, duration_minutes, confirmation_template, reminder_template)
+         VALUES (?, ?, ?, ?)",
+        params![
+            input.name.trim(),
+            input.duration_minutes,
+            input.confirmation_template.trim(),
+            input.reminder_template.trim()
+        ],
+    )?;
+    get_appointment_type(conn, Some(conn.last_insert_rowid()))
+}
+
+fn update_appointment_type_with_conn(
+    conn: &Connection,
+    appointment_type_id: i64,
+    input: &AppointmentTypeInput,
+) -> AppResult<AppointmentType> {
+    validate_appointment_type_input(input)?;
+    let updated = conn.execute(
+        "UPDATE appointment_types
+         SET name=?, duration_minutes=?, confirmation_template=?, reminder_template=?
+         WHERE id=?",
+        params![
+            input.name.trim(),
+            input.duration_minutes,
+            input.confirmation_template.trim(),
+            input.reminder_template.trim(),
+            appointment_type_id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(AppError::NotFound("appointment type not found".to_string()));
+    }
+    get_appointment_type(conn, Some(appointment_type_id))
+}
+
+/// Fills `{name}`, `{time}` and `{gym}` placeholders in an appointment type template.
+fn render_appointment_template(
+    template: &str,
+    lead: &LeadRow,
+    local_time: &str,
+    location: &Location,
+    reminder_notice: &str,
+) -> String {
+    let name = lead.first_name.as_deref().unwrap_or("there");
+    fill_template(
+        template,
+        &HashMap::from([
+            ("name", name),
+            ("time", local_time),
+            ("gym", location.gym_name.as_str()),
+            ("reminder_notice", reminder_notice),
+        ]),
+    )
+    .trim_end()
+    .to_string()
+}
+
+/// Loads the `message_templates` row for `key` and substitutes `{var}` placeholders.
+fn render_template(conn: &Connection, key: &str, vars: HashMap<&str, &str>) -> AppResult<String> {
+    let template = get_template_with_conn(conn, key)?;
+    Ok(fill_template(&template.body, &vars))
+}
+
+/// One pass over the template, so a substituted value is never scanned for placeholders again.
+/// Unknown `{var}`s are left as written.
+fn fill_template(template: &str, vars: &HashMap<&str, &str>) -> String {
+    let mut body = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        body.push_str(&rest[..open]);
+        let after_open = &rest[open + 1..];
+        match after_open
+            .find('}')
+            .and_then(|close| vars.get(&after_open[..close]).map(|value| (close, value)))
+        {
+            Some((close, value)) => {
+                body.push_str(value);
+                rest = &after_open[close + 1..];
+            }
+            None => {
+                body.push('{');
+                rest = after_open;
+            }
+        }
+    }
+    body.push_str(rest);
+    body
+}
+
+fn get_template_with_conn(conn: &Connection, key: &str) -> AppResult<MessageTemplate> {
+    conn.query_row(
+        "SELECT id, key, body, created_at FROM message_templates WHERE key=?",
+        params![key],
+        map_message_template,
+    )
+    .optional()?
+    .ok_or_else(|| AppError::Validation(format!("unknown message template: {key}")))
+}
+
+fn map_message_template(row: &rusqlite::Row<'_>) -> rusqlite::Result<MessageTemplate> {
+    Ok(MessageTemplate {
+        id: row.get(0)?,
+        key: row.get(1)?,
+        body: row.get(2)?,
+        created_at: row.get(3)?,
+    })
+}
+
+// Only seeded keys can be edited; an unknown key would never be rendered.
+fn set_template_with_conn(conn: &Connection, key: &str, body: &str) -> AppResult<MessageTemplate> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err(AppError::Validation(
+            "template body must be non-empty".to_string(),
+        ));
+    }
+    let updated = conn.execute(
+        "UPDATE message_templates SET body=? WHERE key=?",
+        params![body, key],
+    )?;
+    if updated == 0 {
+        return Err(AppError::Validation(format!(
+            "unknown message template: {key}"
+        )));
+    }
+    get_template_with_conn(conn, key)
+}
+
+fn list_templates_with_conn(conn: &Connection) -> AppResult<Vec<MessageTemplate>> {
+    let mut stmt =
+        conn.prepare("SELECT id, key, body, created_at FROM message_templates ORDER BY key")?;
+    let rows = stmt.query_map(params![], map_message_template)?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+/// Coerces common US formats ("5551234567", "(555) 123-4567", "1-555-123-4567") to E.164.
+/// Input that already starts with `+` keeps its own country code.
+fn normalize_phone_e164(raw: &str, default_country_code: &str) -> AppResult<String> {
+    let raw = raw.trim();
+    let digits: String = raw.chars().filter(char::is_ascii_digit).collect();
+    let country_code: String = default_country_code
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect();
+    let normalized = if raw.starts_with('+') && (8..=15).contains(&digits.len()) {
+        format!("+{digits}")
+    } else if digits.len() == 10 && !country_code.is_empty() {
+        format!("+{country_code}{digits}")
+    } else if digits.len() == 11 && digits.starts_with('1') {
+        format!("+{digits}")
+    } else {
+        return Err(AppError::Validation(format!("invalid phone number: {raw}")));
+    };
+    Ok(normalized)
+}
+
+fn normalize_tag(tag: &str) -> AppResult<String> {
+    let normalized = tag.trim().to_lowercase();
+    if normalized.is_empty() {
+        return Err(AppError::Validation("tag must be non-empty".to_string()));
+    }
+    Ok(normalized)
+}
+
+fn default_gym_name() -> &'static str {
+    "Demo Gym Downtown"
+}
+
+fn default_timezone() -> &'static str {
+    "America/New_York"
+}
+
+fn null_if_empty(s: &str) -> Option<String> {
+    let trimmed = s.trim();
+    if trimmed.is_empty() {
+        None
+    } else {
+        Some(trimmed.to_string())
+    }
+}
+
+fn default_business_hours_json() -> &'static str {
+    r#"{"mon":[["09:00","17:00"]],"tue":[["09:00","17:00"]],"wed":[["09:00","17:00"]],"thu":[["09:00","17:00"]],"fri":[["09:00","17:00"]],"sat":[["10:00","14:00"]],"sun":[]}"#
+}
+
+/// The location selected with `set_current_location`, falling back to the first location when the
+/// setting is missing or points at a row that no longer exists.
+fn current_location_id(conn: &Connection) -> AppResult<i64> {
+    let selected =
+        read_setting(conn, "current_location_id")?.and_then(|raw| raw.parse::<i64>().ok());
+    if let Some(id) = selected {
+        let exists: Option<i64> = conn
+            .query_row("SELECT id FROM locations WHERE id=?", params![id], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        if exists.is_some() {
+            return Ok(id);
+        }
+    }
+
+    ensure_primary_location(conn)
+}
+
+fn ensure_primary_location(conn: &Connection) -> AppResult<i64> {
+    let existing_id: Option<i64> = conn
+        .query_row(
+            "SELECT id FROM locations ORDER BY id LIMIT 1",
+            params![],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    if let Some(id) = existing_id {
+        return Ok(id);
+    }
+
+    conn.execute(
+        "INSERT INTO locations (gym_name, timezone, business_hours_json) VALUES (?, ?, ?)",
+        params![
+            default_gym_name(),
+            default_timezone(),
+            default_business_hours_json()
+        ],
+    )?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+fn main() {
+    tauri::Builder::default()
+        .setup(|app| {
+            let app_dir = ensure_app_data_dir(&app.handle()).map_err(AppError::Validation)?;
+            init_tracing(&app_dir)?;
+            let db_path = app_dir.join("db").join("goldbot.sqlite");
+            let clock: Arc<dyn Clock + Send + Sync> = Arc::new(RealClock);
+            initialize_db(&db_path, &app_dir.join("config.toml"), clock.as_ref())?;
+            let pool = build_pool(&db_path)?;
+            app.manage(AppState {
+                pool: pool.clone(),
+                clock: clock.clone(),
+            });
+            let app_handle = app.handle();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    let _ = tauri::async_runtime::spawn_blocking(|| {
+                        thread::sleep(StdDuration::from_secs(15));
+                    })
+                    .await;
+
+                    if let Err(err) = retry_db(|| {
+                        let conn = pool.get()?;
+                        run_due_jobs_with_conn(&conn, clock.as_ref())
+                    }) {
+                        let message = format!("Alert: {err}");
+                        log_command_failure(&app_handle, "run_due_jobs_background", &message);
+                    }
+                }
+            });
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            create_lead,
+            bulk_create_leads,
+            normalize_phone,
+            update_lead,
+            update_lead_consent,
+            set_lead_consent,
+            list_leads,
+            list_leads_paginated,
+            list_leads_by_status,
+            search_leads,
+            get_stale_leads,
+            add_lead_tag,
+            remove_lead_tag,
+            list_agent_queue,
+            list_leads_needing_attention,
+            get_lead_communication_summary,
+            get_lead_detail,
+            get_lead_timeline,
+            get_lead_conversation_history,
+            get_conversation_history,
+            search_messages,
+            add_lead_note,
+            list_lead_notes,
+            delete_lead_note,
+            simulate_inbound_sms,
+            simulate_outbound_sms,
+            simulate_bulk_inbound,
+            send_manual_message,
+            list_outbound_failures,
+            record_outbound_failure,
+            simulate_outbound_failure,
+            cancel_appointment,
+            reschedule_appointment,
+            mark_appointment_no_show,
+            mark_appointment_completed,
+            resolve_staff_attention,
+            mark_needs_attention_resolved,
+            reset_conversation,
+            override_conversation_state,
+            join_appointment_waitlist,
+            list_appointments,
+            export_appointment_ics,
+            list_appointment_types,
+            create_appointment_type,
+            update_appointment_type,
+            set_conversation_appointment_type,
+            get_today_report,
+            get_range_report,
+            get_weekly_report,
+            get_funnel_report,
+            get_source_report,
+            get_kill_switch,
+            get_kill_switch_state,
+            get_location_settings,
+            list_locations,
+            add_location,
+            update_location,
+            set_current_location,
+            update_location_settings,
+            validate_business_hours,
+            update_location_schedule_config,
+            add_reminder_config,
+            remove_reminder_config,
+            add_phone_to_blocklist,
+            remove_phone_from_blocklist,
+            list_blocklist,
+            add_holiday,
+            remove_holiday,
+            list_holidays,
+            get_next_available_slot,
+            get_available_slots,
+            check_slot_available,
+            add_blackout_period,
+            remove_blackout_period,
+            list_blackout_periods,
+            list_follow_up_sequence,
+            upsert_follow_up_step,
+            set_kill_switch,
+            get_auto_kill_switch_threshold,
+            set_auto_kill_switch_threshold,
+            check_db_integrity,
+            backup_db,
+            vacuum_db,
+            get_app_info,
+            schema_version,
+            list_applied_migrations,
+            get_schema_version,
+            checkpoint_db,
+            get_settings,
+            get_setting,
+            set_setting,
+            get_stop_keywords,
+            set_stop_keywords,
+            get_rate_limit_settings,
+            set_rate_limit_settings,
+            get_rate_limit_status,
+            get_help_response,
+            get_conversation_expiry_hours,
+            set_conversation_expiry_hours,
+            get_tcpa_mode,
+            set_tcpa_mode,
+            set_help_response,
+            add_custom_keyword,
+            update_custom_keyword,
+            set_custom_keyword_active,
+            list_custom_keywords,
+            get_template,
+            set_template,
+            list_templates,
+            export_db_path,
+            get_config_path,
+            wipe_all_data_confirmed,
+            reset_demo_data,
+            log_client_error,
+            get_client_errors,
+            open_devtools,
+            run_due_jobs,
+            list_scheduled_jobs,
+            list_audit_log,
+            get_audit_log,
+            export_audit_log_csv,
+            export_leads_csv,
+            purge_old_audit_logs,
+            get_audit_log_retention_days,
+            set_audit_log_retention_days,
+            cancel_scheduled_job,
+            requeue_failed_job,
+            retry_failed_job,
+            get_job_stats,
+            schedule_re_engagement_batch,
+            agent_dry_run,
+            agent_execute,
+            list_agent_actions,
+            agent_execute_batch
+        ])
+        .run(tauri::generate_context!())
+        .expect("error while running Gym Lead Booker app");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::test_helpers::init_in_memory_db;
+
+    fn ts(input: &str) -> DateTime<Utc> {
+        parse_ts(input).expect("timestamp should parse")
+    }
+
+    fn set_business_hours(conn: &Connection, business_hours_json: &str) {
+        conn.execute(
+            "UPDATE locations SET business_hours_json=? WHERE id=1",
+            params![business_hours_json],
+        )
+        .expect("failed to update location business hours");
+    }
+
+    fn insert_lead(conn: &Connection, phone_e164: &str) -> i64 {
+        conn.execute(
+            "INSERT INTO leads (phone_e164, consent, status, opted_out, needs_staff_attention, created_at)
+             VALUES (?, 1, 'awaiting_yes', 0, 0, ?)",
+            params![phone_e164, "2030-01-01T00:00:00Z"],
+        )
+        .expect("failed to insert test lead");
+        conn.last_insert_rowid()
+    }
+
+    fn insert_conversation(conn: &Connection, lead_id: i64) -> i64 {
+        conn.execute(
+            "INSERT INTO conversations (lead_id, state, state_json, repair_attempts)
+             VALUES (?, 'awaiting_yes', '{\"offered_slots\":[]}', 0)",
+            params![lead_id],
+        )
+        .expect("failed to insert test conversation");
+        conn.last_insert_rowid()
+    }
+
+    fn insert_booked_appointment(
+        conn: &Connection,
+        lead_id: i64,
+        start_at: &str,
+        end_at: &str,
+    ) -> i64 {
+        conn.execute(
+            "INSERT INTO appointments (lead_id, start_at, end_at, status, created_at)
+             VALUES (?, ?, ?, 'booked', ?)",
+            params![lead_id, start_at, end_at, "2030-01-01T00:00:00Z"],
+        )
+        .expect("failed to insert test appointment");
+        conn.last_insert_rowid()
+    }
+
+    #[test]
+    fn parse_business_hours_accepts_valid_json_with_multiple_ranges() {
+        let _conn = init_in_memory_db();
+        let parsed = parse_business_hours(
+            r#"{"mon":[["09:00","12:00"],["13:00","17:00"]],"tue":[["10:00","11:30"]]}"#,
+        )
+        .expect("valid business hours json should parse");
+
+        let mon_ranges = parsed.get(&Weekday::Mon).expect("missing monday ranges");
+        assert_eq!(mon_ranges.len(), 2);
+        assert_eq!(
+            mon_ranges[0],
+            (
+                NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+                NaiveTime::from_hms_opt(12, 0, 0).unwrap()
+            )
+        );
+        assert_eq!(
+            mon_ranges[1],
+            (
+                NaiveTime::from_hms_opt(13, 0, 0).unwrap(),
+                NaiveTime::from_hms_opt(17, 0, 0).unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn parse_business_hours_rejects_invalid_json() {
+        let _conn = init_in_memory_db();
+        assert!(parse_business_hours(r#"{"mon":[[123,"10:00"]]}"#).is_err());
+    }
+
+    #[test]
+    fn has_appointment_conflict_detects_overlap_and_non_overlap() {
+        let _conn = init_in_memory_db();
+        let existing = vec![(ts("2030-01-07T14:00:00Z"), ts("2030-01-07T14:30:00Z"))];
+
+        assert!(has_appointment_conflict(
+            ts("2030-01-07T14:10:00Z"),
+            ts("2030-01-07T14:40:00Z"),
+            10,
+            &existing
+        ));
+        assert!(!has_appointment_conflict(
+            ts("2030-01-07T15:00:00Z"),
+            ts("2030-01-07T15:30:00Z"),
+            10,
+            &existing
+        ));
+    }
+
+    #[test]
+    fn has_appointment_conflict_enforces_ten_minute_buffer() {
+        let _conn = init_in_memory_db();
+        let existing = vec![(ts("2030-01-07T14:00:00Z"), ts("2030-01-07T14:30:00Z"))];
+
+        assert!(has_appointment_conflict(
+            ts("2030-01-07T14:35:00Z"),
+            ts("2030-01-07T15:05:00Z"),
+            10,
+            &existing
+        ));
+        assert!(!has_appointment_conflict(
+            ts("2030-01-07T14:40:00Z"),
+            ts("2030-01-07T15:10:00Z"),
+            10,
+            &existing
+        ));
+    }
+
+    #[test]
+    fn generate_slot_choices_returns_two_slots_when_exactly_two_are_available() {
+        let conn = init_in_memory_db();
+        set_business_hours(
+            &conn,
+            r#"{"mon":[["09:00","09:30"]],"tue":[["09:00","09:30"]],"wed":[["09:00","09:30"]],"thu":[],"fri":[],"sat":[],"sun":[]}"#,
+        );
+        let lead_id = insert_lead(&conn, "+15550000001");
+        insert_booked_appointment(
+            &conn,
+            lead_id,
+            "2030-01-08T14:00:00Z",
+            "2030-01-08T14:30:00Z",
+        );
+
+        let location = get_location(&conn).expect("test location should exist");
+        let slots =
+            generate_slot_choices(&conn, &location, ts("2030-01-07T12:00:00Z")).unwrap();
+
+        assert_eq!(slots.len(), 2);
+        assert_eq!(parse_ts(&slots[0].start_at).unwrap(), ts("2030-01-07T14:00:00Z"));
+        assert_eq!(parse_ts(&slots[1].start_at).unwrap(), ts("2030-01-09T14:00:00Z"));
+    }
+
+    #[test]
+    fn generate_slot_choices_returns_fewer_than_two_when_capacity_is_limited() {
+        let conn = init_in_memory_db();
+        set_business_hours(
+            &conn,
+            r#"{"mon":[["09:00","09:30"]],"tue":[["09:00","09:30"]],"wed":[["09:00","09:30"]],"thu":[],"fri":[],"sat":[],"sun":[]}"#,
+        );
+        let lead_id = insert_lead(&conn, "+15550000002");
+        insert_booked_appointment(
+            &conn,
+            lead_id,
+            "2030-01-07T14:00:00Z",
+            "2030-01-07T14:30:00Z",
+        );
+        insert_booked_appointment(
+            &conn,
+            lead_id,
+            "2030-01-08T14:00:00Z",
+            "2030-01-08T14:30:00Z",
+        );
+
+        let location = get_location(&conn).expect("test location should exist");
+        let slots =
+            generate_slot_choices(&conn, &location, ts("2030-01-07T12:00:00Z")).unwrap();
+
+        assert_eq!(slots.len(), 1);
+        assert_eq!(parse_ts(&slots[0].start_at).unwrap(), ts("2030-01-09T14:00:00Z"));
+    }
+
+    #[test]
+    fn business_open_and_next_open_time_respect_open_close_edges() {
+        let conn = init_in_memory_db();
+        set_business_hours(
+            &conn,
+            r#"{"mon":[["09:00","17:00"]],"tue":[["09:00","17:00"]],"wed":[["09:00","17:00"]],"thu":[["09:00","17:00"]],"fri":[["09:00","17:00"]],"sat":[],"sun":[]}"#,
+        );
+        let location = get_location(&conn).expect("test location should exist");
+
+        assert!(!is_business_open(&location, ts("2030-01-07T13:59:00Z")).unwrap());
+        assert!(is_business_open(&location, ts("2030-01-07T14:00:00Z")).unwrap());
+        assert!(is_business_open(&location, ts("2030-01-07T21:59:00Z")).unwrap());
+        assert!(!is_business_open(&location, ts("2030-01-07T22:00:00Z")).unwrap());
+
+        assert_eq!(
+            next_open_time(&conn, &location, ts("2030-01-07T13:59:00Z")).unwrap(),
+            ts("2030-01-07T14:00:00Z")
+        );
+        assert_eq!(
+            next_open_time(&conn, &location, ts("2030-01-07T22:00:00Z")).unwrap(),
+            ts("2030-01-08T14:00:00Z")
+        );
+    }
+
+    #[test]
+    fn add_lead_tag_normalizes_and_rejects_duplicates() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000003");
+
+        add_lead_tag_with_conn(&conn, &RealClock, lead_id, "  Spring-Promo ").unwrap();
+        let err = add_lead_tag_with_conn(&conn, &RealClock, lead_id, "spring-promo").unwrap_err();
+        assert!(
+            err.to_string().contains("already has tag"),
+            "unexpected error: {err}"
+        );
+
+        assert_eq!(
+            list_lead_tags(&conn, lead_id).unwrap(),
+            vec!["spring-promo"]
+        );
+    }
+
+    #[test]
+    fn remove_lead_tag_rejects_missing_tag() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000004");
+        add_lead_tag_with_conn(&conn, &RealClock, lead_id, "open-house").unwrap();
+
+        let err = remove_lead_tag_with_conn(&conn, &RealClock, lead_id, "vip").unwrap_err();
+        assert!(
+            err.to_string().contains("does not have tag"),
+            "unexpected error: {err}"
+        );
+
+        remove_lead_tag_with_conn(&conn, &RealClock, lead_id, "OPEN-HOUSE").unwrap();
+        assert!(list_lead_tags(&conn, lead_id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn search_leads_filters_by_tags() {
+        let conn = init_in_memory_db();
+        let tagged = insert_lead(&conn, "+15550000005");
+        let untagged = insert_lead(&conn, "+15550000006");
+        add_lead_tag_with_conn(&conn, &RealClock, tagged, "open-house").unwrap();
+
+        let all = search_leads_with_conn(&conn, "+1555", None).unwrap();
+        assert_eq!(all.len(), 2);
+
+        let filtered =
+            search_leads_with_conn(&conn, "", Some(&["Open-House".to_string()])).unwrap();
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].id, tagged);
+        assert!(filtered.iter().all(|lead| lead.id != untagged));
+    }
+
+    #[test]
+    fn lead_writes_and_stale_lead_cutoff_use_the_injected_clock() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000190");
+        let clock = FakeClock(ts("2030-01-07T15:00:00Z"));
+
+        add_lead_tag_with_conn(&conn, &clock, lead_id, "vip").unwrap();
+        set_lead_status(&conn, &clock, lead_id, "awaiting_time_choice", "test").unwrap();
+        let stamps: (String, String, String) = conn
+            .query_row(
+                "SELECT
+                   (SELECT created_at FROM lead_tags WHERE lead_id=?1),
+                   (SELECT changed_at FROM lead_status_history WHERE lead_id=?1),
+                   (SELECT created_at FROM audit_log WHERE action_type='add_lead_tag')",
+                params![lead_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(stamps.0, clock.0.to_rfc3339());
+        assert_eq!(stamps.1, clock.0.to_rfc3339());
+        assert_eq!(stamps.2, clock.0.to_rfc3339());
+
+        conn.execute(
+            "UPDATE leads SET last_contact_at='2030-01-01T15:00:00Z' WHERE id=?",
+            params![lead_id],
+        )
+        .unwrap();
+        let stale = get_stale_leads_with_conn(&conn, &clock, 3).unwrap();
+        assert_eq!(
+            stale.iter().map(|lead| lead.id).collect::<Vec<_>>(),
+            vec![lead_id]
+        );
+        let earlier = FakeClock(ts("2030-01-02T15:00:00Z"));
+        assert!(get_stale_leads_with_conn(&conn, &earlier, 3)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn lead_timeline_records_yes_booked_and_opt_out_transitions() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000007");
+        insert_conversation(&conn, lead_id);
+
+        let at = |time: &str| FakeClock(ts(time));
+        test_process_inbound_state_machine(&conn, &at("2030-01-07T15:00:00Z"), lead_id, "YES")
+            .unwrap();
+        test_process_inbound_state_machine(&conn, &at("2030-01-07T15:05:00Z"), lead_id, "1")
+            .unwrap();
+        test_process_inbound_state_machine(&conn, &at("2030-01-07T15:10:00Z"), lead_id, "STOP")
+            .unwrap();
+
+        let timeline = get_lead_timeline_with_conn(&conn, lead_id).unwrap();
+        let transitions: Vec<(Option<&str>, &str, &str)> = timeline
+            .iter()
+            .map(|entry| {
+                (
+                    entry.from_status.as_deref(),
+                    entry.to_status.as_str(),
+                    entry.trigger.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            transitions,
+            vec![
+                (Some("awaiting_yes"), "awaiting_time_choice", "inbound_yes"),
+                (Some("awaiting_time_choice"), "booked", "create_appointment"),
+                (Some("booked"), "opted_out", "set_opt_out"),
+            ]
+        );
+    }
+
+    #[test]
+    fn lead_notes_appear_in_detail_and_can_be_deleted() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000008");
+        insert_conversation(&conn, lead_id);
+
+        let first =
+            add_lead_note_with_conn(&conn, &RealClock, lead_id, "Prefers mornings", Some("Sam"))
+                .unwrap();
+        add_lead_note_with_conn(&conn, &RealClock, lead_id, "Asked about pricing", None).unwrap();
+
+        let detail = get_lead_detail_with_conn(&conn, lead_id).unwrap();
+        assert_eq!(detail.notes.len(), 2);
+        assert_eq!(detail.notes[0].author.as_deref(), Some("Sam"));
+
+        delete_lead_note_with_conn(&conn, &RealClock, first).unwrap();
+        let detail = get_lead_detail_with_conn(&conn, lead_id).unwrap();
+        assert_eq!(detail.notes.len(), 1);
+        assert_eq!(detail.notes[0].body, "Asked about pricing");
+    }
+
+    #[test]
+    fn add_lead_note_rejects_overlong_body() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000009");
+
+        let err = add_lead_note_with_conn(&conn, &RealClock, lead_id, &"x".repeat(2001), None)
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("at most 2000"),
+            "unexpected error: {err}"
+        );
+    }
+
+    #[test]
+    fn cancel_appointment_reverts_lead_and_cancels_reminders() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000010");
+        insert_conversation(&conn, lead_id);
+        let appointment_id = insert_booked_appointment(
+            &conn,
+            lead_id,
+            "2030-01-08T14:00:00Z",
+            "2030-01-08T14:30:00Z",
+        );
+        conn.execute(
+            "UPDATE leads SET status='booked', next_action_at='2030-01-08T14:00:00Z' WHERE id=?",
+            params![lead_id],
+        )
+        .unwrap();
+        conn.execute(
+            "UPDATE conversations SET state='booked', state_json='{\"offered_slots\":[]}' WHERE lead_id=?",
+            params![lead_id],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO scheduled_jobs (job_type, target_id, execute_at, status, payload_json, created_at)
+             VALUES ('appointment_reminder', ?, '2030-01-08T12:00:00Z', 'pending', '{}', '2030-01-01T00:00:00Z')",
+            params![appointment_id],
+        )
+        .unwrap();
+
+        let gateway = ActionGateway::new(&conn);
+        let cancel = || {
+            gateway.cancel_appointment(CancelAppointmentRequest {
+                appointment_id,
+                reason: "member is travelling".to_string(),
+            })
+        };
+        let result = cancel().unwrap();
+        assert_eq!(result.appointment_id, appointment_id);
+        assert_eq!(result.reminder_jobs_cancelled, 1);
+
+        let detail = get_lead_detail_with_conn(&conn, lead_id).unwrap();
+        assert_eq!(detail.lead.status, "awaiting_yes");
+        assert_eq!(detail.lead.next_action_at, None);
+        assert_eq!(detail.appointments[0].status, "cancelled");
+        assert_eq!(detail.conversation.state, "awaiting_yes");
+        assert_eq!(
+            detail.conversation.state_json,
+            serde_json::to_string(&ConversationState::default()).unwrap()
+        );
+
+        assert!(cancel().is_err());
+        let missing = gateway
+            .cancel_appointment(CancelAppointmentRequest {
+                appointment_id: 9_999,
+                reason: "typo".to_string(),
+            })
+            .unwrap_err();
+        assert_eq!(missing.code(), ErrorCode::NotFound);
+    }
+
+    #[test]
+    fn cancel_appointment_rolls_back_when_waitlist_promotion_fails() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000188");
+        insert_conversation(&conn, lead_id);
+        let appointment_id = insert_booked_appointment(
+            &conn,
+            lead_id,
+            "2030-01-08T14:00:00Z",
+            "2030-01-08T14:30:00Z",
+        );
+        conn.execute(
+            "INSERT INTO scheduled_jobs (job_type, target_id, execute_at, status, payload_json, created_at)
+             VALUES ('appointment_reminder', ?, '2030-01-08T12:00:00Z', 'pending', '{}', '2030-01-01T00:00:00Z')",
+            params![appointment_id],
+        )
+        .unwrap();
+        let waiting_lead = insert_lead(&conn, "+15550000189");
+        join_appointment_waitlist_with_conn(&conn, &RealClock, waiting_lead).unwrap();
+        conn.execute_batch(
+            "CREATE TRIGGER waitlist_locked BEFORE UPDATE ON waitlist
+             BEGIN SELECT RAISE(ABORT, 'waitlist locked'); END;",
+        )
+        .unwrap();
+
+        let err = ActionGateway::new(&conn)
+            .cancel_appointment(CancelAppointmentRequest {
+                appointment_id,
+                reason: "member is travelling".to_string(),
+            })
+            .unwrap_err();
+        assert!(err.to_string().contains("waitlist locked"), "{err}");
+        assert_eq!(appointment_status(&conn, appointment_id), "booked");
+        let (reminders, follow_ups): (i64, i64) = conn
+            .query_row(
+                "SELECT
+                   (SELECT COUNT(*) FROM scheduled_jobs WHERE job_type='appointment_reminder' AND status='pending'),
+                   (SELECT COUNT(*) FROM scheduled_jobs WHERE job_type='initial_follow_up' AND target_id=?)",
+                params![waiting_lead],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((reminders, follow_ups), (1, 0));
+    }
+
+    #[test]
+    fn ensure_column_adds_missing_column_once() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE legacy (id INTEGER PRIMARY KEY)")
+            .unwrap();
+
+        ensure_column(&conn, "legacy", "note", "TEXT").unwrap();
+        ensure_column(&conn, "legacy", "note", "TEXT").unwrap();
+
+        conn.execute("INSERT INTO legacy (note) VALUES ('ok')", params![])
+            .unwrap();
+    }
+
+    fn appointment_status(conn: &Connection, appointment_id: i64) -> String {
+        conn.query_row(
+            "SELECT status FROM appointments WHERE id=?",
+            params![appointment_id],
+            |row| row.get(0),
+        )
+        .expect("failed to load appointment status")
+    }
+
+    #[test]
+    fn reschedule_appointment_moves_booking_and_reminder() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000011");
+        insert_conversation(&conn, lead_id);
+        let appointment_id = insert_booked_appointment(
+            &conn,
+            lead_id,
+            "2030-01-08T14:00:00Z",
+            "2030-01-08T14:30:00Z",
+        );
+        let old_reminder_ids = schedule_appointment_reminders(
+            &ActionGateway::new(&conn),
+            lead_id,
+            appointment_id,
+            "2030-01-08T14:00:00Z",
+        )
+        .unwrap();
+        assert_eq!(old_reminder_ids.len(), 1);
+
+        let result = ActionGateway::new(&conn)
+            .reschedule_appointment(RescheduleAppointmentRequest {
+                appointment_id,
+                new_start_at: "2030-01-09T15:00:00Z".to_string(),
+                new_end_at: "2030-01-09T15:30:00Z".to_string(),
+                reason: "member asked for a later day".to_string(),
+            })
+            .unwrap();
+
+        assert_eq!(result.appointment_id, appointment_id);
+        assert_eq!(appointment_status(&conn, appointment_id), "booked");
+        let (start_at, end_at, appointment_count): (String, String, i64) = conn
+            .query_row(
+                "SELECT start_at, end_at, (SELECT COUNT(*) FROM appointments)
+                 FROM appointments WHERE id=?",
+                params![appointment_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(start_at, "2030-01-09T15:00:00Z");
+        assert_eq!(end_at, "2030-01-09T15:30:00Z");
+        assert_eq!(appointment_count, 1);
+
+        let old_status: String = conn
+            .query_row(
+                "SELECT status FROM scheduled_jobs WHERE id=?",
+                params![old_reminder_ids[0]],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(old_status, "cancelled");
+
+        assert_eq!(result.reminder_job_ids.len(), 1);
+        let (execute_at, target_id): (String, i64) = conn
+            .query_row(
+                "SELECT execute_at, target_id FROM scheduled_jobs WHERE id=?",
+                params![result.reminder_job_ids[0]],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(parse_ts(&execute_at).unwrap(), ts("2030-01-09T13:00:00Z"));
+        assert_eq!(target_id, appointment_id);
+
+        let audited: String = conn
+            .query_row(
+                "SELECT response_json FROM audit_log
+                 WHERE action_type='reschedule_appointment' AND success=1",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let audited: Value = serde_json::from_str(&audited).unwrap();
+        assert_eq!(audited["old_start_at"], "2030-01-08T14:00:00Z");
+        assert_eq!(audited["new_start_at"], "2030-01-09T15:00:00Z");
+    }
+
+    #[test]
+    fn reschedule_appointment_allows_overlap_with_its_own_slot() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000180");
+        let appointment_id = insert_booked_appointment(
+            &conn,
+            lead_id,
+            "2030-01-08T14:00:00Z",
+            "2030-01-08T14:30:00Z",
+        );
+
+        ActionGateway::new(&conn)
+            .reschedule_appointment(RescheduleAppointmentRequest {
+                appointment_id,
+                new_start_at: "2030-01-08T14:15:00Z".to_string(),
+                new_end_at: "2030-01-08T14:45:00Z".to_string(),
+                reason: "running late".to_string(),
+            })
+            .unwrap();
+        assert_eq!(appointment_status(&conn, appointment_id), "booked");
+    }
+
+    #[test]
+    fn reschedule_appointment_conflict_leaves_original_booked() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000012");
+        let other_lead_id = insert_lead(&conn, "+15550000013");
+        let old_id = insert_booked_appointment(
+            &conn,
+            lead_id,
+            "2030-01-08T14:00:00Z",
+            "2030-01-08T14:30:00Z",
+        );
+        insert_booked_appointment(
+            &conn,
+            other_lead_id,
+            "2030-01-09T15:00:00Z",
+            "2030-01-09T15:30:00Z",
+        );
+
+        let err = ActionGateway::new(&conn)
+            .reschedule_appointment(RescheduleAppointmentRequest {
+                appointment_id: old_id,
+                new_start_at: "2030-01-09T15:10:00Z".to_string(),
+                new_end_at: "2030-01-09T15:40:00Z".to_string(),
+                reason: "member asked for a later day".to_string(),
+            })
+            .unwrap_err();
+
+        assert!(
+            err.to_string().contains("no longer available"),
+            "unexpected error: {err}"
+        );
+        assert_eq!(appointment_status(&conn, old_id), "booked");
+        let start_at: String = conn
+            .query_row(
+                "SELECT start_at FROM appointments WHERE id=?",
+                params![old_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(start_at, "2030-01-08T14:00:00Z");
+        let appointment_count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM appointments", params![], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(appointment_count, 2);
+    }
+
+    fn insert_past_booked_appointment(conn: &Connection, lead_id: i64) -> i64 {
+        let start = Utc::now() - Duration::hours(2);
+        insert_booked_appointment(
+            conn,
+            lead_id,
+            &start.to_rfc3339(),
+            &(start + Duration::minutes(30)).to_rfc3339(),
+        )
+    }
+
+    #[test]
+    fn no_show_follow_up_messages_lead_and_resets_conversation() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000014");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let appointment_id = insert_past_booked_appointment(&conn, lead_id);
+
+        ActionGateway::new(&conn)
+            .mark_appointment_no_show(appointment_id)
+            .unwrap();
+        assert_eq!(appointment_status(&conn, appointment_id), "no_show");
+        let no_shows = list_leads_by_status_with_conn(&conn, &["no_show".to_string()]).unwrap();
+        assert_eq!(
+            no_shows.iter().map(|lead| lead.id).collect::<Vec<_>>(),
+            vec![lead_id]
+        );
+
+        let result = run_due_jobs_with_conn(&conn, &RealClock).unwrap();
+        assert_eq!((result.processed, result.errors), (1, 0));
+
+        let body: String = conn
+            .query_row(
+                "SELECT body FROM messages WHERE conversation_id=? AND direction='OUTBOUND'",
+                params![conversation_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(body, "We missed you today. Want to rebook?");
+        let detail = get_lead_detail_with_conn(&conn, lead_id).unwrap();
+        assert_eq!(detail.lead.status, "awaiting_yes");
+        assert_eq!(detail.conversation.state, "awaiting_yes");
+    }
+
+    #[test]
+    fn mark_appointment_no_show_rolls_back_when_follow_up_is_refused() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000187");
+        insert_conversation(&conn, lead_id);
+        let appointment_id = insert_past_booked_appointment(&conn, lead_id);
+        set_lead_status(&conn, &RealClock, lead_id, "booked", "test").unwrap();
+        set_kill_switch_with_conn(&conn, &RealClock, true, None).unwrap();
+
+        let err = ActionGateway::new(&conn)
+            .mark_appointment_no_show(appointment_id)
+            .unwrap_err();
+        assert!(
+            matches!(err, AppError::Coded(ErrorCode::KillSwitch, _)),
+            "{err}"
+        );
+        assert_eq!(appointment_status(&conn, appointment_id), "booked");
+        assert_eq!(
+            get_lead_detail_with_conn(&conn, lead_id)
+                .unwrap()
+                .lead
+                .status,
+            "booked"
+        );
+        let (success, jobs): (i64, i64) = conn
+            .query_row(
+                "SELECT success, (SELECT COUNT(*) FROM scheduled_jobs WHERE job_type='no_show_follow_up')
+                 FROM audit_log WHERE action_type='mark_appointment_no_show'",
+                params![],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((success, jobs), (0, 0));
+    }
+
+    #[test]
+    fn no_show_follow_up_is_noop_for_opted_out_lead() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000015");
+        insert_conversation(&conn, lead_id);
+        let appointment_id = insert_past_booked_appointment(&conn, lead_id);
+
+        ActionGateway::new(&conn)
+            .mark_appointment_no_show(appointment_id)
+            .unwrap();
+        conn.execute(
+            "UPDATE leads SET opted_out=1, status='opted_out' WHERE id=?",
+            params![lead_id],
+        )
+        .unwrap();
+
+        let result = run_due_jobs_with_conn(&conn, &RealClock).unwrap();
+        assert_eq!((result.processed, result.errors), (1, 0));
+        let outbound: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM messages WHERE direction='OUTBOUND'",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(outbound, 0);
+    }
+
+    #[test]
+    fn generate_slot_choices_uses_configured_duration_and_buffer() {
+        let conn = init_in_memory_db();
+        set_business_hours(
+            &conn,
+            r#"{"mon":[["09:00","12:00"]],"tue":[],"wed":[],"thu":[],"fri":[],"sat":[],"sun":[]}"#,
+        );
+        let location_id = ensure_primary_location(&conn).unwrap();
+        update_location_schedule_config_with_conn(&conn, location_id, 60, 10, true).unwrap();
+
+        let location = get_location(&conn).unwrap();
+        let slots = generate_slot_choices(&conn, &location, ts("2030-01-07T12:00:00Z")).unwrap();
+
+        assert_eq!(slots.len(), 2);
+        for slot in &slots {
+            let length = parse_ts(&slot.end_at).unwrap() - parse_ts(&slot.start_at).unwrap();
+            assert_eq!(length, Duration::minutes(60));
+        }
+        assert_eq!(parse_ts(&slots[0].start_at).unwrap(), ts("2030-01-07T14:00:00Z"));
+        assert_eq!(parse_ts(&slots[1].start_at).unwrap(), ts("2030-01-07T15:10:00Z"));
+        assert!(parse_ts(&slots[0].end_at).unwrap() <= parse_ts(&slots[1].start_at).unwrap());
+
+        update_location_schedule_config_with_conn(&conn, location_id, 60, 5, true).unwrap();
+        let location = get_location(&conn).unwrap();
+        let slots = generate_slot_choices(&conn, &location, ts("2030-01-07T12:00:00Z")).unwrap();
+        let windows: Vec<(DateTime<Utc>, DateTime<Utc>)> = slots
+            .iter()
+            .map(|slot| {
+                (
+                    parse_ts(&slot.start_at).unwrap(),
+                    parse_ts(&slot.end_at).unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            windows,
+            vec![
+                (ts("2030-01-07T14:00:00Z"), ts("2030-01-07T15:00:00Z")),
+                (ts("2030-01-07T15:05:00Z"), ts("2030-01-07T16:05:00Z")),
+            ]
+        );
+    }
+
+    #[test]
+    fn update_location_schedule_config_rejects_non_positive_values() {
+        let conn = init_in_memory_db();
+        let location_id = ensure_primary_location(&conn).unwrap();
+
+        assert!(
+            update_location_schedule_config_with_conn(&conn, location_id, 0, 10, true).is_err()
+        );
+        assert!(
+            update_location_schedule_config_with_conn(&conn, location_id, 30, -5, true).is_err()
+        );
+        assert!(
+            update_location_schedule_config_with_conn(&conn, location_id, 100, 30, true).is_err()
+        );
+        update_location_schedule_config_with_conn(&conn, location_id, 110, 10, true).unwrap();
+        update_location_schedule_config_with_conn(&conn, location_id, 30, 10, true).unwrap();
+        let settings = get_location_settings_with_conn(&conn, location_id).unwrap();
+        assert_eq!(settings.appointment_duration_minutes, 30);
+        assert_eq!(settings.appointment_buffer_minutes, 10);
+    }
+
+    #[test]
+    fn booking_with_custom_appointment_type_uses_its_templates() {
+        let conn = init_in_memory_db();
+        let clock = FakeClock(ts("2030-01-05T12:00:00Z"));
+        let lead_id = insert_lead(&conn, "+15550000016");
+        conn.execute(
+            "UPDATE leads SET first_name='Sam' WHERE id=?",
+            params![lead_id],
+        )
+        .unwrap();
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let consult = create_appointment_type_with_conn(
+            &conn,
+            &AppointmentTypeInput {
+                name: "PT Consult".to_string(),
+                duration_minutes: 45,
+                confirmation_template: "{name}, your PT consult at {gym} is set for {time}."
+                    .to_string(),
+                reminder_template: "See you soon {name}: PT consult at {time}.".to_string(),
+            },
+        )
+        .unwrap();
+
+        let state = ConversationState {
+            offered_slots: vec![SlotChoice {
+                start_at: "2030-01-07T15:00:00Z".to_string(),
+                end_at: "2030-01-07T15:45:00Z".to_string(),
+            }],
+            appointment_type_id: Some(consult.id),
+        };
+        conn.execute(
+            "UPDATE conversations SET state='awaiting_time_choice', state_json=? WHERE id=?",
+            params![serde_json::to_string(&state).unwrap(), conversation_id],
+        )
+        .unwrap();
+
+        let location = get_location(&conn).unwrap();
+        let lead = get_lead(&conn, lead_id).unwrap();
+        let conversation = get_conversation_by_lead_id(&conn, lead_id).unwrap();
+        process_inbound_state_machine(&conn, &clock, &lead, &conversation, "1").unwrap();
+
+        let local_time = local_display(&location, "2030-01-07T15:00:00Z").unwrap();
+        let body: String = conn
+            .query_row(
+                "SELECT body FROM messages WHERE conversation_id=? AND direction='OUTBOUND'
+                 ORDER BY id DESC LIMIT 1",
+                params![conversation_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(
+            body,
+            format!("Sam, your PT consult at Test Gym is set for {local_time}.")
+        );
+
+        let (appointment_id, appointment_type_id): (i64, Option<i64>) = conn
+            .query_row(
+                "SELECT id, appointment_type_id FROM appointments WHERE lead_id=?",
+                params![lead_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(appointment_type_id, Some(consult.id));
+
+        conn.execute(
+            "UPDATE conversations SET last_outbound_at=NULL WHERE id=?",
+            params![conversation_id],
+        )
+        .unwrap();
+        execute_appointment_reminder(
+            &conn,
+            &clock,
+            ReminderPayload {
+                lead_id,
+                appointment_id,
+                start_at: "2030-01-07T15:00:00Z".to_string(),
+                reminder_config_id: None,
+            },
+        )
+        .unwrap();
+        let reminder: String = conn
+            .query_row(
+                "SELECT body FROM messages WHERE conversation_id=? AND direction='OUTBOUND'
+                 ORDER BY id DESC LIMIT 1",
+                params![conversation_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(
+            reminder,
+            format!("See you soon Sam: PT consult at {local_time}.")
+        );
+    }
+
+    #[test]
+    fn list_appointment_types_seeds_intro_session() {
+        let conn = init_in_memory_db();
+        let types = list_appointment_types_with_conn(&conn).unwrap();
+        assert_eq!(types.len(), 1);
+        assert_eq!(types[0].name, "Intro Session");
+        assert_eq!(types[0].duration_minutes, 30);
+    }
+
+    #[test]
+    fn full_schedule_waitlists_lead_and_cancellation_promotes_them() {
+        let conn = init_in_memory_db();
+        let clock = FakeClock(ts("2030-01-07T12:00:00Z"));
+        set_business_hours(
+            &conn,
+            r#"{"mon":[["09:00","09:30"]],"tue":[["09:00","09:30"]],"wed":[["09:00","09:30"]],"thu":[["09:00","09:30"]],"fri":[["09:00","09:30"]],"sat":[["09:00","09:30"]],"sun":[["09:00","09:30"]]}"#,
+        );
+        let location = get_location(&conn).unwrap();
+        let booked_lead = insert_lead(&conn, "+15550000017");
+        let mut booked_ids = Vec::new();
+        loop {
+            let slots = generate_slot_choices(&conn, &location, clock.now()).unwrap();
+            let Some(slot) = slots.first() else {
+                break;
+            };
+            booked_ids.push(insert_booked_appointment(
+                &conn,
+                booked_lead,
+                &slot.start_at,
+                &slot.end_at,
+            ));
+        }
+
+        let waiter = insert_lead(&conn, "+15550000018");
+        let conversation_id = insert_conversation(&conn, waiter);
+        let lead = get_lead(&conn, waiter).unwrap();
+        let conversation = get_conversation_by_lead_id(&conn, waiter).unwrap();
+        process_inbound_state_machine(&conn, &clock, &lead, &conversation, "YES").unwrap();
+
+        let body: String = conn
+            .query_row(
+                "SELECT body FROM messages WHERE conversation_id=? AND direction='OUTBOUND'",
+                params![conversation_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(body, "All slots are full. You're on the waitlist.");
+        let waiting: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM waitlist WHERE lead_id=? AND status='waiting'",
+                params![waiter],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(waiting, 1);
+
+        let cancelled = ActionGateway::with_clock(&conn, &clock)
+            .cancel_appointment(CancelAppointmentRequest {
+                appointment_id: booked_ids[0],
+                reason: "member is travelling".to_string(),
+            })
+            .unwrap();
+        assert_eq!(cancelled.waitlist_lead_id, Some(waiter));
+
+        let (job_count, waitlist_status): (i64, String) = conn
+            .query_row(
+                "SELECT
+                   (SELECT COUNT(*) FROM scheduled_jobs
+                    WHERE job_type='initial_follow_up' AND target_id=?1 AND status='pending'),
+                   (SELECT status FROM waitlist WHERE lead_id=?1)",
+                params![waiter],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(job_count, 1);
+        assert_eq!(waitlist_status, "notified");
+    }
+
+    #[test]
+    fn export_appointment_ics_round_trips_dtstart_with_stable_uid() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000019");
+        conn.execute(
+            "UPDATE leads SET first_name='Riley' WHERE id=?",
+            params![lead_id],
+        )
+        .unwrap();
+        let appointment_id = insert_booked_appointment(
+            &conn,
+            lead_id,
+            "2030-01-07T14:00:00Z",
+            "2030-01-07T14:30:00Z",
+        );
+
+        let ics = export_appointment_ics_with_conn(&conn, appointment_id).unwrap();
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.contains("DTEND:20300107T143000Z\r\n"));
+        assert!(ics.contains("SUMMARY:Test Gym Intro Session\r\n"));
+        assert!(ics.contains("DESCRIPTION:Intro Session for Riley\r\n"));
+
+        let dtstart = ics
+            .lines()
+            .find_map(|line| line.strip_prefix("DTSTART:"))
+            .unwrap();
+        let parsed = chrono::NaiveDateTime::parse_from_str(dtstart, "%Y%m%dT%H%M%SZ")
+            .unwrap()
+            .and_utc();
+        assert_eq!(parsed, parse_ts("2030-01-07T14:00:00Z").unwrap());
+
+        assert!(ics.contains(&format!("UID:{appointment_id}@goldbot\r\n")));
+        assert_eq!(
+            export_appointment_ics_with_conn(&conn, appointment_id).unwrap(),
+            ics
+        );
+    }
+
+    #[test]
+    fn fold_ics_line_wraps_at_75_octets() {
+        let line = format!("SUMMARY:{}", "x".repeat(100));
+        let folded = fold_ics_line(&line);
+        let parts: Vec<&str> = folded.trim_end_matches("\r\n").split("\r\n").collect();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].len(), 75);
+        assert!(parts[1].starts_with(' '));
+        assert_eq!(folded.replace("\r\n ", ""), format!("{line}\r\n"));
+    }
+
+    #[test]
+    fn booking_schedules_one_reminder_per_config() {
+        let conn = init_in_memory_db();
+        let clock = FakeClock(ts("2030-01-05T12:00:00Z"));
+        let location_id = ensure_primary_location(&conn).unwrap();
+        let default_config = list_reminder_configs(&conn, location_id).unwrap();
+        remove_reminder_config_with_conn(&conn, &clock, default_config[0].id).unwrap();
+        add_reminder_config_with_conn(&conn, &clock, location_id, 24, None).unwrap();
+        let two_hour = add_reminder_config_with_conn(
+            &conn,
+            &clock,
+            location_id,
+            2,
+            Some("Almost time {name}: {time}.".to_string()),
+        )
+        .unwrap();
+        let lead_id = insert_lead(&conn, "+15550000020");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let start_at = "2030-01-07T15:00:00Z";
+        let state = ConversationState {
+            offered_slots: vec![SlotChoice {
+                start_at: start_at.to_string(),
+                end_at: "2030-01-07T15:30:00Z".to_string(),
+            }],
+            appointment_type_id: None,
+        };
+        conn.execute(
+            "UPDATE conversations SET state='awaiting_time_choice', state_json=? WHERE id=?",
+            params![serde_json::to_string(&state).unwrap(), conversation_id],
+        )
+        .unwrap();
+
+        let location = get_location(&conn).unwrap();
+        let lead = get_lead(&conn, lead_id).unwrap();
+        let conversation = get_conversation_by_lead_id(&conn, lead_id).unwrap();
+        process_inbound_state_machine(&conn, &clock, &lead, &conversation, "1").unwrap();
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT execute_at, payload_json FROM scheduled_jobs
+                 WHERE job_type='appointment_reminder' ORDER BY execute_at",
+            )
+            .unwrap();
+        let jobs: Vec<(String, String)> = stmt
+            .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(
+            parse_ts(&jobs[0].0).unwrap(),
+            ts(start_at) - Duration::hours(24)
+        );
+        assert_eq!(
+            parse_ts(&jobs[1].0).unwrap(),
+            ts(start_at) - Duration::hours(2)
+        );
+        let payload: ReminderPayload = serde_json::from_str(&jobs[1].1).unwrap();
+        assert_eq!(payload.reminder_config_id, Some(two_hour.id));
+
+        conn.execute(
+            "UPDATE conversations SET last_outbound_at=NULL WHERE id=?",
+            params![conversation_id],
+        )
+        .unwrap();
+        execute_appointment_reminder(&conn, &clock, payload).unwrap();
+        let body: String = conn
+            .query_row(
+                "SELECT body FROM messages WHERE conversation_id=? AND direction='OUTBOUND'
+                 ORDER BY id DESC LIMIT 1",
+                params![conversation_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let local_time = local_display(&location, start_at).unwrap();
+        assert_eq!(body, format!("Almost time there: {local_time}."));
+    }
+
+    #[test]
+    fn custom_stop_keyword_triggers_opt_out() {
+        let conn = init_in_memory_db();
+        set_stop_keywords_with_conn(
+            &conn,
+            &RealClock,
+            vec![
+                "STOP".to_string(),
+                "UNSUBSCRIBE".to_string(),
+                "QUIT".to_string(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            get_stop_keywords_with_conn(&conn).unwrap(),
+            vec!["STOP", "UNSUBSCRIBE", "QUIT"]
+        );
+
+        let lead_id = insert_lead(&conn, "+15550000021");
+        insert_conversation(&conn, lead_id);
+        let lead = get_lead(&conn, lead_id).unwrap();
+        let conversation = get_conversation_by_lead_id(&conn, lead_id).unwrap();
+        process_inbound_state_machine(&conn, &RealClock, &lead, &conversation, " quit ").unwrap();
+
+        assert!(get_lead(&conn, lead_id).unwrap().opted_out);
+    }
+
+    #[test]
+    fn set_stop_keywords_rejects_empty_or_lowercase_entries() {
+        let conn = init_in_memory_db();
+        assert!(set_stop_keywords_with_conn(&conn, &RealClock, Vec::new()).is_err());
+        assert!(set_stop_keywords_with_conn(&conn, &RealClock, vec!["quit".to_string()]).is_err());
+        assert!(set_stop_keywords_with_conn(&conn, &RealClock, vec![String::new()]).is_err());
+        assert_eq!(
+            get_stop_keywords_with_conn(&conn).unwrap(),
+            default_stop_keywords()
+        );
+    }
+
+    #[test]
+    fn help_keyword_replies_without_changing_state_or_rate_limits() {
+        let conn = init_in_memory_db();
+        let clock = FakeClock(ts("2030-01-07T17:00:00Z"));
+
+        for (index, state) in ["awaiting_yes", "awaiting_time_choice", "booked"]
+            .iter()
+            .enumerate()
+        {
+            let lead_id = insert_lead(&conn, &format!("+1555000003{index}"));
+            let conversation_id = insert_conversation(&conn, lead_id);
+            conn.execute(
+                "UPDATE conversations SET state=? WHERE id=?",
+                params![state, conversation_id],
+            )
+            .unwrap();
+
+            let lead = get_lead(&conn, lead_id).unwrap();
+            let conversation = get_conversation_by_lead_id(&conn, lead_id).unwrap();
+            process_inbound_state_machine(&conn, &clock, &lead, &conversation, "help").unwrap();
+
+            let after = get_conversation_by_lead_id(&conn, lead_id).unwrap();
+            assert_eq!(after.state, *state);
+            assert_eq!(after.last_outbound_at, None);
+            let (body, exempt): (String, i64) = conn
+                .query_row(
+                    "SELECT body, rate_limit_exempt FROM messages
+                     WHERE conversation_id=? AND direction='OUTBOUND'",
+                    params![conversation_id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .unwrap();
+            assert_eq!(
+                body,
+                "For help contact Test Gym or reply STOP to unsubscribe."
+            );
+            assert_eq!(exempt, 1);
+        }
+    }
+
+    fn last_outbound_body(conn: &Connection, conversation_id: i64) -> Option<String> {
+        conn.query_row(
+            "SELECT body FROM messages WHERE conversation_id=? AND direction='OUTBOUND'
+             ORDER BY id DESC LIMIT 1",
+            params![conversation_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .unwrap()
+    }
+
+    #[test]
+    fn custom_keyword_matches_case_insensitively_and_keeps_state() {
+        let conn = init_in_memory_db();
+        add_custom_keyword_with_conn(&conn, &RealClock, "price", "Intro sessions are free.")
+            .unwrap();
+        let lead_id = insert_lead(&conn, "+15550000040");
+        let conversation_id = insert_conversation(&conn, lead_id);
+
+        let lead = get_lead(&conn, lead_id).unwrap();
+        let conversation = get_conversation_by_lead_id(&conn, lead_id).unwrap();
+        process_inbound_state_machine(&conn, &RealClock, &lead, &conversation, " Price ").unwrap();
+
+        assert_eq!(
+            last_outbound_body(&conn, conversation_id).as_deref(),
+            Some("Intro sessions are free.")
+        );
+        assert_eq!(
+            get_conversation_by_lead_id(&conn, lead_id).unwrap().state,
+            "awaiting_yes"
+        );
+    }
+
+    #[test]
+    fn disabled_custom_keyword_falls_through_to_state_machine() {
+        let conn = init_in_memory_db();
+        let info =
+            add_custom_keyword_with_conn(&conn, &RealClock, "INFO", "We are open daily.").unwrap();
+        set_custom_keyword_active_with_conn(&conn, info.id, false).unwrap();
+        let lead_id = insert_lead(&conn, "+15550000041");
+        let conversation_id = insert_conversation(&conn, lead_id);
+
+        let lead = get_lead(&conn, lead_id).unwrap();
+        let conversation = get_conversation_by_lead_id(&conn, lead_id).unwrap();
+        process_inbound_state_machine(&conn, &RealClock, &lead, &conversation, "info").unwrap();
+
+        assert_eq!(
+            last_outbound_body(&conn, conversation_id).as_deref(),
+            Some("Reply YES to get the next two available intro session times.")
+        );
+    }
+
+    #[test]
+    fn custom_keyword_takes_priority_over_time_choice_repair() {
+        let conn = init_in_memory_db();
+        let clock = FakeClock(ts("2030-01-07T17:00:00Z"));
+        add_custom_keyword_with_conn(&conn, &clock, "HOURS", "Open 6am to 10pm.").unwrap();
+        let lead_id = insert_lead(&conn, "+15550000042");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        conn.execute(
+            "UPDATE conversations SET state='awaiting_time_choice' WHERE id=?",
+            params![conversation_id],
+        )
+        .unwrap();
+
+        let lead = get_lead(&conn, lead_id).unwrap();
+        let conversation = get_conversation_by_lead_id(&conn, lead_id).unwrap();
+        process_inbound_state_machine(&conn, &clock, &lead, &conversation, "hours").unwrap();
+
+        assert_eq!(
+            last_outbound_body(&conn, conversation_id).as_deref(),
+            Some("Open 6am to 10pm.")
+        );
+        let after = get_conversation_by_lead_id(&conn, lead_id).unwrap();
+        assert_eq!(after.state, "awaiting_time_choice");
+        assert_eq!(after.repair_attempts, 0);
+    }
+
+    #[test]
+    fn add_custom_keyword_rejects_reserved_words() {
+        let conn = init_in_memory_db();
+        assert!(add_custom_keyword_with_conn(&conn, &RealClock, "stop", "nope").is_err());
+        assert!(add_custom_keyword_with_conn(&conn, &RealClock, "HELP", "nope").is_err());
+    }
+
+    #[test]
+    fn edited_template_is_used_by_initial_follow_up() {
+        let conn = init_in_memory_db();
+        set_template_with_conn(
+            &conn,
+            "initial_follow_up",
+            "Hey {name}! {gym} here. Text YES for intro times.",
+        )
+        .unwrap();
+        let lead_id = insert_lead(&conn, "+15550000043");
+        conn.execute(
+            "UPDATE leads SET first_name='Jo' WHERE id=?",
+            params![lead_id],
+        )
+        .unwrap();
+        let conversation_id = insert_conversation(&conn, lead_id);
+
+        execute_initial_follow_up(&conn, &RealClock, lead_id, 1).unwrap();
+
+        assert_eq!(
+            last_outbound_body(&conn, conversation_id).as_deref(),
+            Some("Hey Jo! Test Gym here. Text YES for intro times.")
+        );
+    }
+
+    #[test]
+    fn seeded_slot_offer_template_matches_original_wording() {
+        let conn = init_in_memory_db();
+        let body = render_template(
+            &conn,
+            "slot_offer",
+            HashMap::from([("slot1", "Mon 9:00 AM"), ("slot2", "Tue 9:00 AM")]),
+        )
+        .unwrap();
+        assert_eq!(
+            body,
+            "Choose a time:\n1) Mon 9:00 AM\n2) Tue 9:00 AM\n\nReply 1 or 2."
+        );
+        assert!(set_template_with_conn(&conn, "does_not_exist", "x").is_err());
+    }
+
+    #[test]
+    fn reset_conversation_returns_to_awaiting_yes_and_cancels_follow_up() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000044");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        conn.execute(
+            "UPDATE conversations SET state='awaiting_time_choice', repair_attempts=2,
+                 state_json='{\"offered_slots\":[{\"start_at\":\"2030-01-07T15:00:00Z\",\"end_at\":\"2030-01-07T15:30:00Z\"}]}'
+             WHERE id=?",
+            params![conversation_id],
+        )
+        .unwrap();
+        conn.execute(
+            "UPDATE leads SET status='awaiting_time_choice' WHERE id=?",
+            params![lead_id],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO scheduled_jobs (job_type, target_id, execute_at, status, payload_json, created_at)
+             VALUES ('initial_follow_up', ?, '2030-01-08T12:00:00Z', 'pending', '{}', '2030-01-01T00:00:00Z')",
+            params![lead_id],
+        )
+        .unwrap();
+
+        ActionGateway::new(&conn)
+            .reset_conversation(lead_id, "lead called the front desk")
+            .unwrap();
+
+        let detail = get_lead_detail_with_conn(&conn, lead_id).unwrap();
+        assert_eq!(detail.lead.status, "awaiting_yes");
+        assert_eq!(detail.conversation.state, "awaiting_yes");
+        assert_eq!(detail.conversation.repair_attempts, 0);
+        let state: ConversationState =
+            serde_json::from_str(&detail.conversation.state_json).unwrap();
+        assert!(state.offered_slots.is_empty());
+        let job_status: String = conn
+            .query_row(
+                "SELECT status FROM scheduled_jobs WHERE target_id=?",
+                params![lead_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(job_status, "cancelled");
+        assert_eq!(last_outbound_body(&conn, conversation_id), None);
+    }
+
+    #[test]
+    fn reset_conversation_rejects_opted_out_lead() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000045");
+        insert_conversation(&conn, lead_id);
+        conn.execute(
+            "UPDATE leads SET opted_out=1, status='opted_out' WHERE id=?",
+            params![lead_id],
+        )
+        .unwrap();
+
+        let err = ActionGateway::new(&conn)
+            .reset_conversation(lead_id, "staff request")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "cannot reset opted-out lead");
+    }
+
+    fn override_request(
+        conversation_id: i64,
+        new_state: &str,
+        state_json: &str,
+    ) -> OverrideConversationStateRequest {
+        OverrideConversationStateRequest {
+            conversation_id,
+            new_state: new_state.to_string(),
+            state_json: state_json.to_string(),
+            reason: "front desk booked in person".to_string(),
+        }
+    }
+
+    #[test]
+    fn override_conversation_state_applies_valid_transitions() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000046");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let gateway = ActionGateway::new(&conn);
+
+        let two_slots = r#"{"offered_slots":[
+            {"start_at":"2030-01-07T15:00:00Z","end_at":"2030-01-07T15:30:00Z"},
+            {"start_at":"2030-01-08T15:00:00Z","end_at":"2030-01-08T15:30:00Z"}]}"#;
+        gateway
+            .override_conversation_state(override_request(
+                conversation_id,
+                "awaiting_time_choice",
+                two_slots,
+            ))
+            .unwrap();
+        let detail = get_lead_detail_with_conn(&conn, lead_id).unwrap();
+        assert_eq!(detail.conversation.state, "awaiting_time_choice");
+        assert_eq!(detail.lead.status, "awaiting_time_choice");
+
+        gateway
+            .override_conversation_state(override_request(
+                conversation_id,
+                "booked",
+                r#"{"offered_slots":[]}"#,
+            ))
+            .unwrap();
+        let detail = get_lead_detail_with_conn(&conn, lead_id).unwrap();
+        assert_eq!(detail.conversation.state, "booked");
+        assert_eq!(detail.lead.status, "booked");
+
+        gateway
+            .override_conversation_state(override_request(conversation_id, "awaiting_rating", "{}"))
+            .unwrap();
+        let detail = get_lead_detail_with_conn(&conn, lead_id).unwrap();
+        assert_eq!(detail.conversation.state, "awaiting_rating");
+        assert_eq!(detail.lead.status, "awaiting_rating");
+        assert!(LEAD_STATUSES.contains(&detail.lead.status.as_str()));
+
+        let severity: String = conn
+            .query_row(
+                "SELECT severity FROM audit_log WHERE action_type='override_conversation_state'
+                 ORDER BY id DESC LIMIT 1",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(severity, "high");
+    }
+
+    #[test]
+    fn override_conversation_state_rejects_unknown_state_and_bad_json() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000047");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let gateway = ActionGateway::new(&conn);
+
+        assert!(gateway
+            .override_conversation_state(override_request(conversation_id, "sleeping", "{}"))
+            .is_err());
+        assert!(gateway
+            .override_conversation_state(override_request(conversation_id, "booked", "{not json"))
+            .is_err());
+        assert!(gateway
+            .override_conversation_state(override_request(
+                conversation_id,
+                "awaiting_time_choice",
+                r#"{"offered_slots":[]}"#,
+            ))
+            .is_err());
+
+        let conversation = get_conversation_by_lead_id(&conn, lead_id).unwrap();
+        assert_eq!(conversation.state, "awaiting_yes");
+    }
+
+    #[test]
+    fn expire_stale_conversations_resets_and_flags_quiet_leads() {
+        let conn = init_in_memory_db();
+        let stale_lead = insert_lead(&conn, "+15550000048");
+        let stale_conversation = insert_conversation(&conn, stale_lead);
+        let replied_lead = insert_lead(&conn, "+15550000049");
+        let replied_conversation = insert_conversation(&conn, replied_lead);
+        let stale_outbound = (Utc::now() - Duration::hours(72)).to_rfc3339();
+        conn.execute(
+            "UPDATE conversations SET state='awaiting_time_choice', last_outbound_at=? WHERE id=?",
+            params![stale_outbound, stale_conversation],
+        )
+        .unwrap();
+        conn.execute(
+            "UPDATE conversations SET state='awaiting_time_choice', last_outbound_at=?, last_inbound_at=?
+             WHERE id=?",
+            params![stale_outbound, now_iso(), replied_conversation],
+        )
+        .unwrap();
+        set_lead_status(
+            &conn,
+            &RealClock,
+            stale_lead,
+            "awaiting_time_choice",
+            "test",
+        )
+        .unwrap();
+        ensure_conversation_expiry_job(&conn, &RealClock).unwrap();
+
+        let result = run_due_jobs_with_conn(&conn, &RealClock).unwrap();
+        assert_eq!(result.processed, 1);
+
+        let detail = get_lead_detail_with_conn(&conn, stale_lead).unwrap();
+        assert_eq!(detail.conversation.state, "awaiting_yes");
+        assert_eq!(detail.lead.status, "awaiting_yes");
+        assert!(detail.lead.needs_staff_attention);
+
+        let detail = get_lead_detail_with_conn(&conn, replied_lead).unwrap();
+        assert_eq!(detail.conversation.state, "awaiting_time_choice");
+        assert!(!detail.lead.needs_staff_attention);
+
+        let next_runs: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM scheduled_jobs
+                 WHERE job_type='expire_stale_conversations' AND status='pending'
+                   AND datetime(execute_at) > datetime('now')",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(next_runs, 1);
+    }
+
+    #[test]
+    fn get_stale_leads_filters_on_last_contact_boundary() {
+        let conn = init_in_memory_db();
+        let contact = |lead_id: i64, days_ago: i64| {
+            conn.execute(
+                "UPDATE leads SET last_contact_at=? WHERE id=?",
+                params![
+                    (Utc::now() - Duration::days(days_ago)).to_rfc3339(),
+                    lead_id
+                ],
+            )
+            .unwrap();
+        };
+        let oldest = insert_lead(&conn, "+15550000050");
+        contact(oldest, 30);
+        let just_past = insert_lead(&conn, "+15550000051");
+        contact(just_past, 8);
+        let recent = insert_lead(&conn, "+15550000052");
+        contact(recent, 6);
+        let booked = insert_lead(&conn, "+15550000053");
+        contact(booked, 30);
+        set_lead_status(&conn, &RealClock, booked, "booked", "test").unwrap();
+        let opted_out = insert_lead(&conn, "+15550000054");
+        contact(opted_out, 30);
+        conn.execute(
+            "UPDATE leads SET opted_out=1 WHERE id=?",
+            params![opted_out],
+        )
+        .unwrap();
+        insert_lead(&conn, "+15550000055");
+
+        let stale = get_stale_leads_with_conn(&conn, &RealClock, 7).unwrap();
+        let ids: Vec<i64> = stale.iter().map(|lead| lead.id).collect();
+        assert_eq!(ids, vec![oldest, just_past]);
+
+        assert!(get_stale_leads_with_conn(&conn, &RealClock, 0).is_err());
+        assert!(get_stale_leads_with_conn(&conn, &RealClock, 366).is_err());
+    }
+
+    #[test]
+    fn re_engagement_batch_spaces_jobs_and_handler_resets_conversation() {
+        let conn = init_in_memory_db();
+        let lead_ids: Vec<i64> = (56..59)
+            .map(|n| {
+                let lead_id = insert_lead(&conn, &format!("+155500000{n}"));
+                insert_conversation(&conn, lead_id);
+                lead_id
+            })
+            .collect();
+        let opted_out = insert_lead(&conn, "+15550000059");
+        conn.execute(
+            "UPDATE leads SET opted_out=1 WHERE id=?",
+            params![opted_out],
+        )
+        .unwrap();
+
+        let mut batch = lead_ids.clone();
+        batch.push(opted_out);
+        batch.push(9999);
+        let result = schedule_re_engagement_batch_with_conn(
+            &conn,
+            &RealClock,
+            &batch,
+            "2030-01-07T15:00:00Z",
+            10,
+        )
+        .unwrap();
+        assert_eq!(result.scheduled, 3);
+        assert_eq!(result.skipped, 1);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].0, 9999);
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT execute_at FROM scheduled_jobs WHERE job_type='re_engagement' ORDER BY id",
+            )
+            .unwrap();
+        let times: Vec<DateTime<Utc>> = stmt
+            .query_map(params![], |row| row.get::<_, String>(0))
+            .unwrap()
+            .map(|raw| parse_ts(&raw.unwrap()).unwrap())
+            .collect();
+        assert_eq!(times.len(), 3);
+        assert_eq!(times[1] - times[0], Duration::seconds(10));
+        assert_eq!(times[2] - times[1], Duration::seconds(10));
+
+        assert!(schedule_re_engagement_batch_with_conn(
+            &conn,
+            &RealClock,
+            &lead_ids,
+            "2030-01-07T15:00:00Z",
+            4
+        )
+        .is_err());
+
+        let conversation = get_conversation_by_lead_id(&conn, lead_ids[0]).unwrap();
+        conn.execute(
+            "UPDATE conversations SET state='awaiting_time_choice', repair_attempts=1 WHERE id=?",
+            params![conversation.id],
+        )
+        .unwrap();
+        execute_re_engagement(
+            &conn,
+            &RealClock,
+            ReEngagementPayload {
+                lead_id: lead_ids[0],
+            },
+        )
+        .unwrap();
+        let conversation = get_conversation_by_lead_id(&conn, lead_ids[0]).unwrap();
+        assert_eq!(conversation.state, "awaiting_yes");
+        assert_eq!(conversation.repair_attempts, 0);
+        assert!(last_outbound_body(&conn, conversation.id)
+            .unwrap()
+            .contains("Still interested"));
+    }
+
+    #[test]
+    fn follow_up_sequence_schedules_next_step_only_while_awaiting_yes() {
+        let conn = init_in_memory_db();
+        let location = get_location(&conn).unwrap();
+        upsert_follow_up_step_with_conn(&conn, location.id, 2, 48, "re_engagement").unwrap();
+        assert!(
+            upsert_follow_up_step_with_conn(&conn, location.id, 3, 48, "re_engagement").is_err()
+        );
+        assert!(
+            upsert_follow_up_step_with_conn(&conn, location.id, 5, 96, "re_engagement").is_err()
+        );
+        let steps = list_follow_up_sequence_with_conn(&conn, location.id).unwrap();
+        assert_eq!(
+            steps
+                .iter()
+                .map(|step| step.delay_hours)
+                .collect::<Vec<_>>(),
+            vec![0, 48]
+        );
+
+        let waiting = insert_lead(&conn, "+15550000060");
+        let waiting_conversation = insert_conversation(&conn, waiting);
+        let replied = insert_lead(&conn, "+15550000061");
+        insert_conversation(&conn, replied);
+        set_lead_status(&conn, &RealClock, replied, "awaiting_time_choice", "test").unwrap();
+
+        let clock = FakeClock(ts("2030-01-07T15:00:00Z"));
+        execute_initial_follow_up(&conn, &clock, waiting, 1).unwrap();
+        execute_initial_follow_up(&conn, &clock, replied, 1).unwrap();
+
+        let next_steps: Vec<(i64, String, String)> = conn
+            .prepare(
+                "SELECT target_id, payload_json, execute_at FROM scheduled_jobs
+                 WHERE job_type='initial_follow_up' AND status='pending'",
+            )
+            .unwrap()
+            .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(next_steps.len(), 1);
+        assert_eq!(next_steps[0].0, waiting);
+        assert_eq!(
+            parse_ts(&next_steps[0].2).unwrap(),
+            ts("2030-01-09T15:00:00Z")
+        );
+        let payload: InitialFollowUpPayload = serde_json::from_str(&next_steps[0].1).unwrap();
+        assert_eq!(payload.step_number, 2);
+
+        conn.execute(
+            "UPDATE conversations SET last_outbound_at=NULL WHERE id=?",
+            params![waiting_conversation],
+        )
+        .unwrap();
+        execute_initial_follow_up(&conn, &RealClock, waiting, 2).unwrap();
+        assert!(last_outbound_body(&conn, waiting_conversation)
+            .unwrap()
+            .contains("Still interested"));
+        let pending: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM scheduled_jobs WHERE job_type='initial_follow_up' AND status='pending'",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(pending, 1);
+    }
+
+    #[test]
+    fn per_lead_rate_limit_follows_settings() {
+        let conn = init_in_memory_db();
+        assert_eq!(
+            load_rate_limit_settings(&conn)
+                .unwrap()
+                .max_outbound_per_lead_per_day,
+            4
+        );
+        assert!(set_rate_limit_settings_with_conn(&conn, &RealClock, 21, 100).is_err());
+        assert!(set_rate_limit_settings_with_conn(&conn, &RealClock, 2, 0).is_err());
+        let limits = set_rate_limit_settings_with_conn(&conn, &RealClock, 1, 100).unwrap();
+        assert_eq!(limits.max_outbound_per_lead_per_day, 1);
+
+        let lead_id = insert_lead(&conn, "+15550000062");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let gateway = ActionGateway::new(&conn);
+        let send = || {
+            gateway.create_outbound_message(OutboundRequest {
+                lead_id,
+                conversation_id,
+                body: "Checking in".to_string(),
+                automated: true,
+                allow_without_consent: false,
+                allow_opted_out_once: false,
+                allow_after_reply: false,
+                ignore_business_hours: true,
+                rate_limit_exempt: false,
+            })
+        };
+
+        send().unwrap();
+        conn.execute(
+            "UPDATE conversations SET last_outbound_at=NULL WHERE id=?",
+            params![conversation_id],
+        )
+        .unwrap();
+        let err = send().unwrap_err().to_string();
+        assert!(err.starts_with("rate limit"), "{err}");
+        assert!(err.contains("max 1 outbound per lead/day"), "{err}");
+    }
+
+    #[test]
+    fn rate_limit_status_reports_usage_and_cooldown() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000063");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let other_lead = insert_lead(&conn, "+15550000064");
+        let other_conversation = insert_conversation(&conn, other_lead);
+        // 00:30 in New York: the lead's 50-minutes-ago send falls on the previous local day.
+        let now = ts("2026-03-10T04:30:00Z");
+        let at = |minutes_ago: i64| (now - Duration::minutes(minutes_ago)).to_rfc3339();
+        for (conversation, created_at, exempt) in [
+            (conversation_id, at(50), 0),
+            (conversation_id, at(30), 0),
+            (conversation_id, at(20), 1),
+            (other_conversation, at(10), 0),
+            (other_conversation, at(180), 0),
+        ] {
+            conn.execute(
+                "INSERT INTO messages (conversation_id, direction, body, status, created_at, rate_limit_exempt)
+                 VALUES (?, 'OUTBOUND', 'hi', 'sent', ?, ?)",
+                params![conversation, created_at, exempt],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            "UPDATE conversations SET last_outbound_at=? WHERE id=?",
+            params![at(30), conversation_id],
+        )
+        .unwrap();
+
+        let location_only = get_rate_limit_status_with_conn(&conn, None, now).unwrap();
+        assert_eq!(location_only.location_hour_used, 3);
+        assert_eq!(location_only.location_hour_limit, 100);
+        assert_eq!(location_only.lead_day_used, None);
+        assert_eq!(location_only.lead_day_limit, None);
+        assert_eq!(location_only.cooldown_seconds, None);
+        let json = serde_json::to_value(&location_only).unwrap();
+        assert!(json.get("lead_day_limit").is_none(), "{json}");
+
+        let status = get_rate_limit_status_with_conn(&conn, Some(lead_id), now).unwrap();
+        assert_eq!(status.lead_day_used, Some(1));
+        assert_eq!(status.lead_day_limit, Some(4));
+        assert_eq!(status.cooldown_seconds, Some(90 * 60));
+
+        let status = get_rate_limit_status_with_conn(&conn, Some(other_lead), now).unwrap();
+        assert_eq!(status.cooldown_seconds, None);
+    }
+
+    #[test]
+    fn send_manual_message_enforces_consent_and_opt_out() {
+        let conn = init_in_memory_db();
+        let gateway = ActionGateway::new(&conn);
+
+        let lead_id = insert_lead(&conn, "+15550000065");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let message_id = gateway
+            .send_manual_message(lead_id, "  See you at the front desk!  ")
+            .unwrap();
+        let detail = get_lead_detail_with_conn(&conn, lead_id).unwrap();
+        let message = detail
+            .messages
+            .iter()
+            .find(|message| message.id == message_id)
+            .unwrap();
+        assert!(message.is_manual);
+        assert_eq!(message.body, "See you at the front desk!");
+        assert_eq!(detail.conversation.state, "awaiting_yes");
+        assert_eq!(
+            last_outbound_body(&conn, conversation_id).as_deref(),
+            Some("See you at the front desk!")
+        );
+
+        let no_consent = insert_lead(&conn, "+15550000066");
+        insert_conversation(&conn, no_consent);
+        conn.execute("UPDATE leads SET consent=0 WHERE id=?", params![no_consent])
+            .unwrap();
+        let err = gateway.send_manual_message(no_consent, "Hi").unwrap_err();
+        assert!(err.to_string().contains("consent required"));
+
+        let opted_out = insert_lead(&conn, "+15550000067");
+        insert_conversation(&conn, opted_out);
+        conn.execute(
+            "UPDATE leads SET opted_out=1 WHERE id=?",
+            params![opted_out],
+        )
+        .unwrap();
+        let err = gateway.send_manual_message(opted_out, "Hi").unwrap_err();
+        assert!(err.to_string().contains("opted out"));
+
+        let audited: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM audit_log WHERE action_type='manual_message'",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(audited, 3);
+    }
+
+    #[test]
+    fn failed_outbound_moves_from_conversation_to_failure_list() {
+        let conn = init_in_memory_db();
+        let gateway = ActionGateway::new(&conn);
+        let lead_id = insert_lead(&conn, "+15550000068");
+        insert_conversation(&conn, lead_id);
+        let message_id = gateway
+            .send_manual_message(lead_id, "Doors open at 6")
+            .unwrap();
+        assert_eq!(
+            get_lead_detail_with_conn(&conn, lead_id)
+                .unwrap()
+                .messages
+                .len(),
+            1
+        );
+
+        mark_message_failed(&conn, &RealClock, message_id, "carrier rejected").unwrap();
+
+        let failures = list_outbound_failures_with_conn(&conn, None).unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].message.id, message_id);
+        assert_eq!(failures[0].message.status, "failed");
+        assert_eq!(failures[0].lead_id, lead_id);
+        assert_eq!(failures[0].failure_reason, "carrier rejected");
+        assert!(get_lead_detail_with_conn(&conn, lead_id)
+            .unwrap()
+            .messages
+            .is_empty());
+
+        assert!(mark_message_failed(&conn, &RealClock, 9999, "missing").is_err());
+        assert!(list_outbound_failures_with_conn(&conn, Some(0)).is_err());
+    }
+
+    #[test]
+    fn simulate_bulk_inbound_moves_every_lead_to_time_choice() {
+        let conn = init_in_memory_db();
+        let clock = FakeClock(ts("2030-01-07T12:00:00Z"));
+        let items: Vec<BulkInboundItem> = (70..80)
+            .map(|n| {
+                let lead_id = insert_lead(&conn, &format!("+155500000{n}"));
+                insert_conversation(&conn, lead_id);
+                BulkInboundItem {
+                    lead_id,
+                    body: "yes".to_string(),
+                }
+            })
+            .collect();
+
+        let result = simulate_bulk_inbound_with_conn(&conn, &clock, &items);
+        assert_eq!(result.processed, 10);
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        for item in &items {
+            let detail = get_lead_detail_with_conn(&conn, item.lead_id).unwrap();
+            assert_eq!(detail.conversation.state, "awaiting_time_choice");
+        }
+
+        let result = simulate_bulk_inbound_with_conn(
+            &conn,
+            &clock,
+            &[BulkInboundItem {
+                lead_id: 9999,
+                body: "YES".to_string(),
+            }],
+        );
+        assert_eq!(result.processed, 0);
+        assert_eq!(result.errors.len(), 1);
+    }
+
+    fn insert_job(conn: &Connection, job_type: &str, status: &str, execute_at: &str) -> i64 {
+        conn.execute(
+            "INSERT INTO scheduled_jobs (job_type, target_id, execute_at, status, payload_json, created_at)
+             VALUES (?, NULL, ?, ?, '{}', ?)",
+            params![job_type, execute_at, status, now_iso()],
+        )
+        .expect("failed to insert test job");
+        conn.last_insert_rowid()
+    }
+
+    #[test]
+    fn list_scheduled_jobs_filters_by_status_and_type() {
+        let conn = init_in_memory_db();
+        let reminder = insert_job(
+            &conn,
+            "appointment_reminder",
+            "pending",
+            "2030-01-03T00:00:00Z",
+        );
+        let follow_up = insert_job(
+            &conn,
+            "initial_follow_up",
+            "pending",
+            "2030-01-01T00:00:00Z",
+        );
+        let done = insert_job(
+            &conn,
+            "initial_follow_up",
+            "completed",
+            "2030-01-02T00:00:00Z",
+        );
+        let failed = insert_job(
+            &conn,
+            "appointment_reminder",
+            "failed",
+            "2030-01-04T00:00:00Z",
+        );
+
+        let ids = |status: Option<&str>, job_type: Option<&str>| -> Vec<i64> {
+            let filter = JobFilter {
+                status: status.map(str::to_string),
+                job_type: job_type.map(str::to_string),
+                ..JobFilter::default()
+            };
+            list_scheduled_jobs_with_conn(&conn, &filter)
+                .unwrap()
+                .iter()
+                .map(|job| job.id)
+                .collect()
+        };
+        assert_eq!(ids(None, None), vec![follow_up, done, reminder, failed]);
+        assert_eq!(ids(Some("pending"), None), vec![follow_up, reminder]);
+        assert_eq!(ids(None, Some("initial_follow_up")), vec![follow_up, done]);
+        assert_eq!(
+            ids(Some("failed"), Some("appointment_reminder")),
+            vec![failed]
+        );
+        assert!(ids(Some("cancelled"), None).is_empty());
+
+        let running = JobFilter {
+            status: Some("running".to_string()),
+            ..JobFilter::default()
+        };
+        assert!(list_scheduled_jobs_with_conn(&conn, &running).is_err());
+        let one = JobFilter {
+            limit: 1,
+            ..JobFilter::default()
+        };
+        assert_eq!(list_scheduled_jobs_with_conn(&conn, &one).unwrap().len(), 1);
+
+        let later_failed = insert_job(&conn, "initial_follow_up", "failed", "2030-01-05T00:00:00Z");
+        assert_eq!(ids(Some("failed"), None), vec![later_failed, failed]);
+
+        let window = JobFilter {
+            since: Some("2030-01-02T00:00:00Z".to_string()),
+            until: Some("2030-01-04T00:00:00Z".to_string()),
+            ..JobFilter::default()
+        };
+        let in_window: Vec<i64> = list_scheduled_jobs_with_conn(&conn, &window)
+            .unwrap()
+            .iter()
+            .map(|job| job.id)
+            .collect();
+        assert_eq!(in_window, vec![done, reminder, failed]);
+        let inverted = JobFilter {
+            since: window.until.clone(),
+            until: window.since.clone(),
+            ..JobFilter::default()
+        };
+        assert!(list_scheduled_jobs_with_conn(&conn, &inverted).is_err());
+    }
+
+    #[test]
+    fn cancel_scheduled_job_only_cancels_pending_jobs() {
+        let conn = init_in_memory_db();
+        let gateway = ActionGateway::new(&conn);
+        let pending = insert_job(
+            &conn,
+            "initial_follow_up",
+            "pending",
+            "2030-01-01T00:00:00Z",
+        );
+        let completed = insert_job(
+            &conn,
+            "initial_follow_up",
+            "completed",
+            "2030-01-01T00:00:00Z",
+        );
+
+        let result = gateway
+            .cancel_scheduled_job(pending, "lead called the front desk")
+            .unwrap();
+        assert_eq!(result.job_id, pending);
+        assert_eq!(result.previous_status, "pending");
+        let cancelled = JobFilter {
+            status: Some("cancelled".to_string()),
+            ..JobFilter::default()
+        };
+        let jobs = list_scheduled_jobs_with_conn(&conn, &cancelled).unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].id, pending);
+
+        let err = gateway
+            .cancel_scheduled_job(completed, "too late")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "job is not pending");
+        assert!(gateway.cancel_scheduled_job(9999, "missing").is_err());
+
+        let audited: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM audit_log WHERE action_type='cancel_scheduled_job'",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(audited, 3);
+    }
+
+    #[test]
+    fn failing_job_backs_off_then_fails_and_can_be_requeued() {
+        let conn = init_in_memory_db();
+        let job_id = insert_job(&conn, "unknown_job", "pending", "2020-01-01T00:00:00Z");
+        let make_due = || {
+            conn.execute(
+                "UPDATE scheduled_jobs SET execute_at='2020-01-01T00:00:00Z' WHERE id=?",
+                params![job_id],
+            )
+            .unwrap();
+        };
+
+        for attempt in 1..=3 {
+            let before = Utc::now();
+            let result = run_due_jobs_with_conn(&conn, &RealClock).unwrap();
+            assert_eq!(result.errors, 1);
+            let job = get_scheduled_job(&conn, job_id).unwrap();
+            assert_eq!(job.status, "pending");
+            assert_eq!(job.retry_count, attempt);
+            let delay = parse_ts(&job.execute_at).unwrap() - before;
+            assert!(
+                (delay - Duration::minutes(attempt * 60))
+                    .num_seconds()
+                    .abs()
+                    <= 5,
+                "attempt {attempt}: {delay}"
+            );
+            make_due();
+        }
 
-#[tauri::command]
-fn get_lead_detail(
-    state: State<AppState>,
-    app: AppHandle,
-    lead_id: i64,
-) -> Result<LeadDetail, String> {
-    let result = retry_db(|| {
-        let conn = open_conn(&state)?;
+        run_due_jobs_with_conn(&conn, &RealClock).unwrap();
+        let job = get_scheduled_job(&conn, job_id).unwrap();
+        assert_eq!(job.status, "failed");
+        assert_eq!(job.retry_count, 3);
+
+        let gateway = ActionGateway::new(&conn);
+        let requeued = gateway.requeue_failed_job(job_id).unwrap();
+        assert_ne!(requeued.id, job_id);
+        assert_eq!(requeued.status, "pending");
+        assert_eq!(requeued.retry_count, 0);
+        assert!(parse_ts(&requeued.execute_at).unwrap() > Utc::now());
+        let original = get_scheduled_job(&conn, job_id).unwrap();
+        assert_eq!(
+            (original.status.as_str(), original.retry_count),
+            ("cancelled", 3)
+        );
+        assert!(gateway.requeue_failed_job(job_id).is_err());
+    }
 
-        let lead = conn
-            .query_row(
-                "SELECT id, phone_e164, first_name, last_name, status, consent, consent_at, consent_source,
-                        opted_out, needs_staff_attention, last_contact_at, next_action_at, created_at
-                 FROM leads WHERE id=?",
-                params![lead_id],
-                |row| {
-                    Ok(LeadDetailLead {
-                        id: row.get(0)?,
-                        phone_e164: row.get(1)?,
-                        first_name: row.get(2)?,
-                        last_name: row.get(3)?,
-                        status: row.get(4)?,
-                        consent: i64_to_bool(row.get(5)?),
-                        consent_at: row.get(6)?,
-                        consent_source: row.get(7)?,
-                        opted_out: i64_to_bool(row.get(8)?),
-                        needs_staff_attention: i64_to_bool(row.get(9)?),
-                        last_contact_at: row.get(10)?,
-                        next_action_at: row.get(11)?,
-                        created_at: row.get(12)?,
-                    })
-                },
+    #[test]
+    fn job_stats_count_statuses_and_average_delay() {
+        let conn = init_in_memory_db();
+        for (job_type, status) in [
+            ("initial_follow_up", "pending"),
+            ("initial_follow_up", "failed"),
+            ("appointment_reminder", "pending"),
+            ("appointment_reminder", "cancelled"),
+        ] {
+            insert_job(&conn, job_type, status, "2030-01-01T00:00:00Z");
+        }
+        for (execute_at, completed_at) in [
+            ("2030-01-01T00:00:00Z", "2030-01-01T00:00:10Z"),
+            ("2030-01-01T00:00:00+00:00", "2030-01-01T00:00:20.500+00:00"),
+        ] {
+            let job_id = insert_job(&conn, "initial_follow_up", "completed", execute_at);
+            conn.execute(
+                "UPDATE scheduled_jobs SET completed_at=? WHERE id=?",
+                params![completed_at, job_id],
             )
-            .optional()?
-            .ok_or_else(|| AppError::Validation("lead not found".to_string()))?;
+            .unwrap();
+        }
+        conn.execute(
+            "UPDATE scheduled_jobs SET created_at='2020-01-01T00:00:00Z' WHERE status='cancelled'",
+            params![],
+        )
+        .unwrap();
+
+        let stats = get_job_stats_with_conn(&conn, None).unwrap();
+        assert_eq!(stats.total_pending, 2);
+        assert_eq!(stats.total_completed, 2);
+        assert_eq!(stats.total_failed, 1);
+        assert_eq!(stats.total_cancelled, 1);
+        let delay = stats.avg_delay_seconds.unwrap();
+        assert!((delay - 15.0).abs() < 1.0, "{delay}");
+        let follow_up = stats
+            .by_type
+            .iter()
+            .find(|entry| entry.job_type == "initial_follow_up")
+            .unwrap();
+        assert_eq!(
+            (follow_up.pending, follow_up.completed, follow_up.failed),
+            (1, 2, 1)
+        );
 
-        let conversation = conn.query_row(
-            "SELECT id, state, state_json, last_inbound_at, last_outbound_at, repair_attempts
-             FROM conversations WHERE lead_id=?",
-            params![lead_id],
-            |row| {
-                Ok(ConversationView {
-                    id: row.get(0)?,
-                    state: row.get(1)?,
-                    state_json: row.get(2)?,
-                    last_inbound_at: row.get(3)?,
-                    last_outbound_at: row.get(4)?,
-                    repair_attempts: row.get(5)?,
-                })
-            },
-        )?;
+        let recent = get_job_stats_with_conn(&conn, Some("2025-01-01T00:00:00Z")).unwrap();
+        assert_eq!(recent.total_cancelled, 0);
+        assert_eq!(recent.total_pending, 2);
+        assert!(get_job_stats_with_conn(&conn, Some("yesterday")).is_err());
+    }
 
-        let mut msg_stmt = conn.prepare(
-            "SELECT id, direction, body, status, created_at
-             FROM messages
-             WHERE conversation_id=?
-             ORDER BY datetime(created_at) ASC",
-        )?;
-        let msg_rows = msg_stmt.query_map(params![conversation.id], |row| {
-            Ok(MessageView {
-                id: row.get(0)?,
-                direction: row.get(1)?,
-                body: row.get(2)?,
-                status: row.get(3)?,
-                created_at: row.get(4)?,
-            })
-        })?;
-        let messages = msg_rows.collect::<Result<Vec<_>, _>>()?;
+    #[test]
+    fn generate_slot_choices_skips_holidays() {
+        let conn = init_in_memory_db();
+        set_business_hours(
+            &conn,
+            r#"{"mon":[["09:00","10:30"]],"tue":[["09:00","10:30"]],"wed":[["09:00","10:30"]],"thu":[],"fri":[],"sat":[],"sun":[]}"#,
+        );
+        assert!(add_holiday_with_conn(&conn, "01/07/2030", "Bad date").is_err());
+        let holiday_id = add_holiday_with_conn(&conn, "2030-01-07", "Founders Day").unwrap();
+        assert!(add_holiday_with_conn(&conn, "2030-01-07", "Duplicate").is_err());
+        assert_eq!(list_holidays_with_conn(&conn).unwrap().len(), 1);
 
-        let mut apt_stmt = conn.prepare(
-            "SELECT id, start_at, end_at, status
-             FROM appointments
-             WHERE lead_id=?
-             ORDER BY datetime(start_at) ASC",
-        )?;
-        let apt_rows = apt_stmt.query_map(params![lead_id], |row| {
-            Ok(AppointmentView {
-                id: row.get(0)?,
-                start_at: row.get(1)?,
-                end_at: row.get(2)?,
-                status: row.get(3)?,
-            })
-        })?;
-        let appointments = apt_rows.collect::<Result<Vec<_>, _>>()?;
+        let location = get_location(&conn).unwrap();
+        let slots = generate_slot_choices(&conn, &location, ts("2030-01-07T12:00:00Z")).unwrap();
+        assert_eq!(slots.len(), 2);
+        assert_eq!(parse_ts(&slots[0].start_at).unwrap(), ts("2030-01-08T14:00:00Z"));
+        assert_eq!(parse_ts(&slots[1].start_at).unwrap(), ts("2030-01-08T14:40:00Z"));
 
-        Ok(LeadDetail {
-            lead,
-            conversation,
-            messages,
-            appointments,
-        })
-    });
+        remove_holiday_with_conn(&conn, holiday_id).unwrap();
+        let slots = generate_slot_choices(&conn, &location, ts("2030-01-07T12:00:00Z")).unwrap();
+        assert_eq!(parse_ts(&slots[0].start_at).unwrap(), ts("2030-01-07T14:00:00Z"));
+        assert!(remove_holiday_with_conn(&conn, holiday_id).is_err());
+    }
 
-    map_cmd_result(result, "get_lead_detail", &app)
-}
+    #[test]
+    fn blackout_periods_close_slots_and_next_open_time() {
+        let conn = init_in_memory_db();
+        set_business_hours(
+            &conn,
+            r#"{"mon":[["09:00","09:30"]],"tue":[["09:00","09:30"]],"wed":[["09:00","09:30"]],"thu":[["09:00","09:30"]],"fri":[["09:00","09:30"]],"sat":[],"sun":[]}"#,
+        );
+        assert!(
+            add_blackout_period_with_conn(&conn, "2030-01-10", "2030-01-08", "Backwards").is_err()
+        );
+        add_blackout_period_with_conn(&conn, "2030-01-08", "2030-01-10", "Renovation").unwrap();
+        assert_eq!(list_blackout_periods_with_conn(&conn).unwrap().len(), 1);
 
-#[tauri::command]
-fn simulate_inbound_sms(
-    state: State<AppState>,
-    app: AppHandle,
-    lead_id: i64,
-    body: String,
-) -> Result<(), String> {
-    let result = retry_db(|| {
-        if body.trim().is_empty() {
-            return Err(AppError::Validation(
-                "inbound body cannot be empty".to_string(),
-            ));
+        let location = get_location(&conn).unwrap();
+        let slots = generate_slot_choices(&conn, &location, ts("2030-01-07T15:00:00Z")).unwrap();
+        assert_eq!(slots.len(), 2);
+        let blackout_end = ts("2030-01-11T05:00:00Z");
+        for slot in &slots {
+            assert!(
+                parse_ts(&slot.start_at).unwrap() >= blackout_end,
+                "{}",
+                slot.start_at
+            );
         }
+        assert_eq!(parse_ts(&slots[0].start_at).unwrap(), ts("2030-01-11T14:00:00Z"));
 
-        let conn = open_conn(&state)?;
-        let location = get_location(&conn)?;
-        let lead = get_lead(&conn, lead_id)?;
-        let conversation = get_conversation_by_lead_id(&conn, lead_id)?;
+        assert_eq!(
+            next_open_time(&conn, &location, ts("2030-01-07T22:00:00Z")).unwrap(),
+            ts("2030-01-11T14:00:00Z")
+        );
+    }
 
-        let now = now_iso();
-        conn.execute(
-            "INSERT INTO messages (conversation_id, direction, body, status, created_at)
-             VALUES (?, 'INBOUND', ?, 'received', ?)",
-            params![conversation.id, body.trim(), now],
-        )?;
+    #[test]
+    fn validate_business_hours_reports_errors_and_warnings() {
+        let week = |sun: &str| {
+            format!(
+                r#"{{"mon":[["06:00","11:00"],["16:00","21:00"]],"tue":[["06:00","21:00"]],"wed":[["06:00","21:00"]],"thu":[["06:00","21:00"]],"fri":[["06:00","21:00"]],"sat":[["08:00","12:00"]],"sun":{sun}}}"#
+            )
+        };
 
-        conn.execute(
-            "UPDATE conversations SET last_inbound_at=? WHERE id=?",
-            params![now, conversation.id],
-        )?;
-        conn.execute(
-            "UPDATE leads SET last_contact_at=? WHERE id=?",
-            params![now, lead_id],
-        )?;
+        let report = validate_business_hours_json(&week(r#"[["08:00","12:00"]]"#));
+        assert!(report.valid, "{:?}", report.errors);
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
 
-        process_inbound_state_machine(&conn, &location, &lead, &conversation, body.trim())
-    });
+        let report = validate_business_hours_json(&week(r#"[["12:00","08:00"]]"#));
+        assert!(!report.valid);
+        assert_eq!(
+            report.errors,
+            vec!["sun range 1: end 08:00 is not after start 12:00"]
+        );
 
-    map_cmd_result(result, "simulate_inbound_sms", &app)
-}
+        let report =
+            validate_business_hours_json(&week(r#"[["08:00","12:00"],["11:30","14:00"]]"#));
+        assert!(!report.valid);
+        assert_eq!(report.errors, vec!["sun: 08:00-12:00 overlaps 11:30-14:00"]);
 
-#[tauri::command]
-fn get_today_report(state: State<AppState>, app: AppHandle) -> Result<TodayReport, String> {
-    let result = retry_db(|| {
-        let conn = open_conn(&state)?;
+        let report = validate_business_hours_json(&week("[]"));
+        assert!(report.valid);
+        assert_eq!(report.warnings, vec!["sun: no hours configured"]);
 
-        let leads_created: i64 = conn.query_row(
-            "SELECT COUNT(*) FROM leads WHERE date(created_at, 'localtime') = date('now', 'localtime')",
-            params![],
-            |row| row.get(0),
-        )?;
+        assert!(!validate_business_hours_json("not json").valid);
+    }
 
-        let contacted: i64 = conn.query_row(
-            "SELECT COUNT(DISTINCT c.lead_id)
-             FROM messages m
-             JOIN conversations c ON c.id = m.conversation_id
-             WHERE m.direction='OUTBOUND'
-               AND date(m.created_at, 'localtime') = date('now', 'localtime')",
-            params![],
-            |row| row.get(0),
-        )?;
+    #[test]
+    fn get_next_available_slot_looks_two_weeks_ahead() {
+        let conn = init_in_memory_db();
+        set_business_hours(
+            &conn,
+            r#"{"mon":[["09:00","09:30"]],"tue":[["09:00","09:30"]],"wed":[["09:00","09:30"]],"thu":[],"fri":[],"sat":[],"sun":[]}"#,
+        );
+        let lead_id = insert_lead(&conn, "+15550000080");
 
-        let booked: i64 = conn.query_row(
-            "SELECT COUNT(*) FROM appointments
-             WHERE status='booked'
-               AND date(created_at, 'localtime') = date('now', 'localtime')",
-            params![],
-            |row| row.get(0),
-        )?;
+        let slot =
+            get_next_available_slot_with_conn(&conn, Some("2030-01-07T12:00:00Z"), Utc::now())
+                .unwrap()
+                .expect("an open slot");
+        assert_eq!(
+            parse_ts(&slot.start_at).unwrap(),
+            ts("2030-01-07T14:00:00Z")
+        );
+        assert_eq!(parse_ts(&slot.end_at).unwrap(), ts("2030-01-07T14:30:00Z"));
 
-        let opt_outs: i64 = conn.query_row(
-            "SELECT COUNT(*) FROM audit_log
-             WHERE action_type='set_opt_out'
-               AND success=1
-               AND date(created_at, 'localtime') = date('now', 'localtime')",
-            params![],
-            |row| row.get(0),
-        )?;
+        let book = |day: NaiveDate| {
+            let start = ts(&format!("{day}T14:00:00Z"));
+            insert_booked_appointment(
+                &conn,
+                lead_id,
+                &start.to_rfc3339(),
+                &(start + Duration::minutes(30)).to_rfc3339(),
+            );
+        };
+        let first_monday = NaiveDate::from_ymd_opt(2030, 1, 7).unwrap();
+        for offset in 0..3 {
+            book(first_monday + Duration::days(offset));
+        }
+        let slot =
+            get_next_available_slot_with_conn(&conn, Some("2030-01-07T12:00:00Z"), Utc::now())
+                .unwrap()
+                .expect("the fourth business day is open");
+        assert_eq!(
+            parse_ts(&slot.start_at).unwrap(),
+            ts("2030-01-14T14:00:00Z")
+        );
 
-        let needs_attention: i64 = conn.query_row(
-            "SELECT COUNT(*) FROM leads WHERE needs_staff_attention=1",
-            params![],
-            |row| row.get(0),
-        )?;
+        // 14 Mon-Wed business days run through 2030-02-05.
+        for offset in 7..30 {
+            let day = first_monday + Duration::days(offset);
+            if day.weekday().num_days_from_monday() < 3 {
+                book(day);
+            }
+        }
+        assert!(
+            get_next_available_slot_with_conn(&conn, Some("2030-01-07T12:00:00Z"), Utc::now())
+                .unwrap()
+                .is_none()
+        );
+        assert!(get_next_available_slot_with_conn(&conn, Some("tomorrow"), Utc::now()).is_err());
+    }
 
-        Ok(TodayReport {
-            leads_created,
-            contacted,
-            booked,
-            opt_outs,
-            needs_attention,
-        })
-    });
+    #[test]
+    fn next_open_time_handles_fall_back_ambiguous_hour() {
+        let conn = init_in_memory_db();
+        set_business_hours(
+            &conn,
+            r#"{"mon":[],"tue":[],"wed":[],"thu":[],"fri":[],"sat":[],"sun":[["01:30","03:00"]]}"#,
+        );
+        let location = get_location(&conn).unwrap();
 
-    map_cmd_result(result, "get_today_report", &app)
-}
+        // 06:00Z is 01:00 EST, after the clocks fell back; the 01:30 EDT occurrence has passed.
+        assert_eq!(
+            next_open_time(&conn, &location, ts("2025-11-02T06:00:00Z")).unwrap(),
+            ts("2025-11-02T06:30:00Z")
+        );
+        // 05:00Z is 01:00 EDT, so the first 01:30 is still ahead.
+        assert_eq!(
+            next_open_time(&conn, &location, ts("2025-11-02T05:00:00Z")).unwrap(),
+            ts("2025-11-02T05:30:00Z")
+        );
 
-#[tauri::command]
-fn get_kill_switch(state: State<AppState>, app: AppHandle) -> Result<bool, String> {
-    let result = retry_db(|| {
-        let conn = open_conn(&state)?;
-        is_kill_switch_enabled(&conn)
-    });
+        let slots = generate_slot_choices(&conn, &location, ts("2025-11-02T04:00:00Z")).unwrap();
+        assert_eq!(parse_ts(&slots[0].start_at).unwrap(), ts("2025-11-02T05:30:00Z"));
+    }
 
-    map_cmd_result(result, "get_kill_switch", &app)
-}
+    #[test]
+    fn tcpa_mode_blocks_automated_outbound_before_8am_even_ignoring_business_hours() {
+        let conn = init_in_memory_db();
+        set_tcpa_mode_with_conn(&conn, &RealClock, true).unwrap();
+        let lead_id = insert_lead(&conn, "+15550000081");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let gateway = ActionGateway::new(&conn);
+        let req = OutboundRequest {
+            lead_id,
+            conversation_id,
+            body: "Still interested?".to_string(),
+            automated: true,
+            allow_without_consent: false,
+            allow_opted_out_once: false,
+            allow_after_reply: false,
+            ignore_business_hours: true,
+            rate_limit_exempt: false,
+        };
 
-#[tauri::command]
-fn get_location_settings(state: State<AppState>, app: AppHandle) -> Result<LocationSettings, String> {
-    let result = retry_db(|| {
-        let conn = open_conn(&state)?;
-        let primary_id = ensure_primary_location(&conn)?;
-        conn.query_row(
-            "SELECT id, gym_name, timezone, business_hours_json FROM locations WHERE id=?",
-            params![primary_id],
-            |row| {
-                Ok(LocationSettings {
-                    id: row.get(0)?,
-                    gym_name: row.get(1)?,
-                    timezone: row.get(2)?,
-                    business_hours_json: row.get(3)?,
-                })
+        // 11:59Z is 07:59 EDT.
+        let err = gateway
+            .validate_outbound_at(&req, ts("2025-06-02T11:59:00Z"))
+            .unwrap_err();
+        assert!(err.to_string().contains("TCPA"));
+        assert!(gateway
+            .validate_outbound_at(&req, ts("2025-06-02T12:00:00Z"))
+            .is_ok());
+        assert!(gateway
+            .validate_outbound_at(&req, ts("2025-06-03T01:00:00Z"))
+            .is_err());
+
+        set_tcpa_mode_with_conn(&conn, &RealClock, false).unwrap();
+        assert!(!get_tcpa_mode_with_conn(&conn).unwrap());
+        let gateway = ActionGateway::new(&conn);
+        assert!(gateway
+            .validate_outbound_at(&req, ts("2025-06-02T11:59:00Z"))
+            .is_ok());
+    }
+
+    #[test]
+    fn run_due_jobs_trips_kill_switch_when_error_threshold_reached() {
+        let conn = init_in_memory_db();
+        set_auto_kill_switch_threshold_with_conn(&conn, &RealClock, 1).unwrap();
+        let failing = insert_job(&conn, "always_fails", "pending", "2020-01-01T00:00:00Z");
+        let other = insert_job(&conn, "always_fails", "pending", "2099-01-01T00:00:00Z");
+
+        let result = run_due_jobs_with_conn(&conn, &RealClock).unwrap();
+        assert_eq!(result.errors, 1);
+        assert!(result.auto_kill_switch_triggered);
+        assert!(is_kill_switch_enabled(&conn).unwrap());
+        for job_id in [failing, other] {
+            assert_eq!(
+                get_scheduled_job(&conn, job_id).unwrap().status,
+                "cancelled"
+            );
+        }
+
+        let reason: String = conn
+            .query_row(
+                "SELECT json_extract(request_json, '$.reason')
+                 FROM audit_log WHERE action_type='auto_kill_switch'",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(reason, "auto_kill_switch: threshold exceeded");
+        assert_eq!(
+            get_kill_switch_state_with_conn(&conn)
+                .unwrap()
+                .reason
+                .as_deref(),
+            Some("auto_kill_switch: threshold exceeded")
+        );
+    }
+
+    #[test]
+    fn run_due_jobs_ignores_errors_when_auto_kill_switch_disabled() {
+        let conn = init_in_memory_db();
+        insert_job(&conn, "always_fails", "pending", "2020-01-01T00:00:00Z");
+
+        let result = run_due_jobs_with_conn(&conn, &RealClock).unwrap();
+        assert_eq!(result.errors, 1);
+        assert!(!result.auto_kill_switch_triggered);
+        assert!(!is_kill_switch_enabled(&conn).unwrap());
+    }
+
+    #[test]
+    fn kill_switch_state_reports_reason_until_disabled() {
+        let conn = init_in_memory_db();
+        insert_job(
+            &conn,
+            "initial_follow_up",
+            "pending",
+            "2099-01-01T00:00:00Z",
+        );
+        let state = get_kill_switch_state_with_conn(&conn).unwrap();
+        assert!(!state.enabled);
+        assert_eq!(state.reason, None);
+        assert_eq!(state.pending_jobs_count, 1);
+
+        set_kill_switch_with_conn(&conn, &RealClock, true, Some("  carrier outage  ")).unwrap();
+        let state = get_kill_switch_state_with_conn(&conn).unwrap();
+        assert!(state.enabled);
+        assert_eq!(state.reason.as_deref(), Some("carrier outage"));
+        assert!(state.enabled_at.is_some());
+        assert_eq!(state.pending_jobs_count, 0);
+
+        set_kill_switch_with_conn(&conn, &RealClock, false, None).unwrap();
+        let state = get_kill_switch_state_with_conn(&conn).unwrap();
+        assert!(!state.enabled);
+        assert_eq!(state.reason, None);
+        assert_eq!(state.enabled_at, None);
+    }
+
+    #[test]
+    fn slot_generation_uses_current_location_hours() {
+        let conn = init_in_memory_db();
+        set_business_hours(
+            &conn,
+            r#"{"mon":[["09:00","10:00"]],"tue":[],"wed":[],"thu":[],"fri":[],"sat":[],"sun":[]}"#,
+        );
+        let second = add_location_with_conn(
+            &conn,
+            &LocationSettingsInput {
+                gym_name: "Uptown Gym".to_string(),
+                timezone: "America/New_York".to_string(),
+                business_hours_json:
+                    r#"{"mon":[["15:00","16:00"]],"tue":[],"wed":[],"thu":[],"fri":[],"sat":[],"sun":[]}"#
+                        .to_string(),
             },
         )
-        .map_err(AppError::from)
-    });
+        .unwrap();
+        assert_eq!(list_locations_with_conn(&conn).unwrap().len(), 2);
 
-    map_cmd_result(result, "get_location_settings", &app)
-}
+        set_current_location_with_conn(&conn, &RealClock, second.id).unwrap();
+        let location = get_location(&conn).unwrap();
+        assert_eq!(location.id, second.id);
+        let slots = generate_slot_choices(&conn, &location, ts("2030-01-07T12:00:00Z")).unwrap();
+        assert_eq!(parse_ts(&slots[0].start_at).unwrap(), ts("2030-01-07T20:00:00Z"));
 
-#[tauri::command]
-fn update_location_settings(
-    state: State<AppState>,
-    app: AppHandle,
-    input: LocationSettingsInput,
-) -> Result<LocationSettings, String> {
-    let result = retry_db(|| {
-        let conn = open_conn(&state)?;
-        let primary_id = ensure_primary_location(&conn)?;
+        assert!(set_current_location_with_conn(&conn, &RealClock, 999).is_err());
+        assert_eq!(get_location(&conn).unwrap().id, second.id);
+    }
 
-        let gym_name = input.gym_name.trim().to_string();
-        if gym_name.is_empty() {
-            return Err(AppError::Validation(
-                "gym_name must be non-empty".to_string(),
-            ));
-        }
+    #[test]
+    fn location_rate_limit_counts_only_that_locations_leads() {
+        let conn = init_in_memory_db();
+        let clock = FakeClock(ts("2030-01-07T17:00:00Z"));
+        let first_id = get_location(&conn).unwrap().id;
+        let second = add_location_with_conn(
+            &conn,
+            &LocationSettingsInput {
+                gym_name: "Uptown Gym".to_string(),
+                timezone: "America/New_York".to_string(),
+                business_hours_json: "{}".to_string(),
+            },
+        )
+        .unwrap();
+        let lead_id = insert_lead(&conn, "+15550000082");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        conn.execute(
+            "INSERT INTO messages (conversation_id, direction, body, status, created_at)
+             VALUES (?, 'OUTBOUND', 'hi', 'sent', ?)",
+            params![
+                conversation_id,
+                (clock.now() - Duration::minutes(5)).to_rfc3339()
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            count_location_outbound_last_hour(&conn, first_id, clock.now()).unwrap(),
+            1
+        );
+        assert_eq!(
+            count_location_outbound_last_hour(&conn, second.id, clock.now()).unwrap(),
+            0
+        );
+    }
 
-        let timezone = input.timezone.trim().to_string();
-        parse_tz(&timezone)?;
+    #[test]
+    fn update_location_timezone_shifts_generated_slots() {
+        let conn = init_in_memory_db();
+        let clock = FakeClock(ts("2030-01-07T12:00:00Z"));
+        set_business_hours(
+            &conn,
+            r#"{"mon":[["09:00","10:00"]],"tue":[],"wed":[],"thu":[],"fri":[],"sat":[],"sun":[]}"#,
+        );
+        let location_id = get_location(&conn).unwrap().id;
+
+        let patch = |timezone: Option<&str>, business_hours_json: Option<&str>| LocationPatch {
+            gym_name: None,
+            timezone: timezone.map(str::to_string),
+            business_hours_json: business_hours_json.map(str::to_string),
+        };
+        let updated = update_location_with_conn(
+            &conn,
+            &clock,
+            location_id,
+            &patch(Some("America/Chicago"), None),
+            clock.now(),
+        )
+        .unwrap()
+        .location;
+        assert_eq!(updated.timezone, "America/Chicago");
+        assert_eq!(updated.gym_name, "Test Gym");
 
-        let business_hours_json = input.business_hours_json.trim().to_string();
-        parse_business_hours(&business_hours_json)?;
+        let location = get_location(&conn).unwrap();
+        let slots = generate_slot_choices(&conn, &location, clock.now()).unwrap();
+        // 09:00 CST is 15:00Z, an hour later than the same wall-clock time in New York.
+        assert_eq!(parse_ts(&slots[0].start_at).unwrap(), ts("2030-01-07T15:00:00Z"));
 
-        conn.execute(
-            "UPDATE locations SET gym_name=?, timezone=?, business_hours_json=? WHERE id=?",
-            params![gym_name, timezone, business_hours_json, primary_id],
-        )?;
+        let before_tz: String = conn
+            .query_row(
+                "SELECT json_extract(response_json, '$.before.timezone')
+                 FROM audit_log WHERE action_type='update_location' AND success=1",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(before_tz, "America/New_York");
 
-        conn.query_row(
-            "SELECT id, gym_name, timezone, business_hours_json FROM locations WHERE id=?",
-            params![primary_id],
-            |row| {
-                Ok(LocationSettings {
-                    id: row.get(0)?,
-                    gym_name: row.get(1)?,
-                    timezone: row.get(2)?,
-                    business_hours_json: row.get(3)?,
-                })
-            },
+        assert!(update_location_with_conn(
+            &conn,
+            &clock,
+            location_id,
+            &patch(Some("Mars/Base"), None),
+            clock.now()
         )
-        .map_err(AppError::from)
-    });
-
-    map_cmd_result(result, "update_location_settings", &app)
-}
+        .is_err());
+        assert!(update_location_with_conn(
+            &conn,
+            &clock,
+            location_id,
+            &patch(None, Some(r#"{"mon":[["10:00","09:00"]]}"#)),
+            clock.now()
+        )
+        .is_err());
+        assert_eq!(get_location(&conn).unwrap().timezone, "America/Chicago");
+    }
 
-#[tauri::command]
-fn set_kill_switch(state: State<AppState>, app: AppHandle, enabled: bool) -> Result<(), String> {
-    let result = retry_db(|| {
-        let conn = open_conn(&state)?;
-        let location = get_location(&conn)?;
-        let now = now_iso();
+    #[test]
+    fn list_audit_log_filters_by_action_type_newest_first() {
+        let conn = init_in_memory_db();
+        for i in 0..20 {
+            let action_type = match i % 3 {
+                0 => "set_opt_out",
+                1 => "create_outbound_message",
+                _ => "schedule_job",
+            };
+            insert_audit(
+                &conn,
+                &RealClock,
+                action_type,
+                "lead",
+                Some(i.to_string()),
+                json!({ "i": i }),
+                None,
+                i % 2 == 0,
+                None,
+            )
+            .unwrap();
+        }
 
-        conn.execute(
-            "INSERT INTO settings (key, value, updated_at)
-             VALUES ('kill_switch', ?, ?)
-             ON CONFLICT(key) DO UPDATE SET value=excluded.value, updated_at=excluded.updated_at",
-            params![if enabled { "true" } else { "false" }, now],
-        )?;
+        let page =
+            list_audit_log_with_conn(&conn, Some("set_opt_out"), None, None, None, None, None)
+                .unwrap();
+        let target_ids: Vec<&str> = page
+            .entries
+            .iter()
+            .map(|entry| entry.target_id.as_deref().unwrap())
+            .collect();
+        assert_eq!(target_ids, ["18", "15", "12", "9", "6", "3", "0"]);
+        assert!(page
+            .entries
+            .iter()
+            .all(|entry| entry.action_type == "set_opt_out"));
+        assert_eq!(page.next_cursor, None);
+
+        let first =
+            list_audit_log_with_conn(&conn, None, None, Some(true), None, Some(4), None).unwrap();
+        assert_eq!(first.entries.len(), 4);
+        assert!(first.entries.iter().all(|entry| entry.success));
+        let second = list_audit_log_with_conn(
+            &conn,
+            None,
+            None,
+            Some(true),
+            None,
+            Some(4),
+            first.next_cursor,
+        )
+        .unwrap();
+        assert!(second.entries[0].id < first.entries[3].id);
+        assert!(list_audit_log_with_conn(&conn, None, None, None, None, Some(0), None).is_err());
+    }
 
-        let _ = insert_audit(
+    #[test]
+    fn export_audit_log_csv_encodes_json_columns() {
+        let conn = init_in_memory_db();
+        insert_audit(
             &conn,
-            "set_kill_switch",
-            "settings",
-            Some("kill_switch".to_string()),
-            json!({ "enabled": enabled }),
-            Some(json!({ "updated_at": now })),
+            &RealClock,
+            "set_opt_out",
+            "lead",
+            Some("7".to_string()),
+            json!({ "reason": "said \"stop\", twice" }),
+            Some(json!({ "ok": true })),
+            false,
+            Some("blocked, already opted out".to_string()),
+        )
+        .unwrap();
+        insert_audit(
+            &conn,
+            &RealClock,
+            "schedule_job",
+            "scheduled_job",
+            None,
+            json!({}),
+            None,
             true,
             None,
+        )
+        .unwrap();
+
+        let csv = export_audit_log_csv_with_conn(&conn, None, None).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "id,action_type,target_type,target_id,success,error_message,created_at,request_json,response_json"
         );
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].contains(",\"blocked, already opted out\","));
 
-        if enabled {
-            let gateway = ActionGateway::new(&conn, &location);
-            gateway.cancel_jobs_on_kill_switch()?;
-        }
+        let columns: Vec<&str> = lines[1].rsplitn(3, ',').collect();
+        let response = String::from_utf8(BASE64.decode(columns[0]).unwrap()).unwrap();
+        let request = String::from_utf8(BASE64.decode(columns[1]).unwrap()).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&request).unwrap(),
+            json!({ "reason": "said \"stop\", twice" })
+        );
+        assert_eq!(
+            serde_json::from_str::<Value>(&response).unwrap(),
+            json!({ "ok": true })
+        );
+        assert!(lines[2].ends_with(','));
 
-        Ok(())
-    });
+        assert!(export_audit_log_csv_with_conn(
+            &conn,
+            Some("2030-01-02T00:00:00Z"),
+            Some("2030-01-01T00:00:00Z")
+        )
+        .is_err());
+        let empty =
+            export_audit_log_csv_with_conn(&conn, Some("2999-01-01T00:00:00Z"), None).unwrap();
+        assert_eq!(empty.lines().count(), 1);
+    }
 
-    map_cmd_result(result, "set_kill_switch", &app)
-}
+    #[test]
+    fn purge_old_audit_logs_removes_entries_past_retention() {
+        let conn = init_in_memory_db();
+        // 29-hour spacing spreads 100 rows over ~120 days; rows 75..99 are older than 90 days.
+        for i in 0..100 {
+            conn.execute(
+                "INSERT INTO audit_log (action_type, target_type, request_json, success, created_at)
+                 VALUES ('seed', 'test', ?, 1, ?)",
+                params![
+                    json!({ "i": i }).to_string(),
+                    (Utc::now() - Duration::hours(i * 29)).to_rfc3339()
+                ],
+            )
+            .unwrap();
+        }
 
-#[tauri::command]
-fn export_db_path(state: State<AppState>, app: AppHandle) -> Result<String, String> {
-    let result = (|| -> AppResult<String> {
-        let path = if state.db_path.is_absolute() {
-            state.db_path.clone()
-        } else {
-            std::env::current_dir()
-                .map_err(|err| AppError::Validation(err.to_string()))?
-                .join(&state.db_path)
+        let preview = purge_old_audit_logs_with_conn(&conn, &RealClock, true, Utc::now()).unwrap();
+        assert_eq!(preview.rows_affected, 25);
+        let count = |conn: &Connection| -> i64 {
+            conn.query_row(
+                "SELECT COUNT(*) FROM audit_log WHERE action_type='seed'",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap()
         };
-        Ok(path.to_string_lossy().to_string())
-    })();
-
-    map_cmd_result(result, "export_db_path", &app)
-}
-
-#[tauri::command]
-fn wipe_all_data_confirmed(
-    state: State<AppState>,
-    app: AppHandle,
-) -> Result<WipeAllDataResult, String> {
-    let result = retry_db(|| {
-        let mut conn = open_conn(&state)?;
-        let tx = conn.transaction()?;
+        assert_eq!(count(&conn), 100);
 
-        let messages = tx.execute("DELETE FROM messages", params![])? as i64;
-        let appointments = tx.execute("DELETE FROM appointments", params![])? as i64;
-        let scheduled_jobs = tx.execute("DELETE FROM scheduled_jobs", params![])? as i64;
-        let audit_log = tx.execute("DELETE FROM audit_log", params![])? as i64;
-        let conversations = tx.execute("DELETE FROM conversations", params![])? as i64;
-        let leads = tx.execute("DELETE FROM leads", params![])? as i64;
+        let result = purge_old_audit_logs_with_conn(&conn, &RealClock, false, Utc::now()).unwrap();
+        assert_eq!(result.rows_affected, 25);
+        assert_eq!(result.oldest_kept, preview.oldest_kept);
+        assert_eq!(count(&conn), 75);
+        let max_i: i64 = conn
+            .query_row(
+                "SELECT MAX(json_extract(request_json, '$.i')) FROM audit_log
+                 WHERE action_type='seed'",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(max_i, 74);
+    }
 
-        let counts = WipeAllDataResult {
-            messages,
-            appointments,
-            scheduled_jobs,
-            audit_log,
-            conversations,
-            leads,
-        };
+    #[test]
+    fn today_report_counts_the_requested_local_day() {
+        let conn = init_in_memory_db();
+        // The test location is America/New_York, so 2030-01-05 local is 05:00Z..05:00Z next day.
+        let mut leads = Vec::new();
+        for (phone, created_at) in [
+            ("+15550000083", "2030-01-05T04:30:00Z"),
+            ("+15550000084", "2030-01-05T05:30:00Z"),
+            ("+15550000085", "2030-01-06T04:59:00Z"),
+            ("+15550000086", "2030-01-06T05:00:00Z"),
+        ] {
+            let lead_id = insert_lead(&conn, phone);
+            conn.execute(
+                "UPDATE leads SET created_at=? WHERE id=?",
+                params![created_at, lead_id],
+            )
+            .unwrap();
+            leads.push(lead_id);
+        }
+        let conversation_id = insert_conversation(&conn, leads[1]);
+        conn.execute(
+            "INSERT INTO messages (conversation_id, direction, body, status, created_at)
+             VALUES (?, 'OUTBOUND', 'hi', 'sent', '2030-01-05T15:00:00Z')",
+            params![conversation_id],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO appointments (lead_id, start_at, end_at, status, created_at)
+             VALUES (?, '2030-01-07T15:00:00Z', '2030-01-07T15:30:00Z', 'booked', '2030-01-05T16:00:00Z')",
+            params![leads[1]],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO audit_log (action_type, target_type, request_json, success, created_at)
+             VALUES ('set_opt_out', 'lead', '{}', 1, '2030-01-06T03:00:00Z')",
+            params![],
+        )
+        .unwrap();
+
+        let report = get_today_report_with_conn(&conn, Some("2030-01-05"), Utc::now()).unwrap();
+        assert_eq!(report.leads_created, 2);
+        assert_eq!(report.contacted, 1);
+        assert_eq!(report.booked, 1);
+        assert_eq!(report.opt_outs, 1);
+
+        let report = get_today_report_with_conn(&conn, Some("2030-01-04"), Utc::now()).unwrap();
+        assert_eq!(report.leads_created, 1);
+        assert_eq!(report.contacted, 0);
+        assert!(get_today_report_with_conn(&conn, Some("01/05/2030"), Utc::now()).is_err());
+    }
 
-        tx.execute(
-            "INSERT INTO audit_log (action_type, target_type, target_id, request_json, response_json, success, error_message, created_at)
-             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
-            params![
-                "wipe_all_data_confirmed",
-                "system",
-                Option::<String>::None,
-                "{}",
-                Some(serde_json::to_string(&counts)?),
-                1,
-                Option::<String>::None,
-                now_iso()
-            ],
-        )?;
+    #[test]
+    fn range_report_breaks_counts_down_by_day() {
+        let conn = init_in_memory_db();
+        for (phone, created_at) in [
+            ("+15550000087", "2030-02-01T15:00:00Z"),
+            ("+15550000088", "2030-02-03T15:00:00Z"),
+            ("+15550000089", "2030-02-03T18:00:00Z"),
+        ] {
+            let lead_id = insert_lead(&conn, phone);
+            conn.execute(
+                "UPDATE leads SET created_at=? WHERE id=?",
+                params![created_at, lead_id],
+            )
+            .unwrap();
+        }
 
-        tx.commit()?;
-        Ok(counts)
-    });
+        let reports = get_range_report_with_conn(&conn, "2030-02-01", "2030-02-03").unwrap();
+        let days: Vec<(&str, i64)> = reports
+            .iter()
+            .map(|day| (day.date.as_str(), day.report.leads_created))
+            .collect();
+        assert_eq!(
+            days,
+            [("2030-02-01", 1), ("2030-02-02", 0), ("2030-02-03", 2)]
+        );
 
-    map_cmd_result(result, "wipe_all_data_confirmed", &app)
-}
+        assert_eq!(
+            get_range_report_with_conn(&conn, "2030-01-01", "2030-03-31")
+                .unwrap()
+                .len(),
+            90
+        );
+        assert!(get_range_report_with_conn(&conn, "2030-01-01", "2030-04-01").is_err());
+        assert!(get_range_report_with_conn(&conn, "2030-02-03", "2030-02-01").is_err());
+    }
 
-#[tauri::command]
-fn run_due_jobs(state: State<AppState>, app: AppHandle) -> Result<RunJobsResult, String> {
-    let result = retry_db(|| {
-        let conn = open_conn(&state)?;
-        run_due_jobs_with_conn(&conn)
-    });
+    #[test]
+    fn funnel_report_tracks_cohort_drop_off() {
+        let conn = init_in_memory_db();
+        let leads: Vec<i64> = (90..100)
+            .map(|n| insert_lead(&conn, &format!("+155500000{n}")))
+            .collect();
+        for (i, lead_id) in leads.iter().enumerate() {
+            let conversation_id = insert_conversation(&conn, *lead_id);
+            if i < 8 {
+                conn.execute(
+                    "INSERT INTO messages (conversation_id, direction, body, status, created_at)
+                     VALUES (?, 'OUTBOUND', 'hi', 'sent', ?)",
+                    params![conversation_id, now_iso()],
+                )
+                .unwrap();
+            }
+            if i < 5 {
+                set_lead_status(&conn, &RealClock, *lead_id, "awaiting_time_choice", "test")
+                    .unwrap();
+            }
+            if i < 3 {
+                insert_booked_appointment(
+                    &conn,
+                    *lead_id,
+                    "2030-01-08T15:00:00Z",
+                    "2030-01-08T15:30:00Z",
+                );
+                set_lead_status(&conn, &RealClock, *lead_id, "booked", "test").unwrap();
+            }
+        }
+        // Said yes, then went quiet and was reset by the expiry sweep.
+        set_lead_status(
+            &conn,
+            &RealClock,
+            leads[4],
+            "awaiting_yes",
+            "conversation_expired",
+        )
+        .unwrap();
+        conn.execute("UPDATE leads SET opted_out=1 WHERE id=?", params![leads[9]])
+            .unwrap();
+
+        let funnel = get_funnel_report_with_conn(&conn, None).unwrap();
+        assert_eq!(funnel.leads_created, 10);
+        assert_eq!(funnel.leads_contacted, 8);
+        assert_eq!(funnel.leads_responded_yes, 5);
+        assert_eq!(funnel.leads_booked, 3);
+        assert_eq!(funnel.leads_opted_out, 1);
+        assert!((funnel.conversion_rate_pct - 30.0).abs() < f64::EPSILON);
+
+        let empty = get_funnel_report_with_conn(&conn, Some("2031-01-01T00:00:00Z")).unwrap();
+        assert_eq!(empty.leads_created, 0);
+        assert_eq!(empty.conversion_rate_pct, 0.0);
+    }
 
-    map_cmd_result(result, "run_due_jobs", &app)
-}
+    #[test]
+    fn source_report_groups_by_consent_source() {
+        let conn = init_in_memory_db();
+        let sources = [
+            Some("web_form"),
+            Some("web_form"),
+            Some("web_form"),
+            Some("walk_in"),
+            Some("walk_in"),
+            None,
+        ];
+        let leads: Vec<i64> = sources
+            .iter()
+            .enumerate()
+            .map(|(i, source)| {
+                let lead_id = insert_lead(&conn, &format!("+1555000010{i}"));
+                conn.execute(
+                    "UPDATE leads SET consent_source=? WHERE id=?",
+                    params![source, lead_id],
+                )
+                .unwrap();
+                lead_id
+            })
+            .collect();
+        for lead_id in [leads[0], leads[3]] {
+            insert_booked_appointment(
+                &conn,
+                lead_id,
+                "2030-01-08T15:00:00Z",
+                "2030-01-08T15:30:00Z",
+            );
+        }
+        for lead_id in [leads[1], leads[2]] {
+            conn.execute("UPDATE leads SET opted_out=1 WHERE id=?", params![lead_id])
+                .unwrap();
+        }
 
-#[tauri::command]
-fn agent_dry_run(
-    state: State<AppState>,
-    app: AppHandle,
-    req: AgentDryRunRequest,
-) -> Result<AgentDryRunResult, String> {
-    let result = retry_db(|| {
-        let conn = open_conn(&state)?;
-        let location = get_location(&conn)?;
-        let gateway = ActionGateway::new(&conn, &location);
+        let report = get_source_report_with_conn(&conn, None).unwrap();
+        let rows: Vec<(Option<&str>, i64, i64, i64)> = report
+            .iter()
+            .map(|row| (row.source.as_deref(), row.leads, row.booked, row.opted_out))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (Some("walk_in"), 2, 1, 0),
+                (Some("web_form"), 3, 1, 2),
+                (None, 1, 0, 0)
+            ]
+        );
+        assert!((report[0].booking_rate_pct - 50.0).abs() < 1e-9);
+        assert!((report[1].booking_rate_pct - 100.0 / 3.0).abs() < 1e-9);
+    }
 
-        let validation = match &req.action {
-            AgentAction::SendOutbound {
-                lead_id,
-                conversation_id,
-                body,
-                automated,
-                allow_without_consent,
-                allow_opted_out_once,
-                allow_after_reply,
-                ignore_business_hours,
-            } => gateway.validate_agent_outbound(&OutboundRequest {
-                lead_id: *lead_id,
-                conversation_id: *conversation_id,
-                body: body.clone(),
-                automated: *automated,
-                allow_without_consent: *allow_without_consent,
-                allow_opted_out_once: *allow_opted_out_once,
-                allow_after_reply: *allow_after_reply,
-                ignore_business_hours: *ignore_business_hours,
-            }),
-            AgentAction::BookAppointment {
-                lead_id,
-                start_at,
-                end_at,
-                status,
-            } => gateway.validate_appointment(&AppointmentRequest {
-                lead_id: *lead_id,
-                start_at: start_at.clone(),
-                end_at: end_at.clone(),
-                status: status.clone(),
-            }),
-            AgentAction::SetOptOut { lead_id, reason } => {
-                gateway.validate_opt_out(&OptOutRequest {
-                    lead_id: *lead_id,
-                    reason: reason.clone(),
-                })
-            }
-            AgentAction::ScheduleJob {
-                job_type,
-                target_id,
-                execute_at,
-                payload_json,
-            } => gateway.validate_schedule_job(&ScheduleJobRequest {
-                job_type: job_type.clone(),
-                target_id: *target_id,
-                execute_at: execute_at.clone(),
-                payload_json: payload_json.clone(),
-            }),
-        };
+    #[test]
+    fn set_setting_applies_each_keys_own_rules() {
+        let conn = init_in_memory_db();
 
-        let normalized = Some(serde_json::to_value(&req.action)?);
-        let response = match validation {
-            Ok(()) => AgentDryRunResult {
-                allowed: true,
-                blocked_reason: None,
-                warnings: Vec::new(),
-                normalized,
-            },
-            Err(err) => AgentDryRunResult {
-                allowed: false,
-                blocked_reason: Some(err.to_string()),
-                warnings: Vec::new(),
-                normalized,
-            },
-        };
+        assert!(set_setting_with_conn(&conn, &RealClock, "stop_keywords", r#"["quit"]"#).is_err());
+        assert!(
+            set_setting_with_conn(&conn, &RealClock, "stop_keywords", r#"{"STOP":1}"#).is_err()
+        );
+        set_setting_with_conn(&conn, &RealClock, "stop_keywords", r#"["QUIT"]"#).unwrap();
+        let keywords = get_stop_keywords_with_conn(&conn).unwrap();
+        assert!(keywords.contains(&"QUIT".to_string()));
+        assert!(keywords.contains(&"STOP".to_string()));
+
+        for (key, value) in [
+            ("max_outbound_per_lead_per_day", "21"),
+            ("max_outbound_per_lead_per_day", "0"),
+            ("max_outbound_per_location_per_hour", "-1"),
+            ("audit_log_retention_days", "0"),
+            ("conversation_expiry_hours", "-5"),
+            ("auto_kill_switch_threshold", "-1"),
+        ] {
+            assert!(
+                set_setting_with_conn(&conn, &RealClock, key, value).is_err(),
+                "{key}={value}"
+            );
+            assert_eq!(read_setting(&conn, key).unwrap(), None, "{key}={value}");
+        }
 
-        let _ = insert_audit(
+        set_setting_with_conn(
             &conn,
-            "agent_dry_run",
-            "agent_action",
-            Some(req.action.action_type().as_str().to_string()),
-            serde_json::to_value(&req)?,
-            Some(json!({
-                "allowed": response.allowed,
-                "blocked_reason": response.blocked_reason,
-                "warnings": response.warnings
-            })),
-            response.allowed,
-            response.blocked_reason.clone(),
+            &RealClock,
+            "max_outbound_per_location_per_hour",
+            "50",
+        )
+        .unwrap();
+        let limits = load_rate_limit_settings(&conn).unwrap();
+        assert_eq!(limits.max_outbound_per_location_per_hour, 50);
+        assert_eq!(limits.max_outbound_per_lead_per_day, 4);
+    }
+
+    #[test]
+    fn set_setting_validates_type_and_allowlist() {
+        let conn = init_in_memory_db();
+
+        let err = set_setting_with_conn(&conn, &RealClock, "conversation_expiry_hours", "soon")
+            .unwrap_err();
+        assert!(err.to_string().contains("must be an integer"));
+        assert_eq!(
+            read_setting(&conn, "conversation_expiry_hours").unwrap(),
+            None
         );
+        assert!(set_setting_with_conn(&conn, &RealClock, "current_location_id", "2").is_err());
+        assert!(set_setting_with_conn(&conn, &RealClock, "stop_keywords", "[\"STOP\"").is_err());
+
+        set_setting_with_conn(&conn, &RealClock, "conversation_expiry_hours", " 72 ").unwrap();
+        assert_eq!(get_conversation_expiry_hours_with_conn(&conn).unwrap(), 72);
+        set_setting_with_conn(&conn, &RealClock, "kill_switch", "1").unwrap();
+        assert!(is_kill_switch_enabled(&conn).unwrap());
+
+        let keys: Vec<String> = get_settings_with_conn(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|setting| setting.key)
+            .collect();
+        assert!(keys.contains(&"conversation_expiry_hours".to_string()));
+        let failures: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM audit_log WHERE action_type='set_setting' AND success=0",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(failures, 3);
+    }
 
-        Ok(response)
-    });
+    #[test]
+    fn check_db_integrity_reports_ok_for_healthy_db() {
+        let conn = init_in_memory_db();
+        insert_lead(&conn, "+15550000110");
 
-    map_cmd_result(result, "agent_dry_run", &app)
-}
+        let result = check_db_integrity_with_conn(&conn).unwrap();
+        assert!(result.ok);
+        assert_eq!(result.messages, ["ok"]);
+    }
 
-#[tauri::command]
-fn agent_execute(
-    state: State<AppState>,
-    app: AppHandle,
-    req: AgentExecuteRequest,
-) -> Result<AgentExecuteResult, String> {
-    let result = retry_db(|| {
-        let conn = open_conn(&state)?;
-        let location = get_location(&conn)?;
-        let gateway = ActionGateway::new(&conn, &location);
+    #[test]
+    fn backup_db_copies_live_database_inside_app_dir() {
+        let conn = init_in_memory_db();
+        insert_lead(&conn, "+15550000111");
+        insert_lead(&conn, "+15550000112");
+        let root = std::env::temp_dir().join(format!("goldbot-backup-test-{}", std::process::id()));
+        fs::create_dir_all(root.join("backups")).unwrap();
+
+        let result =
+            backup_db_with_conn(&conn, &RealClock, &root, "backups/goldbot.sqlite").unwrap();
+        assert!(result.pages_copied > 0);
+        assert!(result.size_bytes > 0);
+
+        let backup = Connection::open(&result.destination_path).unwrap();
+        let leads: i64 = backup
+            .query_row("SELECT COUNT(*) FROM leads", params![], |row| row.get(0))
+            .unwrap();
+        assert_eq!(leads, 2);
+
+        let outside = std::env::temp_dir().join("goldbot-outside.sqlite");
+        assert!(backup_db_with_conn(&conn, &RealClock, &root, outside.to_str().unwrap()).is_err());
+        assert!(backup_db_with_conn(&conn, &RealClock, &root, "../escape.sqlite").is_err());
+
+        drop(backup);
+        fs::remove_dir_all(&root).unwrap();
+    }
 
-        let execution = match &req.action {
-            AgentAction::SendOutbound {
-                lead_id,
-                conversation_id,
-                body,
-                automated,
-                allow_without_consent,
-                allow_opted_out_once,
-                allow_after_reply,
-                ignore_business_hours,
-            } => gateway
-                .create_outbound_message_for_agent(OutboundRequest {
-                    lead_id: *lead_id,
-                    conversation_id: *conversation_id,
-                    body: body.clone(),
-                    automated: *automated,
-                    allow_without_consent: *allow_without_consent,
-                    allow_opted_out_once: *allow_opted_out_once,
-                    allow_after_reply: *allow_after_reply,
-                    ignore_business_hours: *ignore_business_hours,
-                })
-                .map(|message_id| json!({ "message_id": message_id })),
-            AgentAction::BookAppointment {
-                lead_id,
-                start_at,
-                end_at,
-                status,
-            } => gateway
-                .create_appointment(AppointmentRequest {
-                    lead_id: *lead_id,
-                    start_at: start_at.clone(),
-                    end_at: end_at.clone(),
-                    status: status.clone(),
-                })
-                .map(|appointment_id| json!({ "appointment_id": appointment_id })),
-            AgentAction::SetOptOut { lead_id, reason } => gateway
-                .set_opt_out(OptOutRequest {
-                    lead_id: *lead_id,
-                    reason: reason.clone(),
-                })
-                .map(|_| json!({ "result": "opted_out" })),
-            AgentAction::ScheduleJob {
-                job_type,
-                target_id,
-                execute_at,
-                payload_json,
-            } => gateway
-                .schedule_job(ScheduleJobRequest {
-                    job_type: job_type.clone(),
-                    target_id: *target_id,
-                    execute_at: execute_at.clone(),
-                    payload_json: payload_json.clone(),
-                })
-                .map(|job_id| json!({ "job_id": job_id })),
-        };
+    #[test]
+    fn vacuum_db_reclaims_space_after_deletes() {
+        let conn = init_in_memory_db();
+        let filler = "x".repeat(2000);
+        for i in 0..200 {
+            insert_audit(
+                &conn,
+                &RealClock,
+                "seed",
+                "test",
+                None,
+                json!({ "i": i, "filler": filler }),
+                None,
+                true,
+                None,
+            )
+            .unwrap();
+        }
+        conn.execute("DELETE FROM audit_log WHERE action_type='seed'", params![])
+            .unwrap();
 
-        let response = match execution {
-            Ok(result_json) => AgentExecuteResult {
-                success: true,
-                result_json: Some(result_json),
-                error: None,
-            },
-            Err(err) => AgentExecuteResult {
-                success: false,
-                result_json: None,
-                error: Some(err.to_string()),
-            },
+        let result = vacuum_db_with_conn(&conn, &RealClock).unwrap();
+        assert!(result.size_after_bytes < result.size_before_bytes);
+
+        set_kill_switch_with_conn(&conn, &RealClock, true, None).unwrap();
+        assert!(vacuum_db_with_conn(&conn, &RealClock).is_err());
+    }
+
+    #[test]
+    fn checkpoint_db_validates_mode_and_reports_frames() {
+        let dir = std::env::temp_dir().join(format!("goldbot-wal-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("goldbot.sqlite");
+        initialize_db(&db_path, &dir.join("config.toml"), &RealClock).unwrap();
+        let pool = build_pool(&db_path).unwrap();
+        let conn = pool.get().unwrap();
+        insert_lead(&conn, "+15550000113");
+
+        assert!(checkpoint_db_with_conn(&conn, &RealClock, "truncate").is_err());
+        for mode in ["passive", "FULL", "restart"] {
+            let result = checkpoint_db_with_conn(&conn, &RealClock, mode).unwrap();
+            assert!(result.busy_frames >= 0);
+            assert!(result.log_frames >= 0);
+            assert!(result.checkpointed_frames >= 0);
+        }
+
+        drop((conn, pool));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pool_hands_out_concurrent_configured_connections() {
+        let dir = std::env::temp_dir().join(format!("goldbot-pool-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("goldbot.sqlite");
+        initialize_db(&db_path, &dir.join("config.toml"), &RealClock).unwrap();
+        let pool = build_pool(&db_path).unwrap();
+
+        let first = pool.get().unwrap();
+        let second = pool.get().unwrap();
+        insert_lead(&first, "+15550000114");
+        let leads: i64 = second
+            .query_row("SELECT COUNT(*) FROM leads", params![], |row| row.get(0))
+            .unwrap();
+        assert_eq!(leads, 1);
+        insert_lead(&second, "+15550000115");
+        for conn in [&first, &second] {
+            let foreign_keys: i64 = conn
+                .pragma_query_value(None, "foreign_keys", |row| row.get(0))
+                .unwrap();
+            assert_eq!(foreign_keys, 1);
+        }
+
+        drop((first, second, pool));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn command_errors_carry_specific_codes() {
+        let conn = init_in_memory_db();
+        let noon = Utc.with_ymd_and_hms(2030, 1, 2, 17, 0, 0).unwrap();
+        let clock = FakeClock(noon);
+        let gateway = ActionGateway::with_clock(&conn, &clock);
+        let lead_id = insert_lead(&conn, "+15550000116");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let request = OutboundRequest {
+            lead_id,
+            conversation_id,
+            body: "Checking in".to_string(),
+            automated: true,
+            allow_without_consent: false,
+            allow_opted_out_once: false,
+            allow_after_reply: false,
+            ignore_business_hours: true,
+            rate_limit_exempt: false,
+        };
+        let code_at = |req: &OutboundRequest, now: DateTime<Utc>| {
+            gateway.validate_outbound_at(req, now).unwrap_err().code()
         };
 
-        Ok(response)
-    });
+        conn.execute("UPDATE leads SET consent=0 WHERE id=?", params![lead_id])
+            .unwrap();
+        assert_eq!(code_at(&request, noon), ErrorCode::ConsentRequired);
+        conn.execute(
+            "UPDATE leads SET consent=1, opted_out=1 WHERE id=?",
+            params![lead_id],
+        )
+        .unwrap();
+        assert_eq!(code_at(&request, noon), ErrorCode::OptedOut);
+        conn.execute("UPDATE leads SET opted_out=0 WHERE id=?", params![lead_id])
+            .unwrap();
 
-    map_cmd_result(result, "agent_execute", &app)
-}
+        set_tcpa_mode_with_conn(&conn, &clock, true).unwrap();
+        let night = Utc.with_ymd_and_hms(2030, 1, 2, 7, 0, 0).unwrap();
+        assert_eq!(code_at(&request, night), ErrorCode::BusinessHours);
 
-#[tauri::command]
-fn log_client_error(
-    _state: State<AppState>,
-    app: AppHandle,
-    message: String,
-    stack: Option<String>,
-    source: String,
-) -> Result<(), String> {
-    let app_dir = ensure_app_data_dir(&app)?;
-    let log_path = app_dir.join("client_errors.log");
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&log_path)
-        .map_err(|err| format!("failed to open {}: {err}", log_path.display()))?;
-
-    writeln!(file, "timestamp: {}", now_iso())
-        .map_err(|err| format!("failed to write client error timestamp: {err}"))?;
-    writeln!(file, "source: {}", source)
-        .map_err(|err| format!("failed to write client error source: {err}"))?;
-    writeln!(file, "message: {}", message)
-        .map_err(|err| format!("failed to write client error message: {err}"))?;
-    if let Some(stack_text) = stack.filter(|s| !s.trim().is_empty()) {
-        writeln!(file, "stack:")
-            .map_err(|err| format!("failed to write client error stack header: {err}"))?;
-        writeln!(file, "{stack_text}")
-            .map_err(|err| format!("failed to write client error stack: {err}"))?;
-    }
-    writeln!(file).map_err(|err| format!("failed to finish client error log line: {err}"))?;
+        set_rate_limit_settings_with_conn(&conn, &clock, 1, 100).unwrap();
+        gateway.create_outbound_message(request.clone()).unwrap();
+        conn.execute(
+            "UPDATE conversations SET last_outbound_at=NULL WHERE id=?",
+            params![conversation_id],
+        )
+        .unwrap();
+        assert_eq!(code_at(&request, noon), ErrorCode::RateLimit);
+
+        set_kill_switch_with_conn(&conn, &clock, true, None).unwrap();
+        assert_eq!(code_at(&request, noon), ErrorCode::KillSwitch);
+
+        let err = get_lead(&conn, 9_999).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::NotFound);
+        let err = add_lead_note_with_conn(&conn, &clock, lead_id, "   ", None).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::ValidationFailed);
+
+        let response: Value =
+            serde_json::from_str(&error_response_json(&err, "add_lead_note")).unwrap();
+        assert_eq!(response["code"], "validation_failed");
+        assert_eq!(response["message"], err.to_string());
+        assert_eq!(response["detail"]["action"], "add_lead_note");
+    }
 
-    Ok(())
-}
+    #[test]
+    fn list_leads_paginated_reports_total_and_has_more() {
+        let conn = init_in_memory_db();
+        for phone in ["+15550000117", "+15550000118", "+15550000119"] {
+            insert_lead(&conn, phone);
+        }
 
-#[tauri::command]
-fn open_devtools(app: AppHandle) -> Result<(), String> {
-    let window = app
-        .get_window("main")
-        .ok_or_else(|| "main window not found".to_string())?;
-    window.open_devtools();
-    Ok(())
-}
+        assert!(list_leads_paginated_with_conn(&conn, 0, 0).is_err());
 
-fn execute_initial_follow_up(
-    conn: &Connection,
-    location: &Location,
-    lead_id: i64,
-) -> AppResult<()> {
-    let lead = get_lead(conn, lead_id)?;
-    let conversation = get_conversation_by_lead_id(conn, lead_id)?;
-    let gateway = ActionGateway::new(conn, location);
+        let first = list_leads_paginated_with_conn(&conn, 2, 0).unwrap();
+        assert_eq!(first.total, 3);
+        assert_eq!(first.leads.len(), 2);
+        assert!(first.has_more);
 
-    let display_name = lead
-        .first_name
-        .clone()
-        .unwrap_or_else(|| "there".to_string());
+        let second = list_leads_paginated_with_conn(&conn, 2, 2).unwrap();
+        assert_eq!(second.leads.len(), 1);
+        assert!(!second.has_more);
+        assert!(first.leads.iter().all(|lead| lead.id != second.leads[0].id));
 
-    gateway.create_outbound_message(OutboundRequest {
-        lead_id,
-        conversation_id: conversation.id,
-        body: format!(
-            "Hi {display_name}, this is {}. Reply YES to see two available intro session times.",
-            location.gym_name
-        ),
-        automated: true,
-        allow_without_consent: false,
-        allow_opted_out_once: false,
-        allow_after_reply: false,
-        ignore_business_hours: false,
-    })?;
+        let beyond = list_leads_paginated_with_conn(&conn, 2, 10).unwrap();
+        assert!(beyond.leads.is_empty());
+        assert_eq!(beyond.total, 3);
+        assert!(!beyond.has_more);
+    }
 
-    conn.execute(
-        "UPDATE leads SET next_action_at=NULL WHERE id=?",
-        params![lead_id],
-    )?;
-    Ok(())
-}
+    #[derive(Clone, Default)]
+    struct CapturedLog(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
 
-fn execute_appointment_reminder(
-    conn: &Connection,
-    location: &Location,
-    payload: ReminderPayload,
-) -> AppResult<()> {
-    let lead = get_lead(conn, payload.lead_id)?;
-    let conversation = get_conversation_by_lead_id(conn, payload.lead_id)?;
-    let gateway = ActionGateway::new(conn, location);
+    impl std::io::Write for CapturedLog {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
 
-    let local_start = local_display(location, &payload.start_at)?;
-    let display_name = lead
-        .first_name
-        .clone()
-        .unwrap_or_else(|| "there".to_string());
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
 
-    gateway.create_outbound_message(OutboundRequest {
-        lead_id: payload.lead_id,
-        conversation_id: conversation.id,
-        body: format!(
-            "Reminder {display_name}: your gym appointment is at {local_start}. Reply STOP to opt out."
-        ),
-        automated: true,
-        allow_without_consent: false,
-        allow_opted_out_once: false,
-        allow_after_reply: false,
-        ignore_business_hours: false,
-    })?;
+    #[test]
+    fn run_due_jobs_emits_tracing_events() {
+        let conn = init_in_memory_db();
+        let log = CapturedLog::default();
+        let writer = log.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_writer(move || writer.clone())
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            run_due_jobs_with_conn(&conn, &RealClock).unwrap();
+        });
 
-    Ok(())
-}
+        let output = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+        let line = output
+            .lines()
+            .find(|line| line.contains("due jobs run"))
+            .expect("no tracing entry for run_due_jobs");
+        let entry: Value = serde_json::from_str(line).unwrap();
+        assert_eq!(entry["level"], "INFO");
+        assert_eq!(entry["span"]["name"], "run_due_jobs_with_conn");
+    }
 
-fn process_inbound_state_machine(
-    conn: &Connection,
-    location: &Location,
-    lead: &LeadRow,
-    conversation: &ConversationRow,
-    inbound_body: &str,
-) -> AppResult<()> {
-    let gateway = ActionGateway::new(conn, location);
-    let normalized = inbound_body.trim().to_ascii_uppercase();
-    let now = Utc::now();
+    #[test]
+    fn list_leads_by_status_filters_and_validates() {
+        let conn = init_in_memory_db();
+        let waiting = insert_lead(&conn, "+15550000120");
+        let booked = insert_lead(&conn, "+15550000121");
+        let opted_out = insert_lead(&conn, "+15550000122");
+        conn.execute(
+            "UPDATE leads SET status='booked' WHERE id=?",
+            params![booked],
+        )
+        .unwrap();
+        conn.execute(
+            "UPDATE leads SET status='opted_out' WHERE id=?",
+            params![opted_out],
+        )
+        .unwrap();
 
-    if normalized == "STOP" || normalized == "UNSUBSCRIBE" {
-        gateway.set_opt_out(OptOutRequest {
-            lead_id: lead.id,
-            reason: "lead sent stop keyword".to_string(),
-        })?;
+        let all = list_leads_by_status_with_conn(&conn, &[]).unwrap();
+        assert_eq!(all.len(), 3);
 
-        gateway.create_outbound_message(OutboundRequest {
-            lead_id: lead.id,
-            conversation_id: conversation.id,
-            body: "You are unsubscribed and will receive no more automated messages.".to_string(),
-            automated: false,
-            allow_without_consent: true,
-            allow_opted_out_once: true,
-            allow_after_reply: true,
-            ignore_business_hours: true,
-        })?;
+        let filtered =
+            list_leads_by_status_with_conn(&conn, &["booked".to_string(), "opted_out".to_string()])
+                .unwrap();
+        let ids: HashSet<i64> = filtered.iter().map(|lead| lead.id).collect();
+        assert_eq!(ids, HashSet::from([booked, opted_out]));
+        assert!(!ids.contains(&waiting));
+
+        let err = list_leads_by_status_with_conn(&conn, &["archived".to_string()]).unwrap_err();
+        assert!(matches!(err, AppError::Validation(_)), "{err}");
+    }
+
+    #[test]
+    fn initialize_db_seeds_location_and_settings_from_config() {
+        let dir = std::env::temp_dir().join(format!("goldbot-config-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.toml");
+        fs::write(
+            &config_path,
+            r#"
+[location]
+gym_name = "Iron Temple"
+timezone = "America/Chicago"
+
+[settings]
+conversation_expiry_hours = 72
+kill_switch = false
+"#,
+        )
+        .unwrap();
+        let db_path = dir.join("goldbot.sqlite");
+        initialize_db(&db_path, &config_path, &RealClock).unwrap();
+
+        let conn = Connection::open(&db_path).unwrap();
+        let location = get_location(&conn).unwrap();
+        assert_eq!(location.gym_name, "Iron Temple");
+        assert_eq!(location.timezone, "America/Chicago");
+        assert_eq!(
+            read_setting(&conn, "conversation_expiry_hours")
+                .unwrap()
+                .as_deref(),
+            Some("72")
+        );
+        assert!(!is_kill_switch_enabled(&conn).unwrap());
+
+        assert!(load_config(&dir.join("missing.toml"))
+            .unwrap()
+            .settings
+            .is_empty());
+        fs::write(&config_path, "[location\n").unwrap();
+        assert!(load_config(&config_path).is_err());
+
+        drop(conn);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn initialize_db_seeds_all_or_nothing_and_reads_config_only_once() {
+        let dir = std::env::temp_dir().join(format!("goldbot-seed-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.toml");
+        let db_path = dir.join("goldbot.sqlite");
+        let config = |per_lead_day: i64| {
+            format!(
+                "[location]\ngym_name = \"Iron Temple\"\n\n[settings]\nmax_outbound_per_lead_per_day = {per_lead_day}\n"
+            )
+        };
+
+        fs::write(&config_path, config(50)).unwrap();
+        let err = initialize_db(&db_path, &config_path, &RealClock).unwrap_err();
+        assert!(err.to_string().contains("between 1 and 20"), "{err}");
+        let conn = Connection::open(&db_path).unwrap();
+        let locations: i64 = conn
+            .query_row("SELECT COUNT(*) FROM locations", params![], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(locations, 0);
+        drop(conn);
+
+        fs::write(&config_path, config(6)).unwrap();
+        initialize_db(&db_path, &config_path, &RealClock).unwrap();
+        let conn = Connection::open(&db_path).unwrap();
+        assert_eq!(get_location(&conn).unwrap().gym_name, "Iron Temple");
+        assert_eq!(
+            load_rate_limit_settings(&conn)
+                .unwrap()
+                .max_outbound_per_lead_per_day,
+            6
+        );
+        drop(conn);
+
+        fs::write(&config_path, "[location\n").unwrap();
+        initialize_db(&db_path, &config_path, &RealClock).unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn get_app_info_reports_version_and_db_path() {
+        let dir = std::env::temp_dir().join(format!("goldbot-info-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("goldbot.sqlite");
+        initialize_db(&db_path, &dir.join("config.toml"), &RealClock).unwrap();
+        let conn = Connection::open(&db_path).unwrap();
+
+        let info = get_app_info_with_conn(&conn).unwrap();
+        assert!(!info.app_version.is_empty());
+        assert!(info.db_path.ends_with(".sqlite"), "{}", info.db_path);
+        assert!(info.db_size_bytes > 0);
+        assert!(info.kill_switch_enabled);
+        assert_eq!(info.location_name, default_gym_name());
+        assert_eq!(info.location_timezone, default_timezone());
+
+        drop(conn);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reset_demo_data_clears_leads_and_reseeds_location() {
+        let mut conn = init_in_memory_db();
+        for phone in ["+15550000123", "+15550000124", "+15550000125"] {
+            let lead_id = insert_lead(&conn, phone);
+            insert_conversation(&conn, lead_id);
+        }
+        add_location_with_conn(
+            &conn,
+            &LocationSettingsInput {
+                gym_name: "Second Gym".to_string(),
+                timezone: "America/Chicago".to_string(),
+                business_hours_json: default_business_hours_json().to_string(),
+            },
+        )
+        .unwrap();
+
+        let reset = reset_demo_data_with_conn(&mut conn, &RealClock).unwrap();
+        assert!(reset.rows_deleted >= 6);
+        assert_eq!(reset.location_id, 1);
+
+        assert!(list_leads_paginated_with_conn(&conn, 500, 0)
+            .unwrap()
+            .leads
+            .is_empty());
+        let locations = list_locations_with_conn(&conn).unwrap();
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].gym_name, default_gym_name());
+        assert!(get_template_with_conn(&conn, "initial_follow_up").is_ok());
+
+        let audit_rows: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM audit_log WHERE action_type='reset_demo_data' AND severity='high'",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(audit_rows, 1);
+    }
+
+    #[test]
+    fn run_migrations_tracks_user_version_and_history() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert_eq!(schema_version_with_conn(&conn).unwrap(), 0);
+
+        assert_eq!(
+            run_migrations(&conn, &MIGRATIONS[..2]).unwrap(),
+            vec!["001_init.sql", "002_lead_workflow_schema.sql"]
+        );
+        assert_eq!(schema_version_with_conn(&conn).unwrap(), 2);
+        let applied = list_applied_migrations_with_conn(&conn).unwrap();
+        assert_eq!(
+            applied.iter().map(|m| m.version).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(applied[0].description, "initial schema");
 
-        return Ok(());
-    }
+        assert!(run_migrations(&conn, &MIGRATIONS[..2]).unwrap().is_empty());
+        assert_eq!(list_applied_migrations_with_conn(&conn).unwrap().len(), 2);
 
-    if lead.opted_out {
-        return Ok(());
+        let latest = MIGRATIONS.last().unwrap().version;
+        assert_eq!(
+            run_migrations(&conn, MIGRATIONS).unwrap(),
+            MIGRATIONS[2..].iter().map(|m| m.file).collect::<Vec<_>>()
+        );
+        assert_eq!(schema_version_with_conn(&conn).unwrap(), latest);
     }
 
-    if let Some(last_outbound_at) = &conversation.last_outbound_at {
-        let last_outbound = parse_ts(last_outbound_at)?;
-        if now.signed_duration_since(last_outbound) >= Duration::hours(24) {
-            gateway.create_outbound_message(OutboundRequest {
-                lead_id: lead.id,
-                conversation_id: conversation.id,
-                body: "Reply YES to get the next two available intro session times.".to_string(),
-                automated: false,
-                allow_without_consent: false,
-                allow_opted_out_once: false,
-                allow_after_reply: true,
-                ignore_business_hours: true,
-            })?;
+    #[test]
+    fn get_audit_log_filters_counts_and_pages() {
+        let conn = init_in_memory_db();
+        conn.execute("DELETE FROM audit_log", params![]).unwrap();
+        for (action, target_id, success) in [
+            ("create_outbound_message", "1", true),
+            ("create_outbound_message", "2", false),
+            ("create_outbound_message", "1", true),
+            ("set_opt_out", "1", true),
+        ] {
+            insert_audit(
+                &conn,
+                &RealClock,
+                action,
+                "lead",
+                Some(target_id.to_string()),
+                json!({}),
+                None,
+                success,
+                None,
+            )
+            .unwrap();
+        }
 
-            let state = serde_json::to_string(&ConversationState::default())?;
-            conn.execute(
-                "UPDATE conversations SET state='awaiting_yes', state_json=?, repair_attempts=0 WHERE id=?",
-                params![state, conversation.id],
-            )?;
-            conn.execute(
-                "UPDATE leads SET status='awaiting_yes' WHERE id=?",
-                params![lead.id],
-            )?;
-            return Ok(());
+        let all = get_audit_log_with_conn(&conn, &AuditLogFilter::default(), 2, 0).unwrap();
+        assert_eq!(all.total, 4);
+        assert_eq!(all.entries.len(), 2);
+        assert!(all.entries[0].id > all.entries[1].id);
+
+        let filter = AuditLogFilter {
+            action_type: Some("create_outbound_message".to_string()),
+            target_id: Some("1".to_string()),
+            success: Some(true),
+            until: Some("2999-01-01T00:00:00Z".to_string()),
+            ..AuditLogFilter::default()
+        };
+        let page = get_audit_log_with_conn(&conn, &filter, 10, 0).unwrap();
+        assert_eq!(page.total, 2);
+        assert!(page
+            .entries
+            .iter()
+            .all(|entry| entry.target_id.as_deref() == Some("1") && entry.success));
+
+        let past = get_audit_log_with_conn(&conn, &filter, 10, 5).unwrap();
+        assert_eq!(past.total, 2);
+        assert!(past.entries.is_empty());
+        assert!(get_audit_log_with_conn(&conn, &filter, 0, 0).is_err());
+    }
+
+    #[test]
+    fn normalize_phone_e164_coerces_us_formats() {
+        for raw in [
+            "5551234567",
+            "(555) 123-4567",
+            "1-555-123-4567",
+            "+1 555.123.4567",
+        ] {
+            assert_eq!(
+                normalize_phone_e164(raw, "+1").unwrap(),
+                "+15551234567",
+                "{raw}"
+            );
         }
+        assert_eq!(
+            normalize_phone_e164("7946 095 800", "+44").unwrap(),
+            "+447946095800"
+        );
+        let err = normalize_phone_e164("555-1234", "+1").unwrap_err();
+        assert!(matches!(err, AppError::Validation(_)), "{err}");
     }
 
-    match conversation.state.as_str() {
-        "awaiting_yes" => {
-            if normalized == "YES" || normalized == "Y" {
-                let offered = generate_slot_choices(conn, location, now)?;
-                if offered.len() < 2 {
-                    flag_needs_staff_attention(conn, lead.id, "no_slots_available")?;
-                    gateway.create_outbound_message(OutboundRequest {
-                        lead_id: lead.id,
-                        conversation_id: conversation.id,
-                        body: "I couldn't find two matching slots right now. A staff member will follow up shortly."
-                            .to_string(),
-                        automated: false,
-                        allow_without_consent: false,
-                        allow_opted_out_once: false,
-                        allow_after_reply: true,
-                        ignore_business_hours: true,
-                    })?;
-                    return Ok(());
-                }
+    #[test]
+    fn bulk_create_leads_reports_created_duplicates_and_errors() {
+        let conn = init_in_memory_db();
+        let lead = |phone: &str, consent: bool| LeadCreateInput {
+            first_name: "Bulk".to_string(),
+            last_name: String::new(),
+            phone_e164: phone.to_string(),
+            consent,
+            consent_at: None,
+            source: "csv".to_string(),
+        };
+        let inputs = vec![
+            lead("+15550000126", true),
+            lead("555-0000", true),
+            lead("(555) 000-0127", false),
+            lead("+15550000126", true),
+        ];
+
+        set_kill_switch_with_conn(&conn, &RealClock, true, None).unwrap();
+        let err = bulk_create_leads_with_conn(&conn, &RealClock, &inputs).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::KillSwitch);
+        let leads: i64 = conn
+            .query_row("SELECT COUNT(*) FROM leads", params![], |row| row.get(0))
+            .unwrap();
+        assert_eq!(leads, 0);
+
+        set_kill_switch_with_conn(&conn, &RealClock, false, None).unwrap();
+        let result = bulk_create_leads_with_conn(&conn, &RealClock, &inputs).unwrap();
+        assert_eq!(result.created.len(), 2);
+        assert_eq!(result.duplicates.len(), 1);
+        assert_eq!(
+            result.duplicates[0].duplicate_of,
+            Some(result.created[0].lead_id)
+        );
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].index, 1);
+        assert_eq!(result.jobs_scheduled, 1);
+    }
 
-                let state = ConversationState {
-                    offered_slots: offered.clone(),
-                };
-                conn.execute(
-                    "UPDATE conversations SET state='awaiting_time_choice', state_json=?, repair_attempts=0 WHERE id=?",
-                    params![serde_json::to_string(&state)?, conversation.id],
-                )?;
-                conn.execute(
-                    "UPDATE leads SET status='awaiting_time_choice' WHERE id=?",
-                    params![lead.id],
-                )?;
+    #[test]
+    fn blocklisted_phone_blocks_lead_creation_and_outbound() {
+        let conn = init_in_memory_db();
+        let location = get_location(&conn).unwrap();
+        let lead_id = insert_lead(&conn, "+15550000128");
+        let conversation_id = insert_conversation(&conn, lead_id);
 
-                gateway.create_outbound_message(OutboundRequest {
-                    lead_id: lead.id,
-                    conversation_id: conversation.id,
-                    body: format_slot_offer(location, &offered)?,
-                    automated: false,
-                    allow_without_consent: false,
-                    allow_opted_out_once: false,
-                    allow_after_reply: true,
-                    ignore_business_hours: true,
-                })?;
-            } else {
-                gateway.create_outbound_message(OutboundRequest {
-                    lead_id: lead.id,
-                    conversation_id: conversation.id,
-                    body: "Reply YES to get the next two available intro session times."
-                        .to_string(),
+        let entry = add_phone_to_blocklist_with_conn(
+            &conn,
+            &RealClock,
+            "(555) 000-0129",
+            Some("harassment"),
+        )
+        .unwrap();
+        assert_eq!(entry.phone_e164, "+15550000129");
+        assert!(add_phone_to_blocklist_with_conn(&conn, &RealClock, "+15550000129", None).is_err());
+        add_phone_to_blocklist_with_conn(&conn, &RealClock, "+15550000128", None).unwrap();
+        assert_eq!(list_blocklist_with_conn(&conn).unwrap().len(), 2);
+
+        let result = create_lead_with_conn(
+            &conn,
+            &RealClock,
+            &location,
+            &LeadCreateInput {
+                first_name: "Blocked".to_string(),
+                last_name: String::new(),
+                phone_e164: "+15550000129".to_string(),
+                consent: true,
+                consent_at: None,
+                source: "walk-in".to_string(),
+            },
+        )
+        .unwrap();
+        assert!(result.blocked);
+        assert!(!result.created);
+
+        let err = ActionGateway::new(&conn)
+            .validate_outbound_at(
+                &OutboundRequest {
+                    lead_id,
+                    conversation_id,
+                    body: "Hi".to_string(),
                     automated: false,
                     allow_without_consent: false,
                     allow_opted_out_once: false,
                     allow_after_reply: true,
                     ignore_business_hours: true,
-                })?;
-            }
-        }
-        "awaiting_time_choice" => {
-            let state: ConversationState =
-                serde_json::from_str(&conversation.state_json).unwrap_or_default();
-            if normalized == "1" || normalized == "2" {
-                let index = if normalized == "1" { 0 } else { 1 };
-                if let Some(slot) = state.offered_slots.get(index).cloned() {
-                    let appointment_id = gateway.create_appointment(AppointmentRequest {
-                        lead_id: lead.id,
-                        start_at: slot.start_at.clone(),
-                        end_at: slot.end_at.clone(),
-                        status: "booked".to_string(),
-                    })?;
-
-                    conn.execute(
-                        "UPDATE conversations SET state='booked', repair_attempts=0, state_json=? WHERE id=?",
-                        params![serde_json::to_string(&ConversationState::default())?, conversation.id],
-                    )?;
-
-                    let local_slot = local_display(location, &slot.start_at)?;
-                    gateway.create_outbound_message(OutboundRequest {
-                        lead_id: lead.id,
-                        conversation_id: conversation.id,
-                        body: format!(
-                            "Booked. Your intro session is confirmed for {local_slot}. We will send a reminder 2 hours before."
-                        ),
-                        automated: false,
-                        allow_without_consent: false,
-                        allow_opted_out_once: false,
-                        allow_after_reply: true,
-                        ignore_business_hours: true,
-                    })?;
+                    rate_limit_exempt: false,
+                },
+                Utc::now(),
+            )
+            .unwrap_err();
+        assert_eq!(err.to_string(), "phone is on blocklist");
 
-                    let reminder_at = parse_ts(&slot.start_at)? - Duration::hours(2);
-                    if reminder_at > Utc::now() {
-                        let _ = gateway.schedule_job(ScheduleJobRequest {
-                            job_type: "appointment_reminder".to_string(),
-                            target_id: Some(appointment_id),
-                            execute_at: reminder_at.to_rfc3339(),
-                            payload_json: serde_json::to_string(&ReminderPayload {
-                                lead_id: lead.id,
-                                appointment_id,
-                                start_at: slot.start_at,
-                            })?,
-                        });
-                    }
-                } else {
-                    handle_time_choice_repair(conn, location, lead, conversation)?;
-                }
-            } else {
-                handle_time_choice_repair(conn, location, lead, conversation)?;
-            }
-        }
-        "booked" => {
-            gateway.create_outbound_message(OutboundRequest {
-                lead_id: lead.id,
-                conversation_id: conversation.id,
-                body: "You're already booked. Reply if you need staff help rescheduling."
-                    .to_string(),
-                automated: false,
-                allow_without_consent: false,
-                allow_opted_out_once: false,
-                allow_after_reply: true,
-                ignore_business_hours: true,
-            })?;
-        }
-        _ => {
-            conn.execute(
-                "UPDATE conversations SET state='awaiting_yes', state_json=?, repair_attempts=0 WHERE id=?",
-                params![serde_json::to_string(&ConversationState::default())?, conversation.id],
-            )?;
-            gateway.create_outbound_message(OutboundRequest {
-                lead_id: lead.id,
-                conversation_id: conversation.id,
-                body: "Reply YES to get the next two available intro session times.".to_string(),
-                automated: false,
-                allow_without_consent: false,
-                allow_opted_out_once: false,
-                allow_after_reply: true,
-                ignore_business_hours: true,
-            })?;
-        }
+        remove_phone_from_blocklist_with_conn(&conn, &RealClock, "+15550000128").unwrap();
+        assert!(remove_phone_from_blocklist_with_conn(&conn, &RealClock, "+15550000128").is_err());
+        assert_eq!(list_blocklist_with_conn(&conn).unwrap().len(), 1);
     }
 
-    Ok(())
-}
+    #[test]
+    fn export_leads_csv_writes_filtered_rows_to_app_dir() {
+        let conn = init_in_memory_db();
+        let app_dir =
+            std::env::temp_dir().join(format!("goldbot-lead-export-test-{}", std::process::id()));
+        fs::create_dir_all(&app_dir).unwrap();
 
-#[cfg(test)]
-pub(crate) fn test_execute_initial_follow_up(conn: &Connection, lead_id: i64) -> Result<(), String> {
-    let location = get_location(conn).map_err(|err| err.to_string())?;
-    execute_initial_follow_up(conn, &location, lead_id).map_err(|err| err.to_string())
-}
+        let booked = insert_lead(&conn, "+15550000130");
+        conn.execute(
+            "UPDATE leads SET status='booked', first_name='Ann, Jr.' WHERE id=?",
+            params![booked],
+        )
+        .unwrap();
+        insert_booked_appointment(
+            &conn,
+            booked,
+            "2026-03-02T15:00:00Z",
+            "2026-03-02T15:30:00Z",
+        );
+        insert_lead(&conn, "+15550000131");
 
-#[cfg(test)]
-pub(crate) fn test_process_inbound_state_machine(
-    conn: &Connection,
-    lead_id: i64,
-    inbound_body: &str,
-) -> Result<(), String> {
-    let body = inbound_body.trim();
-    if body.is_empty() {
-        return Err("inbound body cannot be empty".to_string());
+        let path = export_leads_csv_with_conn(
+            &conn,
+            &RealClock,
+            &app_dir,
+            &LeadExportFilter {
+                statuses: vec!["booked".to_string()],
+            },
+        )
+        .unwrap();
+        assert!(path.starts_with(&app_dir));
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("leads_export_"));
+        let csv = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(",last_contact_at,appointment_count"));
+        assert!(lines[1].starts_with(&format!("{booked},+15550000130,\"Ann, Jr.\",")));
+        assert!(lines[1].ends_with(",1"));
+
+        let filter = LeadExportFilter { statuses: vec![] };
+        let first = export_leads_csv_with_conn(&conn, &RealClock, &app_dir, &filter).unwrap();
+        let second = export_leads_csv_with_conn(&conn, &RealClock, &app_dir, &filter).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(fs::read_to_string(&first).unwrap().lines().count(), 3);
+
+        assert!(export_leads_csv_with_conn(
+            &conn,
+            &RealClock,
+            &app_dir,
+            &LeadExportFilter {
+                statuses: vec!["lost".to_string()],
+            },
+        )
+        .is_err());
+        fs::remove_dir_all(&app_dir).unwrap();
     }
 
-    let location = get_location(conn).map_err(|err| err.to_string())?;
-    let conversation = get_conversation_by_lead_id(conn, lead_id).map_err(|err| err.to_string())?;
-
-    let now = now_iso();
-    conn.execute(
-        "INSERT INTO messages (conversation_id, direction, body, status, created_at)
-         VALUES (?, 'INBOUND', ?, 'received', ?)",
-        params![conversation.id, body, now],
-    )
-    .map_err(|err| err.to_string())?;
-    conn.execute(
-        "UPDATE conversations SET last_inbound_at=? WHERE id=?",
-        params![now, conversation.id],
-    )
-    .map_err(|err| err.to_string())?;
-    conn.execute(
-        "UPDATE leads SET last_contact_at=? WHERE id=?",
-        params![now, lead_id],
-    )
-    .map_err(|err| err.to_string())?;
-
-    let lead = get_lead(conn, lead_id).map_err(|err| err.to_string())?;
-    let refreshed_conversation =
-        get_conversation_by_lead_id(conn, lead_id).map_err(|err| err.to_string())?;
-
-    process_inbound_state_machine(conn, &location, &lead, &refreshed_conversation, body)
-        .map_err(|err| err.to_string())
-}
+    #[test]
+    fn update_lead_corrects_name_and_rejects_duplicate_phone() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000132");
+        let other_id = insert_lead(&conn, "+15550000133");
 
-fn handle_time_choice_repair(
-    conn: &Connection,
-    location: &Location,
-    lead: &LeadRow,
-    conversation: &ConversationRow,
-) -> AppResult<()> {
-    let gateway = ActionGateway::new(conn, location);
-    let attempts = conversation.repair_attempts + 1;
-    let offered = generate_slot_choices(conn, location, Utc::now())?;
+        let updated = update_lead_with_conn(
+            &conn,
+            &RealClock,
+            lead_id,
+            &LeadPatch {
+                first_name: Some(" Maria ".to_string()),
+                last_name: Some(String::new()),
+                ..LeadPatch::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(updated.first_name.as_deref(), Some("Maria"));
+        assert_eq!(updated.last_name, None);
+        assert_eq!(updated.phone_e164, "+15550000132");
 
-    if offered.len() < 2 {
-        flag_needs_staff_attention(conn, lead.id, "repair_no_slots")?;
-        gateway.create_outbound_message(OutboundRequest {
-            lead_id: lead.id,
-            conversation_id: conversation.id,
-            body:
-                "I couldn't match that response to a slot. A staff member has been flagged to help."
-                    .to_string(),
-            automated: false,
-            allow_without_consent: false,
-            allow_opted_out_once: false,
-            allow_after_reply: true,
-            ignore_business_hours: true,
-        })?;
-        return Ok(());
-    }
+        let err = update_lead_with_conn(
+            &conn,
+            &RealClock,
+            lead_id,
+            &LeadPatch {
+                phone_e164: Some("(555) 000-0133".to_string()),
+                ..LeadPatch::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::ValidationFailed);
+        assert!(err.to_string().contains(&format!("lead {other_id}")));
+        let unchanged = get_lead_summary(&conn, lead_id).unwrap();
+        assert_eq!(unchanged.phone_e164, "+15550000132");
 
-    let mut body = format!(
-        "Please reply with 1 or 2 so I can book your session.\n\n{}",
-        format_slot_offer(location, &offered)?
-    );
+        let moved = update_lead_with_conn(
+            &conn,
+            &RealClock,
+            lead_id,
+            &LeadPatch {
+                phone_e164: Some("555-000-0134".to_string()),
+                ..LeadPatch::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(moved.phone_e164, "+15550000134");
+        assert_eq!(moved.first_name.as_deref(), Some("Maria"));
 
-    if attempts >= 2 {
-        flag_needs_staff_attention(conn, lead.id, "repair_attempts_exceeded")?;
-        body = format!(
-            "{}\n\nI also flagged this conversation for staff follow-up.",
-            body
-        );
+        let audit: String = conn
+            .query_row(
+                "SELECT request_json FROM audit_log WHERE action_type='update_lead' ORDER BY id DESC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(audit.contains("+15550000132"));
+        assert!(audit.contains("+15550000134"));
     }
 
-    conn.execute(
-        "UPDATE conversations SET state='awaiting_time_choice', state_json=?, repair_attempts=? WHERE id=?",
-        params![serde_json::to_string(&ConversationState { offered_slots: offered })?, attempts, conversation.id],
-    )?;
+    #[test]
+    fn update_lead_granting_consent_queues_follow_up_once() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000136");
+        conn.execute("UPDATE leads SET consent=0 WHERE id=?", params![lead_id])
+            .unwrap();
+        let patch = LeadPatch {
+            consent: Some(true),
+            consent_at: Some("2030-01-01T12:00:00Z".to_string()),
+            ..LeadPatch::default()
+        };
 
-    gateway.create_outbound_message(OutboundRequest {
-        lead_id: lead.id,
-        conversation_id: conversation.id,
-        body,
-        automated: false,
-        allow_without_consent: false,
-        allow_opted_out_once: false,
-        allow_after_reply: true,
-        ignore_business_hours: true,
-    })?;
+        let updated = update_lead_with_conn(&conn, &RealClock, lead_id, &patch).unwrap();
+        assert!(updated.consent);
+        update_lead_with_conn(&conn, &RealClock, lead_id, &patch).unwrap();
+        let (pending, consent_at): (i64, String) = conn
+            .query_row(
+                "SELECT (SELECT COUNT(*) FROM scheduled_jobs
+                         WHERE job_type='initial_follow_up' AND status='pending' AND target_id=?1),
+                        consent_at
+                 FROM leads WHERE id=?1",
+                params![lead_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(pending, 1);
+        assert_eq!(parse_ts(&consent_at).unwrap(), ts("2030-01-01T12:00:00Z"));
 
-    Ok(())
-}
+        let booked = insert_lead(&conn, "+15550000137");
+        conn.execute(
+            "UPDATE leads SET consent=0, status='booked' WHERE id=?",
+            params![booked],
+        )
+        .unwrap();
+        update_lead_with_conn(&conn, &RealClock, booked, &patch).unwrap();
+        let booked_pending: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM scheduled_jobs WHERE job_type='initial_follow_up' AND target_id=?",
+                params![booked],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(booked_pending, 0);
+    }
 
-fn flag_needs_staff_attention(conn: &Connection, lead_id: i64, reason: &str) -> AppResult<()> {
-    conn.execute(
-        "UPDATE leads SET needs_staff_attention=1 WHERE id=?",
-        params![lead_id],
-    )?;
-    let _ = insert_audit(
-        conn,
-        "flag_needs_staff_attention",
-        "lead",
-        Some(lead_id.to_string()),
-        json!({ "reason": reason }),
-        Some(json!({ "needs_staff_attention": true })),
-        true,
-        None,
-    );
-    Ok(())
-}
+    #[test]
+    fn generate_slot_choices_uses_each_locations_timezone() {
+        let conn = init_in_memory_db();
+        let monday_nine_to_ten =
+            r#"{"mon":[["09:00","10:00"]],"tue":[],"wed":[],"thu":[],"fri":[],"sat":[],"sun":[]}"#;
+        set_business_hours(&conn, monday_nine_to_ten);
+        let east_id = get_location(&conn).unwrap().id;
+        let west = add_location_with_conn(
+            &conn,
+            &LocationSettingsInput {
+                gym_name: "Coast Gym".to_string(),
+                timezone: "America/Los_Angeles".to_string(),
+                business_hours_json: monday_nine_to_ten.to_string(),
+            },
+        )
+        .unwrap();
 
-fn generate_slot_choices(
-    conn: &Connection,
-    location: &Location,
-    from_utc: DateTime<Utc>,
-) -> AppResult<Vec<SlotChoice>> {
-    let tz = parse_tz(&location.timezone)?;
-    let business_hours = parse_business_hours(&location.business_hours_json)?;
+        let from = ts("2030-01-07T12:00:00Z");
+        let east_slots = generate_slot_choices(&conn, &get_location(&conn).unwrap(), from).unwrap();
+        set_current_location_with_conn(&conn, &RealClock, west.id).unwrap();
+        let west_location = get_location(&conn).unwrap();
+        assert_eq!(west_location.id, west.id);
+        let west_slots = generate_slot_choices(&conn, &west_location, from).unwrap();
 
-    let mut appointments_stmt = conn.prepare(
-        "SELECT start_at, end_at FROM appointments WHERE status='booked' AND datetime(start_at) >= datetime('now', '-1 day')",
-    )?;
-    let appt_rows = appointments_stmt.query_map(params![], |row| {
-        let start: String = row.get(0)?;
-        let end: String = row.get(1)?;
-        Ok((start, end))
-    })?;
+        // 09:00 local is 14:00Z in New York and 17:00Z in Los Angeles.
+        assert_eq!(
+            parse_ts(&east_slots[0].start_at).unwrap(),
+            ts("2030-01-07T14:00:00Z")
+        );
+        assert_eq!(
+            parse_ts(&west_slots[0].start_at).unwrap(),
+            ts("2030-01-07T17:00:00Z")
+        );
 
-    let mut existing: Vec<(DateTime<Utc>, DateTime<Utc>)> = Vec::new();
-    for row in appt_rows {
-        let (start, end) = row?;
-        existing.push((parse_ts(&start)?, parse_ts(&end)?));
+        set_current_location_with_conn(&conn, &RealClock, east_id).unwrap();
+        assert_eq!(get_location(&conn).unwrap().timezone, "America/New_York");
     }
 
-    let local_start = from_utc.with_timezone(&tz);
-    let mut business_days_seen = 0;
-    let mut day_offset = 0;
-    let mut slots: Vec<SlotChoice> = Vec::new();
+    #[test]
+    fn update_lead_consent_schedules_and_cancels_initial_follow_up() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000135");
+        conn.execute("UPDATE leads SET consent=0 WHERE id=?", params![lead_id])
+            .unwrap();
+        let pending_follow_ups = |conn: &Connection| -> i64 {
+            conn.query_row(
+                "SELECT COUNT(*) FROM scheduled_jobs
+                 WHERE job_type='initial_follow_up' AND status='pending' AND target_id=?",
+                params![lead_id],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
 
-    while business_days_seen < 3 && day_offset < 14 {
-        let day: NaiveDate = local_start.date_naive() + Duration::days(day_offset);
-        let weekday = day.weekday();
-        let ranges = business_hours.get(&weekday).cloned().unwrap_or_default();
+        let granted = update_lead_consent_with_conn(
+            &conn,
+            &RealClock,
+            lead_id,
+            true,
+            "2030-01-01T12:00:00Z",
+            "verbal at front desk",
+        )
+        .unwrap();
+        assert!(granted.follow_up_scheduled);
+        assert_eq!(pending_follow_ups(&conn), 1);
+        let lead = get_lead(&conn, lead_id).unwrap();
+        assert!(lead.consent);
 
-        if !ranges.is_empty() {
-            business_days_seen += 1;
-            for (range_start, range_end) in ranges {
-                let mut current_minutes =
-                    range_start.hour() as i64 * 60 + range_start.minute() as i64;
-                let end_minutes = range_end.hour() as i64 * 60 + range_end.minute() as i64;
+        let again = update_lead_consent_with_conn(
+            &conn,
+            &RealClock,
+            lead_id,
+            true,
+            "2030-01-01T12:05:00Z",
+            "verbal at front desk",
+        )
+        .unwrap();
+        assert!(!again.follow_up_scheduled);
+        assert_eq!(pending_follow_ups(&conn), 1);
 
-                while current_minutes + 30 <= end_minutes {
-                    let hour = (current_minutes / 60) as u32;
-                    let minute = (current_minutes % 60) as u32;
-                    let naive_time = NaiveTime::from_hms_opt(hour, minute, 0)
-                        .ok_or_else(|| AppError::Validation("invalid time computed".to_string()))?;
-                    let local_candidate = tz
-                        .from_local_datetime(&day.and_time(naive_time))
-                        .single()
-                        .ok_or_else(|| {
-                            AppError::Validation(
-                                "could not resolve local appointment slot timestamp".to_string(),
-                            )
-                        })?;
-                    let start_utc = local_candidate.with_timezone(&Utc);
-                    let end_utc = start_utc + Duration::minutes(30);
+        let withdrawn = update_lead_consent_with_conn(
+            &conn,
+            &RealClock,
+            lead_id,
+            false,
+            "2030-01-02T12:00:00Z",
+            "called to withdraw",
+        )
+        .unwrap();
+        assert_eq!(withdrawn.follow_ups_cancelled, 1);
+        assert_eq!(pending_follow_ups(&conn), 0);
+        assert!(!get_lead(&conn, lead_id).unwrap().consent);
 
-                    if start_utc <= from_utc {
-                        current_minutes += 40;
-                        continue;
-                    }
+        assert!(update_lead_consent_with_conn(
+            &conn,
+            &RealClock,
+            lead_id,
+            true,
+            "yesterday",
+            "verbal"
+        )
+        .is_err());
+    }
 
-                    if !has_appointment_conflict(start_utc, end_utc, &existing) {
-                        slots.push(SlotChoice {
-                            start_at: start_utc.to_rfc3339(),
-                            end_at: end_utc.to_rfc3339(),
-                        });
-                    }
+    #[test]
+    fn update_lead_consent_rolls_back_when_follow_up_is_refused() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000182");
+        conn.execute("UPDATE leads SET consent=0 WHERE id=?", params![lead_id])
+            .unwrap();
+        set_kill_switch_with_conn(&conn, &RealClock, true, None).unwrap();
 
-                    if slots.len() == 2 {
-                        return Ok(slots);
-                    }
+        let err = update_lead_consent_with_conn(
+            &conn,
+            &RealClock,
+            lead_id,
+            true,
+            "2030-01-01T12:00:00Z",
+            "verbal at front desk",
+        )
+        .unwrap_err();
+        assert!(
+            matches!(err, AppError::Coded(ErrorCode::KillSwitch, _)),
+            "{err}"
+        );
+        assert!(!get_lead(&conn, lead_id).unwrap().consent);
 
-                    current_minutes += 40;
-                }
-            }
-        }
+        let (success, error): (i64, Option<String>) = conn
+            .query_row(
+                "SELECT success, error_message FROM audit_log
+                 WHERE action_type='update_lead_consent' AND target_id=?",
+                params![lead_id.to_string()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(success, 0);
+        assert!(error.unwrap().contains("kill switch"));
+    }
 
-        day_offset += 1;
+    #[test]
+    fn update_lead_rolls_back_field_edits_when_follow_up_is_refused() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000186");
+        conn.execute("UPDATE leads SET consent=0 WHERE id=?", params![lead_id])
+            .unwrap();
+        set_kill_switch_with_conn(&conn, &RealClock, true, None).unwrap();
+        let clock = FakeClock(ts("2030-01-07T15:00:00Z"));
+        let patch = LeadPatch {
+            first_name: Some("Renamed".to_string()),
+            consent: Some(true),
+            ..LeadPatch::default()
+        };
+
+        let err = update_lead_with_conn(&conn, &clock, lead_id, &patch).unwrap_err();
+        assert!(
+            matches!(err, AppError::Coded(ErrorCode::KillSwitch, _)),
+            "{err}"
+        );
+        let lead = get_lead_summary(&conn, lead_id).unwrap();
+        assert_ne!(lead.first_name.as_deref(), Some("Renamed"));
+        assert!(!lead.consent);
+        let success: i64 = conn
+            .query_row(
+                "SELECT success FROM audit_log WHERE action_type='update_lead' AND target_id=?",
+                params![lead_id.to_string()],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(success, 0);
+
+        set_kill_switch_with_conn(&conn, &clock, false, None).unwrap();
+        update_lead_with_conn(&conn, &clock, lead_id, &patch).unwrap();
+        let consent_at: String = conn
+            .query_row(
+                "SELECT consent_at FROM leads WHERE id=?",
+                params![lead_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(ts(&consent_at), clock.0);
     }
 
-    Ok(slots)
-}
+    #[test]
+    fn resolve_staff_attention_applies_resolution_side_effects() {
+        let conn = init_in_memory_db();
+        let flagged_lead = |phone: &str| {
+            let lead_id = insert_lead(&conn, phone);
+            insert_conversation(&conn, lead_id);
+            conn.execute(
+                "UPDATE leads SET status='awaiting_time_choice' WHERE id=?",
+                params![lead_id],
+            )
+            .unwrap();
+            flag_needs_staff_attention(&conn, &RealClock, lead_id, "repair attempts exhausted")
+                .unwrap();
+            lead_id
+        };
+        let pending_jobs = |lead_id: i64| -> i64 {
+            conn.query_row(
+                "SELECT COUNT(*) FROM scheduled_jobs WHERE status='pending' AND target_id=?",
+                params![lead_id],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
 
-fn has_appointment_conflict(
-    candidate_start: DateTime<Utc>,
-    candidate_end: DateTime<Utc>,
-    existing: &[(DateTime<Utc>, DateTime<Utc>)],
-) -> bool {
-    let candidate_end_with_buffer = candidate_end + Duration::minutes(10);
+        for (phone, resolution) in [("+15550000138", "Resolved"), ("+15550000139", "NoAction")] {
+            let lead_id = flagged_lead(phone);
+            let result =
+                resolve_staff_attention_with_conn(&conn, &RealClock, lead_id, resolution, None)
+                    .unwrap();
+            assert_eq!(result.jobs_cancelled, 0);
+            let lead = get_lead_summary(&conn, lead_id).unwrap();
+            assert!(!lead.needs_staff_attention);
+            assert_eq!(lead.status, "awaiting_time_choice");
+        }
 
-    existing.iter().any(|(start, end)| {
-        let existing_end_with_buffer = *end + Duration::minutes(10);
-        candidate_start < existing_end_with_buffer && *start < candidate_end_with_buffer
-    })
-}
+        let re_engaged = flagged_lead("+15550000140");
+        resolve_staff_attention_with_conn(
+            &conn,
+            &RealClock,
+            re_engaged,
+            "ReEngage",
+            Some("called, wants to rebook"),
+        )
+        .unwrap();
+        let lead = get_lead_summary(&conn, re_engaged).unwrap();
+        assert!(!lead.needs_staff_attention);
+        assert_eq!(lead.status, "awaiting_yes");
+        assert_eq!(
+            get_conversation_by_lead_id(&conn, re_engaged)
+                .unwrap()
+                .state,
+            "awaiting_yes"
+        );
 
-fn format_slot_offer(location: &Location, slots: &[SlotChoice]) -> AppResult<String> {
-    if slots.len() < 2 {
-        return Err(AppError::Validation(
-            "expected at least 2 slots for offer".to_string(),
-        ));
+        let archived = flagged_lead("+15550000141");
+        conn.execute(
+            "INSERT INTO scheduled_jobs (job_type, target_id, execute_at, status, payload_json, created_at)
+             VALUES ('re_engagement', ?, '2030-01-01T00:00:00Z', 'pending', '{}', ?)",
+            params![archived, now_iso()],
+        )
+        .unwrap();
+        assert_eq!(pending_jobs(archived), 1);
+        let result =
+            resolve_staff_attention_with_conn(&conn, &RealClock, archived, "Archived", None)
+                .unwrap();
+        assert_eq!(result.jobs_cancelled, 1);
+        assert_eq!(pending_jobs(archived), 0);
+
+        let notes: String = conn
+            .query_row(
+                "SELECT json_extract(request_json, '$.notes') FROM audit_log
+                 WHERE action_type='resolve_staff_attention' AND target_id=?",
+                params![re_engaged.to_string()],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(notes, "called, wants to rebook");
+
+        assert!(
+            resolve_staff_attention_with_conn(&conn, &RealClock, archived, "Resolved", None)
+                .is_err()
+        );
+        let other = flagged_lead("+15550000142");
+        assert!(
+            resolve_staff_attention_with_conn(&conn, &RealClock, other, "Ignored", None).is_err()
+        );
     }
 
-    let first = local_display(location, &slots[0].start_at)?;
-    let second = local_display(location, &slots[1].start_at)?;
+    #[test]
+    fn mark_needs_attention_resolved_requires_resolver_and_warns_when_unflagged() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000143");
+        flag_needs_staff_attention(&conn, &RealClock, lead_id, "asked for a manager").unwrap();
 
-    Ok(format!(
-        "Choose a time:\n1) {first}\n2) {second}\n\nReply 1 or 2."
-    ))
-}
+        let err = mark_needs_attention_resolved_with_conn(
+            &conn,
+            &RealClock,
+            lead_id,
+            "  ",
+            "called back",
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::ValidationFailed);
+        assert!(
+            get_lead_summary(&conn, lead_id)
+                .unwrap()
+                .needs_staff_attention
+        );
 
-fn local_display(location: &Location, iso: &str) -> AppResult<String> {
-    let tz = parse_tz(&location.timezone)?;
-    let dt = parse_ts(iso)?.with_timezone(&tz);
-    Ok(dt.format("%a %b %-d at %-I:%M %p").to_string())
-}
+        let lead = mark_needs_attention_resolved_with_conn(
+            &conn,
+            &RealClock,
+            lead_id,
+            "Dana",
+            "called back",
+        )
+        .unwrap();
+        assert!(!lead.needs_staff_attention);
+        mark_needs_attention_resolved_with_conn(&conn, &RealClock, lead_id, "Dana", "double click")
+            .unwrap();
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT json_extract(request_json, '$.resolved_by'),
+                        json_extract(response_json, '$.warning')
+                 FROM audit_log WHERE action_type='mark_needs_attention_resolved' ORDER BY id",
+            )
+            .unwrap();
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0], ("Dana".to_string(), None));
+        assert!(rows[1].1.is_some());
+    }
 
-fn parse_business_hours(input: &str) -> AppResult<HashMap<Weekday, Vec<(NaiveTime, NaiveTime)>>> {
-    let raw: Value = serde_json::from_str(input)?;
-    let obj = raw
-        .as_object()
-        .ok_or_else(|| AppError::Validation("invalid business_hours_json object".to_string()))?;
+    #[test]
+    fn list_leads_needing_attention_reports_latest_reason_newest_first() {
+        let conn = init_in_memory_db();
+        let first = insert_lead(&conn, "+15550000144");
+        let second = insert_lead(&conn, "+15550000145");
+        insert_lead(&conn, "+15550000146");
 
-    let mut map: HashMap<Weekday, Vec<(NaiveTime, NaiveTime)>> = HashMap::new();
+        flag_needs_staff_attention(&conn, &RealClock, first, "old reason").unwrap();
+        flag_needs_staff_attention(&conn, &RealClock, second, "asked about pricing").unwrap();
+        flag_needs_staff_attention(&conn, &RealClock, first, "repair attempts exhausted").unwrap();
 
-    for (key, value) in obj {
-        let weekday = match key.as_str() {
-            "mon" => Weekday::Mon,
-            "tue" => Weekday::Tue,
-            "wed" => Weekday::Wed,
-            "thu" => Weekday::Thu,
-            "fri" => Weekday::Fri,
-            "sat" => Weekday::Sat,
-            "sun" => Weekday::Sun,
-            _ => continue,
-        };
+        let leads = list_leads_needing_attention_with_conn(&conn).unwrap();
+        assert_eq!(
+            leads.iter().map(|view| view.lead.id).collect::<Vec<_>>(),
+            vec![first, second]
+        );
+        assert_eq!(
+            leads[0].attention_reason.as_deref(),
+            Some("repair attempts exhausted")
+        );
+        assert_eq!(
+            leads[1].attention_reason.as_deref(),
+            Some("asked about pricing")
+        );
+        assert!(leads[1].flagged_at.is_some());
 
-        let mut ranges: Vec<(NaiveTime, NaiveTime)> = Vec::new();
-        if let Some(arr) = value.as_array() {
-            for range in arr {
-                let pair = range.as_array().ok_or_else(|| {
-                    AppError::Validation("business hours range must be [start, end]".to_string())
-                })?;
-                if pair.len() != 2 {
-                    continue;
-                }
-                let start = pair[0]
-                    .as_str()
-                    .ok_or_else(|| AppError::Validation("start time must be string".to_string()))?;
-                let end = pair[1]
-                    .as_str()
-                    .ok_or_else(|| AppError::Validation("end time must be string".to_string()))?;
+        mark_needs_attention_resolved_with_conn(&conn, &RealClock, first, "Dana", "handled")
+            .unwrap();
+        assert_eq!(
+            list_leads_needing_attention_with_conn(&conn).unwrap().len(),
+            1
+        );
+    }
 
-                let start_time = NaiveTime::parse_from_str(start, "%H:%M")?;
-                let end_time = NaiveTime::parse_from_str(end, "%H:%M")?;
-                ranges.push((start_time, end_time));
-            }
+    #[test]
+    fn weekly_report_has_seven_local_days_oldest_first() {
+        let conn = init_in_memory_db();
+        // 03:00Z on the 10th is still the 9th in New York.
+        for (phone, created_at) in [
+            ("+15550000147", "2030-02-10T03:00:00Z"),
+            ("+15550000148", "2030-02-10T15:00:00Z"),
+            ("+15550000149", "2030-02-01T15:00:00Z"),
+        ] {
+            let lead_id = insert_lead(&conn, phone);
+            conn.execute(
+                "UPDATE leads SET created_at=? WHERE id=?",
+                params![created_at, lead_id],
+            )
+            .unwrap();
         }
-        map.insert(weekday, ranges);
+        insert_audit(
+            &conn,
+            &RealClock,
+            "flag_needs_staff_attention",
+            "lead",
+            Some("1".to_string()),
+            json!({ "reason": "test" }),
+            None,
+            true,
+            None,
+        )
+        .unwrap();
+        conn.execute(
+            "UPDATE audit_log SET created_at='2030-02-04T16:00:00Z'
+             WHERE action_type='flag_needs_staff_attention'",
+            [],
+        )
+        .unwrap();
+
+        let week = get_weekly_report_with_conn(&conn, ts("2030-02-10T17:00:00Z")).unwrap();
+        assert_eq!(week.len(), 7);
+        assert_eq!(week[0].date, "2030-02-04");
+        assert_eq!(week[6].date, "2030-02-10");
+        let created: Vec<i64> = week.iter().map(|day| day.leads_created).collect();
+        assert_eq!(created, [0, 0, 0, 0, 0, 1, 1]);
+        assert_eq!(week[0].needs_attention_flagged, 1);
+        assert!(week[1..].iter().all(|day| day.needs_attention_flagged == 0));
     }
 
-    Ok(map)
-}
+    #[test]
+    fn lead_communication_summary_aggregates_messages() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000150");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let now = ts("2030-03-10T12:00:00Z");
+
+        let empty = get_lead_communication_summary_with_conn(&conn, lead_id, now).unwrap();
+        assert_eq!(empty.total_outbound, 0);
+        assert_eq!(empty.days_since_last_contact, None);
+        assert_eq!(empty.inbound_response_rate_pct, None);
+
+        for (direction, status, created_at) in [
+            ("OUTBOUND", "sent", "2030-03-01T12:00:00Z"),
+            ("INBOUND", "received", "2030-03-01T12:05:00Z"),
+            ("OUTBOUND", "sent", "2030-03-02T12:00:00Z"),
+            ("OUTBOUND", "sent", "2030-03-05T12:00:00Z"),
+            ("OUTBOUND", "sent", "2030-03-07T11:00:00Z"),
+            ("OUTBOUND", "failed", "2030-03-09T12:00:00Z"),
+        ] {
+            conn.execute(
+                "INSERT INTO messages (conversation_id, direction, body, status, created_at)
+                 VALUES (?, ?, 'x', ?, ?)",
+                params![conversation_id, direction, status, created_at],
+            )
+            .unwrap();
+        }
 
-fn is_business_open(location: &Location, when_utc: DateTime<Utc>) -> AppResult<bool> {
-    let tz = parse_tz(&location.timezone)?;
-    let local = when_utc.with_timezone(&tz);
-    let business_hours = parse_business_hours(&location.business_hours_json)?;
-    let ranges = business_hours
-        .get(&local.weekday())
-        .cloned()
-        .unwrap_or_default();
+        let summary = get_lead_communication_summary_with_conn(&conn, lead_id, now).unwrap();
+        assert_eq!(summary.total_outbound, 4);
+        assert_eq!(summary.total_inbound, 1);
+        assert_eq!(
+            summary.first_contact_at.as_deref(),
+            Some("2030-03-01T12:00:00Z")
+        );
+        assert_eq!(
+            summary.last_contact_at.as_deref(),
+            Some("2030-03-07T11:00:00Z")
+        );
+        assert_eq!(summary.days_since_last_contact, Some(3));
+        let rate = summary.inbound_response_rate_pct.unwrap();
+        assert!((rate - 25.0).abs() < 0.001);
 
-    let current_time = local.time();
-    Ok(ranges
-        .iter()
-        .any(|(start, end)| current_time >= *start && current_time < *end))
-}
+        assert!(get_lead_communication_summary_with_conn(&conn, 9999, now).is_err());
+    }
 
-fn next_open_time(location: &Location, from_utc: DateTime<Utc>) -> AppResult<DateTime<Utc>> {
-    let tz = parse_tz(&location.timezone)?;
-    let local = from_utc.with_timezone(&tz);
-    let business_hours = parse_business_hours(&location.business_hours_json)?;
+    #[test]
+    fn search_leads_matches_full_name_in_any_order_and_exact_phone() {
+        let conn = init_in_memory_db();
+        let john = insert_lead(&conn, "+15550000151");
+        conn.execute(
+            "UPDATE leads SET first_name='John', last_name='Smith' WHERE id=?",
+            params![john],
+        )
+        .unwrap();
+        let jane = insert_lead(&conn, "+15550000152");
+        conn.execute(
+            "UPDATE leads SET first_name='Jane', last_name='Smithers' WHERE id=?",
+            params![jane],
+        )
+        .unwrap();
+
+        let ids = |query: &str| -> Vec<i64> {
+            search_leads_with_conn(&conn, query, None)
+                .unwrap()
+                .into_iter()
+                .map(|lead| lead.id)
+                .collect()
+        };
+        assert_eq!(ids("John Smith"), vec![john]);
+        assert_eq!(ids("smith john"), vec![john]);
+        assert_eq!(ids("(555) 000-0152"), vec![jane]);
+        assert_eq!(ids("5550000151"), vec![john]);
+        assert_eq!(ids("Smith").len(), 2);
+    }
 
-    for day_offset in 0..21 {
-        let day = local.date_naive() + Duration::days(day_offset);
-        let ranges = business_hours
-            .get(&day.weekday())
-            .cloned()
-            .unwrap_or_default();
-        for (start, _) in ranges {
-            if day_offset == 0 && start <= local.time() {
-                continue;
+    #[test]
+    fn retry_policy_delays_grow_and_stay_capped() {
+        for policy in [RetryPolicy::default(), RetryPolicy::aggressive()] {
+            for jitter in [0, policy.base_delay_ms - 1, u64::MAX] {
+                let delays: Vec<u64> = (1..policy.max_attempts)
+                    .map(|attempt| policy.delay_ms(attempt, jitter))
+                    .collect();
+                assert!(delays.windows(2).all(|pair| pair[0] <= pair[1]));
+                assert!(delays.iter().all(|delay| *delay <= policy.max_delay_ms));
             }
-            let local_dt = tz
-                .from_local_datetime(&day.and_time(start))
-                .single()
-                .ok_or_else(|| {
-                    AppError::Validation(
-                        "unable to resolve local datetime for next business opening".to_string(),
-                    )
-                })?;
-            return Ok(local_dt.with_timezone(&Utc));
+            assert!(policy.delay_ms(1, 0) >= policy.base_delay_ms);
+            assert_eq!(policy.delay_ms(64, 0), policy.max_delay_ms);
         }
+        assert_eq!(RetryPolicy::default().delay_ms(1, 0), 40);
+
+        let mut calls = 0;
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay_ms: 1,
+            max_delay_ms: 1,
+        };
+        let result: AppResult<()> = retry_db_with_policy(&policy, || {
+            calls += 1;
+            Err(AppError::Db(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+                None,
+            )))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 3);
     }
 
-    Ok(from_utc + Duration::hours(24))
-}
+    #[test]
+    fn simulate_outbound_sms_skips_gateway_checks() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000153");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        conn.execute(
+            "UPDATE leads SET consent=0, opted_out=1 WHERE id=?",
+            params![lead_id],
+        )
+        .unwrap();
 
-fn get_location(conn: &Connection) -> AppResult<Location> {
-    let primary_id = ensure_primary_location(conn)?;
-    conn.query_row(
-        "SELECT gym_name, timezone, business_hours_json FROM locations WHERE id=?",
-        params![primary_id],
-        |row| {
-            Ok(Location {
-                gym_name: row.get(0)?,
-                timezone: row.get(1)?,
-                business_hours_json: row.get(2)?,
-            })
-        },
-    )
-    .map_err(AppError::from)
-}
+        let message_id =
+            simulate_outbound_sms_with_conn(&conn, &RealClock, lead_id, " Test ping ").unwrap();
+        let (status, body, direction): (String, String, String) = conn
+            .query_row(
+                "SELECT status, body, direction FROM messages WHERE id=?",
+                params![message_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            (status.as_str(), body.as_str(), direction.as_str()),
+            ("simulated", "Test ping", "OUTBOUND")
+        );
+        let last_outbound: Option<String> = conn
+            .query_row(
+                "SELECT last_outbound_at FROM conversations WHERE id=?",
+                params![conversation_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(last_outbound.is_some());
+        let audits: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM audit_log WHERE action_type='simulate_outbound'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(audits, 1);
+        assert!(simulate_outbound_sms_with_conn(&conn, &RealClock, lead_id, "  ").is_err());
+    }
 
-fn get_lead(conn: &Connection, lead_id: i64) -> AppResult<LeadRow> {
-    conn.query_row(
-        "SELECT id, first_name, consent, opted_out
-         FROM leads WHERE id=?",
-        params![lead_id],
-        |row| {
-            Ok(LeadRow {
-                id: row.get(0)?,
-                first_name: row.get(1)?,
-                consent: i64_to_bool(row.get(2)?),
-                opted_out: i64_to_bool(row.get(3)?),
-            })
-        },
-    )
-    .optional()?
-    .ok_or_else(|| AppError::Validation("lead not found".to_string()))
-}
+    #[test]
+    fn conversation_history_filters_and_pages_across_leads() {
+        let conn = init_in_memory_db();
+        let first = insert_lead(&conn, "+15550000154");
+        let second = insert_lead(&conn, "+15550000155");
+        let first_conversation = insert_conversation(&conn, first);
+        let second_conversation = insert_conversation(&conn, second);
+        for (conversation_id, direction, created_at) in [
+            (first_conversation, "OUTBOUND", "2030-04-01T10:00:00Z"),
+            (first_conversation, "INBOUND", "2030-04-01T10:05:00Z"),
+            (second_conversation, "OUTBOUND", "2030-04-02T10:00:00Z"),
+            (second_conversation, "INBOUND", "2030-04-02T10:05:00Z"),
+            (first_conversation, "OUTBOUND", "2030-04-03T10:00:00Z"),
+        ] {
+            conn.execute(
+                "INSERT INTO messages (conversation_id, direction, body, status, created_at)
+                 VALUES (?, ?, 'x', 'sent', ?)",
+                params![conversation_id, direction, created_at],
+            )
+            .unwrap();
+        }
+        let leads = |page: &MessageHistoryPage| -> Vec<i64> {
+            page.messages.iter().map(|item| item.lead_id).collect()
+        };
 
-fn get_conversation_by_lead_id(conn: &Connection, lead_id: i64) -> AppResult<ConversationRow> {
-    conn.query_row(
-        "SELECT id, state, state_json, last_inbound_at, last_outbound_at, repair_attempts
-         FROM conversations WHERE lead_id=?",
-        params![lead_id],
-        |row| {
-            Ok(ConversationRow {
-                id: row.get(0)?,
-                state: row.get(1)?,
-                state_json: row.get(2)?,
-                last_inbound_at: row.get(3)?,
-                last_outbound_at: row.get(4)?,
-                repair_attempts: row.get(5)?,
-            })
-        },
-    )
-    .map_err(AppError::from)
-}
+        let all = get_conversation_history_with_conn(&conn, None, None, None, None).unwrap();
+        assert_eq!(leads(&all), vec![first, second, second, first, first]);
+        assert_eq!(all.messages[1].lead_phone, "+15550000155");
+        assert_eq!(all.messages[1].conversation_id, second_conversation);
 
-fn is_kill_switch_enabled(conn: &Connection) -> AppResult<bool> {
-    let raw: Option<String> = conn
-        .query_row(
-            "SELECT value FROM settings WHERE key='kill_switch' LIMIT 1",
-            params![],
-            |row| row.get(0),
-        )
-        .optional()?;
+        let inbound =
+            get_conversation_history_with_conn(&conn, Some("inbound"), None, None, None).unwrap();
+        assert_eq!(leads(&inbound), vec![second, first]);
 
-    Ok(matches!(raw.as_deref(), Some("true") | Some("1")))
-}
+        let recent = get_conversation_history_with_conn(
+            &conn,
+            None,
+            Some("2030-04-02T00:00:00Z"),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(leads(&recent), vec![first, second, second]);
 
-fn insert_audit(
-    conn: &Connection,
-    action_type: &str,
-    target_type: &str,
-    target_id: Option<String>,
-    request_json: Value,
-    response_json: Option<Value>,
-    success: bool,
-    error_message: Option<String>,
-) -> AppResult<()> {
-    conn.execute(
-        "INSERT INTO audit_log (action_type, target_type, target_id, request_json, response_json, success, error_message, created_at)
-         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
-        params![
-            action_type,
-            target_type,
-            target_id,
-            serde_json::to_string(&request_json)?,
-            response_json.map(|v| serde_json::to_string(&v)).transpose()?,
-            bool_to_i64(success),
-            error_message,
-            now_iso()
-        ],
-    )?;
-    Ok(())
-}
+        let recent_outbound = get_conversation_history_with_conn(
+            &conn,
+            Some("OUTBOUND"),
+            Some("2030-04-02T00:00:00Z"),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(leads(&recent_outbound), vec![first, second]);
+
+        let page = get_conversation_history_with_conn(&conn, None, None, Some(2), None).unwrap();
+        assert_eq!(page.messages.len(), 2);
+        let rest = get_conversation_history_with_conn(&conn, None, None, Some(5), page.next_cursor)
+            .unwrap();
+        assert_eq!(leads(&rest), vec![second, first, first]);
+        assert_eq!(rest.next_cursor, None);
+
+        assert!(
+            get_conversation_history_with_conn(&conn, Some("SIDEWAYS"), None, None, None).is_err()
+        );
+    }
 
-fn log_kill_switch_block(
-    conn: &Connection,
-    action_type: &str,
-    target_type: &str,
-    target_id: Option<String>,
-    request_json: Value,
-    detail: &str,
-) {
-    let message = format!("kill switch block: {detail}");
-    let _ = insert_audit(
-        conn,
-        action_type,
-        target_type,
-        target_id,
-        request_json,
-        Some(json!({
-            "blocked_by": "kill_switch",
-            "detail": detail
-        })),
-        false,
-        Some(message),
-    );
-}
+    #[test]
+    fn client_errors_are_stored_in_audit_log_newest_first() {
+        let conn = init_in_memory_db();
+        log_client_error_with_conn(&conn, &RealClock, "first failure", None, "App.tsx").unwrap();
+        log_client_error_with_conn(
+            &conn,
+            &RealClock,
+            "second failure",
+            Some("at refreshAll"),
+            "window.onerror",
+        )
+        .unwrap();
+        insert_audit(
+            &conn,
+            &RealClock,
+            "set_opt_out",
+            "lead",
+            None,
+            json!({}),
+            None,
+            true,
+            None,
+        )
+        .unwrap();
 
-fn open_conn(state: &State<AppState>) -> AppResult<Connection> {
-    open_conn_path(&state.db_path)
-}
+        let errors = get_client_errors_with_conn(&conn, 10).unwrap();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].target_type, "frontend");
+        let request: Value = serde_json::from_str(&errors[0].request_json).unwrap();
+        assert_eq!(
+            request,
+            json!({ "message": "second failure", "stack": "at refreshAll", "source": "window.onerror" })
+        );
+        assert_eq!(get_client_errors_with_conn(&conn, 1).unwrap().len(), 1);
+        assert!(get_client_errors_with_conn(&conn, 0).is_err());
+    }
 
-fn open_conn_path(db_path: &Path) -> AppResult<Connection> {
-    let conn = Connection::open(db_path)?;
-    conn.busy_timeout(StdDuration::from_millis(500))?;
-    conn.pragma_update(None, "foreign_keys", "ON")?;
-    Ok(conn)
-}
+    #[test]
+    fn search_messages_matches_body_case_insensitively() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000156");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        for (direction, body) in [
+            ("OUTBOUND", "Want to book a Free Trial?"),
+            ("INBOUND", "is the free trial really free"),
+            ("INBOUND", "what time do you open"),
+            ("OUTBOUND", "See you at 9"),
+        ] {
+            conn.execute(
+                "INSERT INTO messages (conversation_id, direction, body, status, created_at)
+                 VALUES (?, ?, ?, 'sent', ?)",
+                params![conversation_id, direction, body, now_iso()],
+            )
+            .unwrap();
+        }
 
-fn run_due_jobs_with_conn(conn: &Connection) -> AppResult<RunJobsResult> {
-    let location = get_location(conn)?;
+        let results = search_messages_with_conn(&conn, "  FREE trial ", None, None).unwrap();
+        assert_eq!(
+            results.iter().map(|m| m.body.as_str()).collect::<Vec<_>>(),
+            vec![
+                "is the free trial really free",
+                "Want to book a Free Trial?"
+            ]
+        );
+        assert!(results.iter().all(|m| m.lead_id == lead_id));
+
+        let inbound =
+            search_messages_with_conn(&conn, "free trial", Some("INBOUND"), None).unwrap();
+        assert_eq!(inbound.len(), 1);
+        assert_eq!(inbound[0].direction, "INBOUND");
+
+        assert!(search_messages_with_conn(&conn, "refund", None, None)
+            .unwrap()
+            .is_empty());
+        let err = search_messages_with_conn(&conn, "   ", None, None).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::ValidationFailed);
+    }
 
-    if is_kill_switch_enabled(conn)? {
-        let skipped: i64 = conn.query_row(
-            "SELECT COUNT(*) FROM scheduled_jobs
-             WHERE status='pending' AND datetime(execute_at) <= datetime('now')",
-            params![],
-            |row| row.get(0),
-        )?;
-        if skipped > 0 {
-            log_kill_switch_block(
-                conn,
-                "run_due_jobs",
-                "scheduled_job",
-                None,
-                json!({ "scope": "due_jobs", "skipped": skipped }),
-                "due jobs skipped because automation is paused (safe mode)",
-            );
+    #[test]
+    fn lead_conversation_history_merges_messages_and_transitions() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000157");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        for (direction, body, created_at) in [
+            ("OUTBOUND", "Reply YES to book", "2030-05-01T10:00:00Z"),
+            ("INBOUND", "yes", "2030-05-01T10:10:00Z"),
+            ("OUTBOUND", "Reply 1 or 2", "2030-05-01T10:10:30Z"),
+        ] {
+            conn.execute(
+                "INSERT INTO messages (conversation_id, direction, body, status, created_at)
+                 VALUES (?, ?, ?, 'sent', ?)",
+                params![conversation_id, direction, body, created_at],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            "INSERT INTO lead_status_history (lead_id, from_status, to_status, changed_at, trigger)
+             VALUES (?, 'awaiting_yes', 'awaiting_time_choice', '2030-05-01T10:10:05Z', 'inbound_yes')",
+            params![lead_id],
+        )
+        .unwrap();
+
+        let history = get_lead_conversation_history_with_conn(&conn, lead_id).unwrap();
+        assert_eq!(history.len(), 4);
+        assert!(matches!(&history[1], ConversationHistoryItem::Message(m) if m.body == "yes"));
+        match &history[2] {
+            ConversationHistoryItem::StateTransition {
+                from,
+                to,
+                triggered_by,
+                ..
+            } => {
+                assert_eq!(from, "awaiting_yes");
+                assert_eq!(to, "awaiting_time_choice");
+                assert_eq!(triggered_by, "inbound_yes");
+            }
+            other => panic!("expected a transition, got {other:?}"),
         }
-        return Ok(RunJobsResult {
-            processed: 0,
-            skipped,
-            errors: 0,
-        });
-    }
+        assert!(matches!(&history[3], ConversationHistoryItem::Message(_)));
 
-    let mut stmt = conn.prepare(
-        "SELECT id, job_type, target_id, payload_json
-         FROM scheduled_jobs
-         WHERE status='pending' AND datetime(execute_at) <= datetime('now')
-         ORDER BY datetime(execute_at) ASC",
-    )?;
+        let json = serde_json::to_value(&history[2]).unwrap();
+        assert_eq!(json["kind"], "state_transition");
+        assert!(get_lead_conversation_history_with_conn(&conn, 9999).is_err());
+    }
 
-    let mut jobs: Vec<(i64, String, Option<i64>, String)> = Vec::new();
-    let mapped = stmt.query_map(params![], |row| {
-        Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
-    })?;
-    for item in mapped {
-        jobs.push(item?);
+    #[test]
+    fn list_appointments_filters_by_start_range_and_status() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000158");
+        let monday = insert_booked_appointment(
+            &conn,
+            lead_id,
+            "2030-06-03T14:00:00Z",
+            "2030-06-03T14:30:00Z",
+        );
+        let wednesday = insert_booked_appointment(
+            &conn,
+            lead_id,
+            "2030-06-05T14:00:00Z",
+            "2030-06-05T14:30:00Z",
+        );
+        let friday = insert_booked_appointment(
+            &conn,
+            lead_id,
+            "2030-06-07T14:00:00Z",
+            "2030-06-07T14:30:00Z",
+        );
+        conn.execute(
+            "UPDATE appointments SET status='cancelled' WHERE id=?",
+            params![friday],
+        )
+        .unwrap();
+
+        let ids = |status: Option<&str>, after: Option<&str>, before: Option<&str>| -> Vec<i64> {
+            list_appointments_with_conn(&conn, status, after, before, None)
+                .unwrap()
+                .iter()
+                .map(|appointment| appointment.id)
+                .collect()
+        };
+        assert_eq!(ids(None, None, None), vec![monday, wednesday, friday]);
+        assert_eq!(
+            ids(None, Some("2030-06-03T14:00:00Z"), None),
+            vec![wednesday, friday]
+        );
+        assert_eq!(
+            ids(
+                None,
+                Some("2030-06-04T00:00:00Z"),
+                Some("2030-06-06T00:00:00Z")
+            ),
+            vec![wednesday]
+        );
+        assert_eq!(ids(Some("booked"), None, None), vec![monday, wednesday]);
+        assert_eq!(ids(Some("cancelled"), None, None), vec![friday]);
+
+        let detail = &list_appointments_with_conn(&conn, None, None, None, Some(1)).unwrap();
+        assert_eq!(detail.len(), 1);
+        assert_eq!(detail[0].phone_e164, "+15550000158");
+        assert!(list_appointments_with_conn(&conn, Some("done"), None, None, None).is_err());
+        // Rescheduling moves the booking in place, so no row is ever left as "rescheduled".
+        assert!(list_appointments_with_conn(&conn, Some("rescheduled"), None, None, None).is_err());
     }
 
-    let mut processed = 0;
-    let mut skipped = 0;
-    let mut errors = 0;
+    #[test]
+    fn retry_failed_job_copies_job_and_cancels_original() {
+        let conn = init_in_memory_db();
+        let gateway = ActionGateway::new(&conn);
+        let failed = insert_job(&conn, "re_engagement", "failed", "2030-01-01T00:00:00Z");
+        conn.execute(
+            "UPDATE scheduled_jobs SET payload_json='{\"lead_id\":7}', target_id=7 WHERE id=?",
+            params![failed],
+        )
+        .unwrap();
+
+        let new_id = gateway
+            .retry_failed_job(failed, Some("2030-02-01T09:00:00Z"))
+            .unwrap();
+        assert_ne!(new_id, failed);
+        let copy = get_scheduled_job(&conn, new_id).unwrap();
+        assert_eq!(copy.status, "pending");
+        assert_eq!(copy.job_type, "re_engagement");
+        assert_eq!(copy.target_id, Some(7));
+        assert_eq!(copy.payload_json, "{\"lead_id\":7}");
+        assert_eq!(
+            parse_ts(&copy.execute_at).unwrap(),
+            ts("2030-02-01T09:00:00Z")
+        );
+        assert_eq!(
+            get_scheduled_job(&conn, failed).unwrap().status,
+            "cancelled"
+        );
 
-    for (job_id, job_type, target_id, payload_json) in jobs {
-        if is_kill_switch_enabled(conn)? {
-            skipped += 1;
-            log_kill_switch_block(
-                conn,
-                "run_scheduled_job",
-                "scheduled_job",
-                Some(job_id.to_string()),
-                json!({
-                    "job_type": job_type,
-                    "target_id": target_id,
-                    "payload_json": payload_json
-                }),
-                "scheduled job skipped because automation is paused (safe mode)",
-            );
-            continue;
-        }
+        let err = gateway.retry_failed_job(failed, None).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::ValidationFailed);
+
+        let other = insert_job(&conn, "re_engagement", "failed", "2030-01-01T00:00:00Z");
+        let soon = gateway.retry_failed_job(other, None).unwrap();
+        let delay =
+            parse_ts(&get_scheduled_job(&conn, soon).unwrap().execute_at).unwrap() - Utc::now();
+        assert!(delay > Duration::seconds(50) && delay <= Duration::seconds(60));
+
+        let blocked = insert_job(&conn, "re_engagement", "failed", "2030-01-01T00:00:00Z");
+        set_kill_switch_with_conn(&conn, &RealClock, true, None).unwrap();
+        let err = gateway.retry_failed_job(blocked, None).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::KillSwitch);
+        assert_eq!(get_scheduled_job(&conn, blocked).unwrap().status, "failed");
+        set_kill_switch_with_conn(&conn, &RealClock, false, None).unwrap();
+
+        // The copy is only kept if the original can be cancelled with it.
+        conn.execute_batch(
+            "CREATE TRIGGER jobs_locked BEFORE UPDATE OF status ON scheduled_jobs
+             WHEN NEW.status = 'cancelled'
+             BEGIN SELECT RAISE(ABORT, 'jobs locked'); END;",
+        )
+        .unwrap();
+        let pending_before: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM scheduled_jobs WHERE status='pending'",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let err = gateway.retry_failed_job(blocked, None).unwrap_err();
+        assert!(err.to_string().contains("jobs locked"), "{err}");
+        assert_eq!(get_scheduled_job(&conn, blocked).unwrap().status, "failed");
+        let pending_after: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM scheduled_jobs WHERE status='pending'",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(pending_after, pending_before);
+    }
 
-        let run_result = match job_type.as_str() {
-            "initial_follow_up" => {
-                let payload: InitialFollowUpPayload = serde_json::from_str(&payload_json)?;
-                execute_initial_follow_up(conn, &location, payload.lead_id)
-            }
-            "appointment_reminder" => {
-                let payload: ReminderPayload = serde_json::from_str(&payload_json)?;
-                execute_appointment_reminder(conn, &location, payload)
-            }
-            _ => Err(AppError::Validation(format!("unknown job_type: {job_type}"))),
+    #[test]
+    fn set_lead_consent_revoke_cancels_all_pending_jobs_and_regrant_requeues() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000159");
+        conn.execute(
+            "UPDATE leads SET consent_at='2029-12-01T00:00:00+00:00', consent_source='web form' WHERE id=?",
+            params![lead_id],
+        )
+        .unwrap();
+        let appointment_id = insert_booked_appointment(
+            &conn,
+            lead_id,
+            "2030-01-05T15:00:00Z",
+            "2030-01-05T15:30:00Z",
+        );
+        for (job_type, target_id) in [
+            ("initial_follow_up", lead_id),
+            ("re_engagement", lead_id),
+            ("appointment_reminder", appointment_id),
+        ] {
+            conn.execute(
+                "INSERT INTO scheduled_jobs (job_type, target_id, execute_at, status, payload_json, created_at)
+                 VALUES (?, ?, '2030-01-05T13:00:00Z', 'pending', '{}', '2030-01-01T00:00:00Z')",
+                params![job_type, target_id],
+            )
+            .unwrap();
+        }
+        let pending_jobs = |conn: &Connection| -> i64 {
+            conn.query_row(
+                "SELECT COUNT(*) FROM scheduled_jobs WHERE status='pending'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap()
         };
 
-        match run_result {
-            Ok(()) => {
-                processed += 1;
-                conn.execute(
-                    "UPDATE scheduled_jobs SET status='completed' WHERE id=?",
-                    params![job_id],
-                )?;
-            }
-            Err(err) => {
-                errors += 1;
-                conn.execute(
-                    "UPDATE scheduled_jobs SET status='failed' WHERE id=?",
-                    params![job_id],
-                )?;
-                let _ = insert_audit(
-                    conn,
-                    "run_scheduled_job",
-                    "scheduled_job",
-                    Some(job_id.to_string()),
-                    json!({
-                        "job_type": job_type,
-                        "target_id": target_id,
-                        "payload_json": payload_json
-                    }),
-                    None,
-                    false,
-                    Some(err.to_string()),
-                );
-            }
-        }
+        let err = set_lead_consent_with_conn(
+            &conn,
+            &RealClock,
+            lead_id,
+            false,
+            "2030-01-02T12:00:00Z",
+            "phone call",
+            "  ",
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::ValidationFailed);
+        assert_eq!(pending_jobs(&conn), 3);
+        let (success, error): (i64, Option<String>) = conn
+            .query_row(
+                "SELECT success, error_message FROM audit_log WHERE action_type='set_lead_consent'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(success, 0);
+        assert!(error.unwrap().contains("collected_by is required"));
+
+        let revoked = set_lead_consent_with_conn(
+            &conn,
+            &RealClock,
+            lead_id,
+            false,
+            "2030-01-02T12:00:00Z",
+            "phone call",
+            "Dana at front desk",
+        )
+        .unwrap();
+        assert_eq!(revoked.follow_ups_cancelled, 3);
+        assert_eq!(pending_jobs(&conn), 0);
+        assert!(!get_lead(&conn, lead_id).unwrap().consent);
+
+        let request_json: String = conn
+            .query_row(
+                "SELECT request_json FROM audit_log WHERE action_type='set_lead_consent' ORDER BY id DESC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let request: Value = serde_json::from_str(&request_json).unwrap();
+        assert_eq!(request["previous"]["consent"], json!(true));
+        assert_eq!(request["previous"]["consent_source"], json!("web form"));
+        assert_eq!(request["collected_by"], json!("Dana at front desk"));
+
+        let regranted = set_lead_consent_with_conn(
+            &conn,
+            &RealClock,
+            lead_id,
+            true,
+            "2030-01-03T12:00:00Z",
+            "signed paper form",
+            "Dana at front desk",
+        )
+        .unwrap();
+        assert!(regranted.follow_up_scheduled);
+        assert_eq!(pending_jobs(&conn), 1);
+        let source: String = conn
+            .query_row(
+                "SELECT consent_source FROM leads WHERE id=?",
+                params![lead_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(source, "signed paper form");
     }
 
-    Ok(RunJobsResult {
-        processed,
-        skipped,
-        errors,
-    })
-}
+    #[test]
+    fn completed_appointment_asks_for_rating_and_stores_reply() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000160");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let appointment_id = insert_past_booked_appointment(&conn, lead_id);
+
+        let gateway = ActionGateway::new(&conn);
+        gateway.mark_appointment_completed(appointment_id).unwrap();
+        assert_eq!(appointment_status(&conn, appointment_id), "completed");
+        assert!(gateway.mark_appointment_completed(appointment_id).is_err());
+
+        let result = run_due_jobs_with_conn(&conn, &RealClock).unwrap();
+        assert_eq!((result.processed, result.errors), (1, 0));
+        let detail = get_lead_detail_with_conn(&conn, lead_id).unwrap();
+        assert_eq!(detail.conversation.state, "awaiting_rating");
+        assert_eq!(detail.lead.status, "awaiting_rating");
+
+        // The member replies a few minutes after the rating request.
+        let reply_clock = FakeClock(Utc::now() + Duration::minutes(5));
+        test_process_inbound_state_machine(&conn, &reply_clock, lead_id, "great").unwrap();
+        assert_eq!(
+            get_lead_detail_with_conn(&conn, lead_id)
+                .unwrap()
+                .conversation
+                .state,
+            "awaiting_rating"
+        );
 
-fn initialize_db(db_path: &Path) -> AppResult<()> {
-    if let Some(parent) = db_path.parent() {
-        fs::create_dir_all(parent).map_err(|e| AppError::Validation(e.to_string()))?;
+        let second_reply_clock = FakeClock(reply_clock.0 + Duration::minutes(5));
+        test_process_inbound_state_machine(&conn, &second_reply_clock, lead_id, "4").unwrap();
+        let (rated_appointment, rating): (i64, i64) = conn
+            .query_row(
+                "SELECT appointment_id, rating FROM appointment_ratings WHERE lead_id=?",
+                params![lead_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((rated_appointment, rating), (appointment_id, 4));
+
+        let last_body: String = conn
+            .query_row(
+                "SELECT body FROM messages WHERE conversation_id=? AND direction='OUTBOUND'
+                 ORDER BY id DESC LIMIT 1",
+                params![conversation_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(last_body, "Thanks for your feedback!");
+        let detail = get_lead_detail_with_conn(&conn, lead_id).unwrap();
+        assert_eq!(detail.conversation.state, "awaiting_yes");
+        assert_eq!(detail.lead.status, "awaiting_yes");
+
+        let transitions: Vec<(String, String)> = get_lead_timeline_with_conn(&conn, lead_id)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.to_status, entry.trigger))
+            .collect();
+        assert_eq!(
+            transitions[transitions.len() - 2..],
+            [
+                (
+                    "awaiting_rating".to_string(),
+                    "post_appointment_follow_up".to_string()
+                ),
+                ("awaiting_yes".to_string(), "appointment_rating".to_string()),
+            ]
+        );
     }
 
-    let conn = Connection::open(db_path)?;
-    conn.pragma_update(None, "journal_mode", "WAL")?;
-    conn.pragma_update(None, "foreign_keys", "ON")?;
-    conn.execute_batch(include_str!("../migrations/001_init.sql"))?;
+    #[test]
+    fn not_found_errors_are_distinct_from_validation_errors() {
+        let conn = init_in_memory_db();
 
-    let location_count: i64 =
-        conn.query_row("SELECT COUNT(*) FROM locations", params![], |row| {
-            row.get(0)
-        })?;
-    if location_count == 0 {
-        conn.execute(
-            "INSERT INTO locations (gym_name, timezone, business_hours_json) VALUES (?, ?, ?)",
-            params![
-                default_gym_name(),
-                default_timezone(),
-                default_business_hours_json()
-            ],
-        )?;
+        let missing_lead = get_lead_summary(&conn, 9_999).unwrap_err();
+        assert!(matches!(missing_lead, AppError::NotFound(_)));
+        assert!(missing_lead.is_not_found());
+        let missing_conversation = get_conversation_by_lead_id(&conn, 9_999).unwrap_err();
+        assert!(missing_conversation.is_not_found());
+        assert!(AppError::Db(rusqlite::Error::QueryReturnedNoRows).is_not_found());
+
+        let response: Value =
+            serde_json::from_str(&error_response_json(&missing_lead, "get_lead_detail")).unwrap();
+        assert_eq!(response["code"], "not_found");
+        assert_eq!(response["status"], 404);
+        assert_eq!(response["message"], "lead not found");
+
+        let invalid = AppError::Validation("lead not found in import file".to_string());
+        assert!(!invalid.is_not_found());
+        assert_eq!(invalid.code(), ErrorCode::ValidationFailed);
+        let response: Value =
+            serde_json::from_str(&error_response_json(&invalid, "bulk_create_leads")).unwrap();
+        assert_eq!(response["code"], "validation_failed");
+        assert!(response.get("status").is_none());
     }
 
-    conn.execute(
-        "INSERT INTO settings (key, value, updated_at)
-         VALUES ('kill_switch', 'true', ?)
-         ON CONFLICT(key) DO NOTHING",
-        params![now_iso()],
-    )?;
+    #[test]
+    fn agent_dry_run_warnings_flag_each_boundary_condition() {
+        let conn = init_in_memory_db();
+        set_business_hours(
+            &conn,
+            r#"{"mon":[["09:00","17:00"]],"tue":[["09:00","17:00"]],"wed":[["09:00","17:00"]],"thu":[["09:00","17:00"]],"fri":[["09:00","17:00"]]}"#,
+        );
+        // Wednesday 12:00 and 16:45 in New York.
+        let midday = ts("2030-01-02T17:00:00Z");
+        let closing = ts("2030-01-02T21:45:00Z");
+        let lead_id = insert_lead(&conn, "+15550000161");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let action = AgentAction::SendOutbound {
+            lead_id,
+            conversation_id,
+            body: "Still interested?".to_string(),
+            automated: false,
+            allow_without_consent: false,
+            allow_opted_out_once: false,
+            allow_after_reply: false,
+            ignore_business_hours: false,
+        };
+        let warnings = |now| agent_dry_run_warnings(&conn, &action, now).unwrap();
 
-    Ok(())
-}
+        assert!(warnings(midday).is_empty());
+        assert_eq!(
+            warnings(closing),
+            vec!["Business closes in less than 30 minutes".to_string()]
+        );
 
-fn ensure_app_data_dir(app: &AppHandle) -> Result<PathBuf, String> {
-    let app_dir = app
-        .path_resolver()
-        .app_local_data_dir()
-        .ok_or_else(|| "failed to resolve app local data dir".to_string())?;
-    fs::create_dir_all(&app_dir).map_err(|err| format!("failed to create app data dir: {err}"))?;
-    Ok(app_dir)
-}
+        conn.execute(
+            "UPDATE conversations SET last_outbound_at='2030-01-02T15:00:00Z',
+                    last_inbound_at='2030-01-01T15:00:00Z'
+             WHERE id=?",
+            params![conversation_id],
+        )
+        .unwrap();
+        assert_eq!(
+            warnings(midday),
+            vec!["Lead has not replied to last outbound".to_string()]
+        );
+        conn.execute(
+            "UPDATE conversations SET last_outbound_at=NULL, last_inbound_at=NULL WHERE id=?",
+            params![conversation_id],
+        )
+        .unwrap();
 
-fn map_cmd_result<T: Serialize>(
-    result: AppResult<T>,
-    action_name: &str,
-    app: &AppHandle,
-) -> Result<T, String> {
-    match result {
-        Ok(value) => Ok(value),
-        Err(err) => {
-            let message = format!("Alert: {err}");
-            log_command_failure(app, action_name, &message);
-            Err(message)
+        for _ in 0..3 {
+            conn.execute(
+                "INSERT INTO messages (conversation_id, direction, body, status, created_at)
+                 VALUES (?, 'OUTBOUND', 'hi', 'sent', ?)",
+                params![conversation_id, midday.to_rfc3339()],
+            )
+            .unwrap();
         }
+        assert_eq!(
+            warnings(midday),
+            vec!["Lead has 3 of 4 daily outbound messages used".to_string()]
+        );
+
+        let opt_out = AgentAction::SetOptOut {
+            lead_id,
+            reason: "asked".to_string(),
+        };
+        assert!(agent_dry_run_warnings(&conn, &opt_out, closing)
+            .unwrap()
+            .is_empty());
     }
-}
 
-fn log_command_failure(app: &AppHandle, action_name: &str, message: &str) {
-    if let Some(state) = app.try_state::<AppState>() {
-        if let Ok(conn) = Connection::open(&state.db_path) {
-            let _ = insert_audit(
-                &conn,
-                action_name,
-                "command",
-                None,
-                json!({ "action": action_name }),
-                None,
-                false,
-                Some(message.to_string()),
-            );
-        }
+    #[test]
+    fn update_location_timezone_change_flags_jobs_that_would_be_past_due() {
+        let conn = init_in_memory_db();
+        let location_id = get_location(&conn).unwrap().id;
+        let lead_id = insert_lead(&conn, "+15550000162");
+        let appointment_id = insert_booked_appointment(
+            &conn,
+            lead_id,
+            "2030-01-02T20:00:00Z",
+            "2030-01-02T20:30:00Z",
+        );
+        let insert = |job_type: &str, target_id: i64, execute_at: &str| -> i64 {
+            conn.execute(
+                "INSERT INTO scheduled_jobs (job_type, target_id, execute_at, status, payload_json, created_at)
+                 VALUES (?, ?, ?, 'pending', '{}', '2030-01-01T00:00:00Z')",
+                params![job_type, target_id, execute_at],
+            )
+            .unwrap();
+            conn.last_insert_rowid()
+        };
+        // 10:00 and 14:00 in New York; read as London time they become 10:00Z and 14:00Z.
+        let follow_up = insert("initial_follow_up", lead_id, "2030-01-02T15:00:00Z");
+        let reminder = insert(
+            "appointment_reminder",
+            appointment_id,
+            "2030-01-02T19:00:00Z",
+        );
+        let now = ts("2030-01-02T12:00:00Z");
+
+        let result = update_location_with_conn(
+            &conn,
+            &RealClock,
+            location_id,
+            &LocationPatch {
+                timezone: Some("Europe/London".to_string()),
+                ..LocationPatch::default()
+            },
+            now,
+        )
+        .unwrap();
+        assert_eq!(result.location.timezone, "Europe/London");
+        let flagged: Vec<i64> = result.past_due_jobs.iter().map(|job| job.id).collect();
+        assert_eq!(flagged, vec![follow_up]);
+        assert_eq!(
+            get_scheduled_job(&conn, follow_up).unwrap().execute_at,
+            "2030-01-02T15:00:00Z"
+        );
+        assert_eq!(
+            get_scheduled_job(&conn, reminder).unwrap().status,
+            "pending"
+        );
+
+        let renamed = update_location_with_conn(
+            &conn,
+            &RealClock,
+            location_id,
+            &LocationPatch {
+                gym_name: Some("Iron Temple".to_string()),
+                ..LocationPatch::default()
+            },
+            ts("2030-01-02T18:00:00Z"),
+        )
+        .unwrap();
+        assert_eq!(renamed.location.gym_name, "Iron Temple");
+        assert!(renamed.past_due_jobs.is_empty());
     }
-}
 
-fn retry_db<T, F>(mut f: F) -> AppResult<T>
-where
-    F: FnMut() -> AppResult<T>,
-{
-    let mut attempt = 0;
-    loop {
-        attempt += 1;
-        match f() {
-            Ok(value) => return Ok(value),
-            Err(err) if err.is_busy_or_locked() && attempt < 5 => {
-                thread::sleep(StdDuration::from_millis((attempt * 40) as u64));
-            }
-            Err(err) => return Err(err),
-        }
+    #[test]
+    fn agent_execute_batch_rolls_back_when_a_later_action_fails() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000163");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let send = AgentAction::SendOutbound {
+            lead_id,
+            conversation_id,
+            body: "Want to come in this week?".to_string(),
+            automated: false,
+            allow_without_consent: false,
+            allow_opted_out_once: false,
+            allow_after_reply: false,
+            ignore_business_hours: false,
+        };
+        let follow_up = AgentAction::ScheduleJob {
+            job_type: "re_engagement".to_string(),
+            target_id: Some(lead_id),
+            execute_at: "2030-01-03T15:00:00Z".to_string(),
+            payload_json: json!({ "lead_id": lead_id }).to_string(),
+        };
+        let outbound_count = |conn: &Connection| -> i64 {
+            conn.query_row(
+                "SELECT COUNT(*) FROM messages WHERE conversation_id=? AND direction='OUTBOUND'",
+                params![conversation_id],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+
+        let bypass = AgentAction::SendOutbound {
+            lead_id,
+            conversation_id,
+            body: "sneaky".to_string(),
+            automated: false,
+            allow_without_consent: true,
+            allow_opted_out_once: false,
+            allow_after_reply: false,
+            ignore_business_hours: false,
+        };
+        let aborted =
+            agent_execute_batch_with_conn(&conn, &RealClock, &[send.clone(), bypass]).unwrap();
+        assert_eq!(aborted.batch_aborted_at, Some(1));
+        assert_eq!(aborted.results.len(), 2);
+        assert!(aborted.results[0].success);
+        assert!(!aborted.results[1].success);
+        assert_eq!(outbound_count(&conn), 0);
+        let last_outbound_at: Option<String> = conn
+            .query_row(
+                "SELECT last_outbound_at FROM conversations WHERE id=?",
+                params![conversation_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(last_outbound_at, None);
+
+        let committed =
+            agent_execute_batch_with_conn(&conn, &RealClock, &[send, follow_up]).unwrap();
+        assert_eq!(committed.batch_aborted_at, None);
+        assert!(committed.results.iter().all(|result| result.success));
+        assert_eq!(outbound_count(&conn), 1);
+        let jobs: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM scheduled_jobs WHERE job_type='re_engagement' AND target_id=?",
+                params![lead_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(jobs, 1);
+
+        let audited: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM audit_log WHERE action_type='agent_execute_batch'",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(audited, 2);
+        assert!(agent_execute_batch_with_conn(&conn, &RealClock, &[]).is_err());
     }
-}
 
-fn now_iso() -> String {
-    Utc::now().to_rfc3339()
-}
+    #[test]
+    fn get_available_slots_lists_every_open_slot_in_the_window() {
+        let conn = init_in_memory_db();
+        set_business_hours(
+            &conn,
+            r#"{"mon":[["09:00","11:00"]],"tue":[["09:00","10:00"]],"wed":[["09:00","10:00"]],"thu":[["09:00","10:00"]],"fri":[],"sat":[],"sun":[]}"#,
+        );
+        let lead_id = insert_lead(&conn, "+15550000164");
+        // 09:40 New York on Monday 2030-01-07.
+        insert_booked_appointment(
+            &conn,
+            lead_id,
+            "2030-01-07T14:40:00Z",
+            "2030-01-07T15:10:00Z",
+        );
+        let from = Some("2030-01-07T12:00:00Z");
+
+        let one_day = get_available_slots_with_conn(&conn, from, Some(1), Utc::now()).unwrap();
+        let starts: Vec<DateTime<Utc>> = one_day
+            .iter()
+            .map(|slot| parse_ts(&slot.start_at).unwrap())
+            .collect();
+        // 09:00 fits before the booking; 09:40 is taken; 10:20 clears the buffer.
+        assert_eq!(
+            starts,
+            vec![ts("2030-01-07T14:00:00Z"), ts("2030-01-07T15:20:00Z")]
+        );
 
-fn parse_ts(input: &str) -> AppResult<DateTime<Utc>> {
-    Ok(DateTime::parse_from_rfc3339(input)?.with_timezone(&Utc))
-}
+        let default_window = get_available_slots_with_conn(&conn, from, None, Utc::now()).unwrap();
+        // Monday keeps two slots; Tuesday and Wednesday only fit 09:00.
+        assert_eq!(default_window.len(), 2 + 1 + 1);
+        // Four open days a week: the first week has 5 slots, full weeks 6, then Mon + Tue.
+        assert_eq!(
+            get_available_slots_with_conn(&conn, from, Some(14), Utc::now())
+                .unwrap()
+                .len(),
+            5 + 6 + 6 + 3 + 1
+        );
+        assert!(get_available_slots_with_conn(&conn, from, Some(15), Utc::now()).is_err());
+        assert!(get_available_slots_with_conn(&conn, from, Some(0), Utc::now()).is_err());
 
-fn bool_to_i64(v: bool) -> i64 {
-    if v {
-        1
-    } else {
-        0
+        assert!(!check_slot_available_with_conn(
+            &conn,
+            "2030-01-07T15:00:00Z",
+            "2030-01-07T15:30:00Z"
+        )
+        .unwrap());
+        assert!(check_slot_available_with_conn(
+            &conn,
+            "2030-01-07T15:20:00Z",
+            "2030-01-07T15:50:00Z"
+        )
+        .unwrap());
+        assert!(check_slot_available_with_conn(
+            &conn,
+            "2030-01-07T15:50:00Z",
+            "2030-01-07T15:20:00Z"
+        )
+        .is_err());
     }
-}
 
-fn i64_to_bool(v: i64) -> bool {
-    v != 0
-}
+    #[test]
+    fn list_agent_actions_returns_a_leads_actions_newest_first() {
+        let conn = init_in_memory_db();
+        let gateway = ActionGateway::new(&conn);
+        let lead_id = insert_lead(&conn, "+15550000165");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let other_lead = insert_lead(&conn, "+15550000166");
+        insert_conversation(&conn, other_lead);
+
+        let message_id = gateway
+            .create_outbound_message_for_agent(OutboundRequest {
+                lead_id,
+                conversation_id,
+                body: "Want to book an intro session?".to_string(),
+                automated: false,
+                allow_without_consent: false,
+                allow_opted_out_once: false,
+                allow_after_reply: false,
+                ignore_business_hours: false,
+                rate_limit_exempt: false,
+            })
+            .unwrap();
+        gateway
+            .schedule_job(ScheduleJobRequest {
+                job_type: "re_engagement".to_string(),
+                target_id: Some(lead_id),
+                execute_at: "2030-01-03T15:00:00Z".to_string(),
+                payload_json: json!({ "lead_id": lead_id }).to_string(),
+            })
+            .unwrap();
+        gateway
+            .set_opt_out(OptOutRequest {
+                lead_id: other_lead,
+                reason: "asked".to_string(),
+            })
+            .unwrap();
+        gateway
+            .set_opt_out(OptOutRequest {
+                lead_id,
+                reason: "asked".to_string(),
+            })
+            .unwrap();
 
-fn parse_tz(tz_name: &str) -> AppResult<Tz> {
-    tz_name
-        .parse::<Tz>()
-        .map_err(|_| AppError::Validation(format!("invalid timezone: {tz_name}")))
-}
+        let records = list_agent_actions_with_conn(&conn, Some(lead_id), None, None).unwrap();
+        let actions: Vec<&str> = records
+            .iter()
+            .map(|record| record.action_type.as_str())
+            .collect();
+        assert_eq!(
+            actions,
+            vec!["set_opt_out", "schedule_job", "create_outbound_message"]
+        );
+        assert!(records.iter().all(|record| record.success));
+        assert_eq!(records[2].target_type, "conversation");
+        assert_eq!(
+            records[2].response_json.as_ref().unwrap()["message_id"],
+            json!(message_id)
+        );
+        assert_eq!(records[0].request_json["lead_id"], json!(lead_id));
 
-fn default_gym_name() -> &'static str {
-    "Demo Gym Downtown"
-}
+        let everyone = list_agent_actions_with_conn(&conn, None, None, None).unwrap();
+        assert_eq!(everyone.len(), 4);
+        assert_eq!(
+            list_agent_actions_with_conn(&conn, None, None, Some(2))
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(
+            list_agent_actions_with_conn(&conn, None, Some("2999-01-01T00:00:00Z"), None)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(list_agent_actions_with_conn(&conn, None, None, Some(0)).is_err());
+    }
 
-fn default_timezone() -> &'static str {
-    "America/New_York"
-}
+    #[test]
+    fn process_inbound_uses_injected_clock_for_slots_and_expiry() {
+        let conn = init_in_memory_db();
+        set_business_hours(
+            &conn,
+            r#"{"mon":[["09:00","11:00"]],"tue":[],"wed":[],"thu":[],"fri":[],"sat":[],"sun":[]}"#,
+        );
+        let lead_id = insert_lead(&conn, "+15550000167");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let lead = get_lead(&conn, lead_id).unwrap();
+        // 07:00 on Monday 2030-01-07 in New York.
+        let monday_morning = FakeClock(ts("2030-01-07T12:00:00Z"));
+
+        let conversation = get_conversation_by_lead_id(&conn, lead_id).unwrap();
+        process_inbound_state_machine(&conn, &monday_morning, &lead, &conversation, "YES").unwrap();
+        let conversation = get_conversation_by_lead_id(&conn, lead_id).unwrap();
+        assert_eq!(conversation.state, "awaiting_time_choice");
+        let state: ConversationState = serde_json::from_str(&conversation.state_json).unwrap();
+        let offered: Vec<DateTime<Utc>> = state
+            .offered_slots
+            .iter()
+            .map(|slot| parse_ts(&slot.start_at).unwrap())
+            .collect();
+        assert_eq!(
+            offered,
+            vec![ts("2030-01-07T14:00:00Z"), ts("2030-01-07T14:40:00Z")]
+        );
+        assert_eq!(
+            parse_ts(conversation.last_outbound_at.as_deref().unwrap()).unwrap(),
+            monday_morning.now()
+        );
 
-fn null_if_empty(s: &str) -> Option<String> {
-    let trimmed = s.trim();
-    if trimmed.is_empty() {
-        None
-    } else {
-        Some(trimmed.to_string())
+        // A day and an hour later the choice has gone stale, so "1" restarts the flow.
+        let next_day = FakeClock(ts("2030-01-08T13:00:00Z"));
+        process_inbound_state_machine(&conn, &next_day, &lead, &conversation, "1").unwrap();
+        let conversation = get_conversation_by_lead_id(&conn, lead_id).unwrap();
+        assert_eq!(conversation.state, "awaiting_yes");
+        let booked: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM appointments WHERE lead_id=?",
+                params![lead_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(booked, 0);
+        let last_body: String = conn
+            .query_row(
+                "SELECT body FROM messages WHERE conversation_id=? ORDER BY id DESC LIMIT 1",
+                params![conversation_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(
+            last_body,
+            render_template(&conn, "reply_yes_prompt", HashMap::new()).unwrap()
+        );
     }
-}
 
-fn default_business_hours_json() -> &'static str {
-    r#"{"mon":[["09:00","17:00"]],"tue":[["09:00","17:00"]],"wed":[["09:00","17:00"]],"thu":[["09:00","17:00"]],"fri":[["09:00","17:00"]],"sat":[["10:00","14:00"]],"sun":[]}"#
-}
+    #[test]
+    fn agent_execute_replays_result_for_repeated_idempotency_key() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000168");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let request = |key: Option<&str>| AgentExecuteRequest {
+            action: AgentAction::SendOutbound {
+                lead_id,
+                conversation_id,
+                body: "Your intro session is on us this week.".to_string(),
+                automated: false,
+                allow_without_consent: false,
+                allow_opted_out_once: false,
+                allow_after_reply: false,
+                ignore_business_hours: false,
+            },
+            idempotency_key: key.map(str::to_string),
+        };
+        let outbound_count = || -> i64 {
+            conn.query_row(
+                "SELECT COUNT(*) FROM messages WHERE conversation_id=? AND direction='OUTBOUND'",
+                params![conversation_id],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
 
-fn ensure_primary_location(conn: &Connection) -> AppResult<i64> {
-    let existing_id: Option<i64> = conn
-        .query_row(
-            "SELECT id FROM locations ORDER BY id LIMIT 1",
+        let first = agent_execute_with_conn(&conn, &RealClock, &request(Some("retry-1"))).unwrap();
+        assert!(first.success);
+        let replay = agent_execute_with_conn(&conn, &RealClock, &request(Some("retry-1"))).unwrap();
+        assert!(replay.success);
+        assert_eq!(replay.result_json, first.result_json);
+        assert_eq!(outbound_count(), 1);
+
+        // Without a key the duplicate runs and is refused by the gateway's own checks.
+        let unkeyed = agent_execute_with_conn(&conn, &RealClock, &request(None)).unwrap();
+        assert!(!unkeyed.success);
+        assert_eq!(outbound_count(), 1);
+
+        conn.execute(
+            "UPDATE idempotency_records SET created_at='2000-01-01T00:00:00Z'",
             params![],
-            |row| row.get(0),
         )
-        .optional()?;
+        .unwrap();
+        let expired =
+            agent_execute_with_conn(&conn, &RealClock, &request(Some("retry-1"))).unwrap();
+        assert!(!expired.success);
 
-    if let Some(id) = existing_id {
-        return Ok(id);
+        let records: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM idempotency_records",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(records, 0);
+
+        let opt_out = AgentExecuteRequest {
+            action: AgentAction::SetOptOut {
+                lead_id,
+                reason: "asked by phone".to_string(),
+            },
+            idempotency_key: Some("retry-2".to_string()),
+        };
+        assert!(
+            agent_execute_with_conn(&conn, &RealClock, &opt_out)
+                .unwrap()
+                .success
+        );
+        // The same key with a different action is refused rather than replaying the opt-out.
+        let err =
+            agent_execute_with_conn(&conn, &RealClock, &request(Some("retry-2"))).unwrap_err();
+        assert!(matches!(err, AppError::Validation(_)), "{err}");
+        assert_eq!(outbound_count(), 1);
     }
 
-    conn.execute(
-        "INSERT INTO locations (gym_name, timezone, business_hours_json) VALUES (?, ?, ?)",
-        params![
-            default_gym_name(),
-            default_timezone(),
-            default_business_hours_json()
-        ],
-    )?;
+    #[test]
+    fn run_migrations_records_filename_and_checksum() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn, &MIGRATIONS[..2]).unwrap();
 
-    Ok(conn.last_insert_rowid())
-}
+        let applied = list_applied_migrations_with_conn(&conn).unwrap();
+        assert_eq!(applied[0].filename.as_deref(), Some("001_init.sql"));
+        assert_eq!(
+            applied[1].filename.as_deref(),
+            Some("002_lead_workflow_schema.sql")
+        );
+        let checksum = applied[0].checksum.clone().unwrap();
+        assert_eq!(checksum.len(), 64);
+        assert_eq!(checksum, migration_checksum(MIGRATIONS[0].sql));
+        assert_ne!(applied[1].checksum.as_deref(), Some(checksum.as_str()));
 
-fn main() {
-    tauri::Builder::default()
-        .setup(|app| {
-            let app_dir = ensure_app_data_dir(&app.handle()).map_err(AppError::Validation)?;
-            let db_path = app_dir.join("db").join("goldbot.sqlite");
-            initialize_db(&db_path)?;
-            app.manage(AppState { db_path });
-            let app_handle = app.handle();
-            tauri::async_runtime::spawn(async move {
-                loop {
-                    let _ = tauri::async_runtime::spawn_blocking(|| {
-                        thread::sleep(StdDuration::from_secs(15));
-                    })
-                    .await;
-                    let db_path = {
-                        let state = app_handle.state::<AppState>();
-                        state.db_path.clone()
-                    };
+        // Databases migrated before checksums were tracked get them filled in on the next start.
+        conn.execute(
+            "UPDATE schema_migrations SET filename=NULL, checksum=NULL",
+            params![],
+        )
+        .unwrap();
+        run_migrations(&conn, MIGRATIONS).unwrap();
+        let applied = list_applied_migrations_with_conn(&conn).unwrap();
+        assert_eq!(applied.len(), MIGRATIONS.len());
+        assert!(applied
+            .iter()
+            .zip(MIGRATIONS)
+            .all(
+                |(record, migration)| record.filename.as_deref() == Some(migration.file)
+                    && record.checksum == Some(migration_checksum(migration.sql))
+            ));
+        assert!(applied.iter().all(|record| record.error.is_none()));
 
-                    if let Err(err) = retry_db(|| {
-                        let conn = open_conn_path(&db_path)?;
-                        run_due_jobs_with_conn(&conn)
-                    }) {
-                        let message = format!("Alert: {err}");
-                        log_command_failure(&app_handle, "run_due_jobs_background", &message);
-                    }
-                }
-            });
-            Ok(())
-        })
-        .invoke_handler(tauri::generate_handler![
-            create_lead,
-            list_leads,
-            search_leads,
-            list_agent_queue,
-            get_lead_detail,
-            simulate_inbound_sms,
-            get_today_report,
-            get_kill_switch,
-            get_location_settings,
-            update_location_settings,
-            set_kill_switch,
-            export_db_path,
-            wipe_all_data_confirmed,
-            log_client_error,
-            open_devtools,
-            run_due_jobs,
-            agent_dry_run,
-            agent_execute
-        ])
-        .run(tauri::generate_context!())
-        .expect("error while running Gym Lead Booker app");
-}
+        conn.execute(
+            "UPDATE schema_migrations SET checksum='edited' WHERE version=2",
+            params![],
+        )
+        .unwrap();
+        run_migrations(&conn, MIGRATIONS).unwrap();
+        let applied = list_applied_migrations_with_conn(&conn).unwrap();
+        assert_eq!(
+            applied[1].error.as_deref(),
+            Some("checksum mismatch: 002_lead_workflow_schema.sql was edited after it was applied")
+        );
+        assert!(applied[0].error.is_none());
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use super::test_helpers::init_in_memory_db;
+    #[test]
+    fn rating_follow_up_works_after_demo_reset() {
+        let mut conn = init_in_memory_db();
+        reset_demo_data_with_conn(&mut conn, &RealClock).unwrap();
+        conn.execute(
+            "UPDATE locations SET business_hours_json=?, tcpa_mode=0 WHERE id=1",
+            params![r#"{"mon":[["00:00","23:59"]],"tue":[["00:00","23:59"]],"wed":[["00:00","23:59"]],"thu":[["00:00","23:59"]],"fri":[["00:00","23:59"]],"sat":[["00:00","23:59"]],"sun":[["00:00","23:59"]]}"#],
+        )
+        .unwrap();
+        for key in [
+            "post_appointment_follow_up",
+            "rating_repair",
+            "rating_thanks",
+        ] {
+            assert!(
+                get_template_with_conn(&conn, key).is_ok(),
+                "{key} missing after reset"
+            );
+        }
+
+        let lead_id = insert_lead(&conn, "+15550000169");
+        insert_conversation(&conn, lead_id);
+        let appointment_id = insert_past_booked_appointment(&conn, lead_id);
+        let completion = ActionGateway::new(&conn)
+            .mark_appointment_completed(appointment_id)
+            .unwrap();
+        assert!(completion.follow_up_job_id.is_some());
+        assert!(completion.follow_up_error.is_none());
+
+        // The re-seeded housekeeping jobs are due as well.
+        let result = run_due_jobs_with_conn(&conn, &RealClock).unwrap();
+        assert_eq!(result.errors, 0);
+        assert_eq!(
+            get_lead_detail_with_conn(&conn, lead_id)
+                .unwrap()
+                .conversation
+                .state,
+            "awaiting_rating"
+        );
+        let reply_clock = FakeClock(Utc::now() + Duration::minutes(5));
+        test_process_inbound_state_machine(&conn, &reply_clock, lead_id, "5").unwrap();
+        assert_eq!(
+            get_lead_detail_with_conn(&conn, lead_id)
+                .unwrap()
+                .conversation
+                .state,
+            "awaiting_yes"
+        );
+    }
 
-    fn ts(input: &str) -> DateTime<Utc> {
-        parse_ts(input).expect("timestamp should parse")
+    #[test]
+    fn mark_appointment_completed_reports_unscheduled_follow_up() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000170");
+        insert_conversation(&conn, lead_id);
+        let appointment_id = insert_past_booked_appointment(&conn, lead_id);
+        set_kill_switch_with_conn(&conn, &RealClock, true, None).unwrap();
+
+        let completion = ActionGateway::new(&conn)
+            .mark_appointment_completed(appointment_id)
+            .unwrap();
+        assert_eq!(appointment_status(&conn, appointment_id), "completed");
+        assert_eq!(completion.follow_up_job_id, None);
+        assert!(completion.follow_up_error.is_some());
+
+        let response_json: String = conn
+            .query_row(
+                "SELECT response_json FROM audit_log WHERE action_type='mark_appointment_completed'",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let response: Value = serde_json::from_str(&response_json).unwrap();
+        assert_eq!(
+            response["follow_up_error"],
+            json!(completion.follow_up_error)
+        );
     }
 
-    fn set_business_hours(conn: &Connection, business_hours_json: &str) {
-        conn.execute(
-            "UPDATE locations SET business_hours_json=? WHERE id=1",
-            params![business_hours_json],
+    #[test]
+    fn conversation_appointment_type_sets_slot_length_and_booking_type() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000171");
+        insert_conversation(&conn, lead_id);
+        let consult = create_appointment_type_with_conn(
+            &conn,
+            &AppointmentTypeInput {
+                name: "PT Consult".to_string(),
+                duration_minutes: 60,
+                confirmation_template: "PT consult booked for {time}.".to_string(),
+                reminder_template: "PT consult at {time}.".to_string(),
+            },
         )
-        .expect("failed to update location business hours");
+        .unwrap();
+        assert!(set_conversation_appointment_type_with_conn(
+            &conn,
+            &RealClock,
+            lead_id,
+            Some(9_999)
+        )
+        .unwrap_err()
+        .is_not_found());
+        set_conversation_appointment_type_with_conn(&conn, &RealClock, lead_id, Some(consult.id))
+            .unwrap();
+
+        let reply = |at: &str, body: &str| {
+            simulate_inbound_sms_with_conn(&conn, &FakeClock(ts(at)), lead_id, body).unwrap()
+        };
+        reply("2030-01-07T12:00:00Z", "YES");
+        let conversation = get_conversation_by_lead_id(&conn, lead_id).unwrap();
+        let state: ConversationState = serde_json::from_str(&conversation.state_json).unwrap();
+        assert_eq!(state.appointment_type_id, Some(consult.id));
+        assert_eq!(state.offered_slots.len(), 2);
+        for slot in &state.offered_slots {
+            let length = parse_ts(&slot.end_at).unwrap() - parse_ts(&slot.start_at).unwrap();
+            assert_eq!(length, Duration::minutes(60));
+        }
+
+        reply("2030-01-07T12:05:00Z", "1");
+        let appointment_type_id: Option<i64> = conn
+            .query_row(
+                "SELECT appointment_type_id FROM appointments WHERE lead_id=?",
+                params![lead_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(appointment_type_id, Some(consult.id));
     }
 
-    fn insert_lead(conn: &Connection, phone_e164: &str) -> i64 {
-        conn.execute(
-            "INSERT INTO leads (phone_e164, consent, status, opted_out, needs_staff_attention, created_at)
-             VALUES (?, 1, 'awaiting_yes', 0, 0, ?)",
-            params![phone_e164, "2030-01-01T00:00:00Z"],
-        )
-        .expect("failed to insert test lead");
-        conn.last_insert_rowid()
+    #[test]
+    fn default_appointment_type_comes_from_migration() {
+        let conn = init_in_memory_db();
+        let clock = FakeClock(ts("2030-01-07T12:00:00Z"));
+        let default_type = get_appointment_type(&conn, None).unwrap();
+        assert_eq!(default_type.name, "Intro Session");
+        let slots = generate_slot_choices(&conn, &get_location(&conn).unwrap(), clock.now()).unwrap();
+        let length =
+            parse_ts(&slots[0].end_at).unwrap() - parse_ts(&slots[0].start_at).unwrap();
+        assert_eq!(length, Duration::minutes(default_type.duration_minutes));
+
+        run_migrations(&conn, MIGRATIONS).unwrap();
+        let seed = MIGRATIONS
+            .iter()
+            .find(|migration| migration.file == "009_default_appointment_type.sql")
+            .unwrap();
+        conn.execute_batch(seed.sql).unwrap();
+        assert_eq!(list_appointment_types_with_conn(&conn).unwrap().len(), 1);
     }
 
-    fn insert_booked_appointment(conn: &Connection, lead_id: i64, start_at: &str, end_at: &str) {
+    #[test]
+    fn booking_confirmation_describes_configured_reminders() {
+        let conn = init_in_memory_db();
+        let clock = FakeClock(ts("2030-01-05T12:00:00Z"));
+        let location_id = ensure_primary_location(&conn).unwrap();
+        add_reminder_config_with_conn(&conn, &clock, location_id, 24, None).unwrap();
+        let lead_id = insert_lead(&conn, "+15550000172");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let start_at = "2030-01-07T15:00:00Z";
+        let state = ConversationState {
+            offered_slots: vec![SlotChoice {
+                start_at: start_at.to_string(),
+                end_at: "2030-01-07T15:30:00Z".to_string(),
+            }],
+            appointment_type_id: None,
+        };
         conn.execute(
-            "INSERT INTO appointments (lead_id, start_at, end_at, status, created_at)
-             VALUES (?, ?, ?, 'booked', ?)",
-            params![lead_id, start_at, end_at, "2030-01-01T00:00:00Z"],
+            "UPDATE conversations SET state='awaiting_time_choice', state_json=? WHERE id=?",
+            params![serde_json::to_string(&state).unwrap(), conversation_id],
         )
-        .expect("failed to insert test appointment");
+        .unwrap();
+
+        let location = get_location(&conn).unwrap();
+        let lead = get_lead(&conn, lead_id).unwrap();
+        let conversation = get_conversation_by_lead_id(&conn, lead_id).unwrap();
+        process_inbound_state_machine(&conn, &clock, &lead, &conversation, "1").unwrap();
+
+        let body: String = conn
+            .query_row(
+                "SELECT body FROM messages WHERE conversation_id=? AND direction='OUTBOUND'",
+                params![conversation_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let local_time = local_display(&location, start_at).unwrap();
+        assert_eq!(
+            body,
+            format!(
+                "Booked. Your intro session is confirmed for {local_time}. We will send reminders 24 hours and 2 hours before."
+            )
+        );
     }
 
     #[test]
-    fn parse_business_hours_accepts_valid_json_with_multiple_ranges() {
-        let _conn = init_in_memory_db();
-        let parsed = parse_business_hours(
-            r#"{"mon":[["09:00","12:00"],["13:00","17:00"]],"tue":[["10:00","11:30"]]}"#,
+    fn first_release_database_upgrades_through_every_migration() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+        conn.execute_batch(MIGRATIONS[0].sql).unwrap();
+        conn.execute_batch(
+            "INSERT INTO locations (gym_name, timezone, business_hours_json)
+             VALUES ('Old Gym', 'America/New_York', '{}');
+             INSERT INTO leads (phone_e164, first_name, consent, created_at)
+             VALUES ('+15550000186', 'Ada', 1, '2030-01-01T00:00:00Z');",
         )
-        .expect("valid business hours json should parse");
+        .unwrap();
 
-        let mon_ranges = parsed.get(&Weekday::Mon).expect("missing monday ranges");
-        assert_eq!(mon_ranges.len(), 2);
         assert_eq!(
-            mon_ranges[0],
-            (
-                NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
-                NaiveTime::from_hms_opt(12, 0, 0).unwrap()
+            run_migrations(&conn, MIGRATIONS).unwrap().len(),
+            MIGRATIONS.len()
+        );
+        let location_id: i64 = conn
+            .query_row(
+                "SELECT location_id FROM leads WHERE id=1",
+                params![],
+                |row| row.get(0),
             )
+            .unwrap();
+        assert_eq!(location_id, 1);
+        assert!(list_applied_migrations_with_conn(&conn)
+            .unwrap()
+            .iter()
+            .all(|record| record.error.is_none()));
+        let configs = list_reminder_configs(&conn, 1).unwrap();
+        assert_eq!(
+            configs.iter().map(|c| c.hours_before).collect::<Vec<_>>(),
+            vec![2]
         );
+
+        // Configs the gym removes later stay removed on the next start.
+        remove_reminder_config_with_conn(&conn, &RealClock, configs[0].id).unwrap();
+        run_migrations(&conn, MIGRATIONS).unwrap();
+        assert!(list_reminder_configs(&conn, 1).unwrap().is_empty());
+
+        let second = add_location_with_conn(
+            &conn,
+            &LocationSettingsInput {
+                gym_name: "New Gym".to_string(),
+                timezone: "America/Chicago".to_string(),
+                business_hours_json: default_business_hours_json().to_string(),
+            },
+        )
+        .unwrap();
         assert_eq!(
-            mon_ranges[1],
-            (
-                NaiveTime::from_hms_opt(13, 0, 0).unwrap(),
-                NaiveTime::from_hms_opt(17, 0, 0).unwrap()
+            list_reminder_configs(&conn, second.id)
+                .unwrap()
+                .iter()
+                .map(|c| c.hours_before)
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn reminder_config_changes_are_audited() {
+        let conn = init_in_memory_db();
+        let location_id = ensure_primary_location(&conn).unwrap();
+        let config =
+            add_reminder_config_with_conn(&conn, &RealClock, location_id, 24, None).unwrap();
+        remove_reminder_config_with_conn(&conn, &RealClock, config.id).unwrap();
+        assert!(
+            remove_reminder_config_with_conn(&conn, &RealClock, config.id)
+                .unwrap_err()
+                .is_not_found()
+        );
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT action_type, target_id FROM audit_log
+                 WHERE target_type='reminder_config' ORDER BY id",
             )
+            .unwrap();
+        let rows: Vec<(String, String)> = stmt
+            .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                ("add_reminder_config".to_string(), config.id.to_string()),
+                ("remove_reminder_config".to_string(), config.id.to_string()),
+            ]
         );
     }
 
     #[test]
-    fn parse_business_hours_rejects_invalid_json() {
-        let _conn = init_in_memory_db();
-        assert!(parse_business_hours(r#"{"mon":[[123,"10:00"]]}"#).is_err());
+    fn stop_keywords_always_keep_carrier_defaults() {
+        let conn = init_in_memory_db();
+        set_stop_keywords_with_conn(&conn, &RealClock, vec!["CANCEL".to_string()]).unwrap();
+        assert_eq!(
+            get_stop_keywords_with_conn(&conn).unwrap(),
+            vec!["STOP", "UNSUBSCRIBE", "CANCEL"]
+        );
+        let stored: String = conn
+            .query_row(
+                "SELECT value FROM settings WHERE key='stop_keywords'",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(stored, r#"["STOP","UNSUBSCRIBE","CANCEL"]"#);
+
+        for (phone, body) in [("+15550000173", "cancel"), ("+15550000174", "STOP")] {
+            let lead_id = insert_lead(&conn, phone);
+            insert_conversation(&conn, lead_id);
+            let lead = get_lead(&conn, lead_id).unwrap();
+            let conversation = get_conversation_by_lead_id(&conn, lead_id).unwrap();
+            process_inbound_state_machine(&conn, &RealClock, &lead, &conversation, body).unwrap();
+            assert!(get_lead(&conn, lead_id).unwrap().opted_out, "{body}");
+        }
     }
 
     #[test]
-    fn has_appointment_conflict_detects_overlap_and_non_overlap() {
-        let _conn = init_in_memory_db();
-        let existing = vec![(ts("2030-01-07T14:00:00Z"), ts("2030-01-07T14:30:00Z"))];
+    fn help_response_goes_through_gateway_outbound() {
+        let conn = init_in_memory_db();
+        let lead_id = insert_lead(&conn, "+15550000175");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let gateway = ActionGateway::new(&conn);
+
+        let message_id = gateway
+            .send_help_response(lead_id, conversation_id)
+            .unwrap();
+        let (response_json, request_json): (String, String) = conn
+            .query_row(
+                "SELECT response_json, request_json FROM audit_log
+                 WHERE action_type='create_outbound_message' AND success=1",
+                params![],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        let response: Value = serde_json::from_str(&response_json).unwrap();
+        assert_eq!(response["message_id"], json!(message_id));
+        let request: Value = serde_json::from_str(&request_json).unwrap();
+        assert_eq!(request["rate_limit_exempt"], json!(true));
 
-        assert!(has_appointment_conflict(
-            ts("2030-01-07T14:10:00Z"),
-            ts("2030-01-07T14:40:00Z"),
-            &existing
-        ));
-        assert!(!has_appointment_conflict(
-            ts("2030-01-07T15:00:00Z"),
-            ts("2030-01-07T15:30:00Z"),
-            &existing
-        ));
+        let agent_send = gateway.create_outbound_message_for_agent(OutboundRequest {
+            lead_id,
+            conversation_id,
+            body: "Following up".to_string(),
+            automated: false,
+            allow_without_consent: false,
+            allow_opted_out_once: false,
+            allow_after_reply: false,
+            ignore_business_hours: false,
+            rate_limit_exempt: true,
+        });
+        assert_eq!(agent_send.unwrap_err().code(), ErrorCode::RateLimit);
     }
 
     #[test]
-    fn has_appointment_conflict_enforces_ten_minute_buffer() {
-        let _conn = init_in_memory_db();
-        let existing = vec![(ts("2030-01-07T14:00:00Z"), ts("2030-01-07T14:30:00Z"))];
-
-        assert!(has_appointment_conflict(
-            ts("2030-01-07T14:35:00Z"),
-            ts("2030-01-07T15:05:00Z"),
-            &existing
-        ));
-        assert!(!has_appointment_conflict(
-            ts("2030-01-07T14:40:00Z"),
-            ts("2030-01-07T15:10:00Z"),
-            &existing
-        ));
+    fn fill_template_does_not_expand_placeholders_inside_values() {
+        // Each map gets its own hash seed, so repeating catches order-dependent substitution.
+        for _ in 0..20 {
+            let vars = HashMap::from([
+                ("name", "{gym}"),
+                ("gym", "Iron Temple"),
+                ("time", "{name}"),
+            ]);
+            assert_eq!(
+                fill_template("Hi {name} at {gym}, {time} {unknown} {", &vars),
+                "Hi {gym} at Iron Temple, {name} {unknown} {"
+            );
+        }
     }
 
     #[test]
-    fn generate_slot_choices_returns_two_slots_when_exactly_two_are_available() {
+    fn leads_with_upcoming_appointments_are_not_reset() {
         let conn = init_in_memory_db();
-        set_business_hours(
-            &conn,
-            r#"{"mon":[["09:00","09:30"]],"tue":[["09:00","09:30"]],"wed":[["09:00","09:30"]],"thu":[],"fri":[],"sat":[],"sun":[]}"#,
-        );
-        let lead_id = insert_lead(&conn, "+15550000001");
-        insert_booked_appointment(
+        let lead_id = insert_lead(&conn, "+15550000176");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        let appointment_id = insert_booked_appointment(
             &conn,
             lead_id,
-            "2030-01-08T14:00:00Z",
-            "2030-01-08T14:30:00Z",
+            "2030-01-07T15:00:00Z",
+            "2030-01-07T15:30:00Z",
         );
+        conn.execute(
+            "UPDATE conversations SET state='booked' WHERE id=?",
+            params![conversation_id],
+        )
+        .unwrap();
+        let clock = FakeClock(ts("2030-01-06T12:00:00Z"));
+        let gateway = ActionGateway::with_clock(&conn, &clock);
 
-        let location = get_location(&conn).expect("test location should exist");
-        let slots =
-            generate_slot_choices(&conn, &location, ts("2030-01-07T12:00:00Z")).unwrap();
+        let err = gateway
+            .reset_conversation(lead_id, "lead asked to start over")
+            .unwrap_err();
+        assert!(err.to_string().contains("upcoming appointment"));
 
-        assert_eq!(slots.len(), 2);
-        assert_eq!(parse_ts(&slots[0].start_at).unwrap(), ts("2030-01-07T14:00:00Z"));
-        assert_eq!(parse_ts(&slots[1].start_at).unwrap(), ts("2030-01-09T14:00:00Z"));
+        let batch = schedule_re_engagement_batch_with_conn(
+            &conn,
+            &RealClock,
+            &[lead_id],
+            "2030-01-06T12:00:00Z",
+            10,
+        )
+        .unwrap();
+        assert_eq!((batch.scheduled, batch.skipped), (0, 1));
+
+        execute_re_engagement(&conn, &RealClock, ReEngagementPayload { lead_id }).unwrap();
+        assert_eq!(appointment_status(&conn, appointment_id), "booked");
+        assert_eq!(
+            get_conversation_by_lead_id(&conn, lead_id).unwrap().state,
+            "booked"
+        );
+        assert_eq!(last_outbound_body(&conn, conversation_id), None);
     }
 
     #[test]
-    fn generate_slot_choices_returns_fewer_than_two_when_capacity_is_limited() {
+    fn interrupted_follow_up_sequence_flags_lead() {
         let conn = init_in_memory_db();
-        set_business_hours(
-            &conn,
-            r#"{"mon":[["09:00","09:30"]],"tue":[["09:00","09:30"]],"wed":[["09:00","09:30"]],"thu":[],"fri":[],"sat":[],"sun":[]}"#,
+        let location = get_location(&conn).unwrap();
+        upsert_follow_up_step_with_conn(&conn, location.id, 2, 48, "re_engagement").unwrap();
+        let lead_id = insert_lead(&conn, "+15550000177");
+        let conversation_id = insert_conversation(&conn, lead_id);
+        conn.execute_batch(
+            "CREATE TEMP TRIGGER refuse_jobs BEFORE INSERT ON scheduled_jobs
+             BEGIN SELECT RAISE(ABORT, 'database is full'); END;",
+        )
+        .unwrap();
+
+        execute_initial_follow_up(&conn, &RealClock, lead_id, 1).unwrap();
+        assert!(last_outbound_body(&conn, conversation_id).is_some());
+        assert!(
+            get_lead_detail_with_conn(&conn, lead_id)
+                .unwrap()
+                .lead
+                .needs_staff_attention
         );
-        let lead_id = insert_lead(&conn, "+15550000002");
-        insert_booked_appointment(
+        let failed_schedules: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM audit_log WHERE action_type='schedule_job' AND success=0",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(failed_schedules, 1);
+    }
+
+    #[test]
+    fn new_locations_start_with_default_follow_up_step() {
+        let conn = init_in_memory_db();
+        let location = add_location_with_conn(
             &conn,
-            lead_id,
-            "2030-01-07T14:00:00Z",
-            "2030-01-07T14:30:00Z",
+            &LocationSettingsInput {
+                gym_name: "Second Gym".to_string(),
+                timezone: "America/Chicago".to_string(),
+                business_hours_json: default_business_hours_json().to_string(),
+            },
+        )
+        .unwrap();
+        let steps = list_follow_up_sequence_with_conn(&conn, location.id).unwrap();
+        assert_eq!(steps.len(), 1);
+        assert_eq!(
+            (
+                steps[0].step_number,
+                steps[0].delay_hours,
+                steps[0].template_key.as_str()
+            ),
+            (1, 0, "initial_follow_up")
         );
+    }
+
+    #[test]
+    fn inbound_for_lead_at_another_location_uses_that_locations_settings() {
+        let conn = init_in_memory_db();
+        let selected_id = get_location(&conn).unwrap().id;
+        let coast = add_location_with_conn(
+            &conn,
+            &LocationSettingsInput {
+                gym_name: "Coast Gym".to_string(),
+                timezone: "America/Los_Angeles".to_string(),
+                business_hours_json:
+                    r#"{"mon":[["09:00","11:00"]],"tue":[],"wed":[],"thu":[],"fri":[],"sat":[],"sun":[]}"#
+                        .to_string(),
+            },
+        )
+        .unwrap();
+        let lead_id = insert_lead(&conn, "+15550000178");
+        conn.execute(
+            "UPDATE leads SET location_id=? WHERE id=?",
+            params![coast.id, lead_id],
+        )
+        .unwrap();
+        insert_conversation(&conn, lead_id);
+        // A booking at the selected location does not take the slot away from Coast Gym.
+        let other_lead = insert_lead(&conn, "+15550000179");
         insert_booked_appointment(
             &conn,
-            lead_id,
-            "2030-01-08T14:00:00Z",
-            "2030-01-08T14:30:00Z",
+            other_lead,
+            "2030-01-07T17:00:00Z",
+            "2030-01-07T17:30:00Z",
         );
 
-        let location = get_location(&conn).expect("test location should exist");
-        let slots =
-            generate_slot_choices(&conn, &location, ts("2030-01-07T12:00:00Z")).unwrap();
+        let clock = FakeClock(ts("2030-01-07T16:00:00Z"));
+        simulate_inbound_sms_with_conn(&conn, &clock, lead_id, "YES").unwrap();
+        let state: ConversationState = serde_json::from_str(
+            &get_conversation_by_lead_id(&conn, lead_id)
+                .unwrap()
+                .state_json,
+        )
+        .unwrap();
+        let offered: Vec<_> = state
+            .offered_slots
+            .iter()
+            .map(|slot| parse_ts(&slot.start_at).unwrap())
+            .collect();
+        // 09:00 and 09:40 in Los Angeles; the selected location is open all day in New York.
+        assert_eq!(
+            offered,
+            vec![ts("2030-01-07T17:00:00Z"), ts("2030-01-07T17:40:00Z")]
+        );
 
-        assert_eq!(slots.len(), 1);
-        assert_eq!(parse_ts(&slots[0].start_at).unwrap(), ts("2030-01-09T14:00:00Z"));
+        let clock = FakeClock(ts("2030-01-07T16:05:00Z"));
+        simulate_inbound_sms_with_conn(&conn, &clock, lead_id, "1").unwrap();
+        let booked_at: String = conn
+            .query_row(
+                "SELECT start_at FROM appointments WHERE lead_id=? AND status='booked'",
+                params![lead_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(parse_ts(&booked_at).unwrap(), ts("2030-01-07T17:00:00Z"));
+        assert_eq!(get_location(&conn).unwrap().id, selected_id);
     }
 
     #[test]
-    fn business_open_and_next_open_time_respect_open_close_edges() {
+    fn due_job_for_lead_at_another_location_uses_that_locations_settings() {
         let conn = init_in_memory_db();
-        set_business_hours(
+        let selected_id = get_location(&conn).unwrap().id;
+        let coast = add_location_with_conn(
             &conn,
-            r#"{"mon":[["09:00","17:00"]],"tue":[["09:00","17:00"]],"wed":[["09:00","17:00"]],"thu":[["09:00","17:00"]],"fri":[["09:00","17:00"]],"sat":[],"sun":[]}"#,
-        );
-        let location = get_location(&conn).expect("test location should exist");
+            &LocationSettingsInput {
+                gym_name: "Coast Gym".to_string(),
+                timezone: "America/Los_Angeles".to_string(),
+                business_hours_json:
+                    r#"{"mon":[["09:00","11:00"]],"tue":[],"wed":[],"thu":[],"fri":[],"sat":[],"sun":[]}"#
+                        .to_string(),
+            },
+        )
+        .unwrap();
+        let lead_id = insert_lead(&conn, "+15550000181");
+        conn.execute(
+            "UPDATE leads SET location_id=?, first_name='Robin' WHERE id=?",
+            params![coast.id, lead_id],
+        )
+        .unwrap();
+        let conversation_id = insert_conversation(&conn, lead_id);
+        conn.execute(
+            "INSERT INTO scheduled_jobs (job_type, target_id, execute_at, status, payload_json, created_at)
+             VALUES ('initial_follow_up', ?1, '2030-01-07T17:05:00Z', 'pending', ?2, '2030-01-01T00:00:00Z')",
+            params![
+                lead_id,
+                serde_json::to_string(&InitialFollowUpPayload {
+                    lead_id,
+                    step_number: 1,
+                })
+                .unwrap()
+            ],
+        )
+        .unwrap();
 
-        assert!(!is_business_open(&location, ts("2030-01-07T13:59:00Z")).unwrap());
-        assert!(is_business_open(&location, ts("2030-01-07T14:00:00Z")).unwrap());
-        assert!(is_business_open(&location, ts("2030-01-07T21:59:00Z")).unwrap());
-        assert!(!is_business_open(&location, ts("2030-01-07T22:00:00Z")).unwrap());
+        let early = FakeClock(ts("2030-01-07T17:00:00Z"));
+        assert_eq!(run_due_jobs_with_conn(&conn, &early).unwrap().processed, 0);
 
-        assert_eq!(
-            next_open_time(&location, ts("2030-01-07T13:59:00Z")).unwrap(),
-            ts("2030-01-07T14:00:00Z")
-        );
-        assert_eq!(
-            next_open_time(&location, ts("2030-01-07T22:00:00Z")).unwrap(),
-            ts("2030-01-08T14:00:00Z")
-        );
+        // 09:10 in Los Angeles on a Monday: Coast Gym is open.
+        let clock = FakeClock(ts("2030-01-07T17:10:00Z"));
+        let result = run_due_jobs_with_conn(&conn, &clock).unwrap();
+        assert_eq!((result.processed, result.errors), (1, 0));
+
+        let body: String = conn
+            .query_row(
+                "SELECT body FROM messages WHERE conversation_id=? AND direction='OUTBOUND'",
+                params![conversation_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(body.contains("Coast Gym"), "{body}");
+        assert!(!body.contains("Test Gym"), "{body}");
+        assert_eq!(get_location(&conn).unwrap().id, selected_id);
+    }
+
+    #[test]
+    fn every_consent_revocation_path_cancels_the_same_jobs() {
+        let conn = init_in_memory_db();
+        for (phone, path) in [
+            ("+15550000183", "update_lead_consent"),
+            ("+15550000184", "set_lead_consent"),
+            ("+15550000185", "update_lead"),
+        ] {
+            let lead_id = insert_lead(&conn, phone);
+            let appointment_id = insert_booked_appointment(
+                &conn,
+                lead_id,
+                "2030-01-05T15:00:00Z",
+                "2030-01-05T15:30:00Z",
+            );
+            for (job_type, target_id) in [
+                ("initial_follow_up", lead_id),
+                ("re_engagement", lead_id),
+                ("appointment_reminder", appointment_id),
+            ] {
+                conn.execute(
+                    "INSERT INTO scheduled_jobs (job_type, target_id, execute_at, status, payload_json, created_at)
+                     VALUES (?, ?, '2030-01-05T13:00:00Z', 'pending', '{}', '2030-01-01T00:00:00Z')",
+                    params![job_type, target_id],
+                )
+                .unwrap();
+            }
+
+            let at = "2030-01-02T12:00:00Z";
+            let cancelled = match path {
+                "update_lead_consent" => Some(
+                    update_lead_consent_with_conn(
+                        &conn,
+                        &RealClock,
+                        lead_id,
+                        false,
+                        at,
+                        "called to withdraw",
+                    )
+                    .unwrap(),
+                ),
+                "set_lead_consent" => Some(
+                    set_lead_consent_with_conn(
+                        &conn,
+                        &RealClock,
+                        lead_id,
+                        false,
+                        at,
+                        "phone call",
+                        "Dana",
+                    )
+                    .unwrap(),
+                ),
+                _ => {
+                    let patch = LeadPatch {
+                        consent: Some(false),
+                        ..LeadPatch::default()
+                    };
+                    update_lead_with_conn(&conn, &RealClock, lead_id, &patch).unwrap();
+                    None
+                }
+            }
+            .map(|result| result.follow_ups_cancelled);
+            assert!(matches!(cancelled, None | Some(3)), "{path}: {cancelled:?}");
+            let pending: i64 = conn
+                .query_row(
+                    "SELECT COUNT(*) FROM scheduled_jobs
+                     WHERE status='pending'
+                       AND (target_id=?1 OR target_id=?2)",
+                    params![lead_id, appointment_id],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(pending, 0, "{path}");
+            assert!(!get_lead(&conn, lead_id).unwrap().consent, "{path}");
+        }
     }
 }
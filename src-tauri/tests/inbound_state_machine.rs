@@ -5,6 +5,14 @@ use rusqlite::{params, Connection};
 use serde_json::Value;
 
 const FIXED_TS: &str = "2026-01-10T15:00:00Z";
+
+/// A clock `minutes` after `FIXED_TS`, so each step of the flow happens at a known time.
+fn clock_at(minutes: i64) -> app::FakeClock {
+    let start = chrono::DateTime::parse_from_rfc3339(FIXED_TS)
+        .expect("parse FIXED_TS")
+        .with_timezone(&chrono::Utc);
+    app::FakeClock(start + chrono::Duration::minutes(minutes))
+}
 const ALWAYS_OPEN_BUSINESS_HOURS: &str = r#"{
   "mon":[["00:00","23:59"]],
   "tue":[["00:00","23:59"]],
@@ -17,11 +25,11 @@ const ALWAYS_OPEN_BUSINESS_HOURS: &str = r#"{
 
 fn setup_db() -> Connection {
     let conn = Connection::open_in_memory().expect("in-memory DB");
-    conn.execute_batch(include_str!("../migrations/001_init.sql"))
-        .expect("apply schema");
+    app::test_run_migrations(&conn).expect("apply schema");
 
     conn.execute(
-        "INSERT INTO locations (id, gym_name, timezone, business_hours_json) VALUES (?, ?, ?, ?)",
+        "INSERT INTO locations (id, gym_name, timezone, business_hours_json, tcpa_mode)
+         VALUES (?, ?, ?, ?, 0)",
         params![1_i64, "Integration Test Gym", "America/New_York", ALWAYS_OPEN_BUSINESS_HOURS],
     )
     .expect("seed location");
@@ -74,7 +82,7 @@ fn inbound_state_machine_end_to_end() {
     let lead_id = 1_i64;
     let conversation_id = 1_i64;
 
-    app::test_execute_initial_follow_up(&conn, lead_id).expect("initial follow-up executes");
+    app::test_execute_initial_follow_up(&conn, &clock_at(0), lead_id).expect("initial follow-up executes");
     let outbound_after_follow_up: i64 = conn
         .query_row(
             "SELECT COUNT(*) FROM messages WHERE conversation_id=? AND direction='OUTBOUND'",
@@ -84,7 +92,7 @@ fn inbound_state_machine_end_to_end() {
         .expect("count outbound");
     assert_eq!(outbound_after_follow_up, 1);
 
-    app::test_process_inbound_state_machine(&conn, lead_id, "YES").expect("YES flow executes");
+    app::test_process_inbound_state_machine(&conn, &clock_at(5), lead_id, "YES").expect("YES flow executes");
     let (state_after_yes, state_json_after_yes): (String, String) = conn
         .query_row(
             "SELECT state, state_json FROM conversations WHERE lead_id=?",
@@ -100,7 +108,7 @@ fn inbound_state_machine_end_to_end() {
         .expect("offered_slots array");
     assert_eq!(offered_slots.len(), 2);
 
-    app::test_process_inbound_state_machine(&conn, lead_id, "1").expect("choice flow executes");
+    app::test_process_inbound_state_machine(&conn, &clock_at(10), lead_id, "1").expect("choice flow executes");
     let appointment_count: i64 = conn
         .query_row(
             "SELECT COUNT(*) FROM appointments WHERE lead_id=? AND status='booked'",
@@ -118,7 +126,7 @@ fn inbound_state_machine_end_to_end() {
         .expect("state after choice");
     assert_eq!(state_after_choice, "booked");
 
-    app::test_process_inbound_state_machine(&conn, lead_id, "STOP").expect("STOP flow executes");
+    app::test_process_inbound_state_machine(&conn, &clock_at(15), lead_id, "STOP").expect("STOP flow executes");
     let opted_out: i64 = conn
         .query_row("SELECT opted_out FROM leads WHERE id=?", params![lead_id], |row| {
             row.get(0)
@@ -146,7 +154,7 @@ fn inbound_state_machine_end_to_end() {
         )
         .expect("count outbound before blocked attempt");
 
-    let blocked = app::test_execute_initial_follow_up(&conn, lead_id)
+    let blocked = app::test_execute_initial_follow_up(&conn, &clock_at(20), lead_id)
         .expect_err("opted-out lead should block future outbound");
     assert!(
         blocked.contains("lead is opted out; outbound blocked"),
@@ -3,6 +3,7 @@ import { invoke } from '@tauri-apps/api/tauri';
 import {
   createLead,
   exportDbPath,
+  formatError,
   getLocationSettings,
   getKillSwitch,
   getLeadDetail,
@@ -98,11 +99,11 @@ export default function App() {
   }
 
   useEffect(() => {
-    Promise.all([refreshAll(), loadLocationSettings()]).catch((err) => setAlert(String(err)));
+    Promise.all([refreshAll(), loadLocationSettings()]).catch((err) => setAlert(formatError(err)));
   }, []);
 
   useEffect(() => {
-    refreshLeadDetail().catch((err) => setAlert(String(err)));
+    refreshLeadDetail().catch((err) => setAlert(formatError(err)));
   }, [selectedLeadId]);
 
   async function submitLead() {
@@ -118,11 +119,13 @@ export default function App() {
       } else {
         setAlert(null);
       }
-      setSelectedLeadId(result.lead_id);
+      if (!result.blocked) {
+        setSelectedLeadId(result.lead_id);
+      }
       setForm(emptyForm);
       await refreshAll();
     } catch (err) {
-      setAlert(String(err));
+      setAlert(formatError(err));
     } finally {
       setBusy(false);
     }
@@ -136,7 +139,7 @@ export default function App() {
       setInboundText('');
       await refreshAll();
     } catch (err) {
-      setAlert(String(err));
+      setAlert(formatError(err));
     } finally {
       setBusy(false);
     }
@@ -149,7 +152,7 @@ export default function App() {
       setKillSwitchState(value);
       await refreshAll();
     } catch (err) {
-      setAlert(String(err));
+      setAlert(formatError(err));
     } finally {
       setBusy(false);
     }
@@ -162,7 +165,7 @@ export default function App() {
       await loadLocationSettings();
       setAlert('Settings saved.');
     } catch (err) {
-      setAlert(String(err));
+      setAlert(formatError(err));
     } finally {
       setBusy(false);
     }
@@ -184,7 +187,7 @@ export default function App() {
         `Wiped test data: messages=${result.messages}, appointments=${result.appointments}, scheduled_jobs=${result.scheduled_jobs}, audit_log=${result.audit_log}, conversations=${result.conversations}, leads=${result.leads}`
       );
     } catch (err) {
-      setAlert(String(err));
+      setAlert(formatError(err));
     } finally {
       setBusy(false);
     }
@@ -271,7 +274,7 @@ export default function App() {
               ? 'Automation paused (safe mode).'
               : 'Automation active (local simulated outbound).'}
           </p>
-          <button disabled={busy} onClick={() => runDueJobs().then(refreshAll).catch((err) => setAlert(String(err)))}>
+          <button disabled={busy} onClick={() => runDueJobs().then(refreshAll).catch((err) => setAlert(formatError(err)))}>
             Run Due Jobs Now
           </button>
         </article>
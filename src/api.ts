@@ -1,17 +1,82 @@
 import { invoke } from '@tauri-apps/api/tauri';
 import type {
+  AgentAction,
+  AgentActionRecord,
+  AgentBatchResult,
   AgentDryRunRequest,
   AgentDryRunResult,
   AgentExecuteRequest,
   AgentExecuteResult,
+  AppErrorResponse,
+  AppInfo,
+  AppointmentCompletion,
+  AppointmentDetail,
+  AppointmentStatus,
+  AppointmentType,
+  AttentionLeadView,
+  AppointmentTypeInput,
+  AuditEntry,
+  AuditLogFilter,
+  AuditLogPage,
+  AuditPage,
+  BackupResult,
+  BatchScheduleResult,
+  BlackoutPeriod,
+  BlocklistEntry,
+  BulkCreateResult,
+  BulkInboundItem,
+  BulkInboundResult,
+  CancelAppointmentResult,
+  CancelJobResult,
+  CheckpointMode,
+  CheckpointResult,
+  CommSummary,
+  ConversationHistoryItem,
+  CustomKeyword,
+  DailyStats,
+  DayReport,
+  DemoResetResult,
+  FollowUpStep,
+  FunnelReport,
+  Holiday,
+  IntegrityCheckResult,
+  JobFilter,
+  JobStats,
+  KillSwitchState,
   LeadCreateInput,
+  LeadConsentResult,
   LeadCreateResult,
   LeadDetail,
+  LeadExportFilter,
+  LeadPage,
+  LeadPatch,
+  LocationPatch,
   LocationSettings,
+  LocationUpdateResult,
   LeadSummary,
+  MessageFailure,
+  MessageHistoryPage,
+  MessageSearchResult,
+  MessageTemplate,
+  MigrationRecord,
+  Note,
+  PurgeResult,
+  RateLimitSettings,
+  RateLimitStatus,
+  ReminderConfig,
+  RescheduleResult,
   RunJobsResult,
+  ScheduledJob,
+  SettingView,
+  SlotChoice,
+  SourceReport,
+  StaffAttentionResolution,
+  StaffResolution,
+  StatusHistoryEntry,
   TodayReport,
   UpdateLocationSettingsInput,
+  VacuumResult,
+  ValidationReport,
   WipeAllDataResult
 } from './types';
 
@@ -21,42 +86,483 @@ export type ClientErrorLogInput = {
   source: string;
 };
 
+// Commands reject with a JSON-encoded AppErrorResponse; anything else is wrapped as `internal`.
+export function parseAppError(err: unknown): AppErrorResponse {
+  const raw = String(err);
+  try {
+    const parsed = JSON.parse(raw) as AppErrorResponse;
+    if (parsed && typeof parsed.code === 'string' && typeof parsed.message === 'string') {
+      return parsed;
+    }
+  } catch {
+    // not a structured command error
+  }
+  return { code: 'internal', message: raw, detail: null };
+}
+
+export function formatError(err: unknown): string {
+  const parsed = parseAppError(err);
+  if (parsed.status === 404) {
+    return `Not found (404): ${parsed.message}`;
+  }
+  return `Alert: ${parsed.message}`;
+}
+
+export async function bulkCreateLeads(inputs: LeadCreateInput[]): Promise<BulkCreateResult> {
+  return invoke('bulk_create_leads', { inputs });
+}
+
 export async function listLeads(): Promise<LeadSummary[]> {
   return invoke('list_leads');
 }
 
+export async function listLeadsPaginated(limit: number, offset: number): Promise<LeadPage> {
+  return invoke('list_leads_paginated', { limit, offset });
+}
+
+export async function listLeadsByStatus(statuses: string[]): Promise<LeadSummary[]> {
+  return invoke('list_leads_by_status', { statuses });
+}
+
+export async function normalizePhone(raw: string, defaultCountryCode?: string): Promise<string> {
+  return invoke('normalize_phone', { raw, default_country_code: defaultCountryCode ?? null });
+}
+
 export async function createLead(input: LeadCreateInput): Promise<LeadCreateResult> {
   return invoke('create_lead', { input });
 }
 
+export async function updateLeadConsent(
+  leadId: number,
+  consent: boolean,
+  consentAt: string,
+  consentSource: string
+): Promise<LeadConsentResult> {
+  return invoke('update_lead_consent', {
+    lead_id: leadId,
+    consent,
+    consent_at: consentAt,
+    consent_source: consentSource
+  });
+}
+
+export async function setLeadConsent(
+  leadId: number,
+  consent: boolean,
+  consentAt: string,
+  consentSource: string,
+  collectedBy: string
+): Promise<LeadConsentResult> {
+  return invoke('set_lead_consent', {
+    lead_id: leadId,
+    consent,
+    consent_at: consentAt,
+    consent_source: consentSource,
+    collected_by: collectedBy
+  });
+}
+
+export async function updateLead(leadId: number, patch: LeadPatch): Promise<LeadSummary> {
+  return invoke('update_lead', { lead_id: leadId, patch });
+}
+
+export async function searchLeads(query: string, tags?: string[]): Promise<LeadSummary[]> {
+  return invoke('search_leads', { query, tags: tags ?? null });
+}
+
+export async function getStaleLeads(inactiveDays: number): Promise<LeadSummary[]> {
+  return invoke('get_stale_leads', { inactive_days: inactiveDays });
+}
+
+export async function addLeadTag(leadId: number, tag: string): Promise<void> {
+  return invoke('add_lead_tag', { lead_id: leadId, tag });
+}
+
+export async function removeLeadTag(leadId: number, tag: string): Promise<void> {
+  return invoke('remove_lead_tag', { lead_id: leadId, tag });
+}
+
+export async function getLeadCommunicationSummary(leadId: number): Promise<CommSummary> {
+  return invoke('get_lead_communication_summary', { lead_id: leadId });
+}
+
 export async function getLeadDetail(leadId: number): Promise<LeadDetail> {
   return invoke('get_lead_detail', { lead_id: leadId });
 }
 
+export async function getLeadTimeline(leadId: number): Promise<StatusHistoryEntry[]> {
+  return invoke('get_lead_timeline', { lead_id: leadId });
+}
+
+export async function getLeadConversationHistory(
+  leadId: number
+): Promise<ConversationHistoryItem[]> {
+  return invoke('get_lead_conversation_history', { lead_id: leadId });
+}
+
+export async function addLeadNote(leadId: number, body: string, author?: string): Promise<number> {
+  return invoke('add_lead_note', { lead_id: leadId, body, author: author ?? null });
+}
+
+export async function listLeadNotes(leadId: number): Promise<Note[]> {
+  return invoke('list_lead_notes', { lead_id: leadId });
+}
+
+export async function deleteLeadNote(noteId: number): Promise<void> {
+  return invoke('delete_lead_note', { note_id: noteId });
+}
+
 export async function simulateInboundSms(leadId: number, body: string): Promise<void> {
   return invoke('simulate_inbound_sms', { lead_id: leadId, body });
 }
 
-export async function getTodayReport(): Promise<TodayReport> {
-  return invoke('get_today_report');
+// Debug builds only; returns the new message id.
+export async function simulateOutboundSms(leadId: number, body: string): Promise<number> {
+  return invoke('simulate_outbound_sms', { lead_id: leadId, body });
+}
+
+// Debug builds only; release builds reject the call.
+export async function simulateBulkInbound(messages: BulkInboundItem[]): Promise<BulkInboundResult> {
+  return invoke('simulate_bulk_inbound', { messages });
+}
+
+export async function sendManualMessage(leadId: number, body: string): Promise<number> {
+  return invoke('send_manual_message', { lead_id: leadId, body });
+}
+
+export async function listOutboundFailures(limit?: number): Promise<MessageFailure[]> {
+  return invoke('list_outbound_failures', { limit: limit ?? null });
+}
+
+export async function searchMessages(
+  query: string,
+  direction?: 'INBOUND' | 'OUTBOUND',
+  limit?: number
+): Promise<MessageSearchResult[]> {
+  return invoke('search_messages', { query, direction: direction ?? null, limit: limit ?? null });
+}
+
+export async function getConversationHistory(
+  options: {
+    direction?: 'INBOUND' | 'OUTBOUND';
+    since?: string;
+    limit?: number;
+    cursor?: number;
+  } = {}
+): Promise<MessageHistoryPage> {
+  return invoke('get_conversation_history', {
+    direction: options.direction ?? null,
+    since: options.since ?? null,
+    limit: options.limit ?? null,
+    cursor: options.cursor ?? null
+  });
+}
+
+export async function recordOutboundFailure(messageId: number, reason: string): Promise<void> {
+  return invoke('record_outbound_failure', { message_id: messageId, reason });
+}
+
+export async function simulateOutboundFailure(messageId: number, reason: string): Promise<void> {
+  return invoke('simulate_outbound_failure', { message_id: messageId, reason });
+}
+
+export async function cancelAppointment(appointmentId: number, reason: string): Promise<CancelAppointmentResult> {
+  return invoke('cancel_appointment', { appointment_id: appointmentId, reason });
+}
+
+export async function rescheduleAppointment(
+  appointmentId: number,
+  newStartAt: string,
+  newEndAt: string,
+  reason: string
+): Promise<RescheduleResult> {
+  return invoke('reschedule_appointment', {
+    appointment_id: appointmentId,
+    new_start_at: newStartAt,
+    new_end_at: newEndAt,
+    reason
+  });
+}
+
+export async function resolveStaffAttention(
+  leadId: number,
+  resolution: StaffResolution,
+  notes?: string
+): Promise<StaffAttentionResolution> {
+  return invoke('resolve_staff_attention', { lead_id: leadId, resolution, notes: notes ?? null });
+}
+
+export async function markNeedsAttentionResolved(
+  leadId: number,
+  resolvedBy: string,
+  note: string
+): Promise<LeadSummary> {
+  return invoke('mark_needs_attention_resolved', {
+    lead_id: leadId,
+    resolved_by: resolvedBy,
+    note
+  });
+}
+
+export async function resetConversation(leadId: number, reason: string): Promise<void> {
+  return invoke('reset_conversation', { lead_id: leadId, reason });
+}
+
+export async function overrideConversationState(
+  conversationId: number,
+  newState: string,
+  stateJson: string,
+  reason: string
+): Promise<void> {
+  return invoke('override_conversation_state', {
+    conversation_id: conversationId,
+    new_state: newState,
+    state_json: stateJson,
+    reason
+  });
+}
+
+export async function markAppointmentNoShow(appointmentId: number): Promise<void> {
+  return invoke('mark_appointment_no_show', { appointment_id: appointmentId });
+}
+
+export async function markAppointmentCompleted(
+  appointmentId: number
+): Promise<AppointmentCompletion> {
+  return invoke('mark_appointment_completed', { appointment_id: appointmentId });
+}
+
+export async function listAppointments(
+  options: {
+    status?: AppointmentStatus;
+    startAfter?: string;
+    startBefore?: string;
+    limit?: number;
+  } = {}
+): Promise<AppointmentDetail[]> {
+  return invoke('list_appointments', {
+    status: options.status ?? null,
+    start_after: options.startAfter ?? null,
+    start_before: options.startBefore ?? null,
+    limit: options.limit ?? null
+  });
+}
+
+export async function exportAppointmentIcs(appointmentId: number): Promise<string> {
+  return invoke('export_appointment_ics', { appointment_id: appointmentId });
+}
+
+export async function joinAppointmentWaitlist(leadId: number): Promise<number> {
+  return invoke('join_appointment_waitlist', { lead_id: leadId });
+}
+
+export async function listAppointmentTypes(): Promise<AppointmentType[]> {
+  return invoke('list_appointment_types');
+}
+
+export async function createAppointmentType(input: AppointmentTypeInput): Promise<AppointmentType> {
+  return invoke('create_appointment_type', { input });
+}
+
+export async function updateAppointmentType(
+  appointmentTypeId: number,
+  input: AppointmentTypeInput
+): Promise<AppointmentType> {
+  return invoke('update_appointment_type', { appointment_type_id: appointmentTypeId, input });
+}
+
+export async function setConversationAppointmentType(
+  leadId: number,
+  appointmentTypeId?: number
+): Promise<void> {
+  return invoke('set_conversation_appointment_type', {
+    lead_id: leadId,
+    appointment_type_id: appointmentTypeId ?? null
+  });
+}
+
+export async function getTodayReport(date?: string): Promise<TodayReport> {
+  return invoke('get_today_report', { date: date ?? null });
+}
+
+export async function getFunnelReport(since?: string): Promise<FunnelReport> {
+  return invoke('get_funnel_report', { since: since ?? null });
+}
+
+export async function getSourceReport(since?: string): Promise<SourceReport[]> {
+  return invoke('get_source_report', { since: since ?? null });
+}
+
+export async function getRangeReport(startDate: string, endDate: string): Promise<DayReport[]> {
+  return invoke('get_range_report', { start_date: startDate, end_date: endDate });
+}
+
+export async function getWeeklyReport(): Promise<DailyStats[]> {
+  return invoke('get_weekly_report');
 }
 
 export async function getKillSwitch(): Promise<boolean> {
   return invoke('get_kill_switch');
 }
 
-export async function setKillSwitch(enabled: boolean): Promise<void> {
-  return invoke('set_kill_switch', { enabled });
+export async function getKillSwitchState(): Promise<KillSwitchState> {
+  return invoke('get_kill_switch_state');
+}
+
+export async function setKillSwitch(enabled: boolean, reason?: string): Promise<void> {
+  return invoke('set_kill_switch', { enabled, reason: reason ?? null });
+}
+
+export async function getAutoKillSwitchThreshold(): Promise<number> {
+  return invoke('get_auto_kill_switch_threshold');
+}
+
+export async function setAutoKillSwitchThreshold(threshold: number): Promise<void> {
+  return invoke('set_auto_kill_switch_threshold', { threshold });
+}
+
+export async function getStopKeywords(): Promise<string[]> {
+  return invoke('get_stop_keywords');
+}
+
+export async function setStopKeywords(keywords: string[]): Promise<void> {
+  return invoke('set_stop_keywords', { keywords });
+}
+
+export async function getRateLimitSettings(): Promise<RateLimitSettings> {
+  return invoke('get_rate_limit_settings');
+}
+
+export async function getRateLimitStatus(leadId?: number): Promise<RateLimitStatus> {
+  return invoke('get_rate_limit_status', { lead_id: leadId ?? null });
+}
+
+export async function setRateLimitSettings(
+  perLeadDay: number,
+  perLocationHour: number
+): Promise<RateLimitSettings> {
+  return invoke('set_rate_limit_settings', {
+    per_lead_day: perLeadDay,
+    per_location_hour: perLocationHour
+  });
+}
+
+export async function getHelpResponse(): Promise<string> {
+  return invoke('get_help_response');
+}
+
+export async function setHelpResponse(text: string): Promise<void> {
+  return invoke('set_help_response', { text });
+}
+
+export async function checkDbIntegrity(): Promise<IntegrityCheckResult> {
+  return invoke('check_db_integrity');
+}
+
+export async function backupDb(destinationPath: string): Promise<BackupResult> {
+  return invoke('backup_db', { destination_path: destinationPath });
+}
+
+export async function vacuumDb(): Promise<VacuumResult> {
+  return invoke('vacuum_db');
+}
+
+export async function getAppInfo(): Promise<AppInfo> {
+  return invoke('get_app_info');
+}
+
+export async function schemaVersion(): Promise<number> {
+  return invoke('schema_version');
+}
+
+export async function listAppliedMigrations(): Promise<MigrationRecord[]> {
+  return invoke('list_applied_migrations');
+}
+
+export async function getSchemaVersion(): Promise<MigrationRecord[]> {
+  return invoke('get_schema_version');
+}
+
+export async function checkpointDb(mode: CheckpointMode): Promise<CheckpointResult> {
+  return invoke('checkpoint_db', { mode });
+}
+
+export async function getSettings(): Promise<SettingView[]> {
+  return invoke('get_settings');
+}
+
+export async function getSetting(key: string): Promise<string | null> {
+  return invoke('get_setting', { key });
+}
+
+export async function setSetting(key: string, value: string): Promise<void> {
+  return invoke('set_setting', { key, value });
+}
+
+export async function getConversationExpiryHours(): Promise<number> {
+  return invoke('get_conversation_expiry_hours');
+}
+
+export async function setConversationExpiryHours(hours: number): Promise<void> {
+  return invoke('set_conversation_expiry_hours', { hours });
+}
+
+export async function getTcpaMode(): Promise<boolean> {
+  return invoke('get_tcpa_mode');
+}
+
+export async function setTcpaMode(enabled: boolean): Promise<void> {
+  return invoke('set_tcpa_mode', { enabled });
+}
+
+export async function addCustomKeyword(keyword: string, responseBody: string): Promise<CustomKeyword> {
+  return invoke('add_custom_keyword', { keyword, response_body: responseBody });
+}
+
+export async function updateCustomKeyword(
+  id: number,
+  keyword: string,
+  responseBody: string
+): Promise<CustomKeyword> {
+  return invoke('update_custom_keyword', { id, keyword, response_body: responseBody });
+}
+
+export async function setCustomKeywordActive(id: number, active: boolean): Promise<void> {
+  return invoke('set_custom_keyword_active', { id, active });
+}
+
+export async function listCustomKeywords(): Promise<CustomKeyword[]> {
+  return invoke('list_custom_keywords');
+}
+
+export async function getTemplate(key: string): Promise<MessageTemplate> {
+  return invoke('get_template', { key });
+}
+
+export async function setTemplate(key: string, body: string): Promise<MessageTemplate> {
+  return invoke('set_template', { key, body });
+}
+
+export async function listTemplates(): Promise<MessageTemplate[]> {
+  return invoke('list_templates');
 }
 
 export async function exportDbPath(): Promise<string> {
   return invoke('export_db_path');
 }
 
+export async function getConfigPath(): Promise<string> {
+  return invoke('get_config_path');
+}
+
 export async function wipeAllDataConfirmed(): Promise<WipeAllDataResult> {
   return invoke('wipe_all_data_confirmed');
 }
 
+export async function resetDemoData(): Promise<DemoResetResult> {
+  return invoke('reset_demo_data');
+}
+
 export async function getLocationSettings(): Promise<LocationSettings> {
   return invoke('get_location_settings');
 }
@@ -65,14 +571,226 @@ export async function updateLocationSettings(input: UpdateLocationSettingsInput)
   return invoke('update_location_settings', { input });
 }
 
+export async function listLocations(): Promise<LocationSettings[]> {
+  return invoke('list_locations');
+}
+
+export async function addLocation(input: UpdateLocationSettingsInput): Promise<LocationSettings> {
+  return invoke('add_location', { input });
+}
+
+export async function updateLocation(
+  locationId: number,
+  patch: LocationPatch
+): Promise<LocationUpdateResult> {
+  return invoke('update_location', { location_id: locationId, patch });
+}
+
+export async function setCurrentLocation(locationId: number): Promise<LocationSettings> {
+  return invoke('set_current_location', { location_id: locationId });
+}
+
+export async function validateBusinessHours(json: string): Promise<ValidationReport> {
+  return invoke('validate_business_hours', { json });
+}
+
+export async function updateLocationScheduleConfig(
+  locationId: number,
+  durationMinutes: number,
+  bufferMinutes: number,
+  reminderEnabled: boolean
+): Promise<LocationSettings> {
+  return invoke('update_location_schedule_config', {
+    location_id: locationId,
+    duration_minutes: durationMinutes,
+    buffer_minutes: bufferMinutes,
+    reminder_enabled: reminderEnabled
+  });
+}
+
+export async function addReminderConfig(
+  locationId: number,
+  hoursBefore: number,
+  template: string | null
+): Promise<ReminderConfig> {
+  return invoke('add_reminder_config', {
+    location_id: locationId,
+    hours_before: hoursBefore,
+    template
+  });
+}
+
+export async function removeReminderConfig(reminderConfigId: number): Promise<void> {
+  return invoke('remove_reminder_config', { reminder_config_id: reminderConfigId });
+}
+
+export async function listFollowUpSequence(locationId: number): Promise<FollowUpStep[]> {
+  return invoke('list_follow_up_sequence', { location_id: locationId });
+}
+
+export async function upsertFollowUpStep(
+  locationId: number,
+  stepNumber: number,
+  delayHours: number,
+  templateKey: string
+): Promise<FollowUpStep> {
+  return invoke('upsert_follow_up_step', {
+    location_id: locationId,
+    step_number: stepNumber,
+    delay_hours: delayHours,
+    template_key: templateKey
+  });
+}
+
+export async function getNextAvailableSlot(fromUtc?: string): Promise<SlotChoice | null> {
+  return invoke('get_next_available_slot', { from_utc: fromUtc ?? null });
+}
+
+export async function getAvailableSlots(fromUtc?: string, daysAhead?: number): Promise<SlotChoice[]> {
+  return invoke('get_available_slots', { from_utc: fromUtc ?? null, days_ahead: daysAhead ?? null });
+}
+
+export async function checkSlotAvailable(startAt: string, endAt: string): Promise<boolean> {
+  return invoke('check_slot_available', { start_at: startAt, end_at: endAt });
+}
+
+export async function addPhoneToBlocklist(
+  phoneE164: string,
+  reason?: string
+): Promise<BlocklistEntry> {
+  return invoke('add_phone_to_blocklist', { phone_e164: phoneE164, reason: reason ?? null });
+}
+
+export async function removePhoneFromBlocklist(phoneE164: string): Promise<void> {
+  return invoke('remove_phone_from_blocklist', { phone_e164: phoneE164 });
+}
+
+export async function listBlocklist(): Promise<BlocklistEntry[]> {
+  return invoke('list_blocklist');
+}
+
+export async function addHoliday(date: string, name: string): Promise<number> {
+  return invoke('add_holiday', { date, name });
+}
+
+export async function removeHoliday(id: number): Promise<void> {
+  return invoke('remove_holiday', { id });
+}
+
+export async function listHolidays(): Promise<Holiday[]> {
+  return invoke('list_holidays');
+}
+
+export async function addBlackoutPeriod(
+  startDate: string,
+  endDate: string,
+  reason: string
+): Promise<number> {
+  return invoke('add_blackout_period', { start_date: startDate, end_date: endDate, reason });
+}
+
+export async function removeBlackoutPeriod(id: number): Promise<void> {
+  return invoke('remove_blackout_period', { id });
+}
+
+export async function listBlackoutPeriods(): Promise<BlackoutPeriod[]> {
+  return invoke('list_blackout_periods');
+}
+
 export async function runDueJobs(): Promise<RunJobsResult> {
   return invoke('run_due_jobs');
 }
 
+export async function listScheduledJobs(filter: JobFilter = {}): Promise<ScheduledJob[]> {
+  return invoke('list_scheduled_jobs', { filter });
+}
+
+export async function listAuditLog(filter: AuditLogFilter = {}): Promise<AuditPage> {
+  return invoke('list_audit_log', {
+    action_type: filter.actionType ?? null,
+    target_type: filter.targetType ?? null,
+    success: filter.success ?? null,
+    since: filter.since ?? null,
+    limit: filter.limit ?? null,
+    cursor: filter.cursor ?? null
+  });
+}
+
+export async function getAuditLog(
+  filter: AuditLogFilter,
+  limit: number,
+  offset: number
+): Promise<AuditLogPage> {
+  return invoke('get_audit_log', {
+    filter: {
+      action_type: filter.actionType ?? null,
+      target_type: filter.targetType ?? null,
+      target_id: filter.targetId ?? null,
+      success: filter.success ?? null,
+      since: filter.since ?? null,
+      until: filter.until ?? null
+    },
+    limit,
+    offset
+  });
+}
+
+export async function exportAuditLogCsv(since?: string, until?: string): Promise<string> {
+  return invoke('export_audit_log_csv', { since: since ?? null, until: until ?? null });
+}
+
+export async function exportLeadsCsv(filter: LeadExportFilter = {}): Promise<string> {
+  return invoke('export_leads_csv', { filter });
+}
+
+export async function purgeOldAuditLogs(dryRun: boolean): Promise<PurgeResult> {
+  return invoke('purge_old_audit_logs', { dry_run: dryRun });
+}
+
+export async function getAuditLogRetentionDays(): Promise<number> {
+  return invoke('get_audit_log_retention_days');
+}
+
+export async function setAuditLogRetentionDays(days: number): Promise<void> {
+  return invoke('set_audit_log_retention_days', { days });
+}
+
+export async function cancelScheduledJob(jobId: number, reason: string): Promise<CancelJobResult> {
+  return invoke('cancel_scheduled_job', { job_id: jobId, reason });
+}
+
+export async function requeueFailedJob(jobId: number): Promise<ScheduledJob> {
+  return invoke('requeue_failed_job', { job_id: jobId });
+}
+
+export async function retryFailedJob(jobId: number, executeAt?: string): Promise<number> {
+  return invoke('retry_failed_job', { job_id: jobId, execute_at: executeAt ?? null });
+}
+
+export async function getJobStats(since?: string): Promise<JobStats> {
+  return invoke('get_job_stats', { since: since ?? null });
+}
+
+export async function scheduleReEngagementBatch(
+  leadIds: number[],
+  startAt: string,
+  intervalSeconds: number
+): Promise<BatchScheduleResult> {
+  return invoke('schedule_re_engagement_batch', {
+    lead_ids: leadIds,
+    start_at: startAt,
+    interval_seconds: intervalSeconds
+  });
+}
+
 export async function listAgentQueue(): Promise<LeadSummary[]> {
   return invoke('list_agent_queue');
 }
 
+export async function listLeadsNeedingAttention(): Promise<AttentionLeadView[]> {
+  return invoke('list_leads_needing_attention');
+}
+
 export async function agentDryRun(req: AgentDryRunRequest): Promise<AgentDryRunResult> {
   return invoke('agent_dry_run', { req });
 }
@@ -81,6 +799,18 @@ export async function agentExecute(req: AgentExecuteRequest): Promise<AgentExecu
   return invoke('agent_execute', { req });
 }
 
+export async function listAgentActions(
+  leadId?: number,
+  since?: string,
+  limit?: number
+): Promise<AgentActionRecord[]> {
+  return invoke('list_agent_actions', { lead_id: leadId ?? null, since: since ?? null, limit: limit ?? null });
+}
+
+export async function agentExecuteBatch(actions: AgentAction[]): Promise<AgentBatchResult> {
+  return invoke('agent_execute_batch', { actions });
+}
+
 export async function logClientError(input: ClientErrorLogInput): Promise<void> {
   return invoke('log_client_error', {
     message: input.message,
@@ -88,3 +818,7 @@ export async function logClientError(input: ClientErrorLogInput): Promise<void>
     source: input.source
   });
 }
+
+export async function getClientErrors(limit: number): Promise<AuditEntry[]> {
+  return invoke('get_client_errors', { limit });
+}
@@ -10,12 +10,67 @@ export type LeadSummary = {
   created_at: string;
 };
 
+export type AttentionLeadView = LeadSummary & {
+  attention_reason: string | null;
+  flagged_at: string | null;
+};
+
 export type Message = {
   id: number;
   direction: 'INBOUND' | 'OUTBOUND';
   body: string;
   status: string;
   created_at: string;
+  is_manual: boolean;
+};
+
+export type MessageFailure = Message & {
+  lead_id: number;
+  failed_at: string;
+  failure_reason: string;
+};
+
+export type ConversationHistoryItem =
+  | (Message & { kind: 'message' })
+  | {
+      kind: 'state_transition';
+      from: string;
+      to: string;
+      triggered_by: string;
+      at: string;
+    };
+
+export type MessageHistoryItem = Message & {
+  lead_id: number;
+  lead_phone: string;
+  conversation_id: number;
+};
+
+export type MessageSearchResult = {
+  message_id: number;
+  conversation_id: number;
+  lead_id: number;
+  direction: 'INBOUND' | 'OUTBOUND';
+  body: string;
+  created_at: string;
+};
+
+export type MessageHistoryPage = {
+  messages: MessageHistoryItem[];
+  next_cursor: number | null;
+};
+
+export type StaffResolution = 'Resolved' | 'ReEngage' | 'Archived' | 'NoAction';
+
+export type StaffAttentionResolution = {
+  lead_id: number;
+  resolution: StaffResolution;
+  jobs_cancelled: number;
+};
+
+export type SlotChoice = {
+  start_at: string;
+  end_at: string;
 };
 
 export type Appointment = {
@@ -34,6 +89,14 @@ export type Conversation = {
   state_json: string;
 };
 
+export type Note = {
+  id: number;
+  lead_id: number;
+  body: string;
+  author: string | null;
+  created_at: string;
+};
+
 export type LeadDetail = {
   lead: LeadSummary & {
     consent_at: string | null;
@@ -44,6 +107,17 @@ export type LeadDetail = {
   conversation: Conversation;
   messages: Message[];
   appointments: Appointment[];
+  tags: string[];
+  notes: Note[];
+};
+
+export type StatusHistoryEntry = {
+  id: number;
+  lead_id: number;
+  from_status: string | null;
+  to_status: string;
+  changed_at: string;
+  trigger: string;
 };
 
 export type LeadCreateInput = {
@@ -60,6 +134,49 @@ export type LeadCreateResult = {
   lead_id: number;
   duplicate_of: number | null;
   note: string | null;
+  blocked: boolean;
+};
+
+export type BulkCreateError = {
+  index: number;
+  message: string;
+};
+
+export type BulkCreateResult = {
+  created: LeadCreateResult[];
+  duplicates: LeadCreateResult[];
+  errors: BulkCreateError[];
+  jobs_scheduled: number;
+};
+
+export type CancelAppointmentResult = {
+  appointment_id: number;
+  reminder_jobs_cancelled: number;
+  waitlist_lead_id: number | null;
+};
+
+export type RescheduleResult = {
+  appointment_id: number;
+  reminder_job_ids: number[];
+  old_start_at: string;
+  old_end_at: string;
+  new_start_at: string;
+  new_end_at: string;
+};
+
+export type AppointmentType = {
+  id: number;
+  name: string;
+  duration_minutes: number;
+  confirmation_template: string;
+  reminder_template: string;
+};
+
+export type AppointmentTypeInput = {
+  name: string;
+  duration_minutes: number;
+  confirmation_template: string;
+  reminder_template: string;
 };
 
 export type TodayReport = {
@@ -70,10 +187,226 @@ export type TodayReport = {
   needs_attention: number;
 };
 
+export type FunnelReport = {
+  leads_created: number;
+  leads_contacted: number;
+  leads_responded_yes: number;
+  leads_booked: number;
+  leads_opted_out: number;
+  conversion_rate_pct: number;
+};
+
+export type SourceReport = {
+  source: string | null;
+  leads: number;
+  booked: number;
+  opted_out: number;
+  booking_rate_pct: number;
+};
+
+export type DayReport = TodayReport & {
+  date: string;
+};
+
+export type CommSummary = {
+  lead_id: number;
+  total_outbound: number;
+  total_inbound: number;
+  first_contact_at: string | null;
+  last_contact_at: string | null;
+  days_since_last_contact: number | null;
+  inbound_response_rate_pct: number | null;
+};
+
+export type DailyStats = {
+  date: string;
+  leads_created: number;
+  contacted: number;
+  booked: number;
+  opt_outs: number;
+  needs_attention_flagged: number;
+};
+
+export type AuditEntry = {
+  id: number;
+  action_type: string;
+  target_type: string;
+  target_id: string | null;
+  request_json: string;
+  response_json: string | null;
+  success: boolean;
+  error_message: string | null;
+  created_at: string;
+  severity: string;
+};
+
+export type AuditPage = {
+  entries: AuditEntry[];
+  next_cursor: number | null;
+};
+
+export type AuditLogFilter = {
+  actionType?: string;
+  targetType?: string;
+  targetId?: string;
+  success?: boolean;
+  since?: string;
+  until?: string;
+  limit?: number;
+  cursor?: number;
+};
+
+export type AuditLogPage = {
+  entries: AuditEntry[];
+  total: number;
+};
+
+export type BackupResult = {
+  destination_path: string;
+  pages_copied: number;
+  size_bytes: number;
+};
+
+export type AppointmentStatus = 'booked' | 'cancelled' | 'no_show' | 'completed';
+
+export type AppointmentDetail = {
+  id: number;
+  lead_id: number;
+  first_name: string | null;
+  last_name: string | null;
+  phone_e164: string;
+  start_at: string;
+  end_at: string;
+  status: AppointmentStatus;
+  appointment_type_id: number | null;
+  created_at: string;
+};
+
+export type AppInfo = {
+  app_version: string;
+  db_path: string;
+  db_size_bytes: number;
+  schema_version: number;
+  kill_switch_enabled: boolean;
+  location_name: string;
+  location_timezone: string;
+};
+
+export type VacuumResult = {
+  size_before_bytes: number;
+  size_after_bytes: number;
+};
+
+export type CheckpointMode = 'passive' | 'full' | 'restart';
+
+export type CheckpointResult = {
+  busy_frames: number;
+  log_frames: number;
+  checkpointed_frames: number;
+};
+
+export type IntegrityCheckResult = {
+  ok: boolean;
+  messages: string[];
+};
+
+export type SettingView = {
+  key: string;
+  value: string;
+  updated_at: string;
+};
+
+export type PurgeResult = {
+  rows_affected: number;
+  oldest_kept: string | null;
+};
+
+export type ScheduledJobStatus = 'pending' | 'completed' | 'failed' | 'cancelled';
+
+export type JobFilter = {
+  status?: ScheduledJobStatus;
+  job_type?: string;
+  since?: string;
+  until?: string;
+  limit?: number;
+};
+
+export type ScheduledJob = {
+  id: number;
+  job_type: string;
+  target_id: number | null;
+  execute_at: string;
+  status: ScheduledJobStatus;
+  payload_json: string;
+  created_at: string;
+  retry_count: number;
+  max_retries: number;
+  completed_at: string | null;
+};
+
+export type JobTypeStats = {
+  job_type: string;
+  pending: number;
+  completed: number;
+  failed: number;
+  cancelled: number;
+};
+
+export type JobStats = {
+  total_pending: number;
+  total_completed: number;
+  total_failed: number;
+  total_cancelled: number;
+  avg_delay_seconds: number | null;
+  by_type: JobTypeStats[];
+};
+
+export type CancelJobResult = {
+  job_id: number;
+  previous_status: ScheduledJobStatus;
+};
+
 export type RunJobsResult = {
   processed: number;
   skipped: number;
   errors: number;
+  auto_kill_switch_triggered: boolean;
+};
+
+export type KillSwitchState = {
+  enabled: boolean;
+  reason: string | null;
+  enabled_at: string | null;
+  pending_jobs_count: number;
+};
+
+export type RateLimitSettings = {
+  max_outbound_per_lead_per_day: number;
+  max_outbound_per_location_per_hour: number;
+};
+
+export type RateLimitStatus = {
+  location_hour_used: number;
+  location_hour_limit: number;
+  lead_day_used?: number;
+  lead_day_limit?: number;
+  cooldown_seconds?: number;
+};
+
+export type BulkInboundItem = {
+  lead_id: number;
+  body: string;
+};
+
+export type BulkInboundResult = {
+  processed: number;
+  errors: [number, string][];
+};
+
+export type BatchScheduleResult = {
+  scheduled: number;
+  skipped: number;
+  errors: [number, string][];
 };
 
 export type WipeAllDataResult = {
@@ -82,6 +415,10 @@ export type WipeAllDataResult = {
   scheduled_jobs: number;
   audit_log: number;
   conversations: number;
+  lead_tags: number;
+  lead_status_history: number;
+  lead_notes: number;
+  waitlist: number;
   leads: number;
 };
 
@@ -90,6 +427,61 @@ export type LocationSettings = {
   gym_name: string;
   timezone: string;
   business_hours_json: string;
+  appointment_duration_minutes: number;
+  appointment_buffer_minutes: number;
+  reminder_enabled: boolean;
+};
+
+export type MessageTemplate = {
+  id: number;
+  key: string;
+  body: string;
+  created_at: string;
+};
+
+export type CustomKeyword = {
+  id: number;
+  keyword: string;
+  response_body: string;
+  active: boolean;
+  created_at: string;
+};
+
+export type ReminderConfig = {
+  id: number;
+  location_id: number;
+  hours_before: number;
+  template: string | null;
+};
+
+export type BlocklistEntry = {
+  id: number;
+  phone_e164: string;
+  reason: string | null;
+  added_at: string;
+};
+
+export type Holiday = {
+  id: number;
+  location_id: number;
+  date: string;
+  name: string;
+};
+
+export type BlackoutPeriod = {
+  id: number;
+  location_id: number;
+  start_date: string;
+  end_date: string;
+  reason: string;
+};
+
+export type FollowUpStep = {
+  id: number;
+  location_id: number;
+  step_number: number;
+  delay_hours: number;
+  template_key: string;
 };
 
 export type UpdateLocationSettingsInput = {
@@ -98,6 +490,13 @@ export type UpdateLocationSettingsInput = {
   business_hours_json: string;
 };
 
+export type LocationPatch = Partial<UpdateLocationSettingsInput>;
+
+export type LocationUpdateResult = {
+  location: LocationSettings;
+  past_due_jobs: ScheduledJob[];
+};
+
 export type AgentAction =
   | {
       action_type: 'send_outbound';
@@ -116,6 +515,7 @@ export type AgentAction =
       start_at: string;
       end_at: string;
       status: string;
+      appointment_type_id?: number | null;
     }
   | {
       action_type: 'set_opt_out';
@@ -143,6 +543,7 @@ export type AgentDryRunResult = {
 
 export type AgentExecuteRequest = {
   action: AgentAction;
+  idempotency_key?: string | null;
 };
 
 export type AgentExecuteResult = {
@@ -150,3 +551,87 @@ export type AgentExecuteResult = {
   result_json: Record<string, unknown> | null;
   error: string | null;
 };
+
+export type AgentBatchResult = {
+  results: AgentExecuteResult[];
+  batch_aborted_at: number | null;
+};
+
+export type AgentActionRecord = {
+  id: number;
+  action_type: string;
+  target_type: string;
+  success: boolean;
+  request_json: Record<string, unknown>;
+  response_json: Record<string, unknown> | null;
+  created_at: string;
+};
+
+export type ValidationReport = {
+  valid: boolean;
+  errors: string[];
+  warnings: string[];
+};
+
+export type ErrorCode =
+  | 'db_busy'
+  | 'not_found'
+  | 'consent_required'
+  | 'opted_out'
+  | 'rate_limit'
+  | 'business_hours'
+  | 'validation_failed'
+  | 'kill_switch'
+  | 'internal';
+
+export type AppErrorResponse = {
+  code: ErrorCode;
+  message: string;
+  detail: Record<string, unknown> | null;
+  status?: 404;
+};
+
+export type LeadConsentResult = {
+  lead_id: number;
+  consent: boolean;
+  follow_up_scheduled: boolean;
+  follow_ups_cancelled: number;
+};
+
+export type LeadExportFilter = {
+  statuses?: string[];
+};
+
+export type LeadPatch = {
+  first_name?: string | null;
+  last_name?: string | null;
+  phone_e164?: string | null;
+  consent?: boolean | null;
+  consent_at?: string | null;
+};
+
+export type LeadPage = {
+  leads: LeadSummary[];
+  total: number;
+  has_more: boolean;
+};
+
+export type AppointmentCompletion = {
+  appointment_id: number;
+  follow_up_job_id: number | null;
+  follow_up_error: string | null;
+};
+
+export type DemoResetResult = {
+  rows_deleted: number;
+  location_id: number;
+};
+
+export type MigrationRecord = {
+  version: number;
+  applied_at: string;
+  description: string;
+  filename: string | null;
+  checksum: string | null;
+  error: string | null;
+};